@@ -0,0 +1,126 @@
+use colored::Colorize;
+use crate::render;
+use reqwest::header::{HeaderName, HeaderValue, COOKIE};
+use std::collections::HashMap;
+
+/// Violating workers listed under the summary before the rest are elided
+const MAX_LISTED: usize = 10;
+
+/// `--identity`: what each worker sends so a load balancer can pin it to a backend
+#[derive(Clone, Debug)]
+pub enum Identity {
+    /// `header:NAME`, sent as `NAME: <id>`
+    Header(HeaderName),
+    /// `cookie:NAME`, sent as `Cookie: NAME=<id>`
+    Cookie(String),
+}
+
+impl Identity {
+    /// Parse `header:NAME` or `cookie:NAME`
+    pub fn parse(s: &str) -> Result<Identity, String> {
+        let (kind, name) = s.split_once(':').ok_or("expected header:NAME or cookie:NAME")?;
+        let name = name.trim();
+        match kind.trim() {
+            "header" => HeaderName::try_from(name)
+                .map(Identity::Header)
+                .map_err(|e| format!("invalid header name: {}", e)),
+            "cookie" if !name.is_empty() && HeaderValue::from_str(name).is_ok() && !name.contains([';', '=']) => {
+                Ok(Identity::Cookie(name.to_string()))
+            }
+            "cookie" => Err(format!("invalid cookie name {:?}", name)),
+            other => Err(format!("unknown identity kind {:?}, expected header or cookie", other)),
+        }
+    }
+
+    /// Worker `worker`'s identity: the same on every request it sends, and unique to the
+    /// run unless `--seed` is repeated
+    pub fn for_worker(&self, seed: u64, worker: usize) -> (HeaderName, HeaderValue) {
+        let id = format!("benchy-{:08x}-{}", seed as u32, worker);
+        match self {
+            Identity::Header(name) => (name.clone(), HeaderValue::from_str(&id).expect("id is ASCII")),
+            Identity::Cookie(name) => (
+                COOKIE,
+                HeaderValue::from_str(&format!("{}={}", name, id)).expect("name was validated"),
+            ),
+        }
+    }
+
+    /// For the banner, e.g. "cookie session"
+    pub fn describe(&self) -> String {
+        match self {
+            Identity::Header(name) => format!("header {}", name),
+            Identity::Cookie(name) => format!("cookie {}", name),
+        }
+    }
+}
+
+#[derive(Default)]
+struct WorkerBackends {
+    backends: HashMap<String, u64>,
+    /// Responses without the `--affinity-header` header
+    unlabeled: u64,
+}
+
+/// `--affinity-header`: which backends each worker's responses came from, owned by the
+/// collector. A worker that saw more than one backend lost its affinity.
+pub struct AffinityTally {
+    header: HeaderName,
+    workers: Vec<WorkerBackends>,
+}
+
+impl AffinityTally {
+    pub fn new(header: HeaderName, workers: usize) -> Self {
+        AffinityTally {
+            header,
+            workers: (0..workers).map(|_| WorkerBackends::default()).collect(),
+        }
+    }
+
+    pub fn record(&mut self, worker: usize, backend: Option<String>) {
+        let entry = &mut self.workers[worker];
+        match backend {
+            Some(backend) => *entry.backends.entry(backend).or_default() += 1,
+            None => entry.unlabeled += 1,
+        }
+    }
+
+    /// Workers whose responses came from more than one backend
+    pub fn violations(&self) -> usize {
+        self.workers.iter().filter(|w| w.backends.len() > 1).count()
+    }
+
+    pub fn print(&self) {
+        let labeled = self.workers.iter().filter(|w| !w.backends.is_empty()).count();
+        let unlabeled: u64 = self.workers.iter().map(|w| w.unlabeled).sum();
+        if labeled == 0 && unlabeled == 0 {
+            return;
+        }
+
+        println!("\n{}", format!("--- Affinity: {} ---", self.header).cyan().bold());
+        let mut backends: Vec<&String> = self.workers.iter().flat_map(|w| w.backends.keys()).collect();
+        backends.sort();
+        backends.dedup();
+        render::print("Backends:".white(), format!("{} across {} workers", backends.len(), labeled));
+
+        let violations = self.violations();
+        let summary = format!("{} workers saw >1 backend", violations);
+        render::print("Violations:".white(), if violations > 0 { summary.red().bold() } else { summary.green() });
+        if unlabeled > 0 {
+            render::print(
+                "Unlabeled:".white(),
+                format!("{} responses had no {} header", unlabeled, self.header).yellow(),
+            );
+        }
+
+        let violating = self.workers.iter().enumerate().filter(|(_, w)| w.backends.len() > 1);
+        for (worker, entry) in violating.clone().take(MAX_LISTED) {
+            let mut counts: Vec<(&String, &u64)> = entry.backends.iter().collect();
+            counts.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+            let counts: Vec<String> = counts.iter().map(|(backend, n)| format!("{} ({})", backend, n)).collect();
+            render::print(format!("Worker {}:", worker).white(), counts.join(", "));
+        }
+        if violations > MAX_LISTED {
+            render::print("...".white(), format!("{} more workers", violations - MAX_LISTED).dimmed());
+        }
+    }
+}
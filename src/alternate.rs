@@ -0,0 +1,264 @@
+//! `--alternate A_URL,B_URL`: the whole load switches between two targets every
+//! `--slice`, so both see the same network conditions, and each pair of neighbouring
+//! slices gives one A-to-B difference. Those paired differences vary far less than two
+//! separate runs do.
+//!
+//! A slice ends by letting every request still in flight finish; no new request goes
+//! out until they have, so each request is attributed to the slice and target it was
+//! sent in.
+
+use crate::render::{self, Table};
+use crate::units::LatencyUnit;
+use crate::Percentiles;
+use colored::Colorize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// Set in `Slicer::state` from a slice's end until its requests have drained
+const CLOSING: u64 = 1 << 31;
+
+/// Bits of `Slicer::state` counting requests in flight
+const IN_FLIGHT: u64 = CLOSING - 1;
+
+/// Parse `A_URL,B_URL`
+pub fn parse(s: &str) -> Result<[String; 2], String> {
+    let Some((a, b)) = s.split_once(',') else {
+        return Err("expected A_URL,B_URL".to_string());
+    };
+    for url in [a, b] {
+        reqwest::Url::parse(url).map_err(|e| format!("invalid URL {:?}: {}", url, e))?;
+    }
+    Ok([a.to_string(), b.to_string()])
+}
+
+/// Which target slice `index` sends to: 0 for A, 1 for B
+pub fn target(index: u64) -> usize {
+    (index % 2) as usize
+}
+
+/// The current slice, shared by every worker
+pub struct Slicer {
+    slice: Duration,
+    /// Slice index in the upper 32 bits, `CLOSING`, and the slice's requests in flight
+    /// in the lower 31, so a request can't start in one slice and be counted in the next
+    state: AtomicU64,
+    drained: Notify,
+    opened: Notify,
+    /// When each slice started, from the start of the run
+    starts: Mutex<Vec<Duration>>,
+}
+
+impl Slicer {
+    pub fn new(slice: Duration) -> Self {
+        Slicer {
+            slice,
+            state: AtomicU64::new(0),
+            drained: Notify::new(),
+            opened: Notify::new(),
+            starts: Mutex::new(vec![Duration::ZERO]),
+        }
+    }
+
+    /// Whether new requests may go out
+    pub fn is_open(&self) -> bool {
+        self.state.load(Ordering::SeqCst) & CLOSING == 0
+    }
+
+    /// The slice requests in flight belong to
+    pub fn index(&self) -> u64 {
+        self.state.load(Ordering::SeqCst) >> 32
+    }
+
+    /// Count a request going out: the slice it belongs to. One begun just as the slice
+    /// closed still belongs to it, and is drained with it.
+    pub fn begin(&self) -> u64 {
+        self.state.fetch_add(1, Ordering::SeqCst) >> 32
+    }
+
+    /// Count a begun request as finished, however it ended
+    pub fn finish(&self) {
+        let state = self.state.fetch_sub(1, Ordering::SeqCst);
+        if state & CLOSING != 0 && state & IN_FLIGHT == 1 {
+            self.drained.notify_one();
+        }
+    }
+
+    /// Park until the next slice opens; returns at once if one is open
+    pub async fn wait_open(&self) {
+        loop {
+            // Registered before the check so an opening in between isn't missed
+            let opened = self.opened.notified();
+            if self.is_open() {
+                return;
+            }
+            opened.await;
+        }
+    }
+
+    /// Close each slice after `--slice` and open the next once it has drained, until
+    /// aborted
+    pub async fn run(&self, start: Instant) {
+        loop {
+            tokio::time::sleep(self.slice).await;
+            let index = self.state.fetch_or(CLOSING, Ordering::SeqCst) >> 32;
+            loop {
+                let state = self.state.load(Ordering::SeqCst);
+                // Fails if a request began since the load, which then drains too
+                if state & IN_FLIGHT == 0
+                    && self
+                        .state
+                        .compare_exchange(state, (index + 1) << 32, Ordering::SeqCst, Ordering::SeqCst)
+                        .is_ok()
+                {
+                    break;
+                }
+                self.drained.notified().await;
+            }
+            self.starts.lock().unwrap().push(start.elapsed());
+            self.opened.notify_waiters();
+        }
+    }
+
+    /// When each slice started, from the start of the run
+    pub fn starts(&self) -> Vec<Duration> {
+        self.starts.lock().unwrap().clone()
+    }
+}
+
+#[derive(Default)]
+struct SliceEntry {
+    latencies: Vec<Duration>,
+    failed: u64,
+}
+
+/// Latencies and failures for each slice, owned by the collector
+#[derive(Default)]
+pub struct SliceStats {
+    entries: Vec<SliceEntry>,
+}
+
+impl SliceStats {
+    pub fn record(&mut self, slice: u64, latency: Duration, failed: bool) {
+        let slice = slice as usize;
+        if self.entries.len() <= slice {
+            self.entries.resize_with(slice + 1, SliceEntry::default);
+        }
+        let entry = &mut self.entries[slice];
+        entry.latencies.push(latency);
+        if failed {
+            entry.failed += 1;
+        }
+    }
+
+    /// The `--- Alternate ---` section: each target's aggregate, every slice, and the
+    /// paired difference
+    pub fn print(&mut self, targets: &[String; 2], starts: &[Duration], unit: LatencyUnit) {
+        if self.entries.is_empty() {
+            return;
+        }
+
+        println!("\n{}", "--- Alternate ---".cyan().bold());
+        for (target, (name, url)) in ["A", "B"].into_iter().zip(targets).enumerate() {
+            let slices = self.entries.iter().skip(target).step_by(2);
+            let failed: u64 = slices.clone().map(|e| e.failed).sum();
+            let mut latencies: Vec<Duration> = slices.flat_map(|e| e.latencies.iter().copied()).collect();
+            let p = Percentiles::from_unsorted(&mut latencies);
+            let errors = format!("{:.2}% errors", failed as f64 / p.count.max(1) as f64 * 100.0);
+            render::print(
+                format!("{}:", name).white(),
+                format!(
+                    "{} requests, avg {}, P50 {}, P99 {}, {}  {}",
+                    p.count,
+                    unit.format(p.avg),
+                    unit.format(p.p50),
+                    unit.format(p.p99).red(),
+                    if failed > 0 { errors.red() } else { errors.normal() },
+                    url.dimmed()
+                ),
+            );
+        }
+
+        println!();
+        let mut table = Table::new(["Slice", "Target", "Start", "Requests", "Errors", "Avg", "P50", "P99"]);
+        for (index, entry) in self.entries.iter_mut().enumerate() {
+            let failed = entry.failed;
+            let p = Percentiles::from_unsorted(&mut entry.latencies);
+            let start = starts.get(index).map_or("-".to_string(), |start| format!("{:.1}s", start.as_secs_f64()));
+            let errors = failed.to_string();
+            table.row([
+                index.to_string().white().to_string(),
+                ["A", "B"][target(index as u64)].to_string(),
+                start,
+                p.count.to_string(),
+                if failed > 0 { errors.red().to_string() } else { errors },
+                unit.format(p.avg),
+                unit.format(p.p50),
+                unit.format(p.p99).red().to_string(),
+            ]);
+        }
+        table.print();
+
+        self.print_delta(unit);
+    }
+
+    /// B's average latency less A's, over each A slice and the B slice after it, with
+    /// a 95% confidence interval from Student's t
+    fn print_delta(&self, unit: LatencyUnit) {
+        let avg = |entry: &SliceEntry| {
+            let sum: Duration = entry.latencies.iter().sum();
+            sum.as_secs_f64() / entry.latencies.len() as f64
+        };
+        let pairs: Vec<(f64, f64)> = self
+            .entries
+            .chunks_exact(2)
+            .filter(|pair| pair.iter().all(|e| !e.latencies.is_empty()))
+            .map(|pair| (avg(&pair[0]), avg(&pair[1])))
+            .collect();
+        let n = pairs.len();
+        if n == 0 {
+            render::print("Paired delta:".white(), "no complete A/B pair of slices".dimmed());
+            return;
+        }
+
+        let deltas: Vec<f64> = pairs.iter().map(|(a, b)| b - a).collect();
+        let mean = deltas.iter().sum::<f64>() / n as f64;
+        let baseline = pairs.iter().map(|(a, _)| a).sum::<f64>() / n as f64;
+        let relative = format!("{:+.1}%", mean / baseline * 100.0);
+        let pairs = if n == 1 { "1 pair".to_string() } else { format!("{} pairs", n) };
+        let line = format!("B - A avg {} ({}) over {}", signed(mean, unit), relative, pairs);
+        if n < 2 {
+            render::print("Paired delta:".white(), format!("{}, {}", line, "2 pairs needed for an interval".dimmed()));
+            return;
+        }
+
+        let variance = deltas.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+        let margin = t_975(n - 1) * (variance / n as f64).sqrt();
+        let interval = format!("95% CI [{}, {}]", signed(mean - margin, unit), signed(mean + margin, unit));
+        let verdict = if mean - margin > 0.0 {
+            "B is slower".red().bold()
+        } else if mean + margin < 0.0 {
+            "B is faster".green().bold()
+        } else {
+            "no significant difference".normal()
+        };
+        render::print("Paired delta:".white(), format!("{}, {}: {}", line, interval, verdict));
+    }
+}
+
+/// `seconds` in `unit`, with its sign
+fn signed(seconds: f64, unit: LatencyUnit) -> String {
+    let sign = if seconds < 0.0 { "-" } else { "+" };
+    format!("{}{}", sign, unit.format(Duration::from_secs_f64(seconds.abs())))
+}
+
+/// Student's t for a two-sided 95% interval with `df` degrees of freedom; the normal
+/// value past 30
+fn t_975(df: usize) -> f64 {
+    const T: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160, 2.145, 2.131,
+        2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042,
+    ];
+    T.get(df.wrapping_sub(1)).copied().unwrap_or(1.960)
+}
@@ -0,0 +1,204 @@
+use crate::dns::BenchResolver;
+use crate::protocol::Protocol;
+use crate::transport::{BoxError, TlsStats};
+use crate::{build_tls, ClientOptions, ErrorDetails, RequestResult, WorkerClient};
+use reqwest::dns::{Name, Resolve};
+use reqwest::Version;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Outcome of the `--h3-alt-svc` discovery, one entry per worker
+#[derive(Default)]
+pub struct AltSvcStats {
+    pub switched: AtomicU64,
+    /// Workers that stayed on HTTP/2, usually because no `h3` alternative was advertised
+    pub not_switched: AtomicU64,
+    pub h2_requests: AtomicU64,
+    pub h3_requests: AtomicU64,
+    /// Time from the start of the run until each worker switched
+    switch_times: Mutex<Vec<Duration>>,
+}
+
+impl AltSvcStats {
+    pub fn record_switch(&self, after: Duration) {
+        self.switched.fetch_add(1, Ordering::Relaxed);
+        self.switch_times.lock().unwrap().push(after);
+    }
+
+    pub fn record_request(&self, version: Version) {
+        if version == Version::HTTP_3 {
+            self.h3_requests.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.h2_requests.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn take_switch_times(&self) -> Vec<Duration> {
+        std::mem::take(&mut *self.switch_times.lock().unwrap())
+    }
+}
+
+/// An `h3` alternative advertised by the origin
+#[derive(Debug)]
+pub struct AltAuthority {
+    /// `None` when the alternative is on the origin's own host (`h3=":443"`)
+    pub host: Option<String>,
+    pub port: u16,
+}
+
+/// Pick the first `h3` alternative out of an Alt-Svc header value, e.g.
+/// `h3=":443"; ma=86400, h3-29=":443"`. Draft versions are ignored.
+pub fn parse_h3(value: &str) -> Option<AltAuthority> {
+    for alternative in value.split(',') {
+        // Parameters such as `ma` follow the first `;`
+        let alternative = alternative.split(';').next()?.trim();
+        let Some((protocol, authority)) = alternative.split_once('=') else {
+            continue;
+        };
+        if protocol.trim() != "h3" {
+            continue;
+        }
+
+        // A malformed alternative doesn't rule out the ones after it
+        let authority = authority.trim().trim_matches('"');
+        let Some((host, port)) = authority.rsplit_once(':') else {
+            continue;
+        };
+        let Ok(port) = port.parse() else {
+            continue;
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        return Some(AltAuthority {
+            host: (!host.is_empty()).then(|| host.to_string()),
+            port,
+        });
+    }
+    None
+}
+
+/// Resolve the alternative to the address QUIC should dial, falling back to the
+/// origin host when the alternative doesn't name one. Names go through the run's
+/// resolver, so `--dns-server`, the DNS cache and the address order apply.
+pub async fn resolve(alt: &AltAuthority, origin_host: &str, resolver: &BenchResolver) -> Result<SocketAddr, BoxError> {
+    let host = alt.host.as_deref().unwrap_or(origin_host);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = IpAddr::from_str(host) {
+        return Ok(SocketAddr::new(ip, alt.port));
+    }
+    let mut addr = resolver
+        .resolve(Name::from_str(host)?)
+        .await?
+        .next()
+        .ok_or_else(|| format!("{} has no addresses", host))?;
+    addr.set_port(alt.port);
+    Ok(addr)
+}
+
+/// Everything a worker needs to follow the origin's Alt-Svc to HTTP/3
+pub struct Discovery {
+    pub origin_host: String,
+    pub insecure: bool,
+    pub resumption: bool,
+    pub require_h3: bool,
+    pub tls_stats: Arc<TlsStats>,
+}
+
+impl Discovery {
+    /// Switch `client` to HTTP/3 at the alternative advertised by the worker's first
+    /// response. Staying on HTTP/2 is only an error with `--require-h3`.
+    pub async fn switch(
+        &self,
+        client: &mut WorkerClient,
+        first: &RequestResult,
+        stats: &AltSvcStats,
+        run_start: Instant,
+    ) -> Result<(), ErrorDetails> {
+        match self.try_switch(client, first).await {
+            Ok(()) => {
+                stats.record_switch(run_start.elapsed());
+                Ok(())
+            }
+            Err(reason) => {
+                stats.not_switched.fetch_add(1, Ordering::Relaxed);
+                if !self.require_h3 {
+                    return Ok(());
+                }
+                Err(ErrorDetails {
+                    message: format!("--require-h3: {}", reason),
+                    status: None,
+                    headers: None,
+                    body: None,
+                    trailers: None,
+                })
+            }
+        }
+    }
+
+    async fn try_switch(&self, client: &mut WorkerClient, first: &RequestResult) -> Result<(), String> {
+        let header = match first {
+            RequestResult::Success(sample) | RequestResult::Failed(sample) => sample.alt_svc.as_ref(),
+            RequestResult::Error(_) => None,
+        };
+        let alt = header
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_h3)
+            .ok_or_else(|| format!("{} does not advertise h3 via Alt-Svc", self.origin_host))?;
+
+        let addr = resolve(&alt, &self.origin_host, &client.opts.resolver)
+            .await
+            .map_err(|e| format!("could not resolve Alt-Svc alternative: {}", e))?;
+
+        let tls = build_tls(Protocol::H3, self.insecure, true, self.resumption, &self.tls_stats)
+            .map_err(|e| e.to_string())?;
+        let opts = ClientOptions {
+            protocol: Protocol::H3,
+            // Requests keep the origin's URL (and so its Host and SNI) but dial the alternative
+            resolve_override: Some((self.origin_host.clone(), addr)),
+            ..client.opts.clone()
+        };
+        *client = WorkerClient::new(opts, tls).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{AddressOrder, DnsStats};
+
+    #[test]
+    fn picks_the_first_well_formed_h3_alternative() {
+        let alt = parse_h3(r#"h3-29=":443"; ma=86400, h3=":8443"; ma=3600"#).unwrap();
+        assert_eq!((alt.host, alt.port), (None, 8443));
+        let alt = parse_h3(r#"h2="alt.example.com:443", h3="[2001:db8::1]:443""#).unwrap();
+        assert_eq!((alt.host.as_deref(), alt.port), (Some("2001:db8::1"), 443));
+        assert!(parse_h3(r#"h2=":443", clear"#).is_none());
+    }
+
+    #[test]
+    fn a_malformed_alternative_is_skipped() {
+        let alt = parse_h3(r#"h3="no-port", h3=":99999", h3="alt.example.com:443""#).unwrap();
+        assert_eq!((alt.host.as_deref(), alt.port), (Some("alt.example.com"), 443));
+        assert!(parse_h3(r#"h3="no-port""#).is_none());
+    }
+
+    #[tokio::test]
+    async fn names_go_through_the_run_resolver() {
+        let stats = Arc::new(DnsStats::default());
+        let resolver = BenchResolver::new(None, None, AddressOrder::V4First, false, stats.clone());
+        let on_origin = AltAuthority { host: None, port: 8443 };
+        assert_eq!(resolve(&on_origin, "127.0.0.1", &resolver).await.unwrap(), "127.0.0.1:8443".parse().unwrap());
+        assert_eq!(stats.take_lookup_times().len(), 0);
+
+        let named = AltAuthority {
+            host: Some("localhost".to_string()),
+            port: 8443,
+        };
+        let addr = resolve(&named, "origin.invalid", &resolver).await.unwrap();
+        assert_eq!((addr.ip().is_loopback(), addr.port()), (true, 8443));
+        assert_eq!(stats.take_lookup_times().len(), 1, "the lookup wasn't timed by the run's resolver");
+    }
+}
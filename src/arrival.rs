@@ -0,0 +1,420 @@
+use crate::ratelimit::RateLimits;
+use crate::render::{self, Table};
+use crate::units::LatencyUnit;
+use crate::Percentiles;
+use clap::ValueEnum;
+use colored::Colorize;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Falling this far behind schedule means the client couldn't keep up with the pacing
+const LAG_WARNING: Duration = Duration::from_millis(100);
+
+/// Mixed into the seed so the schedule draws from a different stream than the worker's
+/// body generator, and pacing doesn't shift generated bodies
+const SCHEDULE_STREAM: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// How send times are spaced under `--rate` (`--arrival`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Arrival {
+    /// Evenly spaced, one every 1/rate seconds
+    #[default]
+    Uniform,
+    /// Exponentially distributed gaps averaging 1/rate seconds, so load comes in bursts
+    Poisson,
+}
+
+impl Arrival {
+    pub fn name(self) -> &'static str {
+        match self {
+            Arrival::Uniform => "uniform",
+            Arrival::Poisson => "poisson",
+        }
+    }
+}
+
+/// `--rate`: requests per second, which must be positive
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+        _ => Err(format!("expected a positive number of requests per second, got {:?}", s)),
+    }
+}
+
+/// One worker's send times under `--rate`
+pub struct Schedule {
+    arrival: Arrival,
+    /// Seconds between sends on average
+    mean_gap: f64,
+    next: Instant,
+    rng: SmallRng,
+    /// `--auto-rate`: the advertised limit, which may lengthen the gap or hold sends
+    limits: Option<Arc<RateLimits>>,
+}
+
+impl Schedule {
+    /// `rate` is this worker's share of `--rate`
+    pub fn new(arrival: Arrival, rate: f64, seed: u64, worker: usize, limits: Option<Arc<RateLimits>>) -> Self {
+        Schedule {
+            arrival,
+            mean_gap: 1.0 / rate,
+            next: Instant::now(),
+            rng: SmallRng::seed_from_u64(seed.wrapping_add(worker as u64) ^ SCHEDULE_STREAM),
+            limits,
+        }
+    }
+
+    pub fn next_due(&self) -> Instant {
+        self.next
+    }
+
+    pub fn is_due(&self) -> bool {
+        Instant::now() >= self.next
+    }
+
+    /// Book the following send one gap after this one was due, so sends delayed by a
+    /// full pipeline are caught up and the mean rate holds
+    pub fn advance(&mut self) {
+        let mean_gap = self.limits.as_ref().map_or(self.mean_gap, |limits| limits.gap(self.mean_gap));
+        let gap = match self.arrival {
+            Arrival::Uniform => mean_gap,
+            // Inverse transform sampling; 1 - u is in (0, 1], so the log is finite
+            Arrival::Poisson => -(1.0 - self.rng.gen::<f64>()).ln() * mean_gap,
+        };
+        self.next += Duration::from_secs_f64(gap);
+        if let Some(limits) = &self.limits {
+            self.next = self.next.max(limits.held_until());
+        }
+    }
+
+    /// Start over from now, so sends missed while paused aren't made up in a burst
+    pub fn restart(&mut self) {
+        self.next = Instant::now();
+    }
+}
+
+/// `--burst`: `size` requests released together at the start of every `interval`
+#[derive(Clone, Copy, Debug)]
+pub struct Burst {
+    pub size: u64,
+    pub interval: Duration,
+}
+
+impl Burst {
+    /// Parse `SIZE/INTERVAL`, e.g. `50/1s`
+    pub fn parse(s: &str) -> Result<Burst, String> {
+        let (size, interval) = s.split_once('/').ok_or("expected SIZE/INTERVAL, e.g. 50/1s")?;
+        let size: u64 = size.trim().parse().map_err(|e| format!("invalid SIZE: {}", e))?;
+        let interval = humantime::parse_duration(interval.trim()).map_err(|e| format!("invalid INTERVAL: {}", e))?;
+        if size == 0 || interval.is_zero() {
+            return Err("SIZE and INTERVAL must be positive".to_string());
+        }
+        Ok(Burst { size, interval })
+    }
+
+    /// Worker `worker`'s part of each burst; the first `size % workers` take one more
+    pub fn share(&self, workers: usize, worker: usize) -> u64 {
+        self.size / workers as u64 + if (worker as u64) < self.size % workers as u64 { 1 } else { 0 }
+    }
+}
+
+/// Where a request fell in its worker's part of a burst
+#[derive(Clone, Copy, Debug)]
+pub struct Position {
+    pub index: u64,
+    pub of: u64,
+}
+
+/// One worker's bursts under `--burst`, on a grid of intervals from the start of the run
+pub struct Bursts {
+    share: u64,
+    interval: Duration,
+    /// When the current burst was due to start
+    started: Instant,
+    /// When the next burst starts
+    next: Instant,
+    /// Requests of the current burst not yet sent
+    left: u64,
+    released: u64,
+}
+
+impl Bursts {
+    pub fn new(burst: Burst, share: u64, start: Instant) -> Self {
+        Bursts {
+            share,
+            interval: burst.interval,
+            started: start,
+            next: start,
+            left: 0,
+            released: 0,
+        }
+    }
+
+    /// Start every burst whose time has come. Requests a previous burst never got to
+    /// send are dropped rather than carried over, and returned as backlogged.
+    fn roll(&mut self) -> u64 {
+        let now = Instant::now();
+        let mut backlogged = 0;
+        while now >= self.next {
+            backlogged += self.left;
+            self.left = self.share;
+            self.released = 0;
+            self.started = self.next;
+            self.next += self.interval;
+        }
+        backlogged
+    }
+
+    /// Skip to the next burst on the grid without counting the ones missed
+    fn restart(&mut self) {
+        let now = Instant::now();
+        if now >= self.next {
+            let behind = (now - self.next).as_nanos() / self.interval.as_nanos() + 1;
+            self.next += self.interval * behind as u32;
+        }
+        self.left = 0;
+    }
+}
+
+/// How a worker paces its sends: `--rate` or `--burst`
+pub enum Pacer {
+    Rate(Schedule),
+    Burst(Bursts),
+}
+
+impl Pacer {
+    /// Catch up with the clock, returning requests dropped from bursts that ran out
+    /// of time (always 0 for `--rate`)
+    pub fn roll(&mut self) -> u64 {
+        match self {
+            Pacer::Rate(_) => 0,
+            Pacer::Burst(bursts) => bursts.roll(),
+        }
+    }
+
+    pub fn is_due(&self) -> bool {
+        match self {
+            Pacer::Rate(schedule) => schedule.is_due(),
+            Pacer::Burst(bursts) => bursts.left > 0,
+        }
+    }
+
+    /// Account for one send, returning its place in the burst
+    pub fn advance(&mut self) -> Option<Position> {
+        match self {
+            Pacer::Rate(schedule) => {
+                schedule.advance();
+                None
+            }
+            Pacer::Burst(bursts) => {
+                let position = Position {
+                    index: bursts.released,
+                    of: bursts.share,
+                };
+                bursts.left -= 1;
+                bursts.released += 1;
+                Some(position)
+            }
+        }
+    }
+
+    /// How far behind schedule a send going out now is: past its due time under
+    /// `--rate`, past the start of its burst under `--burst`
+    pub fn lag(&self) -> Duration {
+        let due = match self {
+            Pacer::Rate(schedule) => schedule.next,
+            Pacer::Burst(bursts) => bursts.started,
+        };
+        Instant::now().saturating_duration_since(due)
+    }
+
+    /// When the next send may go out, if nothing is due now
+    pub fn next_due(&self) -> Instant {
+        match self {
+            Pacer::Rate(schedule) => schedule.next_due(),
+            Pacer::Burst(bursts) => bursts.next,
+        }
+    }
+
+    /// After a pause or backoff: resume without making up for lost time
+    pub fn restart(&mut self) {
+        match self {
+            Pacer::Rate(schedule) => schedule.restart(),
+            Pacer::Burst(bursts) => bursts.restart(),
+        }
+    }
+}
+
+/// How closely the workers kept to `--rate` or `--burst`, from cheap counters updated
+/// on every paced send
+#[derive(Default)]
+pub struct PacingStats {
+    submitted: AtomicU64,
+    /// Nanoseconds, summed and the largest
+    lag_total: AtomicU64,
+    max_lag: AtomicU64,
+    /// End of the paced period, in nanoseconds from the start of the run: when the
+    /// send after the last one was due, or the last send if it was late
+    until: AtomicU64,
+}
+
+impl PacingStats {
+    /// Count one send, `lag` behind schedule, after which the next was due `until`
+    /// into the run
+    pub fn record(&self, lag: Duration, until: Duration) {
+        let lag = lag.as_nanos() as u64;
+        self.submitted.fetch_add(1, Ordering::Relaxed);
+        self.lag_total.fetch_add(lag, Ordering::Relaxed);
+        self.max_lag.fetch_max(lag, Ordering::Relaxed);
+        self.until.fetch_max(until.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn submitted(&self) -> u64 {
+        self.submitted.load(Ordering::Relaxed)
+    }
+
+    /// Achieved rate against `target` req/s, over the paced period less `paused`.
+    /// `windows` are the fewest and most sends in a one-second window, if there were
+    /// enough windows to tell.
+    pub fn print(&self, target: f64, paused: Duration, windows: Option<(u64, u64)>, unit: LatencyUnit) {
+        let submitted = self.submitted();
+        if submitted == 0 {
+            return;
+        }
+        let period = Duration::from_nanos(self.until.load(Ordering::Relaxed)).saturating_sub(paused);
+        let achieved = submitted as f64 / period.as_secs_f64();
+        let mut line = format!("{:.1} req/s ({} of target)", achieved, error(achieved, target));
+        if let Some((most, fewest)) = windows {
+            line.push_str(&format!(
+                ", {} ({}) to {} ({}) over 1s windows",
+                fewest,
+                error(fewest as f64, target),
+                most,
+                error(most as f64, target)
+            ));
+        }
+        render::print("Achieved:".white(), line);
+
+        let max_lag = Duration::from_nanos(self.max_lag.load(Ordering::Relaxed));
+        let avg_lag = Duration::from_nanos(self.lag_total.load(Ordering::Relaxed) / submitted);
+        let max = unit.format(max_lag);
+        render::print(
+            "Lag:".white(),
+            format!(
+                "max {}, avg {} behind schedule",
+                if max_lag > LAG_WARNING { max.red().bold() } else { max.green() },
+                unit.format(avg_lag)
+            ),
+        );
+        if max_lag > LAG_WARNING {
+            render::print_wrapped(
+                "Warning:".yellow().bold(),
+                format!("client couldn't sustain the requested rate, fell up to {} behind", unit.format(max_lag))
+                    .yellow(),
+            );
+        }
+    }
+}
+
+/// `measured` against `target` as a signed percentage, colored by how far off it is
+fn error(measured: f64, target: f64) -> colored::ColoredString {
+    let error = (measured - target) / target * 100.0;
+    // Rounds to 0.0 either way, without a minus sign for tiny shortfalls
+    let text = if error.abs() < 0.05 { "+0.0%".to_string() } else { format!("{:+.1}%", error) };
+    if error.abs() <= 5.0 {
+        text.green()
+    } else {
+        text.yellow()
+    }
+}
+
+/// Latencies of the first and last request of each worker's part of a burst, owned by
+/// the collector
+#[derive(Default)]
+pub struct BurstLatencies {
+    first: Vec<Duration>,
+    last: Vec<Duration>,
+}
+
+impl BurstLatencies {
+    pub fn record(&mut self, position: Position, latency: Duration) {
+        if position.index == 0 {
+            self.first.push(latency);
+        }
+        if position.index + 1 == position.of {
+            self.last.push(latency);
+        }
+    }
+
+    pub fn print(&mut self, unit: LatencyUnit) {
+        if self.first.is_empty() {
+            return;
+        }
+
+        println!("\n{}", "--- Burst Position ---".cyan().bold());
+        let mut table = Table::new(["Position", "Avg", "P50", "P95", "P99"]);
+        for (name, latencies) in [("First", &mut self.first), ("Last", &mut self.last)] {
+            if latencies.is_empty() {
+                continue;
+            }
+            let p = Percentiles::from_unsorted(latencies);
+            table.row([
+                format!("{}:", name).white().to_string(),
+                unit.format(p.avg),
+                unit.format(p.p50),
+                unit.format(p.p95).yellow().to_string(),
+                unit.format(p.p99).red().to_string(),
+            ]);
+        }
+        table.print();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SENDS: usize = 100_000;
+
+    /// The mean and standard deviation of `SENDS` gaps, in seconds
+    fn gaps(arrival: Arrival, rate: f64) -> (f64, f64) {
+        let mut schedule = Schedule::new(arrival, rate, 7, 0, None);
+        let mut gaps = Vec::with_capacity(SENDS);
+        for _ in 0..SENDS {
+            let due = schedule.next_due();
+            schedule.advance();
+            gaps.push((schedule.next_due() - due).as_secs_f64());
+        }
+        let mean = gaps.iter().sum::<f64>() / SENDS as f64;
+        let variance = gaps.iter().map(|gap| (gap - mean).powi(2)).sum::<f64>() / SENDS as f64;
+        (mean, variance.sqrt())
+    }
+
+    fn assert_near(measured: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (measured - expected).abs() <= expected * tolerance,
+            "{} is not within {}% of {}",
+            measured,
+            tolerance * 100.0,
+            expected
+        );
+    }
+
+    #[test]
+    fn uniform_gaps_are_one_over_the_rate() {
+        let (mean, deviation) = gaps(Arrival::Uniform, 250.0);
+        assert_near(mean, 0.004, 0.001);
+        assert!(deviation < 1e-9, "uniform gaps vary by {}", deviation);
+    }
+
+    #[test]
+    fn poisson_gaps_average_one_over_the_rate() {
+        let (mean, deviation) = gaps(Arrival::Poisson, 250.0);
+        assert_near(mean, 0.004, 0.02);
+        // Exponential: as spread out as they are long
+        assert_near(deviation, 0.004, 0.03);
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// First pause after a connection error
+const INITIAL_DELAY: Duration = Duration::from_millis(100);
+
+/// Pauses stop doubling here
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+pub struct BackoffStats {
+    /// Runs of consecutive connection errors, across all workers
+    episodes: AtomicU64,
+    /// Total time workers spent paused, in nanoseconds
+    paused: AtomicU64,
+}
+
+impl BackoffStats {
+    pub fn episodes(&self) -> u64 {
+        self.episodes.load(Ordering::Relaxed)
+    }
+
+    pub fn paused(&self) -> Duration {
+        Duration::from_nanos(self.paused.load(Ordering::Relaxed))
+    }
+}
+
+/// Per-worker `--backoff-on-connect-errors` state
+#[derive(Default)]
+pub struct Backoff {
+    /// In an episode: the last request failed to connect
+    active: bool,
+    /// Pauses taken in the current episode; each one doubles the next
+    pauses: u32,
+}
+
+impl Backoff {
+    /// Track the outcome of a request; anything but a connection error ends the episode
+    pub fn record(&mut self, connect_error: bool, stats: &BackoffStats) {
+        if !connect_error {
+            if self.active {
+                tracing::debug!(pauses = self.pauses, "backoff ended, resuming full speed");
+            }
+            self.active = false;
+            self.pauses = 0;
+            return;
+        }
+        if !self.active {
+            tracing::debug!("connection error, backing off");
+            stats.episodes.fetch_add(1, Ordering::Relaxed);
+            self.active = true;
+        }
+    }
+
+    /// Whether the worker should hold back until a request gets through
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    /// Sleep for the current delay: 100ms, doubling per pause up to 10s
+    pub async fn wait(&mut self, stats: &BackoffStats) {
+        let delay = INITIAL_DELAY.saturating_mul(1 << self.pauses.min(16)).min(MAX_DELAY);
+        self.pauses = self.pauses.saturating_add(1);
+        tracing::debug!(?delay, pause = self.pauses, "backing off before retrying");
+
+        let start = Instant::now();
+        tokio::time::sleep(delay).await;
+        stats
+            .paused
+            .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+}
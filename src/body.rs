@@ -0,0 +1,255 @@
+use reqwest::header::{HeaderMap, CONTENT_LENGTH};
+use ring::digest;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use xxhash_rust::xxh3::Xxh3;
+
+/// What to verify about successful response bodies
+#[derive(Default)]
+pub struct BodyChecks {
+    /// Fingerprint every body to count distinct variants (`--verify-consistency`)
+    pub consistency: bool,
+    /// Digest every body must match (`--expect-body-sha256`)
+    pub expect_sha256: Option<[u8; 32]>,
+    /// Allowed body length (`--expect-size`, `--expect-size-range`)
+    pub expect_size: Option<RangeInclusive<u64>>,
+}
+
+/// Outcome of streaming a response body
+pub struct BodyRead {
+    pub len: u64,
+    /// Set when the stream failed part way through
+    pub error: Option<reqwest::Error>,
+}
+
+impl BodyRead {
+    /// Why the body is shorter (or longer) than the response promised, if it is
+    pub fn truncation(&self, content_length: Option<u64>) -> Option<String> {
+        match (&self.error, content_length) {
+            (Some(e), Some(expected)) => Some(format!(
+                "body truncated: Content-Length {}, received {} bytes: {}",
+                expected, self.len, e
+            )),
+            (Some(e), None) => Some(format!("body truncated after {} bytes: {}", self.len, e)),
+            (None, Some(expected)) if expected != self.len => {
+                Some(format!("Content-Length {}, received {} bytes", expected, self.len))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The Content-Length header, if the response sent a valid one.
+///
+/// Read from the headers rather than the body's size hint, which a chunked response
+/// doesn't have even when it declares a length too.
+pub fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.trim().parse().ok())
+}
+
+/// Hashes a body as it streams past, so nothing is buffered
+pub struct BodyHasher {
+    xxh3: Option<Xxh3>,
+    sha256: Option<digest::Context>,
+}
+
+impl BodyHasher {
+    /// `None` when no check is enabled, so the read path skips hashing entirely
+    pub fn new(checks: &BodyChecks) -> Option<Self> {
+        if !checks.consistency && checks.expect_sha256.is_none() {
+            return None;
+        }
+
+        Some(BodyHasher {
+            xxh3: checks.consistency.then(Xxh3::new),
+            sha256: checks
+                .expect_sha256
+                .as_ref()
+                .map(|_| digest::Context::new(&digest::SHA256)),
+        })
+    }
+
+    pub fn update(&mut self, chunk: &[u8]) {
+        if let Some(xxh3) = &mut self.xxh3 {
+            xxh3.update(chunk);
+        }
+        if let Some(sha256) = &mut self.sha256 {
+            sha256.update(chunk);
+        }
+    }
+}
+
+/// Min, total and max of a set of lengths
+struct Sizes {
+    count: AtomicU64,
+    total: AtomicU64,
+    min: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Default for Sizes {
+    fn default() -> Self {
+        Sizes {
+            count: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+            min: AtomicU64::new(u64::MAX),
+            max: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Sizes {
+    fn record(&self, len: u64) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total.fetch_add(len, Ordering::Relaxed);
+        self.min.fetch_min(len, Ordering::Relaxed);
+        self.max.fetch_max(len, Ordering::Relaxed);
+    }
+
+    fn summary(&self) -> Option<(u64, u64, u64)> {
+        let count = self.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return None;
+        }
+        Some((
+            self.min.load(Ordering::Relaxed),
+            self.total.load(Ordering::Relaxed) / count,
+            self.max.load(Ordering::Relaxed),
+        ))
+    }
+}
+
+#[derive(Default)]
+pub struct BodyStats {
+    /// Bodies seen per xxh3 fingerprint
+    variants: Mutex<HashMap<u64, u64>>,
+    pub digest_mismatches: AtomicU64,
+    pub size_mismatches: AtomicU64,
+    /// Bodies whose length disagreed with their Content-Length, however they ended
+    pub length_mismatches: AtomicU64,
+    /// The first of them: the URL and what was declared and received
+    length_example: Mutex<Option<String>>,
+    /// Bytes actually read
+    read: Sizes,
+    /// Content-Length of HEAD responses, which promise a body that never comes
+    declared: Sizes,
+}
+
+impl BodyStats {
+    pub fn record_size(&self, len: u64) {
+        self.read.record(len);
+    }
+
+    /// A HEAD response's Content-Length, if it sent one
+    pub fn record_declared(&self, headers: &HeaderMap) {
+        if let Some(len) = content_length(headers) {
+            self.declared.record(len);
+        }
+    }
+
+    /// Compare a body read from `url` with its Content-Length, counting a mismatch:
+    /// why the body is wrong, if it is, as for `BodyRead::truncation`
+    pub fn check_length(&self, read: &BodyRead, content_length: Option<u64>, url: &str) -> Option<String> {
+        let reason = read.truncation(content_length)?;
+        if content_length.is_some_and(|expected| expected != read.len) {
+            self.length_mismatches.fetch_add(1, Ordering::Relaxed);
+            let mut example = self.length_example.lock().unwrap();
+            if example.is_none() {
+                let expected = content_length.unwrap_or_default();
+                *example = Some(format!("{}: Content-Length {}, received {} bytes", url, expected, read.len));
+            }
+        }
+        Some(reason)
+    }
+
+    /// The first body that disagreed with its Content-Length
+    pub fn length_example(&self) -> Option<String> {
+        self.length_example.lock().unwrap().clone()
+    }
+
+    /// Min, average and max body size, if any body was read
+    pub fn sizes(&self) -> Option<(u64, u64, u64)> {
+        self.read.summary()
+    }
+
+    /// Body bytes read so far, across every response
+    pub fn received(&self) -> u64 {
+        self.read.total.load(Ordering::Relaxed)
+    }
+
+    /// Min, average and max Content-Length of HEAD responses, if any sent one
+    pub fn declared_sizes(&self) -> Option<(u64, u64, u64)> {
+        self.declared.summary()
+    }
+
+    /// Check a fully read body against the size and digest expectations, returning
+    /// why it fails, if it does
+    pub fn check(&self, len: u64, hasher: Option<BodyHasher>, checks: &BodyChecks) -> Option<String> {
+        if let Some(range) = checks.expect_size.as_ref().filter(|r| !r.contains(&len)) {
+            self.size_mismatches.fetch_add(1, Ordering::Relaxed);
+            return Some(format!(
+                "body was {} bytes, expected {}",
+                len,
+                if range.start() == range.end() {
+                    range.start().to_string()
+                } else {
+                    format!("{}-{}", range.start(), range.end())
+                }
+            ));
+        }
+
+        let hasher = hasher?;
+        if let Some(xxh3) = hasher.xxh3 {
+            *self.variants.lock().unwrap().entry(xxh3.digest()).or_default() += 1;
+        }
+
+        let (sha256, expected) = (hasher.sha256?, checks.expect_sha256.as_ref()?);
+        let actual = sha256.finish();
+        if actual.as_ref() == expected.as_slice() {
+            return None;
+        }
+
+        self.digest_mismatches.fetch_add(1, Ordering::Relaxed);
+        Some(format!("body sha256 {}, expected {}", hex(actual.as_ref()), hex(expected)))
+    }
+
+    /// Body count per fingerprint, most common first
+    pub fn variants(&self) -> Vec<(u64, u64)> {
+        let mut variants: Vec<_> = self.variants.lock().unwrap().iter().map(|(h, n)| (*h, *n)).collect();
+        variants.sort_by_key(|&(hash, n)| (std::cmp::Reverse(n), hash));
+        variants
+    }
+}
+
+/// Parse `MIN-MAX` (inclusive) for `--expect-size-range`
+pub fn parse_size_range(s: &str) -> Result<RangeInclusive<u64>, String> {
+    let (min, max) = s.split_once('-').ok_or("expected MIN-MAX")?;
+    let min: u64 = min.trim().parse().map_err(|e| format!("invalid MIN: {}", e))?;
+    let max: u64 = max.trim().parse().map_err(|e| format!("invalid MAX: {}", e))?;
+    if min > max {
+        return Err("MIN is larger than MAX".to_string());
+    }
+    Ok(min..=max)
+}
+
+/// Parse a 64-digit hex SHA-256 digest
+pub fn parse_sha256(s: &str) -> Result<[u8; 32], String> {
+    let s = s.trim();
+    if s.len() != 64 || !s.is_ascii() {
+        return Err("expected 64 hex digits".to_string());
+    }
+
+    let mut digest = [0u8; 32];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).map_err(|e| e.to_string())?;
+    }
+    Ok(digest)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,270 @@
+//! `--cache-analysis`: a cache's cold and hot latency in one command. The cold phase
+//! sends every request to a URL of its own (a `benchy-bust` query parameter), so each
+//! one misses and goes to the origin; the hot phase repeats the run's own URLs, which
+//! the cache can answer after their first request. Both phases go over the workers'
+//! connections with the same headers, and report side by side, with how often the
+//! cache status header said HIT.
+
+use crate::prime;
+use crate::render::{self, Table};
+use crate::results::histogram;
+use crate::units::LatencyUnit;
+use crate::{error_chain, thousands, with_headers, Percentiles, RequestConfig, WorkerClient};
+use colored::Colorize;
+use futures::stream::{self, StreamExt};
+use reqwest::header::HeaderName;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// The query parameter that makes each cold request's URL unique
+const BUST_PARAM: &str = "benchy-bust";
+
+/// What one phase's responses took and what the cache said about them
+#[derive(Default)]
+struct Phase {
+    latencies: Vec<Duration>,
+    /// Not 2xx, or not received whole
+    failed: u64,
+    /// Why the first failed request failed
+    first_failure: Option<String>,
+    /// Values of the cache status header, as sent
+    statuses: BTreeMap<String, u64>,
+    /// Responses without the cache status header, failed requests included
+    absent: u64,
+}
+
+/// How a cache status value reads
+#[derive(PartialEq, Eq)]
+enum Verdict {
+    Hit,
+    Miss,
+    Other,
+}
+
+/// `HIT`, `TCP_HIT`, `Hit from cloudfront` and the like count as hits; `MISS` and
+/// its variants as misses
+fn verdict(status: &str) -> Verdict {
+    let status = status.to_ascii_uppercase();
+    if status.contains("HIT") {
+        Verdict::Hit
+    } else if status.contains("MISS") {
+        Verdict::Miss
+    } else {
+        Verdict::Other
+    }
+}
+
+impl Phase {
+    fn count(&self, wanted: Verdict) -> u64 {
+        self.statuses.iter().filter(|(status, _)| verdict(status) == wanted).map(|(_, n)| n).sum()
+    }
+
+    fn requests(&self) -> u64 {
+        self.latencies.len() as u64
+    }
+
+    /// Share of the phase's requests the cache said it hit
+    fn hit_ratio(&self) -> f64 {
+        self.count(Verdict::Hit) as f64 / self.requests().max(1) as f64
+    }
+
+    /// e.g. `HIT 973, MISS 27, 0 without it`
+    fn describe(&self) -> String {
+        let mut parts: Vec<String> =
+            self.statuses.iter().map(|(status, n)| format!("{} {}", status, thousands(*n))).collect();
+        parts.push(format!("{} without it", thousands(self.absent)));
+        parts.join(", ")
+    }
+
+    fn to_json(&self) -> Value {
+        let histogram = histogram(&self.latencies);
+        json!({
+            "requests": self.requests(),
+            "failed": self.failed,
+            "latencies_ns": self.latencies.iter().map(|d| d.as_nanos() as u64).collect::<Vec<u64>>(),
+            "latency": {
+                "avg_ns": histogram.mean().round() as u64,
+                "p50_ns": histogram.value_at_quantile(0.50),
+                "p95_ns": histogram.value_at_quantile(0.95),
+                "p99_ns": histogram.value_at_quantile(0.99),
+                "max_ns": histogram.max(),
+            },
+            "cache_status": self.statuses,
+            "without_cache_status": self.absent,
+            "hit_ratio": self.hit_ratio(),
+        })
+    }
+}
+
+/// Both phases of `--cache-analysis`
+pub struct CacheAnalysis {
+    header: HeaderName,
+    cold: Phase,
+    hot: Phase,
+}
+
+/// Run the cold phase and then the hot one, `requests` each, with up to `in_flight`
+/// requests at a time spread over the workers' clients
+pub async fn run(
+    workers: &[WorkerClient],
+    config: &RequestConfig,
+    header: &HeaderName,
+    requests: u64,
+    in_flight: usize,
+) -> CacheAnalysis {
+    // Tells this run's cold URLs apart from an earlier run's, which may be cached
+    let run: u32 = rand::random();
+    let cold = phase(workers, config, header, requests, in_flight, Some(run)).await;
+    let hot = phase(workers, config, header, requests, in_flight, None).await;
+    CacheAnalysis {
+        header: header.clone(),
+        cold,
+        hot,
+    }
+}
+
+/// One phase: the run's URLs in turn, each made unique with `bust` if given
+async fn phase(
+    workers: &[WorkerClient],
+    config: &RequestConfig,
+    header: &HeaderName,
+    requests: u64,
+    in_flight: usize,
+    bust: Option<u32>,
+) -> Phase {
+    let urls = prime::urls(config);
+    // With --hosts-file, each host only over its own workers' connections
+    let pools: Vec<Vec<&WorkerClient>> = urls
+        .iter()
+        .map(|&(i, _)| match &config.hosts {
+            Some(hosts) => workers.iter().enumerate().filter(|(w, _)| hosts.of(*w) == i).map(|(_, w)| w).collect(),
+            None => workers.iter().collect(),
+        })
+        .collect();
+
+    let results = stream::iter(0..requests)
+        .map(|n| {
+            let index = n as usize % urls.len();
+            let pool = &pools[index];
+            let worker = pool[(n as usize / urls.len()) % pool.len()];
+            let url = match bust {
+                Some(run) => busted(urls[index].1, run, n),
+                None => urls[index].1.to_string(),
+            };
+            async move {
+                let request = worker.client.reqwest.request(config.method.clone(), &url);
+                let request = with_headers(request, config, None, None, None, None, worker.opts.protocol.version());
+                let start = Instant::now();
+                match worker.client.send(request).await {
+                    Ok(resp) => {
+                        let status =
+                            resp.headers().get(header).map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned());
+                        let code = resp.status();
+                        // Read to the end, so the object is cached whole
+                        let failure = match resp.bytes().await {
+                            Err(e) => Some(error_chain(&e)),
+                            Ok(_) if !code.is_success() => Some(format!("status {}", code.as_u16())),
+                            Ok(_) => None,
+                        };
+                        (start.elapsed(), failure, status)
+                    }
+                    Err(e) => (start.elapsed(), Some(error_chain(e.as_ref())), None),
+                }
+            }
+        })
+        .buffer_unordered(in_flight.max(1))
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut phase = Phase::default();
+    for (latency, failure, status) in results {
+        phase.latencies.push(latency);
+        if let Some(failure) = failure {
+            phase.failed += 1;
+            phase.first_failure.get_or_insert(failure);
+        }
+        match status {
+            Some(status) => *phase.statuses.entry(status).or_default() += 1,
+            None => phase.absent += 1,
+        }
+    }
+    phase
+}
+
+/// `url` with a query parameter no other request of any run has
+fn busted(url: &str, run: u32, n: u64) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(mut url) => {
+            url.query_pairs_mut().append_pair(BUST_PARAM, &format!("{:08x}-{}", run, n));
+            url.into()
+        }
+        // Checked before the run, so never the case
+        Err(_) => url.to_string(),
+    }
+}
+
+impl CacheAnalysis {
+    /// The two phases side by side, then the hot phase's hit ratio
+    pub fn print(&mut self, unit: LatencyUnit) {
+        let cold = Percentiles::from_unsorted(&mut self.cold.latencies);
+        let hot = Percentiles::from_unsorted(&mut self.hot.latencies);
+        let unit = unit.resolve(hot.p50);
+
+        println!("\n{}", "--- Cache Analysis ---".cyan().bold());
+        let mut table = Table::new(["Phase", "Requests", "Failed", "Avg", "P50", "P95", "P99", "Hits"]);
+        for (label, phase, p) in [("Cold:", &self.cold, &cold), ("Hot:", &self.hot, &hot)] {
+            table.row([
+                label.white().to_string(),
+                thousands(phase.requests()),
+                if phase.failed > 0 { thousands(phase.failed).red().to_string() } else { "0".to_string() },
+                unit.format(p.avg),
+                unit.format(p.p50),
+                unit.format(p.p95),
+                unit.format(p.p99),
+                format!("{:.1}%", phase.hit_ratio() * 100.0),
+            ]);
+        }
+        table.print();
+
+        let ratio = format!("{:.1}% of hot requests ({})", self.hot.hit_ratio() * 100.0, self.hot.describe());
+        render::print_wrapped("Hit ratio:".white(), if self.hot.hit_ratio() > 0.0 { ratio.green() } else { ratio.yellow() });
+        render::print_wrapped("Cold status:".white(), format!("{}: {}", self.header, self.cold.describe()));
+        if !hot.p50.is_zero() {
+            render::print(
+                "Speedup:".white(),
+                format!("hot P50 {:.1}x faster than cold", cold.p50.as_secs_f64() / hot.p50.as_secs_f64()),
+            );
+        }
+        for (label, phase) in [("cold", &self.cold), ("hot", &self.hot)] {
+            if let Some(failure) = &phase.first_failure {
+                let error = format!("{} {} requests failed, the first with: {}", thousands(phase.failed), label, failure);
+                render::print_wrapped("Error:".red().bold(), error.red());
+            }
+        }
+        let cold_hits = self.cold.count(Verdict::Hit);
+        if cold_hits > 0 {
+            let note = format!(
+                "{} cold requests were hits: the cache may ignore the {} query parameter, so cold latency is understated",
+                thousands(cold_hits),
+                BUST_PARAM
+            );
+            render::print_wrapped("Note:".yellow().bold(), note.yellow());
+        }
+        if self.hot.statuses.is_empty() {
+            let note = format!("no response carried {}; see --cache-status-header", self.header);
+            render::print_wrapped("Note:".yellow().bold(), note.yellow());
+        }
+    }
+
+    /// For `--save-results`: both phases, nested
+    pub fn to_json(&self) -> Value {
+        json!({
+            "cache_analysis": {
+                "cache_status_header": self.header.as_str(),
+                "cold": self.cold.to_json(),
+                "hot": self.hot.to_json(),
+            }
+        })
+    }
+}
@@ -0,0 +1,40 @@
+//! Wall-clock times for a run that measures with `Instant`s. The system clock is read
+//! once, as setup begins, next to an `Instant`, and every later time is placed from
+//! that pair: samples only carry `Instant`s, nothing reads the system clock per
+//! request, and a clock step mid-run (NTP, a VM resuming) can't reorder them.
+
+use std::time::{Instant, SystemTime};
+
+/// The run's one pairing of the system clock with the monotonic one
+#[derive(Clone, Copy)]
+pub struct RunClock {
+    wall: SystemTime,
+    at: Instant,
+}
+
+impl RunClock {
+    pub fn start() -> RunClock {
+        RunClock {
+            wall: SystemTime::now(),
+            at: Instant::now(),
+        }
+    }
+
+    /// The `Instant` the system clock was read next to
+    pub fn began(&self) -> Instant {
+        self.at
+    }
+
+    /// The wall-clock time of `at`
+    pub fn wall(&self, at: Instant) -> SystemTime {
+        match at.checked_duration_since(self.at) {
+            Some(after) => self.wall + after,
+            None => self.wall - self.at.duration_since(at),
+        }
+    }
+}
+
+/// e.g. `2026-10-15T09:30:00.123456Z`
+pub fn rfc3339(at: SystemTime) -> String {
+    humantime::format_rfc3339_micros(at).to_string()
+}
@@ -0,0 +1,230 @@
+//! `--compress-body gzip|zstd`: request bodies sent compressed, with Content-Encoding.
+//! A body that is the same every time (`--data-file`, a `-d` without placeholders,
+//! `--data-dir`'s files, `--workload` bodies) is compressed once at startup; a generated
+//! one is compressed as it is sent, by an encoder each worker keeps and reuses, so the
+//! compressor's state isn't allocated per request.
+
+use crate::payload::BodyPool;
+use crate::render;
+use crate::sent::bytes;
+use crate::template::BodyTemplate;
+use crate::thousands;
+use crate::urls::UrlMix;
+use bytes::Bytes;
+use clap::ValueEnum;
+use colored::Colorize;
+use flate2::{Compress, Crc, FlushCompress, Status};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// gzip's member header: deflate, no flags, no mtime, unknown OS
+const GZIP_HEADER: [u8; 10] = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+
+/// Levels `gzip` and `zstd` default to on the command line
+const GZIP_LEVEL: u32 = 6;
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Codec {
+    Gzip,
+    Zstd,
+}
+
+impl Codec {
+    /// As sent in Content-Encoding
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gzip",
+            Codec::Zstd => "zstd",
+        }
+    }
+}
+
+/// Where a request's body came from, so one compressed at startup is sent as it is
+#[derive(Clone, Copy)]
+pub enum Source {
+    /// A `--workload` entry's
+    Entry(usize),
+    /// A `--data-dir` file
+    File(usize),
+    /// `--data-file`, or `-d` without placeholders
+    Fixed,
+    /// Different every time: `-d` with placeholders, or whatever `--script` made of it
+    Generated,
+}
+
+/// A body compressed at startup, with its size before
+struct Precompressed {
+    body: Bytes,
+    raw: u64,
+}
+
+/// One worker's compressor state, reset rather than rebuilt for each body
+pub struct Encoder {
+    codec: Codec,
+    deflate: Option<Compress>,
+    zstd: Option<zstd::bulk::Compressor<'static>>,
+}
+
+impl Encoder {
+    pub fn new(codec: Codec) -> Encoder {
+        Encoder {
+            codec,
+            deflate: None,
+            zstd: None,
+        }
+    }
+
+    pub fn encode(&mut self, body: &[u8]) -> Bytes {
+        match self.codec {
+            Codec::Gzip => {
+                let deflate = self.deflate.get_or_insert_with(|| Compress::new(flate2::Compression::new(GZIP_LEVEL), false));
+                deflate.reset();
+                let mut out = Vec::with_capacity(GZIP_HEADER.len() + body.len() / 2 + 64);
+                out.extend_from_slice(&GZIP_HEADER);
+                loop {
+                    if out.len() == out.capacity() {
+                        out.reserve(out.capacity());
+                    }
+                    let read = deflate.total_in() as usize;
+                    match deflate.compress_vec(&body[read..], &mut out, FlushCompress::Finish) {
+                        Ok(Status::StreamEnd) => break,
+                        Ok(_) => {}
+                        Err(e) => unreachable!("deflate into a growing buffer can't fail: {}", e),
+                    }
+                }
+                let mut crc = Crc::new();
+                crc.update(body);
+                out.extend_from_slice(&crc.sum().to_le_bytes());
+                out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+                Bytes::from(out)
+            }
+            Codec::Zstd => {
+                let zstd = self.zstd.get_or_insert_with(|| {
+                    zstd::bulk::Compressor::new(ZSTD_LEVEL).expect("the default zstd level is valid")
+                });
+                Bytes::from(zstd.compress(body).expect("zstd compresses any input"))
+            }
+        }
+    }
+}
+
+/// The static bodies, compressed, and what was sent over the run
+pub struct Compression {
+    codec: Codec,
+    entries: Vec<Option<Precompressed>>,
+    files: Vec<Precompressed>,
+    fixed: Option<Precompressed>,
+    raw: AtomicU64,
+    compressed: AtomicU64,
+    /// Bodies compressed as they were sent, and the time that took
+    generated: AtomicU64,
+    generated_nanos: AtomicU64,
+    /// 415 Unsupported Media Type responses
+    rejected: AtomicU64,
+}
+
+impl Compression {
+    /// Compress every body that is the same each time it is sent
+    pub fn new(codec: Codec, body: Option<&BodyTemplate>, files: Option<&BodyPool>, urls: Option<&UrlMix>) -> Compression {
+        let mut encoder = Encoder::new(codec);
+        let mut precompress = |body: &Bytes| Precompressed {
+            body: encoder.encode(body),
+            raw: body.len() as u64,
+        };
+        let fixed = body
+            .filter(|template| template.is_static())
+            .map(|template| precompress(&template.render(&mut crate::template::BodyGenerator::new(0, 0))));
+        let files = files.map_or_else(Vec::new, |pool| (0..pool.len()).map(|i| precompress(&pool.body(i))).collect());
+        let entries = urls.map_or_else(Vec::new, |mix| {
+            (0..mix.len())
+                .map(|i| mix.request(i).and_then(|entry| entry.body.as_ref()).map(&mut precompress))
+                .collect()
+        });
+        Compression {
+            codec,
+            entries,
+            files,
+            fixed,
+            raw: AtomicU64::new(0),
+            compressed: AtomicU64::new(0),
+            generated: AtomicU64::new(0),
+            generated_nanos: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// `body` from `source`, compressed, and counted as sent
+    pub fn encode(&self, source: Source, body: Bytes, encoder: &mut Encoder) -> Bytes {
+        let precompressed = match source {
+            Source::Entry(index) => self.entries.get(index).and_then(Option::as_ref),
+            Source::File(index) => self.files.get(index),
+            Source::Fixed => self.fixed.as_ref(),
+            Source::Generated => None,
+        };
+        let (compressed, raw) = match precompressed {
+            Some(precompressed) => (precompressed.body.clone(), precompressed.raw),
+            None => {
+                let start = Instant::now();
+                let compressed = encoder.encode(&body);
+                self.generated.fetch_add(1, Ordering::Relaxed);
+                self.generated_nanos.fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+                (compressed, body.len() as u64)
+            }
+        };
+        self.raw.fetch_add(raw, Ordering::Relaxed);
+        self.compressed.fetch_add(compressed.len() as u64, Ordering::Relaxed);
+        compressed
+    }
+
+    /// Count a response's status, for the 415s of a server that won't take the encoding
+    pub fn record_status(&self, status: u16) {
+        if status == 415 {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn print(&self) {
+        let raw = self.raw.load(Ordering::Relaxed);
+        let compressed = self.compressed.load(Ordering::Relaxed);
+        if raw == 0 {
+            return;
+        }
+        println!("\n{}", "--- Body Compression ---".cyan().bold());
+        render::print("Encoding:".white(), self.codec.name());
+        render::print(
+            "Uploaded:".white(),
+            format!(
+                "{} raw, {} compressed ({:.2}x)",
+                bytes(raw),
+                bytes(compressed).green(),
+                raw as f64 / compressed.max(1) as f64
+            ),
+        );
+        let generated = self.generated.load(Ordering::Relaxed);
+        if let Some(nanos) = self.generated_nanos.load(Ordering::Relaxed).checked_div(generated) {
+            let each = Duration::from_nanos(nanos);
+            render::print(
+                "Per request:".white(),
+                format!("{} generated bodies compressed as sent, {:.1?} each", thousands(generated), each),
+            );
+        }
+        let rejected = self.rejected.load(Ordering::Relaxed);
+        if rejected > 0 {
+            render::print(
+                "Rejected:".white(),
+                format!(
+                    "{} responses were 415 Unsupported Media Type — the server may not accept Content-Encoding: {}",
+                    thousands(rejected),
+                    self.codec.name()
+                )
+                .red()
+                .bold(),
+            );
+        }
+    }
+}
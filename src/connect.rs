@@ -0,0 +1,414 @@
+use crate::cryptodrift::CryptoDrift;
+use crate::transport::{BoxError, TlsStats};
+use hyper_util::client::legacy::connect::{Connection, HttpInfo};
+use reqwest::{Response, Version};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower_layer::Layer;
+use tower_service::Service;
+
+tokio::task_local! {
+    /// The TLS handshake of the connection being set up
+    static HANDSHAKE: Arc<Handshake>;
+}
+
+/// What the TLS session store and certificate verifier saw of one connection's handshake
+#[derive(Debug, Default)]
+pub struct Handshake {
+    started: OnceLock<Instant>,
+    /// A resumed handshake never gets as far as the certificate
+    certificate: AtomicBool,
+    /// The counters of the config that built the ClientHello
+    tls: OnceLock<Arc<TlsStats>>,
+}
+
+impl Handshake {
+    pub fn certificate_verified(&self) {
+        self.certificate.store(true, Ordering::Relaxed);
+    }
+
+    /// Count the handshake, if one started, once the connection is up or has failed.
+    /// Only a completed one can be told full from resumed: one that failed or timed out
+    /// never saw a certificate either.
+    fn finish(&self, ok: bool) {
+        let Some(tls) = self.tls.get() else {
+            return;
+        };
+        let counter = match (ok, self.certificate.load(Ordering::Relaxed)) {
+            (false, _) => &tls.failed,
+            (true, true) => &tls.full,
+            (true, false) => &tls.resumed,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Mark the start of the TLS handshake on the connection being set up, and return it.
+/// Called by the TLS session store as the ClientHello is built; a no-op outside
+/// [`ConnectTiming`] and [`counted`].
+pub fn tls_started(stats: &Arc<TlsStats>) -> Option<Arc<Handshake>> {
+    HANDSHAKE
+        .try_with(|handshake| {
+            let _ = handshake.started.set(Instant::now());
+            let _ = handshake.tls.set(stats.clone());
+            handshake.clone()
+        })
+        .ok()
+}
+
+/// Mark the connection being set up as having verified a certificate, i.e. a full
+/// handshake. Called by the certificate verifier; returns false outside [`ConnectTiming`]
+/// and [`counted`], as when quinn finishes a handshake on the connection's own task.
+pub fn certificate_verified() -> bool {
+    HANDSHAKE.try_with(|handshake| handshake.certificate_verified()).is_ok()
+}
+
+/// Set up a connection outside [`ConnectTiming`] (QUIC), counting its TLS handshake in
+/// the [`TlsStats`] of the config that started it
+pub async fn counted<T, E>(fut: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    let handshake = Arc::new(Handshake::default());
+    let result = HANDSHAKE.scope(handshake.clone(), fut).await;
+    handshake.finish(result.is_ok());
+    result
+}
+
+/// Connection establishment counters shared by every client's connector
+#[derive(Default)]
+pub struct ConnectStats {
+    pub established: AtomicU64,
+    pub failed: AtomicU64,
+    setup_times: Mutex<Vec<Duration>>,
+    /// Through `--proxy`: TCP to the proxy plus the CONNECT exchange
+    tunnel_times: Mutex<Vec<Duration>>,
+    /// Through `--proxy`: the TLS handshake inside the tunnel
+    tls_times: Mutex<Vec<Duration>>,
+    /// Connections per remote address actually dialed
+    remotes: Mutex<HashMap<SocketAddr, u64>>,
+    /// Per worker, connections that offered h2 but got HTTP/1.1, and the first one's
+    /// remote address
+    downgrades: Mutex<BTreeMap<usize, (Option<SocketAddr>, u64)>>,
+    /// Every TLS handshake, for telling client crypto saturation from a slow server
+    pub crypto: Arc<CryptoDrift>,
+}
+
+impl ConnectStats {
+    pub fn record(&self, setup: Duration, remote: Option<SocketAddr>) {
+        self.established.fetch_add(1, Ordering::Relaxed);
+        self.setup_times.lock().unwrap().push(setup);
+        if let Some(remote) = remote {
+            *self.remotes.lock().unwrap().entry(remote).or_default() += 1;
+        }
+    }
+
+    /// Count a downgraded connection of `worker`'s; true for the worker's first
+    fn record_downgrade(&self, worker: usize, remote: Option<SocketAddr>) -> bool {
+        let mut downgrades = self.downgrades.lock().unwrap();
+        let (_, n) = downgrades.entry(worker).or_insert((remote, 0));
+        *n += 1;
+        *n == 1
+    }
+
+    /// Workers with downgraded connections, with the first one's remote address and
+    /// how many there were, in worker order
+    pub fn downgrades(&self) -> Vec<(usize, Option<SocketAddr>, u64)> {
+        self.downgrades
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&worker, &(remote, n))| (worker, remote, n))
+            .collect()
+    }
+
+    fn record_tunnel(&self, tunnel: Duration, tls: Duration) {
+        self.tunnel_times.lock().unwrap().push(tunnel);
+        self.tls_times.lock().unwrap().push(tls);
+    }
+
+    pub fn take_setup_times(&self) -> Vec<Duration> {
+        std::mem::take(&mut *self.setup_times.lock().unwrap())
+    }
+
+    pub fn take_tunnel_times(&self) -> Vec<Duration> {
+        std::mem::take(&mut *self.tunnel_times.lock().unwrap())
+    }
+
+    pub fn take_tls_times(&self) -> Vec<Duration> {
+        std::mem::take(&mut *self.tls_times.lock().unwrap())
+    }
+
+    /// Connection count per remote IP, most used first, which for a load balancer is
+    /// how the connections were spread over its backends
+    pub fn remotes(&self) -> Vec<(IpAddr, u64)> {
+        let mut remotes: HashMap<IpAddr, u64> = HashMap::new();
+        for (addr, n) in self.remotes.lock().unwrap().iter() {
+            *remotes.entry(addr.ip()).or_default() += n;
+        }
+        let mut remotes: Vec<_> = remotes.into_iter().collect();
+        remotes.sort_by_key(|&(ip, n)| (std::cmp::Reverse(n), ip));
+        remotes
+    }
+}
+
+/// A connection our own clients (HTTP/3, `--expect-continue`, `--h2c-upgrade`) gave up
+/// on setting up after `--connect-timeout`; reqwest's own connections fail with its
+/// timeout error instead
+#[derive(Debug)]
+pub struct ConnectTimeout(pub Duration);
+
+impl fmt::Display for ConnectTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "connection not established within {}", humantime::format_duration(self.0))
+    }
+}
+
+impl std::error::Error for ConnectTimeout {}
+
+/// `connecting`, given up on with a [`ConnectTimeout`] once `timeout` has passed
+pub async fn within<T, E>(timeout: Duration, connecting: impl Future<Output = Result<T, E>>) -> Result<T, BoxError>
+where
+    E: Into<BoxError>,
+{
+    match tokio::time::timeout(timeout, connecting).await {
+        Ok(result) => result.map_err(Into::into),
+        Err(_) => Err(ConnectTimeout(timeout).into()),
+    }
+}
+
+/// Connector layer that times TCP (+TLS) establishment for every new connection, and
+/// logs each one at `-v`.
+///
+/// Connections are only created on pool misses, so this sits off the hot path.
+/// Through a proxy, reqwest tunnels and then handshakes inside the same connector
+/// call, so the two are told apart by when the ClientHello was built ([`tls_started`]).
+#[derive(Clone)]
+pub struct ConnectTimingLayer {
+    stats: Arc<ConnectStats>,
+    proxied: bool,
+    /// The worker whose client this is, for the log
+    worker: Option<usize>,
+    /// h2c: connections speak HTTP/2 without negotiating it
+    h2_prior_knowledge: bool,
+    /// ALPN offers h2 first, so a connection that gets HTTP/1.1 was downgraded
+    offers_h2: bool,
+}
+
+impl ConnectTimingLayer {
+    pub fn new(
+        stats: Arc<ConnectStats>,
+        proxied: bool,
+        worker: Option<usize>,
+        h2_prior_knowledge: bool,
+        offers_h2: bool,
+    ) -> Self {
+        ConnectTimingLayer {
+            stats,
+            proxied,
+            worker,
+            h2_prior_knowledge,
+            offers_h2,
+        }
+    }
+}
+
+impl<S> Layer<S> for ConnectTimingLayer {
+    type Service = ConnectTiming<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConnectTiming {
+            inner,
+            stats: self.stats.clone(),
+            proxied: self.proxied,
+            worker: self.worker,
+            h2_prior_knowledge: self.h2_prior_knowledge,
+            offers_h2: self.offers_h2,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ConnectTiming<S> {
+    inner: S,
+    stats: Arc<ConnectStats>,
+    proxied: bool,
+    worker: Option<usize>,
+    h2_prior_knowledge: bool,
+    offers_h2: bool,
+}
+
+impl<S, R> Service<R> for ConnectTiming<S>
+where
+    S: Service<R>,
+    S::Response: Connection,
+    S::Error: std::fmt::Display,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        let start = Instant::now();
+        let stats = self.stats.clone();
+        let proxied = self.proxied;
+        let worker = self.worker;
+        let h2_prior_knowledge = self.h2_prior_knowledge;
+        let offers_h2 = self.offers_h2;
+        let handshake = Arc::new(Handshake::default());
+        let fut = HANDSHAKE.scope(handshake.clone(), self.inner.call(req));
+
+        Box::pin(async move {
+            let result = fut.await;
+            match &result {
+                Ok(conn) => {
+                    let setup = start.elapsed();
+                    let connected = conn.connected();
+                    let mut extras = http::Extensions::new();
+                    connected.get_extras(&mut extras);
+                    let remote = extras.get::<HttpInfo>().map(|info| info.remote_addr());
+                    let local = extras.get::<HttpInfo>().map(|info| info.local_addr());
+                    let tls_started = handshake.started.get().copied();
+                    let tls = match tls_started {
+                        Some(_) if handshake.certificate.load(Ordering::Relaxed) => "full",
+                        Some(_) => "resumed",
+                        None => "none",
+                    };
+                    let protocol = if connected.is_negotiated_h2() || h2_prior_knowledge { "h2" } else { "h1" };
+                    tracing::info!(
+                        worker,
+                        ?remote,
+                        protocol,
+                        tls,
+                        tls_handshake = ?tls_started.map(|started| started.elapsed()),
+                        ?setup,
+                        "connection established"
+                    );
+                    stats.record(setup, remote);
+                    if let Some(tls_started) = tls_started {
+                        stats.crypto.record_handshake(local, tls_started.elapsed());
+                    }
+                    // Only the first per worker is logged: which backends of a mixed fleet
+                    // lack HTTP/2, without a line per reconnect
+                    if let (true, "h1", Some(worker)) = (offers_h2, protocol, worker) {
+                        if stats.record_downgrade(worker, remote) {
+                            tracing::warn!(worker, ?remote, "connection negotiated HTTP/1.1 instead of HTTP/2");
+                        }
+                    }
+                    // Plain http:// targets go through the proxy without a tunnel
+                    if let (true, Some(tls_started)) = (proxied, tls_started) {
+                        stats.record_tunnel(tls_started - start, tls_started.elapsed());
+                    }
+                }
+                Err(e) => {
+                    tracing::info!(error = %e, "connection failed");
+                    stats.failed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            handshake.finish(result.is_ok());
+            result
+        })
+    }
+}
+
+/// Negotiated HTTP version per connection, for `--protocol auto`.
+///
+/// Connections are told apart by their local address, which hyper attaches to
+/// every response received over TCP.
+#[derive(Default)]
+pub struct ProtocolMix {
+    connections: Mutex<HashMap<SocketAddr, Version>>,
+}
+
+impl ProtocolMix {
+    pub fn record(&self, resp: &Response) {
+        if let Some(info) = resp.extensions().get::<HttpInfo>() {
+            self.connections
+                .lock()
+                .unwrap()
+                .entry(info.local_addr())
+                .or_insert(resp.version());
+        }
+    }
+
+    /// Connection count per version, most common first
+    pub fn counts(&self) -> Vec<(Version, u64)> {
+        let mut counts: Vec<(Version, u64)> = Vec::new();
+        for version in self.connections.lock().unwrap().values() {
+            match counts.iter_mut().find(|(v, _)| v == version) {
+                Some((_, n)) => *n += 1,
+                None => counts.push((*version, 1)),
+            }
+        }
+        counts.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What the session store and verifier do for a handshake that ends in `outcome`
+    async fn handshake(stats: &Arc<TlsStats>, certificate: bool, outcome: Result<(), BoxError>) {
+        let _ = counted(async {
+            tls_started(stats);
+            if certificate {
+                assert!(certificate_verified());
+            }
+            outcome
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn handshakes_are_counted_as_they_end() {
+        let stats = Arc::new(TlsStats::default());
+        handshake(&stats, true, Ok(())).await;
+        handshake(&stats, false, Ok(())).await;
+        handshake(&stats, false, Ok(())).await;
+        // Timed out before the server's certificate, or rejected it
+        handshake(&stats, false, Err(ConnectTimeout(Duration::from_secs(1)).into())).await;
+        handshake(&stats, true, Err("bad certificate".into())).await;
+
+        assert_eq!(stats.full.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.resumed.load(Ordering::Relaxed), 2);
+        assert_eq!(stats.failed.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn connections_without_tls_are_not_counted() {
+        let stats = Arc::new(TlsStats::default());
+        let _ = counted(async { Err::<(), BoxError>("connection refused".into()) }).await;
+        assert_eq!(stats.failed.load(Ordering::Relaxed), 0);
+        assert!(!certificate_verified());
+    }
+
+    #[tokio::test]
+    async fn a_verification_off_the_task_reaches_the_handshake() {
+        // As quinn does: the ClientHello is built here, the certificate checked elsewhere
+        let stats = Arc::new(TlsStats::default());
+        let _ = counted(async {
+            let handshake = tls_started(&stats).unwrap();
+            tokio::spawn(async move {
+                assert!(!certificate_verified());
+                handshake.certificate_verified();
+            })
+            .await
+            .unwrap();
+            Ok::<(), BoxError>(())
+        })
+        .await;
+        assert_eq!(stats.full.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.resumed.load(Ordering::Relaxed), 0);
+    }
+}
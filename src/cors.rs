@@ -0,0 +1,86 @@
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN,
+};
+use reqwest::{Method, RequestBuilder, StatusCode};
+
+/// What an OPTIONS preflight (`--preflight-origin`) asks permission for
+pub struct Preflight {
+    pub method: Method,
+    pub headers: Vec<HeaderName>,
+}
+
+/// Sends an Origin header and validates the CORS headers that come back, either on
+/// preflights (`--preflight-origin`) or on ordinary requests (`--cors-check`)
+pub struct CorsCheck {
+    origin: HeaderValue,
+    preflight: Option<Preflight>,
+}
+
+impl CorsCheck {
+    pub fn new(origin: &str, preflight: Option<Preflight>) -> Result<Self, String> {
+        let origin = HeaderValue::from_str(origin).map_err(|e| format!("invalid origin '{}': {}", origin, e))?;
+        Ok(CorsCheck { origin, preflight })
+    }
+
+    pub fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        let request = request.header(ORIGIN, self.origin.clone());
+        let Some(preflight) = &self.preflight else {
+            return request;
+        };
+
+        let request = request.header(ACCESS_CONTROL_REQUEST_METHOD, preflight.method.as_str());
+        if preflight.headers.is_empty() {
+            request
+        } else {
+            let names: Vec<&str> = preflight.headers.iter().map(HeaderName::as_str).collect();
+            request.header(ACCESS_CONTROL_REQUEST_HEADERS, names.join(","))
+        }
+    }
+
+    /// Describe why a response fails the CORS checks, if it does. Non-2xx responses
+    /// are left to the normal status handling.
+    pub fn check(&self, status: StatusCode, headers: &HeaderMap) -> Option<String> {
+        let preflight_status = matches!(status, StatusCode::OK | StatusCode::NO_CONTENT);
+        if self.preflight.is_some() && status.is_success() && !preflight_status {
+            return Some(format!("preflight returned {}, expected 200 or 204", status.as_u16()));
+        }
+
+        match headers.get(ACCESS_CONTROL_ALLOW_ORIGIN) {
+            None => return Some("Access-Control-Allow-Origin missing".to_string()),
+            Some(value) if value != self.origin && value != "*" => {
+                return Some(format!(
+                    "Access-Control-Allow-Origin: expected {}, got {}",
+                    String::from_utf8_lossy(self.origin.as_bytes()),
+                    String::from_utf8_lossy(value.as_bytes())
+                ));
+            }
+            Some(_) => {}
+        }
+
+        let preflight = self.preflight.as_ref()?;
+
+        // GET, HEAD and POST are CORS-safelisted and need not be listed
+        let safelisted = matches!(preflight.method, Method::GET | Method::HEAD | Method::POST);
+        if !safelisted && !allows(headers, &ACCESS_CONTROL_ALLOW_METHODS, preflight.method.as_str()) {
+            return Some(format!("Access-Control-Allow-Methods does not allow {}", preflight.method));
+        }
+
+        preflight
+            .headers
+            .iter()
+            .find(|name| !allows(headers, &ACCESS_CONTROL_ALLOW_HEADERS, name.as_str()))
+            .map(|name| format!("Access-Control-Allow-Headers does not allow {}", name))
+    }
+}
+
+/// Whether the comma-separated list in `header` contains `value` or `*`
+fn allows(headers: &HeaderMap, header: &HeaderName, value: &str) -> bool {
+    headers
+        .get_all(header)
+        .iter()
+        .filter_map(|list| list.to_str().ok())
+        .flat_map(|list| list.split(','))
+        .map(str::trim)
+        .any(|item| item == "*" || item.eq_ignore_ascii_case(value))
+}
@@ -0,0 +1,212 @@
+//! Whether benchy's own TLS is the bottleneck. Driving thousands of fresh handshakes a
+//! second, the client's crypto saturates before the server does, and handshakes stretch
+//! out as if the server were slow. The tell is that requests on connections already set
+//! up aren't any slower: per one-second window, the P50 of the handshakes completed in it
+//! is set against the P50 TTFB of the requests sent on warm connections, and a handshake
+//! P50 that grows over the run while the warm TTFB stays flat is flagged.
+
+use crate::clock::{self, RunClock};
+use hyper_util::client::legacy::connect::HttpInfo;
+use reqwest::Response;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Samples of each kind a window needs to count
+const MIN_SAMPLES: usize = 5;
+
+/// Counted windows averaged at each end of the run, by their median
+const EDGE: usize = 3;
+
+/// Handshake P50 growth, first windows to last, from which it counts as growing...
+const GROWTH: f64 = 2.0;
+/// ...as long as it grew by this much, so microsecond-scale noise doesn't
+const MIN_GROWTH: Duration = Duration::from_millis(1);
+
+/// Warm TTFB P50 growth up to which the server counts as unchanged
+const FLAT: f64 = 1.25;
+
+/// Handshakes and warm-connection TTFBs as they happen, shared by the connectors and
+/// the workers
+#[derive(Default)]
+pub struct CryptoDrift {
+    /// TLS connections so far, so a cleartext run skips the lookups
+    tls_connections: AtomicU64,
+    /// When each TLS connection, by its local address, finished setting up
+    connected: Mutex<HashMap<SocketAddr, Instant>>,
+    /// When each handshake finished, and how long it took
+    handshakes: Mutex<Vec<(Instant, Duration)>>,
+    /// When each request on a warm connection was sent, and its TTFB
+    warm: Mutex<Vec<(Instant, Duration)>>,
+}
+
+impl CryptoDrift {
+    /// A TLS connection set up from `local`, whose handshake took `handshake`
+    pub fn record_handshake(&self, local: Option<SocketAddr>, handshake: Duration) {
+        let now = Instant::now();
+        self.tls_connections.fetch_add(1, Ordering::Relaxed);
+        self.handshakes.lock().unwrap().push((now, handshake));
+        if let Some(local) = local {
+            self.connected.lock().unwrap().insert(local, now);
+        }
+    }
+
+    /// A response to a request sent at `sent`, counted if its connection was already up
+    /// by then
+    pub fn record_response(&self, resp: &Response, sent: Instant, ttfb: Duration) {
+        if self.tls_connections.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let Some(info) = resp.extensions().get::<HttpInfo>() else {
+            return;
+        };
+        let connected = self.connected.lock().unwrap().get(&info.local_addr()).copied();
+        if connected.is_some_and(|connected| connected <= sent) {
+            self.warm.lock().unwrap().push((sent, ttfb));
+        }
+    }
+
+    /// The windows from `start`, and the verdict, if there were enough of both to judge
+    pub fn finish(&self, start: Instant, clock: &RunClock) -> Option<DriftReport> {
+        let handshakes = std::mem::take(&mut *self.handshakes.lock().unwrap());
+        let warm = std::mem::take(&mut *self.warm.lock().unwrap());
+        if handshakes.is_empty() {
+            return None;
+        }
+        let by_window = |samples: Vec<(Instant, Duration)>| {
+            let mut windows: Vec<Vec<Duration>> = Vec::new();
+            for (at, sample) in samples {
+                let window = at.saturating_duration_since(start).as_secs() as usize;
+                if windows.len() <= window {
+                    windows.resize_with(window + 1, Vec::new);
+                }
+                windows[window].push(sample);
+            }
+            windows
+        };
+        let mut handshakes = by_window(handshakes);
+        let mut warm = by_window(warm);
+        warm.resize_with(handshakes.len().max(warm.len()), Vec::new);
+        handshakes.resize_with(warm.len(), Vec::new);
+        let windows = handshakes
+            .iter_mut()
+            .zip(&mut warm)
+            .enumerate()
+            .map(|(i, (handshakes, warm))| DriftWindow {
+                at: start + Duration::from_secs(i as u64),
+                handshakes: handshakes.len(),
+                handshake_p50: median(handshakes),
+                warm: warm.len(),
+                warm_ttfb_p50: median(warm),
+            })
+            .collect();
+        Some(DriftReport::new(windows, clock))
+    }
+}
+
+/// The middle sample, sorting `samples`
+fn median(samples: &mut [Duration]) -> Option<Duration> {
+    samples.sort_unstable();
+    samples.get(samples.len().checked_sub(1)? / 2).copied()
+}
+
+struct DriftWindow {
+    at: Instant,
+    handshakes: usize,
+    handshake_p50: Option<Duration>,
+    warm: usize,
+    warm_ttfb_p50: Option<Duration>,
+}
+
+/// The P50s at the start and end of the run, over the windows with enough of both
+struct Edges {
+    handshake: (Duration, Duration),
+    warm_ttfb: (Duration, Duration),
+    /// Handshakes a second over the last windows
+    rate: f64,
+}
+
+pub struct DriftReport {
+    windows: Vec<DriftWindow>,
+    /// Window start times on the wall clock, for the JSON
+    walls: Vec<String>,
+    edges: Option<Edges>,
+}
+
+impl DriftReport {
+    fn new(windows: Vec<DriftWindow>, clock: &RunClock) -> DriftReport {
+        let walls = windows.iter().map(|window| clock::rfc3339(clock.wall(window.at))).collect();
+        let counted: Vec<&DriftWindow> =
+            windows.iter().filter(|window| window.handshakes >= MIN_SAMPLES && window.warm >= MIN_SAMPLES).collect();
+        let edges = (counted.len() >= 2 * EDGE).then(|| {
+            let (first, last) = (&counted[..EDGE], &counted[counted.len() - EDGE..]);
+            let edge = |windows: &[&DriftWindow], p50: fn(&DriftWindow) -> Option<Duration>| {
+                median(&mut windows.iter().filter_map(|window| p50(window)).collect::<Vec<_>>()).unwrap_or_default()
+            };
+            Edges {
+                handshake: (edge(first, |w| w.handshake_p50), edge(last, |w| w.handshake_p50)),
+                warm_ttfb: (edge(first, |w| w.warm_ttfb_p50), edge(last, |w| w.warm_ttfb_p50)),
+                rate: last.iter().map(|window| window.handshakes).sum::<usize>() as f64 / EDGE as f64,
+            }
+        });
+        DriftReport { windows, walls, edges }
+    }
+
+    /// Whether the handshakes slowed down while the server didn't
+    pub fn client_bound(&self) -> bool {
+        self.edges.as_ref().is_some_and(|edges| {
+            let (early, late) = edges.handshake;
+            let (warm_early, warm_late) = edges.warm_ttfb;
+            late.as_secs_f64() >= GROWTH * early.as_secs_f64()
+                && late.saturating_sub(early) >= MIN_GROWTH
+                && warm_late.as_secs_f64() <= FLAT * warm_early.as_secs_f64().max(f64::MIN_POSITIVE)
+        })
+    }
+
+    /// The warning, with the numbers it rests on
+    pub fn warning(&self) -> Option<String> {
+        let edges = self.edges.as_ref().filter(|_| self.client_bound())?;
+        let (early, late) = edges.handshake;
+        let (warm_early, warm_late) = edges.warm_ttfb;
+        Some(format!(
+            "client crypto may be the bottleneck — TLS handshake P50 grew {:.1?} → {:.1?} ({:.1}x) from the first \
+             seconds to the last while warm-connection TTFB P50 held at {:.1?} → {:.1?}, at {:.0} handshakes/s; \
+             resume sessions, reuse connections, or add --processes",
+            early,
+            late,
+            late.as_secs_f64() / early.as_secs_f64().max(f64::MIN_POSITIVE),
+            warm_early,
+            warm_late,
+            edges.rate
+        ))
+    }
+
+    /// For `--output json`: every window's handshake and warm TTFB P50s, and the verdict
+    pub fn to_json(&self) -> Value {
+        let micros = |p50: Option<Duration>| p50.map(|p50| p50.as_micros() as u64);
+        let windows: Vec<Value> = self
+            .windows
+            .iter()
+            .zip(&self.walls)
+            .map(|(window, at)| {
+                json!({
+                    "at": at,
+                    "handshakes": window.handshakes,
+                    "handshake_p50_us": micros(window.handshake_p50),
+                    "warm_requests": window.warm,
+                    "warm_ttfb_p50_us": micros(window.warm_ttfb_p50),
+                })
+            })
+            .collect();
+        let edge = |(first, last): (Duration, Duration)| json!([first.as_micros() as u64, last.as_micros() as u64]);
+        json!({
+            "windows": windows,
+            "handshake_p50_first_last_us": self.edges.as_ref().map(|edges| edge(edges.handshake)),
+            "warm_ttfb_p50_first_last_us": self.edges.as_ref().map(|edges| edge(edges.warm_ttfb)),
+            "client_crypto_bound": self.client_bound(),
+        })
+    }
+}
@@ -0,0 +1,252 @@
+use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig};
+use hickory_resolver::name_server::TokioConnectionProvider;
+use hickory_resolver::TokioResolver;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+/// DNS lookup counters shared by every client
+#[derive(Default)]
+pub struct DnsStats {
+    pub failures: AtomicU64,
+    /// One per resolve, zero for answers from the cache
+    lookup_times: Mutex<Vec<Duration>>,
+    /// `--spread-dns`: the address each worker was given for each host
+    spread: Mutex<HashMap<(String, usize), IpAddr>>,
+}
+
+impl DnsStats {
+    pub fn take_lookup_times(&self) -> Vec<Duration> {
+        std::mem::take(&mut *self.lookup_times.lock().unwrap())
+    }
+
+    /// Workers given each address by `--spread-dns`, most first
+    pub fn spread(&self) -> Vec<(IpAddr, u64)> {
+        let mut counts: HashMap<IpAddr, u64> = HashMap::new();
+        for ip in self.spread.lock().unwrap().values() {
+            *counts.entry(*ip).or_default() += 1;
+        }
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by_key(|&(ip, n)| (std::cmp::Reverse(n), ip));
+        counts
+    }
+}
+
+/// Marker error so failed lookups can be told apart from TCP connect failures
+#[derive(Debug)]
+pub struct DnsError(String);
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "dns error: {}", self.0)
+    }
+}
+
+impl std::error::Error for DnsError {}
+
+/// Which address family to try first when a name has both A and AAAA records.
+///
+/// The connector races the first address's family against the other (Happy
+/// Eyeballs), so ordering decides which family normally wins.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum AddressOrder {
+    /// Keep the resolver's order
+    System,
+    V6First,
+    V4First,
+}
+
+impl AddressOrder {
+    fn apply(self, addrs: &mut [SocketAddr]) {
+        match self {
+            AddressOrder::System => {}
+            // Stable sorts, so the resolver's order is kept within each family
+            AddressOrder::V6First => addrs.sort_by_key(|a| a.is_ipv4()),
+            AddressOrder::V4First => addrs.sort_by_key(|a| a.is_ipv6()),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Backend {
+    /// getaddrinfo via tokio, what reqwest does by default
+    System,
+    Hickory(Box<TokioResolver>),
+}
+
+/// Answers shared by every client, so a name is looked up once per run rather than
+/// once per connection, or once per `--dns-ttl-override` with one
+struct DnsCache {
+    ttl: Option<Duration>,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+    /// `--spread-dns`: each worker's turn in the round-robin, per host
+    slots: Mutex<HashMap<String, HashMap<usize, usize>>>,
+}
+
+struct CacheEntry {
+    /// Filled by the first lookup; concurrent ones wait for it instead of resolving too
+    addrs: Arc<OnceCell<Vec<SocketAddr>>>,
+    created: Instant,
+}
+
+impl DnsCache {
+    /// The entry for `host`, a fresh one if it has outlived the TTL
+    fn entry(&self, host: &str) -> Arc<OnceCell<Vec<SocketAddr>>> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.entry(host.to_string()).or_insert_with(|| CacheEntry {
+            addrs: Arc::new(OnceCell::new()),
+            created: Instant::now(),
+        });
+        if self.ttl.is_some_and(|ttl| entry.created.elapsed() >= ttl) {
+            *entry = CacheEntry {
+                addrs: Arc::new(OnceCell::new()),
+                created: Instant::now(),
+            };
+        }
+        entry.addrs.clone()
+    }
+
+    /// `worker`'s turn among the workers resolving `host`, kept across re-resolves
+    fn slot(&self, host: &str, worker: usize) -> usize {
+        let mut slots = self.slots.lock().unwrap();
+        let host = slots.entry(host.to_string()).or_default();
+        let next = host.len();
+        *host.entry(worker).or_insert(next)
+    }
+}
+
+/// Resolver installed on every client so lookups are timed, cached for the whole
+/// process and, optionally, sent to a specific server with an overridden cache TTL.
+#[derive(Clone)]
+pub struct BenchResolver {
+    backend: Backend,
+    order: AddressOrder,
+    stats: Arc<DnsStats>,
+    cache: Arc<DnsCache>,
+    /// `--spread-dns`
+    spread: bool,
+    /// The worker whose clients use this copy, for `--spread-dns`
+    worker: Option<usize>,
+}
+
+impl BenchResolver {
+    pub fn new(
+        server: Option<SocketAddr>,
+        ttl_override: Option<Duration>,
+        order: AddressOrder,
+        spread: bool,
+        stats: Arc<DnsStats>,
+    ) -> BenchResolver {
+        let backend = match server {
+            Some(server) => {
+                let servers = NameServerConfigGroup::from_ips_clear(&[server.ip()], server.port(), true);
+                let config = ResolverConfig::from_parts(None, vec![], servers);
+                let mut builder =
+                    TokioResolver::builder_with_config(config, TokioConnectionProvider::default());
+                if let Some(ttl) = ttl_override {
+                    // Pin every cached answer to the override, regardless of the record's TTL
+                    let opts = builder.options_mut();
+                    opts.positive_min_ttl = Some(ttl);
+                    opts.positive_max_ttl = Some(ttl);
+                }
+                Backend::Hickory(Box::new(builder.build()))
+            }
+            None => Backend::System,
+        };
+
+        BenchResolver {
+            backend,
+            order,
+            stats,
+            cache: Arc::new(DnsCache {
+                ttl: ttl_override,
+                entries: Mutex::new(HashMap::new()),
+                slots: Mutex::new(HashMap::new()),
+            }),
+            spread,
+            worker: None,
+        }
+    }
+
+    /// A copy for one worker's clients, sharing the cache
+    pub fn for_worker(&self, worker: usize) -> Arc<BenchResolver> {
+        Arc::new(BenchResolver {
+            worker: Some(worker),
+            ..self.clone()
+        })
+    }
+}
+
+impl Resolve for BenchResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        let host = name.as_str().to_string();
+
+        Box::pin(async move {
+            let stats = &resolver.stats;
+            let start = Instant::now();
+            let cached = resolver.cache.entry(&host);
+            let hit = cached.initialized();
+
+            let result = cached
+                .get_or_try_init(|| async {
+                    let result: Result<Vec<SocketAddr>, String> = match &resolver.backend {
+                        Backend::Hickory(hickory) => hickory
+                            .lookup_ip(host.as_str())
+                            .await
+                            .map(|ips| ips.iter().map(|ip| SocketAddr::new(ip, 0)).collect())
+                            .map_err(|e| format!("{}: {}", host, e)),
+                        Backend::System => tokio::net::lookup_host((host.as_str(), 0))
+                            .await
+                            .map(|addrs| addrs.collect())
+                            .map_err(|e| format!("{}: {}", host, e)),
+                    };
+                    match result {
+                        Ok(mut addrs) if !addrs.is_empty() => {
+                            resolver.order.apply(&mut addrs);
+                            Ok(addrs)
+                        }
+                        Ok(_) => Err(format!("{} has no addresses", host)),
+                        Err(e) => Err(e),
+                    }
+                })
+                .await;
+
+            match result {
+                Ok(addrs) => {
+                    // A cache hit costs the connection no DNS time
+                    let elapsed = if hit { Duration::ZERO } else { start.elapsed() };
+                    stats.lookup_times.lock().unwrap().push(elapsed);
+                    let mut addrs = addrs.clone();
+                    if let (true, Some(worker)) = (resolver.spread, resolver.worker) {
+                        // The worker's own address first; the rest stay as fallbacks
+                        let turn = resolver.cache.slot(&host, worker) % addrs.len();
+                        addrs.rotate_left(turn);
+                        stats.spread.lock().unwrap().insert((host, worker), addrs[0].ip());
+                    }
+                    let addrs: Addrs = Box::new(addrs.into_iter());
+                    Ok(addrs)
+                }
+                Err(e) => {
+                    stats.failures.fetch_add(1, Ordering::Relaxed);
+                    Err(DnsError(e).into())
+                }
+            }
+        })
+    }
+}
+
+/// Parse `IP[:port]`, defaulting to port 53
+pub fn parse_server(s: &str) -> Result<SocketAddr, String> {
+    if let Ok(addr) = s.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    s.parse::<std::net::IpAddr>()
+        .map(|ip| SocketAddr::new(ip, 53))
+        .map_err(|_| format!("invalid DNS server '{}', expected IP[:port]", s))
+}
@@ -0,0 +1,307 @@
+//! `--dry-run`: everything a run does before its first request, and nothing after. The
+//! arguments are checked and every file they name loaded as for the run, then the
+//! configuration the run would use is printed with an estimate of the memory its
+//! results take, and a client is built for each kind of target to load the TLS
+//! settings. `--dry-run=probe` also sends the first request a worker would, once.
+
+use crate::connect::ConnectStats;
+use crate::render;
+use crate::resources::mib;
+use crate::template::BodyGenerator;
+use crate::thresholds::Thresholds;
+use crate::transport::TlsStats;
+use crate::units::LatencyUnit;
+use crate::{send_request, thousands, Args, ClientOptions, ErrorDetails, Outgoing, RequestConfig, RequestResult, Stats};
+use clap::ValueEnum;
+use colored::Colorize;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// What `--dry-run` does besides checking the configuration
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum DryRun {
+    /// Send nothing
+    Config,
+    /// Send the first request once
+    Probe,
+}
+
+/// Bytes of a `Duration` or an `Instant` kept per request
+const SAMPLE: u64 = 16;
+
+/// What the results of a run take in memory: the per-request samples the collector
+/// keeps until the report, some of them reserved for all of `-n` at the start
+struct Estimate {
+    per_request: u64,
+    reserved: u64,
+    total: u64,
+}
+
+impl Estimate {
+    fn of(args: &Args, config: &RequestConfig) -> Estimate {
+        let n = args.requests;
+        // Latency, TTFB, client queueing and completion time, reserved up front
+        let mut reserved_per_request = 4 * SAMPLE;
+        if args.correct_latency {
+            reserved_per_request += SAMPLE;
+        }
+        // Download time; upload and server wait too with a body
+        let mut per_request = SAMPLE;
+        if config.body.is_some() || config.bodies.is_some() {
+            per_request += 2 * SAMPLE;
+        }
+        let breakdowns = [
+            args.group_by_header.is_some(),
+            args.group_by_header_sent && config.header_file.is_some(),
+            (args.per_url_stats || args.per_entry_stats) && config.urls.is_some(),
+            args.alternate.is_some(),
+            config.hosts.is_some(),
+            config.hol.is_some(),
+            args.burst.is_some(),
+            args.expect_continue,
+        ];
+        per_request += SAMPLE * breakdowns.iter().filter(|&&on| on).count() as u64;
+        // Latencies and TTFBs copied out, and their JSON
+        if args.save_results.is_some() || args.processes > 1 {
+            per_request += 2 * SAMPLE + 2 * 12;
+        }
+        let reserved = n.saturating_mul(reserved_per_request);
+        Estimate {
+            per_request: reserved_per_request + per_request,
+            reserved,
+            total: reserved.saturating_add(n.saturating_mul(per_request)),
+        }
+    }
+
+    fn describe(&self, args: &Args) -> String {
+        let mut line = format!(
+            "~{} for {} requests ({} bytes each, {} of it allocated at the start)",
+            mib(self.total),
+            thousands(args.requests),
+            self.per_request,
+            mib(self.reserved)
+        );
+        // Each child keeps its share, and the parent every latency and TTFB again
+        if args.processes > 1 {
+            let parent = args.requests.saturating_mul(2 * SAMPLE + 2 * 12);
+            line += &format!(", split over {} processes, plus ~{} in the parent", args.processes, mib(parent));
+        }
+        if args.max_duration.is_some() {
+            line += "; --max-duration may end the run sooner, but -n is what's reserved";
+        }
+        line
+    }
+}
+
+/// Check, print and possibly probe, instead of running
+pub async fn run(
+    mode: DryRun,
+    args: &Args,
+    config: &RequestConfig,
+    client_opts: &ClientOptions,
+    seed: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Counters of their own, so nothing here is mistaken for the run's
+    let tls_stats = Arc::new(TlsStats::default());
+    let client_opts = ClientOptions {
+        conn_stats: Arc::new(ConnectStats::default()),
+        ..client_opts.clone()
+    };
+    // One client per kind of target: plain HTTP, or TLS with its settings loaded
+    let mut clients = Vec::new();
+    for i in 0..args.connections {
+        let is_https = crate::https_target(args, config, i);
+        if clients.iter().any(|&(https, _)| https == is_https) {
+            continue;
+        }
+        clients.push((is_https, crate::worker_client(args, &client_opts, &tls_stats, is_https, i)?));
+    }
+
+    println!("\n{}", "--- Dry Run ---".cyan().bold());
+    let url = match (&config.urls, &config.hosts) {
+        (Some(mix), _) if config.entry(0).is_some() => format!("{} ({} workload entries)", config.url(0), mix.len()),
+        (Some(mix), _) => format!("{} ({} URLs)", config.url(0), mix.len()),
+        (None, Some(hosts)) => format!("{} ({})", config.url(0), hosts.describe()),
+        (None, None) => config.url(0).to_string(),
+    };
+    render::print("Request:".white(), format!("{} {}", config.method(0), url));
+    render::print("Protocol:".white(), describe_protocol(args, &clients));
+    render::print("Load:".white(), describe_load(args));
+    render::print(
+        "Timeouts:".white(),
+        format!(
+            "connect {}, drain {}",
+            humantime::format_duration(args.connect_timeout),
+            humantime::format_duration(args.drain_timeout)
+        ),
+    );
+    let body = match (&config.bodies, &config.body) {
+        (Some(pool), _) => Some(format!("{} files, {} in memory", pool.len(), mib(pool.size()))),
+        (None, Some(template)) => {
+            let first = template.render(&mut BodyGenerator::new(seed, 0)).len();
+            let kind = if template.is_static() { "the same each time" } else { "generated per request" };
+            Some(format!("{} bytes, {}", thousands(first as u64), kind))
+        }
+        (None, None) => None,
+    };
+    if let Some(body) = body {
+        render::print("Body:".white(), body);
+    }
+    if let Some(file) = &config.header_file {
+        render::print("Headers:".white(), file.describe());
+    }
+    let checks = describe_checks(args, config);
+    if !checks.is_empty() {
+        render::print_wrapped("Checks:".white(), checks.join(", "));
+    }
+    let thresholds = Thresholds::of(args).describe(args.latency_unit);
+    if !thresholds.is_empty() {
+        render::print("Thresholds:".white(), thresholds.join(", "));
+    }
+    let outputs = describe_outputs(args);
+    if !outputs.is_empty() {
+        render::print_wrapped("Writes:".white(), outputs.join(", "));
+    }
+    render::print("Seed:".white(), seed);
+    render::print_wrapped("Memory:".white(), Estimate::of(args, config).describe(args));
+
+    if mode == DryRun::Probe {
+        // Worker 0's client, which every run has
+        let https = crate::https_target(args, config, 0);
+        let (_, worker) = clients.iter_mut().find(|(built, _)| *built == https).expect("built for every kind of target");
+        let identity = args.identity.as_ref().map(|identity| identity.for_worker(seed, 0));
+        let outgoing = Outgoing {
+            url: 0,
+            body: match (config.entry(0), &config.bodies, &config.body) {
+                (Some(entry), _, _) => entry.body.clone(),
+                (None, Some(pool), _) => Some(pool.body(0)),
+                (None, None, Some(template)) => Some(template.render(&mut BodyGenerator::new(seed, 0))),
+                (None, None, None) => None,
+            },
+            body_file: config.bodies.as_ref().map(|_| 0),
+            script: None,
+            request_id: None,
+            header: config.header_file.as_ref().map(|_| 0),
+            pushed: Instant::now(),
+        };
+        let (client, version) = worker.next();
+        let result = send_request(client, version, outgoing, identity.as_ref(), config, &Stats::default()).await;
+        match probe_outcome(result, args.latency_unit) {
+            Ok(outcome) => render::print("Probe:".white(), outcome.green()),
+            Err(details) => {
+                crate::print_error_details(&details);
+                std::process::exit(1);
+            }
+        }
+    }
+    let sent = if mode == DryRun::Probe { "one request sent" } else { "nothing sent" };
+    render::print("Dry run:".white(), format!("configuration is valid, {}", sent).green());
+    Ok(())
+}
+
+fn describe_protocol(args: &Args, clients: &[(bool, crate::WorkerClient)]) -> String {
+    let mut line = args.protocol.label().to_string();
+    if args.h3_alt_svc {
+        line += ", then HTTP/3 via Alt-Svc";
+    }
+    let kinds: Vec<&str> = clients.iter().map(|&(https, _)| if https { "https" } else { "http" }).collect();
+    line += &format!(", client built for {} targets", kinds.join(" and "));
+    if args.insecure {
+        line += ", certificates not verified";
+    }
+    if let Some(proxy) = &args.proxy {
+        line += &format!(", through {}", proxy);
+    }
+    line
+}
+
+fn describe_load(args: &Args) -> String {
+    let mut line = format!(
+        "{} requests over {} connections × {} streams",
+        thousands(args.requests),
+        args.connections,
+        args.pipeline
+    );
+    if args.processes > 1 {
+        line += &format!(" in {} processes", args.processes);
+    }
+    if let Some(rate) = args.rate {
+        line += &format!(", {} req/s", rate);
+    }
+    if let Some(burst) = &args.burst {
+        line += &format!(", {} every {}", burst.size, humantime::format_duration(burst.interval));
+    }
+    if let Some(max) = args.max_duration {
+        line += &format!(", for at most {}", humantime::format_duration(max));
+    }
+    if let Some(ramp) = args.ramp_down {
+        line += &format!(" with a {} ramp-down", humantime::format_duration(ramp));
+    }
+    line
+}
+
+/// The response checks the run applies, by flag
+fn describe_checks(args: &Args, config: &RequestConfig) -> Vec<String> {
+    let counted = [
+        (config.assert_headers.len(), "--assert-header"),
+        (config.assert_trailers.len(), "--assert-trailer"),
+        (args.assert_json.len(), "--assert-json"),
+    ];
+    let mut checks: Vec<String> = counted
+        .into_iter()
+        .filter(|&(n, _)| n > 0)
+        .map(|(n, flag)| format!("{} {}", n, flag))
+        .collect();
+    let flags = [
+        (config.body_checks.expect_size.is_some(), "body size"),
+        (config.body_checks.expect_sha256.is_some(), "body digest"),
+        (config.body_checks.consistency, "--verify-consistency"),
+        (config.cors.is_some(), "CORS"),
+        (config.lint.is_some(), "--lint-responses"),
+        (config.require_protocol, "--require-protocol"),
+    ];
+    checks.extend(flags.into_iter().filter(|&(on, _)| on).map(|(_, name)| name.to_string()));
+    checks
+}
+
+/// Files and endpoints the run would write to or open
+fn describe_outputs(args: &Args) -> Vec<String> {
+    let files = [
+        ("--save-results", &args.save_results),
+        ("--heatmap", &args.heatmap),
+        ("--junit", &args.junit),
+        ("--error-dump", &args.error_dump),
+    ];
+    let mut outputs: Vec<String> = files
+        .into_iter()
+        .filter_map(|(flag, path)| Some(format!("{} {}", flag, path.as_ref()?.display())))
+        .collect();
+    if let Some(addr) = args.prometheus_listen {
+        outputs.push(format!("--prometheus-listen {}", addr));
+    }
+    if let Some(url) = args.publish.as_deref().and_then(|url| reqwest::Url::parse(url).ok()) {
+        outputs.push(format!("--publish {}", crate::publish::redacted(&url)));
+    }
+    outputs
+}
+
+/// The probe's status and latency, or why it failed. The run's checks are applied
+/// fail-fast, so a failure comes with its details.
+fn probe_outcome(result: RequestResult, unit: LatencyUnit) -> Result<String, ErrorDetails> {
+    match result {
+        RequestResult::Success(sample) => Ok(format!(
+            "{} in {}",
+            sample.status.unwrap_or_default(),
+            unit.resolve(sample.latency).format(sample.latency)
+        )),
+        RequestResult::Error(details) => Err(details),
+        // Only without -f, which the probe always has
+        RequestResult::Failed(sample) => Err(ErrorDetails {
+            message: sample.unreachable.unwrap_or_else(|| "the probe failed".to_string()),
+            status: sample.status,
+            headers: None,
+            body: None,
+            trailers: None,
+        }),
+    }
+}
@@ -0,0 +1,94 @@
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{StatusCode, Version};
+use std::fmt::Write as _;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Writes failing responses to `--error-dump DIR`, up to a limit
+pub struct ErrorDump {
+    dir: PathBuf,
+    limit: u64,
+    /// Dump slots handed out so far
+    reserved: AtomicU64,
+    /// Failing responses seen, dumped or not
+    failures: AtomicU64,
+}
+
+/// A failing response, captured for the dump
+pub struct FailedExchange<'a> {
+    pub method: &'a str,
+    pub url: &'a str,
+    /// Only sent with `--request-id-header`
+    pub request_id: Option<(&'a HeaderName, &'a HeaderValue)>,
+    pub version: Version,
+    pub status: StatusCode,
+    pub headers: &'a HeaderMap,
+    pub body: &'a [u8],
+    /// Only captured with `--assert-trailer`
+    pub trailers: Option<&'a HeaderMap>,
+}
+
+impl ErrorDump {
+    /// Create the directory up front so a bad path fails before the run starts
+    pub fn create(dir: &Path, limit: u64) -> io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        if std::fs::metadata(dir)?.permissions().readonly() {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "directory is read-only"));
+        }
+
+        Ok(ErrorDump {
+            dir: dir.to_path_buf(),
+            limit,
+            reserved: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+        })
+    }
+
+    /// Count a failing response and claim a dump slot for it if any are left.
+    /// Slots are numbered from 1 in the order failures arrive.
+    pub fn reserve(&self) -> Option<u64> {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        self.reserved
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| (n < self.limit).then_some(n + 1))
+            .ok()
+            .map(|n| n + 1)
+    }
+
+    /// Write `<slot>_<status>.txt` with the request line and any request id, response
+    /// headers, body and any trailers
+    pub async fn write(&self, slot: u64, exchange: &FailedExchange<'_>) -> io::Result<()> {
+        let mut text = format!("{} {} {:?}\n", exchange.method, exchange.url, exchange.version);
+        if let Some((name, id)) = exchange.request_id {
+            let _ = writeln!(text, "{}: {}", name, String::from_utf8_lossy(id.as_bytes()));
+        }
+        text.push('\n');
+        let _ = writeln!(text, "{:?} {}", exchange.version, exchange.status);
+        for (name, value) in exchange.headers {
+            let _ = writeln!(text, "{}: {}", name, String::from_utf8_lossy(value.as_bytes()));
+        }
+        text.push('\n');
+        text.push_str(&String::from_utf8_lossy(exchange.body));
+        if let Some(trailers) = exchange.trailers {
+            text.push_str("\n\n");
+            for (name, value) in trailers {
+                let _ = writeln!(text, "{}: {}", name, String::from_utf8_lossy(value.as_bytes()));
+            }
+        }
+
+        let path = self.dir.join(format!("{}_{}.txt", slot, exchange.status.as_u16()));
+        tokio::fs::write(path, text).await
+    }
+
+    pub fn dumped(&self) -> u64 {
+        self.reserved.load(Ordering::Relaxed)
+    }
+
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
@@ -0,0 +1,164 @@
+//! The banner's Endpoint, TLS and Server lines: one HEAD request before the run, so
+//! benchmarking the wrong thing (stale DNS, an intercepting proxy) shows up before
+//! the load starts rather than after.
+
+use crate::connect::ConnectStats;
+use crate::dns::{BenchResolver, DnsStats};
+use crate::protocol::PROBE_TIMEOUT;
+use crate::render;
+use crate::transport::{self, TlsDetails, TlsStats};
+use crate::{build_tls, error_chain, Args, ClientOptions, HttpClient};
+use colored::Colorize;
+use reqwest::header::SERVER;
+use reqwest::{StatusCode, Version};
+use rustls::pki_types::ServerName;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// How much of the TLS session the probe could see
+enum Tls {
+    Plaintext,
+    /// Our own handshake with the same address and config
+    Direct(TlsDetails),
+    /// QUIC always runs TLS 1.3; quinn doesn't say which suite
+    Quic,
+    /// The session is end to end inside the CONNECT tunnel, where we can't see it
+    Proxied,
+}
+
+/// What the probe request found
+struct Endpoint {
+    remote: Option<SocketAddr>,
+    version: Version,
+    status: StatusCode,
+    server: Option<String>,
+    tls: Tls,
+}
+
+/// Probe unless `--no-probe`, and print what was found; a failed probe is only a
+/// warning, since the run will report the same failure in detail
+pub async fn print(args: &Args) {
+    if args.no_probe {
+        return;
+    }
+    match probe(args).await {
+        Ok(endpoint) => endpoint.print(args.insecure, args.proxy.is_some()),
+        Err(e) => render::print("Endpoint:".white(), format!("probe failed: {}", e).yellow()),
+    }
+}
+
+/// Send one HEAD request on a client built like the workers' but with its own
+/// counters, so it shows up in neither the stats nor the latencies
+async fn probe(args: &Args) -> Result<Endpoint, String> {
+    let url = reqwest::Url::parse(&args.url).map_err(|e| e.to_string())?;
+    let is_https = url.scheme() == "https";
+    let tls = build_tls(args.protocol, args.insecure, is_https, false, &Arc::new(TlsStats::default()))
+        .map_err(|e| e.to_string())?;
+    let opts = ClientOptions {
+        protocol: args.protocol,
+        conn_stats: Arc::new(ConnectStats::default()),
+        resolver: Arc::new(BenchResolver::new(
+            args.dns_server,
+            None,
+            args.address_order,
+            false,
+            Arc::new(DnsStats::default()),
+        )),
+        resolve_override: None,
+        requests_per_connection: None,
+        max_client_age: None,
+        quic: args.http3.then(|| args.quic_tuning()),
+        proxy: args.proxy.clone(),
+        expect_continue: false,
+        h2_keepalive: None,
+        connect_timeout: args.connect_timeout,
+        // Its own counters, so the probe's connection isn't counted
+        h2c_upgrade: args.h2c_upgrade.then(Arc::default),
+        worker: None,
+    };
+    let client = HttpClient::new(&opts, tls.as_ref()).map_err(|e| error_chain(e.as_ref()))?;
+
+    let mut request = client.reqwest.head(url.as_str());
+    if let Some(version) = opts.protocol.version() {
+        request = request.version(version);
+    }
+    // Our QUIC client doesn't apply reqwest's per-request timeout
+    let resp = tokio::time::timeout(PROBE_TIMEOUT, client.send(request))
+        .await
+        .map_err(|_| format!("no response within {:?}", PROBE_TIMEOUT))?
+        .map_err(|e| error_chain(e.as_ref()))?;
+
+    let remote = match &client.h3 {
+        Some(h3) => h3.remote().await,
+        None => resp.remote_addr(),
+    };
+    let tls = match (&tls, remote) {
+        (None, _) => Tls::Plaintext,
+        (Some(_), _) if client.h3.is_some() => Tls::Quic,
+        (Some(_), _) if opts.proxy.is_some() => Tls::Proxied,
+        (Some(tls), Some(remote)) => {
+            let host = url.host_str().unwrap_or_default().trim_start_matches('[').trim_end_matches(']');
+            let server_name = ServerName::try_from(host.to_string()).map_err(|e| e.to_string())?;
+            let details = tokio::time::timeout(PROBE_TIMEOUT, transport::tls_details(remote, server_name, tls))
+                .await
+                .map_err(|_| "TLS handshake timed out".to_string())?
+                .map_err(|e| error_chain(e.as_ref()))?;
+            Tls::Direct(details)
+        }
+        (Some(_), None) => return Err("the response didn't say which address it came from".to_string()),
+    };
+
+    Ok(Endpoint {
+        remote,
+        version: resp.version(),
+        status: resp.status(),
+        server: resp
+            .headers()
+            .get(SERVER)
+            .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned()),
+        tls,
+    })
+}
+
+impl Endpoint {
+    fn print(&self, insecure: bool, proxied: bool) {
+        let remote = match (self.remote, proxied) {
+            (Some(remote), true) => format!("{} (proxy)", remote),
+            (Some(remote), false) => remote.to_string(),
+            (None, _) => "unknown address".to_string(),
+        };
+        let status = format!("HEAD {}", self.status);
+        render::print(
+            "Endpoint:".white(),
+            format!(
+                "{} over {:?}, {}",
+                remote.green(),
+                self.version,
+                if self.status.is_success() { status.green() } else { status.yellow() }
+            ),
+        );
+
+        let certificate = if insecure {
+            "certificate not verified (-k)".yellow()
+        } else {
+            "certificate verified".green()
+        };
+        match &self.tls {
+            Tls::Plaintext => {}
+            Tls::Direct(details) => render::print(
+                "TLS:".white(),
+                format!("{}, {}, {}", details.version, details.cipher_suite, certificate),
+            ),
+            Tls::Quic => render::print("TLS:".white(), format!("TLS 1.3 (QUIC), {}", certificate)),
+            Tls::Proxied => render::print(
+                "TLS:".white(),
+                format!("{}; {}", certificate, "version and cipher aren't visible through --proxy".dimmed()),
+            ),
+        }
+
+        match &self.server {
+            Some(server) => render::print("Server:".white(), server),
+            None => render::print("Server:".white(), "no Server header".dimmed()),
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use crate::connect::ConnectTimeout;
+use crate::dns::DnsError;
+use crate::quic;
+use crate::render;
+use crate::upgrade::UpgradeError;
+use colored::Colorize;
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Coarse classification of transport-level request failures
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorKind {
+    /// The connection was shut down by an HTTP/2 GOAWAY
+    GoAway,
+    /// The server refused the stream before processing it (RST_STREAM REFUSED_STREAM)
+    RefusedStream,
+    /// Any other RST_STREAM from the server
+    StreamReset,
+    /// The server refused the request's header block: a 431, an HTTP/2 FRAME_SIZE_ERROR
+    /// or COMPRESSION_ERROR reset or GOAWAY from the server, or more than an HTTP/3
+    /// server's MAX_FIELD_SECTION_SIZE
+    HeadersTooLarge,
+    /// The connection was dropped as dead: `--h2-keepalive` got no answer to a PING, or
+    /// an HTTP/3 connection heard nothing back for its idle timeout
+    KeepAlive,
+    /// The connection negotiated a different HTTP version than the request required, or
+    /// what came back wasn't HTTP/2 framing
+    Protocol,
+    /// `--h2c-upgrade` couldn't switch a connection to HTTP/2
+    Upgrade,
+    /// The body ended early or disagreed with Content-Length
+    Truncated,
+    /// Name resolution failed
+    Dns,
+    /// `--proxy` answered CONNECT with 407 Proxy Authentication Required
+    ProxyAuth,
+    /// `--proxy` answered CONNECT with any other non-200 status, or closed the tunnel
+    Tunnel,
+    /// This machine ran out of file descriptors or ephemeral ports
+    ClientResources,
+    /// No connection could be set up within `--connect-timeout`
+    ConnectTimeout,
+    Connect,
+    Timeout,
+    Other,
+}
+
+impl ErrorKind {
+    pub const ALL: [ErrorKind; 16] = [
+        ErrorKind::GoAway,
+        ErrorKind::RefusedStream,
+        ErrorKind::StreamReset,
+        ErrorKind::HeadersTooLarge,
+        ErrorKind::KeepAlive,
+        ErrorKind::Protocol,
+        ErrorKind::Upgrade,
+        ErrorKind::Truncated,
+        ErrorKind::Dns,
+        ErrorKind::ProxyAuth,
+        ErrorKind::Tunnel,
+        ErrorKind::ClientResources,
+        ErrorKind::ConnectTimeout,
+        ErrorKind::Connect,
+        ErrorKind::Timeout,
+        ErrorKind::Other,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ErrorKind::GoAway => "goaway",
+            ErrorKind::RefusedStream => "refused stream",
+            ErrorKind::StreamReset => "stream reset",
+            ErrorKind::HeadersTooLarge => "headers too large",
+            ErrorKind::KeepAlive => "keepalive",
+            ErrorKind::Protocol => "protocol",
+            ErrorKind::Upgrade => "h2c upgrade",
+            ErrorKind::Truncated => "truncated",
+            ErrorKind::Dns => "dns",
+            ErrorKind::ProxyAuth => "proxy 407",
+            ErrorKind::Tunnel => "tunnel",
+            ErrorKind::ClientResources => "client limits",
+            ErrorKind::ConnectTimeout => "connect timeout",
+            ErrorKind::Connect => "connect",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Other => "other",
+        }
+    }
+}
+
+/// `e` and everything in its `source()` chain
+fn causes<'a>(e: &'a (dyn Error + 'static)) -> impl Iterator<Item = &'a (dyn Error + 'static)> {
+    std::iter::successors(Some(e), |&err| err.source())
+}
+
+/// Find the first error of type `T` in the `source()` chain
+fn find_cause<'a, T: Error + 'static>(e: &'a (dyn Error + 'static)) -> Option<&'a T> {
+    let mut source: Option<&(dyn Error + 'static)> = Some(e);
+    while let Some(err) = source {
+        if let Some(found) = err.downcast_ref::<T>() {
+            return Some(found);
+        }
+        source = err.source();
+    }
+    None
+}
+
+/// Walk the error chain looking for the most specific cause we recognize
+pub fn classify(e: &(dyn Error + 'static)) -> ErrorKind {
+    if let Some(h2) = find_cause::<h2::Error>(e) {
+        // h2 itself found the connection's frames malformed, as when an HTTP/1.1 server
+        // answers h2c prior knowledge
+        if h2.is_library()
+            && h2.is_go_away()
+            && matches!(h2.reason(), Some(h2::Reason::PROTOCOL_ERROR | h2::Reason::FRAME_SIZE_ERROR))
+        {
+            return ErrorKind::Protocol;
+        }
+        // What an HPACK block past the server's limits gets back, as a reset of its
+        // stream or a GOAWAY: either only reaches a request whose stream was opened, so
+        // whose header block went out. The same reasons raised on our side are framing
+        // trouble like any other.
+        if matches!(h2.reason(), Some(h2::Reason::FRAME_SIZE_ERROR | h2::Reason::COMPRESSION_ERROR)) {
+            return if h2.is_remote() {
+                ErrorKind::HeadersTooLarge
+            } else if h2.is_reset() {
+                ErrorKind::StreamReset
+            } else {
+                ErrorKind::Protocol
+            };
+        }
+        if h2.is_go_away() {
+            return ErrorKind::GoAway;
+        }
+        if h2.is_reset() {
+            return if h2.reason() == Some(h2::Reason::REFUSED_STREAM) {
+                ErrorKind::RefusedStream
+            } else {
+                ErrorKind::StreamReset
+            };
+        }
+    }
+
+    // Before DNS: a lookup can fail for want of a socket too, and a client that
+    // couldn't be replaced mid-run is short of something similar
+    if is_exhaustion(e) || find_cause::<crate::RebuildError>(e).is_some() {
+        return ErrorKind::ClientResources;
+    }
+
+    // hyper's keep-alive error is private, and would otherwise pass for a timeout
+    if causes(e).any(|err| err.to_string() == "keep-alive timed out") {
+        return ErrorKind::KeepAlive;
+    }
+
+    // hyper-util only exposes its error kinds through formatting, e.g. ALPN picking
+    // http/1.1 for a request pinned to HTTP/2
+    if find_cause::<hyper_util::client::legacy::Error>(e)
+        .is_some_and(|e| e.to_string().contains("UserUnsupportedVersion"))
+    {
+        return ErrorKind::Protocol;
+    }
+
+    if find_cause::<DnsError>(e).is_some() {
+        return ErrorKind::Dns;
+    }
+
+    // Before the upgrade and QUIC wrappers, and before reqwest's own timeouts, which
+    // a connect timeout would otherwise pass for
+    if find_cause::<ConnectTimeout>(e).is_some()
+        || find_cause::<reqwest::Error>(e).is_some_and(|e| e.is_connect() && e.is_timeout())
+    {
+        return ErrorKind::ConnectTimeout;
+    }
+
+    if find_cause::<UpgradeError>(e).is_some() {
+        return ErrorKind::Upgrade;
+    }
+
+    // Proxy CONNECT failures are formatted the same way, and only tell 407 apart
+    if let Some(kind) = tunnel_failure(e) {
+        return kind;
+    }
+
+    if find_cause::<quic::ConnectError>(e).is_some() {
+        return ErrorKind::Connect;
+    }
+
+    // The QUIC idle timeout, with traffic or `--h3-keepalive` PINGs left unacknowledged
+    // for that long, is h3's counterpart to hyper's keep-alive error
+    let connection = match find_cause::<h3::error::StreamError>(e) {
+        Some(h3::error::StreamError::ConnectionError { 0: connection, .. }) => Some(connection),
+        _ => find_cause::<h3::error::ConnectionError>(e),
+    };
+    if matches!(connection, Some(h3::error::ConnectionError::Timeout { .. })) {
+        return ErrorKind::KeepAlive;
+    }
+
+    // h3 won't send a header block past the server's MAX_FIELD_SECTION_SIZE
+    if matches!(find_cause::<h3::error::StreamError>(e), Some(h3::error::StreamError::HeaderTooBig { .. })) {
+        return ErrorKind::HeadersTooLarge;
+    }
+
+    match find_cause::<reqwest::Error>(e) {
+        Some(e) if e.is_timeout() => ErrorKind::Timeout,
+        Some(e) if e.is_connect() => ErrorKind::Connect,
+        _ => ErrorKind::Other,
+    }
+}
+
+fn tunnel_failure(e: &(dyn Error + 'static)) -> Option<ErrorKind> {
+    let mut source: Option<&(dyn Error + 'static)> = Some(e);
+    while let Some(err) = source {
+        match err.to_string().strip_prefix("tunnel error: ") {
+            Some("proxy authorization required") => return Some(ErrorKind::ProxyAuth),
+            // The tunnel couldn't be opened at all, e.g. the proxy is down
+            Some("failed to create underlying connection") => return None,
+            Some(_) => return Some(ErrorKind::Tunnel),
+            None => source = err.source(),
+        }
+    }
+    None
+}
+
+/// Whether `e` comes from this machine running out of file descriptors (EMFILE,
+/// ENFILE) or ephemeral ports (EADDRNOTAVAIL, EADDRINUSE)
+fn is_exhaustion(e: &(dyn Error + 'static)) -> bool {
+    // As in connection_failure, nested io::Errors hide their inner error from source()
+    let mut source: Option<&(dyn Error + 'static)> = Some(e);
+    while let Some(err) = source {
+        source = match err.downcast_ref::<std::io::Error>() {
+            Some(io) => {
+                #[cfg(unix)]
+                if let Some(libc::EMFILE | libc::ENFILE | libc::EADDRNOTAVAIL | libc::EADDRINUSE) = io.raw_os_error() {
+                    return true;
+                }
+                io.get_ref().map(|inner| inner as &(dyn Error + 'static))
+            }
+            None => err.source(),
+        };
+    }
+    false
+}
+
+/// What kept a request from reaching the server, for failures of `kind` that happen
+/// before it's sent; `None` for the rest
+pub fn connection_failure(kind: ErrorKind, e: &(dyn Error + 'static)) -> Option<&'static str> {
+    match kind {
+        ErrorKind::Dns => return Some("DNS lookup failed"),
+        ErrorKind::ProxyAuth => return Some("proxy wants authentication"),
+        ErrorKind::Tunnel => return Some("proxy tunnel failed"),
+        ErrorKind::Upgrade => return Some("h2c upgrade failed"),
+        ErrorKind::ConnectTimeout => return Some("connection timed out"),
+        ErrorKind::Connect => {}
+        _ => return None,
+    }
+    // rustls errors travel inside (nested) io::Errors, whose source() skips them
+    let mut source: Option<&(dyn Error + 'static)> = Some(e);
+    while let Some(err) = source {
+        if err.is::<rustls::Error>() {
+            return Some("TLS handshake failed");
+        }
+        if err.is::<quic::ConnectError>() {
+            return Some("QUIC handshake failed");
+        }
+        source = match err.downcast_ref::<std::io::Error>() {
+            Some(io) => match io.kind() {
+                std::io::ErrorKind::ConnectionRefused => return Some("connection refused"),
+                std::io::ErrorKind::TimedOut => return Some("connection timed out"),
+                _ => io.get_ref().map(|inner| inner as &(dyn Error + 'static)),
+            },
+            None => err.source(),
+        };
+    }
+    Some("connection failed")
+}
+
+/// The HTTP status a failed request carried, if any
+pub fn status(e: &(dyn Error + 'static)) -> Option<u16> {
+    find_cause::<reqwest::Error>(e)?.status().map(|s| s.as_u16())
+}
+
+/// Whether the server is guaranteed not to have processed the request, so it can
+/// be sent again on a new connection: a graceful GOAWAY or REFUSED_STREAM.
+///
+/// This mirrors the rule reqwest's default retry policy applies silently.
+pub fn is_safe_to_retry(e: &(dyn Error + 'static)) -> bool {
+    match find_cause::<h2::Error>(e) {
+        Some(h2) if h2.is_remote() => {
+            (h2.is_go_away() && h2.reason() == Some(h2::Reason::NO_ERROR))
+                || (h2.is_reset() && h2.reason() == Some(h2::Reason::REFUSED_STREAM))
+        }
+        _ => false,
+    }
+}
+
+/// Lock-free per-kind failure counters
+#[derive(Default)]
+pub struct ErrorCounts([AtomicU64; ErrorKind::ALL.len()]);
+
+impl ErrorCounts {
+    pub fn record(&self, kind: ErrorKind) {
+        self.0[kind as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Add `count` at once, e.g. another process's tally
+    pub fn add(&self, kind: ErrorKind, count: u64) {
+        self.0[kind as usize].fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, kind: ErrorKind) -> u64 {
+        self.0[kind as usize].load(Ordering::Relaxed)
+    }
+
+    pub fn total(&self) -> u64 {
+        ErrorKind::ALL.iter().map(|k| self.get(*k)).sum()
+    }
+
+    pub fn print(&self) {
+        if self.total() == 0 {
+            return;
+        }
+
+        println!("\n{}", "--- Errors ---".cyan().bold());
+        for kind in ErrorKind::ALL {
+            let count = self.get(kind);
+            if count > 0 {
+                render::print(format!("{}:", kind.label()).white(), count.to_string().red());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RebuildError;
+
+    #[test]
+    fn failed_client_rebuild_is_client_resources() {
+        let bind = std::io::Error::other("quinn endpoint could not be created");
+        let e = RebuildError(Box::new(bind));
+        assert_eq!(classify(&e), ErrorKind::ClientResources);
+        assert_eq!(connection_failure(ErrorKind::ClientResources, &e), None);
+    }
+
+    #[test]
+    fn our_own_frame_size_and_compression_errors_are_protocol() {
+        for reason in [h2::Reason::FRAME_SIZE_ERROR, h2::Reason::COMPRESSION_ERROR] {
+            assert_eq!(classify(&h2::Error::from(reason)), ErrorKind::Protocol, "{:?}", reason);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn descriptor_exhaustion_is_client_resources() {
+        let e = std::io::Error::from_raw_os_error(libc::EMFILE);
+        assert_eq!(classify(&e), ErrorKind::ClientResources);
+    }
+}
@@ -0,0 +1,276 @@
+//! HTTP/1.1 uploads with `Expect: 100-continue` for `--expect-continue`.
+//!
+//! hyper only reports interim responses to callers that register for them on the
+//! request itself, which reqwest has no way to do, so these requests go over our own
+//! hyper connections. As with quic.rs, requests are built with reqwest's builder and
+//! responses handed back as `reqwest::Response`.
+
+use crate::connect::{self, ConnectStats};
+use crate::dns::BenchResolver;
+use crate::transport::BoxError;
+use bytes::Bytes;
+use futures::task::AtomicWaker;
+use http::header::{EXPECT, HOST};
+use http::HeaderValue;
+use http_body::{Body, Frame, SizeHint};
+use hyper::client::conn::http1::SendRequest;
+use hyper_util::rt::TokioIo;
+use reqwest::dns::{Name, Resolve};
+use rustls::pki_types::ServerName;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::time::Sleep;
+use tokio_rustls::TlsConnector;
+
+/// How long to wait for 100 Continue before sending the body anyway, as curl does
+pub const CONTINUE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What came of a request's `Expect: 100-continue`, attached to its response
+#[derive(Clone, Copy, Debug)]
+pub enum Continue {
+    /// 100 Continue arrived this long after the request went out
+    Received(Duration),
+    /// Nothing arrived within [`CONTINUE_TIMEOUT`], so the body was sent anyway
+    TimedOut,
+    /// The final response came first, e.g. an early 413 or 417
+    Final,
+}
+
+/// `--expect-continue` outcomes that aren't per-request latencies
+#[derive(Default)]
+pub struct ContinueStats {
+    pub timed_out: AtomicU64,
+    pub final_first: AtomicU64,
+    /// 417 Expectation Failed responses
+    pub expectation_failed: AtomicU64,
+}
+
+impl ContinueStats {
+    /// Count `outcome` and the response's status, returning the wait for 100 Continue
+    pub fn record(&self, outcome: Continue, status: u16) -> Option<Duration> {
+        if status == 417 {
+            self.expectation_failed.fetch_add(1, Ordering::Relaxed);
+        }
+        match outcome {
+            Continue::Received(wait) => return Some(wait),
+            Continue::TimedOut => self.timed_out.fetch_add(1, Ordering::Relaxed),
+            Continue::Final => self.final_first.fetch_add(1, Ordering::Relaxed),
+        };
+        None
+    }
+}
+
+/// Shared by a request body and the interim response callback
+#[derive(Default)]
+struct Gate {
+    continued: OnceLock<Instant>,
+    timed_out: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl Gate {
+    fn outcome(&self, sent: Instant) -> Continue {
+        if self.timed_out.load(Ordering::Relaxed) {
+            Continue::TimedOut
+        } else if let Some(continued) = self.continued.get() {
+            Continue::Received(continued.saturating_duration_since(sent))
+        } else {
+            Continue::Final
+        }
+    }
+}
+
+/// A request body held back until 100 Continue arrives or [`CONTINUE_TIMEOUT`] passes
+struct GatedBody {
+    inner: reqwest::Body,
+    gate: Arc<Gate>,
+    timeout: Pin<Box<Sleep>>,
+    open: bool,
+}
+
+impl Body for GatedBody {
+    type Data = Bytes;
+    type Error = reqwest::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        if !self.open {
+            self.gate.waker.register(cx.waker());
+            if self.gate.continued.get().is_some() {
+                self.open = true;
+            } else if self.timeout.as_mut().poll(cx).is_ready() {
+                self.gate.timed_out.store(true, Ordering::Relaxed);
+                self.open = true;
+            } else {
+                return Poll::Pending;
+            }
+        }
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        // Keeps Content-Length on the request
+        self.inner.size_hint()
+    }
+}
+
+type Sender = SendRequest<GatedBody>;
+
+/// One worker's HTTP/1.1 connections, opened as needed and reused once a response
+/// has been read to the end
+pub struct ContinueClient {
+    tls: Option<Arc<rustls::ClientConfig>>,
+    resolver: Arc<BenchResolver>,
+    conn_stats: Arc<ConnectStats>,
+    connect_timeout: Duration,
+    idle: Arc<Mutex<Vec<Sender>>>,
+}
+
+impl ContinueClient {
+    pub fn new(
+        tls: Option<Arc<rustls::ClientConfig>>,
+        resolver: Arc<BenchResolver>,
+        conn_stats: Arc<ConnectStats>,
+        connect_timeout: Duration,
+    ) -> ContinueClient {
+        ContinueClient {
+            tls,
+            resolver,
+            conn_stats,
+            connect_timeout,
+            idle: Arc::default(),
+        }
+    }
+
+    /// An idle connection's sender, or a new connection's if none is left
+    async fn sender(&self, uri: &http::Uri) -> Result<Sender, BoxError> {
+        loop {
+            let Some(mut sender) = self.idle.lock().unwrap().pop() else {
+                break;
+            };
+            if sender.ready().await.is_ok() {
+                return Ok(sender);
+            }
+        }
+        self.connect(uri).await
+    }
+
+    async fn connect(&self, uri: &http::Uri) -> Result<Sender, BoxError> {
+        let host = uri.host().ok_or("URL has no host")?.trim_start_matches('[').trim_end_matches(']');
+        let port = uri.port_u16().unwrap_or(if self.tls.is_some() { 443 } else { 80 });
+        let addr = match IpAddr::from_str(host) {
+            Ok(ip) => SocketAddr::new(ip, port),
+            Err(_) => {
+                let mut addr = self
+                    .resolver
+                    .resolve(Name::from_str(host)?)
+                    .await?
+                    .next()
+                    .ok_or_else(|| format!("{} did not resolve to any address", host))?;
+                addr.set_port(port);
+                addr
+            }
+        };
+
+        let start = Instant::now();
+        let result = connect::within(self.connect_timeout, self.handshake(host, addr)).await;
+        match &result {
+            Ok(_) => self.conn_stats.record(start.elapsed(), Some(addr)),
+            Err(e) => {
+                tracing::info!(error = %e, "connection failed");
+                self.conn_stats.failed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+
+    async fn handshake(&self, host: &str, addr: SocketAddr) -> Result<Sender, BoxError> {
+        let tcp = tokio::net::TcpStream::connect(addr).await?;
+        tcp.set_nodelay(true)?;
+        let sender = match &self.tls {
+            Some(tls) => {
+                let server_name = ServerName::try_from(host.to_string())?;
+                let stream = TlsConnector::from(tls.clone()).connect(server_name, tcp).await?;
+                let (sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+                tokio::spawn(conn);
+                sender
+            }
+            None => {
+                let (sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(tcp)).await?;
+                tokio::spawn(conn);
+                sender
+            }
+        };
+        tracing::info!(remote = ?addr, "connection established");
+        Ok(sender)
+    }
+
+    pub async fn send(&self, request: reqwest::Request) -> Result<reqwest::Response, BoxError> {
+        let (mut parts, body) = http::Request::<reqwest::Body>::try_from(request)?.into_parts();
+        let mut sender = self.sender(&parts.uri).await?;
+
+        // hyper's connection-level client sends the URI as given and adds no Host
+        let authority = parts.uri.authority().ok_or("URL has no host")?.as_str();
+        parts.headers.insert(HOST, HeaderValue::from_str(authority)?);
+        parts.headers.insert(EXPECT, HeaderValue::from_static("100-continue"));
+        parts.uri = parts.uri.path_and_query().map_or("/", |p| p.as_str()).parse()?;
+
+        let gate = Arc::new(Gate::default());
+        let body = GatedBody {
+            inner: body,
+            gate: gate.clone(),
+            timeout: Box::pin(tokio::time::sleep(CONTINUE_TIMEOUT)),
+            open: false,
+        };
+        let mut request = http::Request::from_parts(parts, body);
+        let interim = gate.clone();
+        hyper::ext::on_informational(&mut request, move |response| {
+            if response.status() == http::StatusCode::CONTINUE {
+                let _ = interim.continued.set(Instant::now());
+                interim.waker.wake();
+            }
+        });
+
+        let sent = Instant::now();
+        let response = sender.send_request(request).await?;
+        let (mut parts, body) = response.into_parts();
+        parts.extensions.insert(gate.outcome(sent));
+        let body = ResponseBody {
+            inner: body,
+            sender: Some(sender),
+            idle: self.idle.clone(),
+        };
+        Ok(reqwest::Response::from(http::Response::from_parts(parts, reqwest::Body::wrap(body))))
+    }
+}
+
+/// A response body that hands its connection back for reuse once read to the end
+struct ResponseBody {
+    inner: hyper::body::Incoming,
+    sender: Option<Sender>,
+    idle: Arc<Mutex<Vec<Sender>>>,
+}
+
+impl Body for ResponseBody {
+    type Data = Bytes;
+    type Error = hyper::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        let frame = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(None) = frame {
+            if let Some(sender) = self.sender.take() {
+                self.idle.lock().unwrap().push(sender);
+            }
+        }
+        frame
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
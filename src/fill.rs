@@ -0,0 +1,137 @@
+//! `--fill eager|gradual[:DURATION]`: how each worker fills its pipeline at the start.
+//! Eager sends `-p` requests at once, so the first round trip carries `-c × -p`
+//! requests together; gradual starts each worker at one in flight and raises its depth
+//! evenly to `-p` over the duration.
+
+use std::time::{Duration, Instant};
+
+/// Ramp length for `--fill gradual` without a duration
+const DEFAULT_RAMP: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Fill {
+    #[default]
+    Eager,
+    Gradual(Duration),
+}
+
+pub fn parse(s: &str) -> Result<Fill, String> {
+    match s.split_once(':') {
+        None if s == "eager" => Ok(Fill::Eager),
+        None if s == "gradual" => Ok(Fill::Gradual(DEFAULT_RAMP)),
+        Some(("gradual", over)) => match humantime::parse_duration(over) {
+            Ok(over) if !over.is_zero() => Ok(Fill::Gradual(over)),
+            Ok(_) => Err("the gradual fill needs a duration longer than 0s".to_string()),
+            Err(e) => Err(format!("invalid duration {:?}: {}", over, e)),
+        },
+        _ => Err(format!("expected eager, gradual or gradual:DURATION, got {:?}", s)),
+    }
+}
+
+impl Fill {
+    /// A worker's ramp, if it has one, from `start` up to `pipeline`
+    pub fn ramp(self, pipeline: usize, start: Instant) -> Option<Ramp> {
+        match self {
+            Fill::Gradual(over) if pipeline > 1 => Some(Ramp { start, over, pipeline }),
+            _ => None,
+        }
+    }
+
+    pub fn describe(self) -> String {
+        match self {
+            Fill::Eager => "eager".to_string(),
+            Fill::Gradual(over) => format!("gradual over {}", humantime::format_duration(over)),
+        }
+    }
+}
+
+/// One worker's pipeline depth as it fills
+pub struct Ramp {
+    start: Instant,
+    over: Duration,
+    pipeline: usize,
+}
+
+impl Ramp {
+    /// Requests the worker may have in flight now: 1 at the start, `pipeline` once the
+    /// ramp is over
+    pub fn depth(&self) -> usize {
+        self.depth_after(self.start.elapsed())
+    }
+
+    /// The depth `elapsed` into the ramp
+    fn depth_after(&self, elapsed: Duration) -> usize {
+        let steps = (self.pipeline - 1) as f64 * elapsed.as_secs_f64() / self.over.as_secs_f64();
+        (1 + steps as usize).min(self.pipeline)
+    }
+
+    /// When the depth next goes up, or `None` once it is `pipeline`
+    pub fn next_step(&self) -> Option<Instant> {
+        self.next_step_after(self.start.elapsed()).map(|step| self.start + step)
+    }
+
+    /// How far into the ramp the depth next goes up, from `elapsed` into it
+    fn next_step_after(&self, elapsed: Duration) -> Option<Duration> {
+        let depth = self.depth_after(elapsed);
+        if depth >= self.pipeline {
+            return None;
+        }
+        Some(self.over.mul_f64(depth as f64 / (self.pipeline - 1) as f64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn parses_each_strategy() {
+        assert_eq!(parse("eager"), Ok(Fill::Eager));
+        assert_eq!(parse("gradual"), Ok(Fill::Gradual(DEFAULT_RAMP)));
+        assert_eq!(parse("gradual:500ms"), Ok(Fill::Gradual(ms(500))));
+        assert!(parse("gradual:0s").is_err());
+        assert!(parse("gradual:soon").is_err());
+        assert!(parse("lazy").is_err());
+    }
+
+    #[test]
+    fn only_a_gradual_fill_of_a_pipeline_ramps() {
+        let now = Instant::now();
+        assert!(Fill::Eager.ramp(8, now).is_none());
+        assert!(Fill::Gradual(ms(1000)).ramp(1, now).is_none());
+        assert!(Fill::Gradual(ms(1000)).ramp(2, now).is_some());
+    }
+
+    #[test]
+    fn depth_rises_evenly_from_one_to_the_pipeline() {
+        // -p 5 over 1s: one more every 250ms
+        let ramp = Fill::Gradual(ms(1000)).ramp(5, Instant::now()).unwrap();
+        let depths: Vec<usize> = [0, 100, 249, 250, 499, 500, 750, 999, 1000, 5000]
+            .into_iter()
+            .map(|elapsed| ramp.depth_after(ms(elapsed)))
+            .collect();
+        assert_eq!(depths, [1, 1, 1, 2, 2, 3, 4, 4, 5, 5]);
+    }
+
+    #[test]
+    fn each_step_comes_when_the_depth_goes_up() {
+        let ramp = Fill::Gradual(ms(1000)).ramp(5, Instant::now()).unwrap();
+        assert_eq!(ramp.next_step_after(ms(0)), Some(ms(250)));
+        assert_eq!(ramp.next_step_after(ms(300)), Some(ms(500)));
+        assert_eq!(ramp.next_step_after(ms(999)), Some(ms(1000)));
+        assert_eq!(ramp.next_step_after(ms(1000)), None);
+
+        // Waiting until each step finds the depth one higher, all the way up
+        let mut elapsed = Duration::ZERO;
+        let mut depths = vec![ramp.depth_after(elapsed)];
+        while let Some(step) = ramp.next_step_after(elapsed) {
+            elapsed = step;
+            depths.push(ramp.depth_after(elapsed));
+        }
+        assert_eq!(depths, [1, 2, 3, 4, 5]);
+    }
+}
@@ -0,0 +1,111 @@
+//! `--abort-if-rps-below RPS --for DURATION`: stop a long run as soon as throughput
+//! stays under a floor for a whole window, instead of finding out from `--min-rps` at
+//! the end.
+//!
+//! The sampler checks after each one-second window, over the last `--for` of them.
+//! Only windows the run wasn't paused in count, and never the first, while connections
+//! open. Nothing is checked once `--ramp-down` begins or every request of `-n` has gone
+//! out, when throughput falls on purpose.
+
+use crate::progress::Throughput;
+use crate::thresholds::Evaluation;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// The floor and the lowest windowed rate seen so far
+pub struct RpsFloor {
+    min: f64,
+    /// In one-second windows
+    window: usize,
+    lowest: Option<f64>,
+}
+
+/// Where throughput first fell below the floor
+#[derive(Clone)]
+pub struct Breach {
+    /// From the start of the run to the end of the window
+    pub at: Duration,
+    pub window: Duration,
+    pub rps: f64,
+    pub min: f64,
+    /// Completions in each second of the run up to the breach
+    pub series: Vec<u64>,
+}
+
+impl RpsFloor {
+    pub fn new(min: f64, window: Duration) -> Self {
+        RpsFloor {
+            min,
+            window: window.as_secs() as usize,
+            lowest: None,
+        }
+    }
+
+    /// Check the last `--for` of `throughput`, `elapsed` into the run: the breach, if
+    /// they fell short
+    pub fn check(&mut self, throughput: &Throughput, elapsed: Duration) -> Option<Breach> {
+        let recent = throughput.last_active(self.window)?;
+        let rps = recent.iter().sum::<u64>() as f64 / self.window as f64;
+        self.lowest = Some(self.lowest.map_or(rps, |lowest| lowest.min(rps)));
+        (rps < self.min).then(|| Breach {
+            at: elapsed,
+            window: Duration::from_secs(self.window as u64),
+            rps,
+            min: self.min,
+            series: throughput.series().to_vec(),
+        })
+    }
+
+    /// The floor as a threshold, next to `--min-rps` and the others
+    pub fn evaluation(&self, breach: Option<&Breach>) -> Evaluation {
+        let measured = match (breach, self.lowest) {
+            (Some(breach), _) => format!("{:.2} in the {}s ending at {:.0?}", breach.rps, self.window, breach.at),
+            (None, Some(lowest)) => format!("lowest {:.2} over {}s", lowest, self.window),
+            (None, None) => format!("not checked, no full {}s window", self.window),
+        };
+        Evaluation {
+            name: "rps floor",
+            passed: breach.is_none(),
+            measured,
+            required: format!(">= {:.2} over any {}s", self.min, self.window),
+        }
+    }
+}
+
+impl Breach {
+    /// What the sampler says when it stops the run
+    pub fn message(&self) -> String {
+        format!(
+            "{:.1} req/s in the {}s ending at {:.0?}, below --abort-if-rps-below {}; stopping the run",
+            self.rps,
+            self.window.as_secs(),
+            self.at,
+            self.min
+        )
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "at_secs": self.at.as_secs_f64(),
+            "window_secs": self.window.as_secs(),
+            "rps": self.rps,
+            "min_rps": self.min,
+            "completed_per_sec": self.series,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Option<Breach> {
+        Some(Breach {
+            at: Duration::from_secs_f64(value.get("at_secs")?.as_f64()?),
+            window: Duration::from_secs(value.get("window_secs")?.as_u64()?),
+            rps: value.get("rps")?.as_f64()?,
+            min: value.get("min_rps")?.as_f64()?,
+            series: value
+                .get("completed_per_sec")?
+                .as_array()?
+                .iter()
+                .map(Value::as_u64)
+                .collect::<Option<Vec<u64>>>()?,
+        })
+    }
+}
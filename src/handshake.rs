@@ -0,0 +1,208 @@
+use crate::render;
+use crate::transport::{self, Handshake, Target, TlsStats};
+use crate::{error_chain, print_error_details, print_percentiles, Args, ErrorDetails, Percentiles};
+use colored::Colorize;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct WorkerResult {
+    durations: Vec<Duration>,
+    resumed: u64,
+    failed: u64,
+    zero_rtt_accepted: u64,
+    zero_rtt_rejected: u64,
+    first_error: Option<ErrorDetails>,
+}
+
+/// Per-worker connection state: each worker owns its TLS config (and therefore its
+/// session cache), like an independent client would.
+enum Connector {
+    Tcp(Option<Arc<rustls::ClientConfig>>),
+    Quic {
+        endpoint: quinn::Endpoint,
+        config: quinn::ClientConfig,
+        stats: Arc<TlsStats>,
+    },
+}
+
+impl Connector {
+    fn new(args: &Args, target: &Target) -> Result<Self, transport::BoxError> {
+        let stats = Arc::new(TlsStats::default());
+        let resumption = !args.no_session_resumption;
+
+        if args.http3 {
+            let tls = transport::tls_config(&[b"h3"], args.insecure, resumption, &stats)?;
+            let tuning = args.quic_tuning();
+            let bind = if target.addr.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+            Ok(Connector::Quic {
+                endpoint: tuning.endpoint(bind.parse()?)?,
+                config: tuning.client_config(tls)?,
+                stats,
+            })
+        } else if target.is_https {
+            let tls = transport::tls_config(&[b"h2"], args.insecure, resumption, &stats)?;
+            Ok(Connector::Tcp(Some(tls)))
+        } else {
+            Ok(Connector::Tcp(None))
+        }
+    }
+
+    async fn handshake(&self, target: &Target, liveness: bool) -> Result<Handshake, transport::BoxError> {
+        match self {
+            Connector::Tcp(tls) => transport::handshake_tcp(target, tls.as_ref(), liveness).await,
+            Connector::Quic { endpoint, config, stats } => {
+                transport::handshake_quic(endpoint, config, stats, target, liveness).await
+            }
+        }
+    }
+
+    async fn finish(self, result: &mut WorkerResult) {
+        if let Connector::Quic { endpoint, stats, .. } = self {
+            result.zero_rtt_accepted = stats.zero_rtt_accepted.load(Ordering::Relaxed);
+            result.zero_rtt_rejected = stats.zero_rtt_rejected.load(Ordering::Relaxed);
+            // Let the CONNECTION_CLOSE frames go out before the socket is dropped
+            endpoint.wait_idle().await;
+        }
+    }
+}
+
+/// Run `--handshake-only`: every "request" is a fresh connection that is closed
+/// as soon as the handshake (and optional liveness request) completes.
+pub async fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let target = Arc::new(Target::resolve(&args.url).await.map_err(|e| e.to_string())?);
+
+    let kind = match (args.http3, target.is_https) {
+        (true, _) => "QUIC",
+        (false, true) => "TCP+TLS",
+        (false, false) => "TCP",
+    };
+
+    let details = format!(
+        "({} handshakes) with {} connections, {} total handshakes",
+        kind.magenta(),
+        args.connections.to_string().green(),
+        args.requests.to_string().green()
+    );
+    println!("{}", render::banner(&args.url, details));
+    if args.http3 {
+        render::print("QUIC:".white(), args.quic_tuning().describe());
+    }
+
+    let abort_flag = Arc::new(AtomicBool::new(false));
+    let reqs_per_worker = args.requests / args.connections as u64;
+    let remainder = args.requests % args.connections as u64;
+
+    // Build every connector up front so configuration errors surface before the timer starts
+    let mut connectors = Vec::with_capacity(args.connections);
+    for _ in 0..args.connections {
+        connectors.push(Connector::new(&args, &target).map_err(|e| e.to_string())?);
+    }
+
+    let start = Instant::now();
+    let mut handles = Vec::with_capacity(args.connections);
+
+    for (i, connector) in connectors.into_iter().enumerate() {
+        let target = target.clone();
+        let abort_flag = abort_flag.clone();
+        let fail_fast = args.fail_fast;
+        let liveness = args.handshake_liveness;
+        let my_reqs = reqs_per_worker + if (i as u64) < remainder { 1 } else { 0 };
+
+        handles.push(tokio::spawn(async move {
+            let mut result = WorkerResult::default();
+
+            for _ in 0..my_reqs {
+                if abort_flag.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                match connector.handshake(&target, liveness).await {
+                    Ok(hs) => {
+                        result.durations.push(hs.duration);
+                        if hs.resumed {
+                            result.resumed += 1;
+                        }
+                    }
+                    Err(e) => {
+                        result.failed += 1;
+                        if fail_fast {
+                            result.first_error = Some(ErrorDetails {
+                                message: error_chain(e.as_ref()),
+                                status: None,
+                                headers: None,
+                                body: None,
+                                trailers: None,
+                            });
+                            abort_flag.store(true, Ordering::Relaxed);
+                            break;
+                        }
+                    }
+                }
+            }
+
+            connector.finish(&mut result).await;
+            result
+        }));
+    }
+
+    let mut durations = Vec::with_capacity(args.requests as usize);
+    let mut resumed = 0u64;
+    let mut failed = 0u64;
+    let mut zero_rtt_accepted = 0u64;
+    let mut zero_rtt_rejected = 0u64;
+    let mut first_error = None;
+
+    for h in handles {
+        let result = h.await?;
+        durations.extend(result.durations);
+        resumed += result.resumed;
+        failed += result.failed;
+        zero_rtt_accepted += result.zero_rtt_accepted;
+        zero_rtt_rejected += result.zero_rtt_rejected;
+        if first_error.is_none() {
+            first_error = result.first_error;
+        }
+    }
+
+    let total_time = start.elapsed();
+
+    if let Some(err) = first_error {
+        print_error_details(&err);
+        std::process::exit(1);
+    }
+
+    let success = durations.len() as u64;
+    let hps = success as f64 / total_time.as_secs_f64();
+
+    println!("\n{}", "--- Results ---".cyan().bold());
+    render::print("Total time:".white(), format!("{:?}", total_time));
+    render::print("Handshakes/s:".white(), format!("{:.2}", hps).green().bold());
+    render::print("Success:".white(), success.to_string().green());
+    if failed > 0 {
+        render::print("Failed:".white(), failed.to_string().red().bold());
+    } else {
+        render::print("Failed:".white(), "0".dimmed());
+    }
+    if target.is_https || args.http3 {
+        render::print("Full:".white(), (success - resumed).to_string().green());
+        render::print("Resumed:".white(), resumed.to_string().green());
+    }
+    if args.http3 && zero_rtt_accepted + zero_rtt_rejected > 0 {
+        render::print(
+            "0-RTT:".white(),
+            format!(
+                "{} accepted, {} rejected",
+                zero_rtt_accepted.to_string().green(),
+                zero_rtt_rejected.to_string().yellow()
+            ),
+        );
+    }
+
+    let handshakes = Percentiles::from_unsorted(&mut durations);
+    print_percentiles("Handshake", &handshakes, args.latency_unit.resolve(handshakes.p50));
+
+    Ok(())
+}
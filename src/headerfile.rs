@@ -0,0 +1,167 @@
+//! `--header-file`: one extra request header per line, each request sending the next
+//! (or, with `--header-order random`, a seeded random) line, e.g. to spread a run over
+//! thousands of API keys or tenants. With `--group-by-header-sent` the results break
+//! down failures per line, to spot the tenants being throttled.
+
+use crate::render::{self, Table};
+use crate::units::LatencyUnit;
+use crate::{thousands, Percentiles};
+use clap::ValueEnum;
+use colored::Colorize;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use reqwest::header::{HeaderName, HeaderValue};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Mixed into the seed so header picks don't shift body or URL picks
+const HEADER_STREAM: u64 = 0x5851_f42d_4c95_7f2d;
+
+/// Lines `--group-by-header-sent` lists, most failures first
+const TOP_LINES: usize = 20;
+
+/// Which line a request sends (`--header-order`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum HeaderOrder {
+    /// Each line in turn, across all connections
+    #[default]
+    RoundRobin,
+    /// Uniformly at random, seeded per connection (see --seed)
+    Random,
+}
+
+/// The lines of `--header-file`, checked once at startup
+pub struct HeaderFile {
+    headers: Vec<(HeaderName, HeaderValue)>,
+    order: HeaderOrder,
+    /// Shared by every worker, so round-robin takes no lock
+    next: AtomicUsize,
+}
+
+impl HeaderFile {
+    /// `name: value` per line; blank lines and `#` comments are skipped
+    pub fn load(path: &Path, order: HeaderOrder) -> Result<HeaderFile, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let mut headers = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |what: String| format!("{}:{}: {}", path.display(), n + 1, what);
+            let (name, value) = line.split_once(':').ok_or_else(|| invalid("expected NAME: VALUE".into()))?;
+            let name = HeaderName::from_bytes(name.trim().as_bytes())
+                .map_err(|_| invalid(format!("invalid header name {:?}", name.trim())))?;
+            let value = HeaderValue::from_str(value.trim())
+                .map_err(|_| invalid(format!("invalid value for {}", name)))?;
+            headers.push((name, value));
+        }
+        if headers.is_empty() {
+            return Err(format!("{} lists no headers", path.display()));
+        }
+        Ok(HeaderFile {
+            headers,
+            order,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn header(&self, index: usize) -> (&HeaderName, &HeaderValue) {
+        let (name, value) = &self.headers[index];
+        (name, value)
+    }
+
+    /// Index of the line the next request sends
+    pub fn pick(&self, rng: &mut SmallRng) -> usize {
+        match self.order {
+            HeaderOrder::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed) % self.headers.len(),
+            HeaderOrder::Random => rng.gen_range(0..self.headers.len()),
+        }
+    }
+
+    /// For the banner, e.g. `4,000 lines, round-robin`
+    pub fn describe(&self) -> String {
+        let order = match self.order {
+            HeaderOrder::RoundRobin => "round-robin",
+            HeaderOrder::Random => "random",
+        };
+        format!("{} lines, {}", thousands(self.headers.len() as u64), order)
+    }
+}
+
+/// One worker's header picks under `--header-order random`, seeded from `--seed` plus
+/// the worker index
+pub fn picker(seed: u64, worker: usize) -> SmallRng {
+    SmallRng::seed_from_u64(seed.wrapping_add(worker as u64) ^ HEADER_STREAM)
+}
+
+/// `--group-by-header-sent`: requests, failures and latency per line sent
+pub struct SentGroups {
+    lines: Vec<SentGroup>,
+}
+
+#[derive(Default)]
+struct SentGroup {
+    failed: u64,
+    latencies: Vec<Duration>,
+}
+
+impl SentGroups {
+    pub fn new(lines: usize) -> SentGroups {
+        SentGroups {
+            lines: (0..lines).map(|_| SentGroup::default()).collect(),
+        }
+    }
+
+    pub fn record(&mut self, line: usize, failed: bool, latency: Duration) {
+        let group = &mut self.lines[line];
+        group.failed += failed as u64;
+        group.latencies.push(latency);
+    }
+
+    /// The lines with the most failures, then the rest summed up
+    pub fn print(&mut self, file: &HeaderFile, unit: LatencyUnit) {
+        let mut rows: Vec<(usize, &mut SentGroup)> =
+            self.lines.iter_mut().enumerate().filter(|(_, group)| !group.latencies.is_empty()).collect();
+        if rows.is_empty() {
+            return;
+        }
+        let rate = |group: &SentGroup| group.failed as f64 / group.latencies.len() as f64;
+        rows.sort_by(|a, b| rate(b.1).total_cmp(&rate(a.1)).then(b.1.failed.cmp(&a.1.failed)).then(a.0.cmp(&b.0)));
+
+        println!("\n{}", "--- By Header Sent ---".cyan().bold());
+        let mut table = Table::new(["Requests", "Failed", "P50", "P99", "Header"]);
+        let rest = rows.split_off(rows.len().min(TOP_LINES));
+        for (line, group) in rows {
+            let p = Percentiles::from_unsorted(&mut group.latencies);
+            let failed = format!("{:.2}%", rate(group) * 100.0);
+            let (name, value) = file.header(line);
+            table.row([
+                p.count.to_string(),
+                if group.failed > 0 { failed.red().to_string() } else { failed },
+                unit.format(p.p50),
+                unit.format(p.p99),
+                format!("{}: {}", name, value.to_str().unwrap_or("(binary)")),
+            ]);
+        }
+        table.print();
+        if !rest.is_empty() {
+            let requests: usize = rest.iter().map(|(_, group)| group.latencies.len()).sum();
+            let failed: u64 = rest.iter().map(|(_, group)| group.failed).sum();
+            render::print(
+                "Others:".white(),
+                format!(
+                    "{} lines, {} requests, {} failed",
+                    thousands(rest.len() as u64),
+                    thousands(requests as u64),
+                    thousands(failed)
+                ),
+            );
+        }
+    }
+}
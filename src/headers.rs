@@ -0,0 +1,174 @@
+use crate::render::{self, Table};
+use crate::units::LatencyUnit;
+use crate::Percentiles;
+use colored::Colorize;
+use regex::Regex;
+use reqwest::header::{HeaderMap, HeaderName};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How many distinct values `--collect-header` prints
+const TOP_VALUES: usize = 10;
+
+/// Distinct `--group-by-header` values kept before the rest share one group
+const MAX_GROUPS: usize = 32;
+
+#[derive(Clone, Debug)]
+enum Matcher {
+    Exact(String),
+    Regex(Regex),
+}
+
+/// A `--assert-header` check: `name: value` for an exact match, or
+/// `name: /pattern/` for a regex match
+#[derive(Clone, Debug)]
+pub struct HeaderAssertion {
+    name: HeaderName,
+    matcher: Matcher,
+}
+
+impl HeaderAssertion {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (name, value) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected 'name: value', got '{}'", s))?;
+        let name = HeaderName::try_from(name.trim()).map_err(|e| format!("invalid header name: {}", e))?;
+
+        let value = value.trim();
+        let matcher = match value.strip_prefix('/').and_then(|v| v.strip_suffix('/')) {
+            Some(pattern) => Matcher::Regex(Regex::new(pattern).map_err(|e| e.to_string())?),
+            None => Matcher::Exact(value.to_string()),
+        };
+
+        Ok(HeaderAssertion { name, matcher })
+    }
+
+    /// Describe why `headers` fail this assertion, if they do
+    pub fn check(&self, headers: &HeaderMap) -> Option<String> {
+        self.check_in(headers, "header")
+    }
+
+    /// As [`check`](Self::check), for `--assert-trailer`; a response that ended
+    /// without trailers fails
+    pub fn check_trailers(&self, trailers: Option<&HeaderMap>) -> Option<String> {
+        match trailers {
+            Some(trailers) => self.check_in(trailers, "trailer"),
+            None => Some(format!("trailer {} missing, response had no trailers", self.name)),
+        }
+    }
+
+    fn check_in(&self, headers: &HeaderMap, what: &str) -> Option<String> {
+        let Some(value) = headers.get(&self.name) else {
+            return Some(format!("{} {} missing", what, self.name));
+        };
+        let value = String::from_utf8_lossy(value.as_bytes());
+
+        let matches = match &self.matcher {
+            Matcher::Exact(expected) => value == expected.as_str(),
+            Matcher::Regex(re) => re.is_match(&value),
+        };
+        if matches {
+            return None;
+        }
+
+        let expected = match &self.matcher {
+            Matcher::Exact(expected) => expected.clone(),
+            Matcher::Regex(re) => format!("/{}/", re.as_str()),
+        };
+        Some(format!("{} {}: expected {}, got {}", what, self.name, expected, value))
+    }
+}
+
+/// Distribution of one header's values across every response (`--collect-header`)
+pub struct HeaderTally {
+    name: HeaderName,
+    values: Mutex<HashMap<String, u64>>,
+}
+
+impl HeaderTally {
+    pub fn new(name: HeaderName) -> Self {
+        HeaderTally {
+            name,
+            values: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, headers: &HeaderMap) {
+        let value = match headers.get(&self.name) {
+            Some(v) => String::from_utf8_lossy(v.as_bytes()).into_owned(),
+            None => "(absent)".to_string(),
+        };
+        *self.values.lock().unwrap().entry(value).or_default() += 1;
+    }
+
+    pub fn print(&self) {
+        let values = self.values.lock().unwrap();
+        let total: u64 = values.values().sum();
+        if total == 0 {
+            return;
+        }
+
+        let mut counts: Vec<(&String, &u64)> = values.iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+        println!("\n{}", format!("--- Header: {} ---", self.name).cyan().bold());
+        for (value, count) in counts.iter().take(TOP_VALUES) {
+            render::print(
+                format!("{}:", value).white(),
+                format!("{} ({:.1}%)", count.to_string().green(), **count as f64 * 100.0 / total as f64),
+            );
+        }
+        if counts.len() > TOP_VALUES {
+            render::print("...".white(), format!("{} more values", counts.len() - TOP_VALUES).dimmed());
+        }
+    }
+}
+
+/// Latencies split by the value of the `--group-by-header` header, owned by the
+/// collector so no locking is needed
+#[derive(Default)]
+pub struct LatencyGroups {
+    groups: HashMap<String, Vec<Duration>>,
+}
+
+impl LatencyGroups {
+    pub fn record(&mut self, value: String, latency: Duration) {
+        let key = if self.groups.len() >= MAX_GROUPS && !self.groups.contains_key(&value) {
+            "(other)".to_string()
+        } else {
+            value
+        };
+        self.groups.entry(key).or_default().push(latency);
+    }
+
+    /// Each value's percentiles, the most requests first
+    pub fn percentiles(mut self) -> Vec<(String, Percentiles)> {
+        let mut rows: Vec<(String, Percentiles)> = self
+            .groups
+            .iter_mut()
+            .map(|(value, latencies)| (value.clone(), Percentiles::from_unsorted(latencies)))
+            .collect();
+        rows.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(&b.0)));
+        rows
+    }
+}
+
+/// The `--group-by-header` table, from [`LatencyGroups::percentiles`]
+pub fn print_groups(header: &str, rows: &[(String, Percentiles)], unit: LatencyUnit) {
+    if rows.is_empty() {
+        return;
+    }
+
+    println!("\n{}", format!("--- Latency by {} ---", header).cyan().bold());
+    let mut table = Table::new(["Value", "Count", "P50", "P99"]);
+    for (value, p) in rows {
+        table.row([
+            format!("{}:", value).white().to_string(),
+            p.count.to_string(),
+            unit.format(p.p50),
+            unit.format(p.p99).red().to_string(),
+        ]);
+    }
+    table.print();
+}
@@ -0,0 +1,156 @@
+//! `--heatmap PATH`: latency over time, as the number of responses in each one-second
+//! window (the sampler's windows, by completion) and each latency bucket, so a stall
+//! shows up where it happened rather than as a fatter tail.
+//!
+//! The buckets are fixed, 1-2-5 steps from 100µs to 50s, so every window's column
+//! lines up with the others and with other runs. PATH ending in `.json` gets JSON,
+//! anything else CSV; both give each window's start as a wall-clock time too. The results also draw it in the terminal, when it is wide enough.
+
+use colored::Colorize;
+use serde_json::{json, Value};
+use std::io::Write;
+use crate::clock;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Upper bounds of every bucket but the last, which is open-ended, in microseconds
+const BOUNDS_US: [u64; 18] = [
+    100, 200, 500, 1_000, 2_000, 5_000, 10_000, 20_000, 50_000, 100_000, 200_000, 500_000, 1_000_000, 2_000_000,
+    5_000_000, 10_000_000, 20_000_000, 50_000_000,
+];
+
+const BUCKETS: usize = BOUNDS_US.len() + 1;
+
+/// Columns the terminal needs past the row labels for the heatmap to be drawn
+const MIN_COLUMNS: usize = 20;
+
+/// Cell shades, from one response to the busiest cell
+const SHADES: [char; 4] = ['░', '▒', '▓', '█'];
+
+/// Counts per one-second window and latency bucket, owned by the collector
+#[derive(Default)]
+pub struct Heatmap {
+    windows: Vec<[u64; BUCKETS]>,
+}
+
+impl Heatmap {
+    /// Count a response that completed `at` into the run
+    pub fn record(&mut self, at: Duration, latency: Duration) {
+        let window = at.as_secs() as usize;
+        if self.windows.len() <= window {
+            self.windows.resize(window + 1, [0; BUCKETS]);
+        }
+        let us = latency.as_micros().min(u64::MAX as u128) as u64;
+        self.windows[window][BOUNDS_US.partition_point(|&bound| bound <= us)] += 1;
+    }
+
+    /// `started` is when the first window began
+    pub fn write(&self, path: &Path, started: SystemTime) -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let at = |window: usize| clock::rfc3339(started + Duration::from_secs(window as u64));
+        if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::to_writer_pretty(&mut file, &self.to_json(at))?;
+        } else {
+            // The wall-clock column last, so the bucket columns keep their places
+            let labels: Vec<String> = (0..BUCKETS).map(label).collect();
+            writeln!(file, "start_secs,{},start_time", labels.join(","))?;
+            for (start, counts) in self.windows.iter().enumerate() {
+                let counts: Vec<String> = counts.iter().map(u64::to_string).collect();
+                writeln!(file, "{},{},{}", start, counts.join(","), at(start))?;
+            }
+        }
+        file.flush()
+    }
+
+    fn to_json(&self, at: impl Fn(usize) -> String) -> Value {
+        let buckets: Vec<Value> = (0..BUCKETS)
+            .map(|bucket| {
+                json!({
+                    "min_us": bucket.checked_sub(1).map_or(0, |below| BOUNDS_US[below]),
+                    "max_us": BOUNDS_US.get(bucket),
+                })
+            })
+            .collect();
+        let windows: Vec<Value> = self
+            .windows
+            .iter()
+            .enumerate()
+            .map(|(start, counts)| json!({ "start_secs": start, "start_time": at(start), "counts": counts.to_vec() }))
+            .collect();
+        json!({ "window_secs": 1, "buckets": buckets, "windows": windows })
+    }
+
+    /// The `--- Latency Heatmap ---` section: slowest bucket at the top, time left to
+    /// right, neighbouring windows merged to fit `width`; nothing if it's too narrow
+    pub fn print(&self, width: Option<usize>) {
+        let used: Vec<usize> = (0..BUCKETS).filter(|&b| self.windows.iter().any(|w| w[b] > 0)).collect();
+        let (Some(&fastest), Some(&slowest)) = (used.first(), used.last()) else {
+            return;
+        };
+        let label_width = 12;
+        let Some(columns) = width.and_then(|w| w.checked_sub(label_width + 1)).filter(|&c| c >= MIN_COLUMNS) else {
+            return;
+        };
+
+        let per_column = self.windows.len().div_ceil(columns);
+        let merged: Vec<[u64; BUCKETS]> = self
+            .windows
+            .chunks(per_column)
+            .map(|chunk| {
+                let mut counts = [0; BUCKETS];
+                for window in chunk {
+                    for (sum, n) in counts.iter_mut().zip(window) {
+                        *sum += n;
+                    }
+                }
+                counts
+            })
+            .collect();
+        let busiest = merged.iter().flat_map(|c| c.iter()).copied().max().unwrap_or(0);
+
+        println!("\n{}", "--- Latency Heatmap ---".cyan().bold());
+        for bucket in (fastest..=slowest).rev() {
+            // Shade on a log scale, so a handful of slow responses still shows
+            let row: String = merged
+                .iter()
+                .map(|counts| match counts[bucket] {
+                    0 => ' ',
+                    n => {
+                        let level = (n as f64).ln_1p() / (busiest as f64).ln_1p() * SHADES.len() as f64;
+                        SHADES[(level.ceil() as usize).clamp(1, SHADES.len()) - 1]
+                    }
+                })
+                .collect();
+            println!("{:>w$} {}", label(bucket).white(), row, w = label_width);
+        }
+        let seconds = if per_column == 1 {
+            "1s per column".to_string()
+        } else {
+            format!("{}s per column", per_column)
+        };
+        println!(
+            "{:>w$} {}",
+            "",
+            format!("0s to {}s, {}", self.windows.len(), seconds).dimmed(),
+            w = label_width
+        );
+    }
+}
+
+/// A bucket's range, e.g. `100us-200us` or `>=50s`
+fn label(bucket: usize) -> String {
+    match (bucket.checked_sub(1).map(|below| BOUNDS_US[below]), BOUNDS_US.get(bucket)) {
+        (None, Some(&max)) => format!("<{}", micros(max)),
+        (Some(min), Some(&max)) => format!("{}-{}", micros(min), micros(max)),
+        (Some(min), None) => format!(">={}", micros(min)),
+        (None, None) => unreachable!("there is more than one bucket"),
+    }
+}
+
+fn micros(us: u64) -> String {
+    match us {
+        us if us >= 1_000_000 => format!("{}s", us / 1_000_000),
+        us if us >= 1_000 => format!("{}ms", us / 1_000),
+        us => format!("{}us", us),
+    }
+}
@@ -0,0 +1,215 @@
+//! `--hol-probe slow_url=URL,small_url=URL`: head-of-line blocking on one connection.
+//! Each connection gives one stream to the large, slow object and fills its other
+//! pipeline slots with the small one, and the small requests are split by whether a
+//! slow transfer was in flight on their connection at any point while they were. After
+//! each slow transfer its stream rests as long as the transfer took, sending small
+//! requests meanwhile, so both kinds are measured side by side.
+//! Over HTTP/2 a stalled TCP segment holds back every stream behind it; over HTTP/3
+//! only the stream it belongs to, so the gap between the two rows is the HOL cost.
+
+use crate::render::{self, Table};
+use crate::units::LatencyUnit;
+use crate::Percentiles;
+use colored::Colorize;
+use reqwest::Url;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// URL index of the slow object
+pub const SLOW: usize = 0;
+/// URL index of the small object
+pub const SMALL: usize = 1;
+
+/// `slow_url=URL,small_url=URL` as given; each may be a path, resolved against URL
+#[derive(Clone, Debug)]
+pub struct Targets {
+    slow: String,
+    small: String,
+}
+
+pub fn parse(s: &str) -> Result<Targets, String> {
+    let (mut slow, mut small) = (None, None);
+    for part in s.split(',') {
+        match part.split_once('=') {
+            Some(("slow_url", url)) if !url.is_empty() => slow = Some(url.to_string()),
+            Some(("small_url", url)) if !url.is_empty() => small = Some(url.to_string()),
+            _ => return Err(format!("expected slow_url=URL,small_url=URL, got {:?}", part)),
+        }
+    }
+    match (slow, small) {
+        (Some(slow), Some(small)) => Ok(Targets { slow, small }),
+        _ => Err("both slow_url and small_url are needed".to_string()),
+    }
+}
+
+/// The two URLs, resolved
+pub struct HolProbe {
+    urls: [String; 2],
+}
+
+impl HolProbe {
+    /// Both on `base`'s origin, so they share its connections
+    pub fn new(targets: &Targets, base: &str) -> Result<HolProbe, String> {
+        let base = Url::parse(base).map_err(|e| e.to_string())?;
+        let resolve = |url: &str| {
+            let resolved = base.join(url).map_err(|e| format!("--hol-probe: invalid URL {:?}: {}", url, e))?;
+            if resolved.origin() != base.origin() {
+                return Err(format!(
+                    "--hol-probe: {} is not on the target's origin {}, so it wouldn't share its connections",
+                    resolved,
+                    base.origin().ascii_serialization()
+                ));
+            }
+            Ok(resolved.to_string())
+        };
+        Ok(HolProbe {
+            urls: [resolve(&targets.slow)?, resolve(&targets.small)?],
+        })
+    }
+
+    pub fn url(&self, index: usize) -> &str {
+        &self.urls[index]
+    }
+
+    pub fn describe(&self) -> String {
+        format!("1 stream per connection on {}, the rest on {}", self.urls[SLOW], self.urls[SMALL])
+    }
+}
+
+/// One worker's connection: whether its slow stream is busy, and which of its small
+/// requests overlapped a slow transfer
+#[derive(Default)]
+pub struct Connection {
+    /// The slow request in flight, and when it went out
+    slow: Option<(u64, Instant)>,
+    /// No slow request goes out before then
+    rest_until: Option<Instant>,
+    /// Slow requests sent so far, to tell whether one came and went during a small one
+    slow_sent: u64,
+    /// Small requests in flight by submission index: the slow count when they went out,
+    /// and whether a slow transfer was in flight then
+    small: HashMap<u64, (u64, bool)>,
+}
+
+impl Connection {
+    /// The URL index for the next request: the slow object unless one is in flight or
+    /// its stream is resting
+    pub fn pick(&self) -> usize {
+        if self.slow.is_none() && self.rest_until.is_none_or(|until| Instant::now() >= until) {
+            SLOW
+        } else {
+            SMALL
+        }
+    }
+
+    /// Request `seq` for `url` went out
+    pub fn sent(&mut self, seq: u64, url: usize) {
+        if url == SLOW {
+            self.slow = Some((seq, Instant::now()));
+            self.slow_sent += 1;
+        } else {
+            self.small.insert(seq, (self.slow_sent, self.slow.is_some()));
+        }
+    }
+
+    /// Request `seq` ended, however it did: for a small request, whether a slow
+    /// transfer was in flight at any point while it was
+    pub fn done(&mut self, seq: u64) -> bool {
+        if let Some((_, sent)) = self.slow.filter(|&(slow, _)| slow == seq) {
+            self.slow = None;
+            self.rest_until = Some(Instant::now() + sent.elapsed());
+            return false;
+        }
+        match self.small.remove(&seq) {
+            Some((slow_sent, active)) => active || self.slow.is_some() || self.slow_sent != slow_sent,
+            None => false,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Row {
+    latencies: Vec<Duration>,
+    failed: u64,
+}
+
+/// Latencies of the slow requests and of the small ones with and without a slow
+/// transfer beside them, owned by the collector
+#[derive(Default)]
+pub struct HolStats {
+    slow: Row,
+    contended: Row,
+    alone: Row,
+}
+
+impl HolStats {
+    pub fn record(&mut self, url: usize, contended: bool, latency: Duration, failed: bool) {
+        let row = match (url, contended) {
+            (SLOW, _) => &mut self.slow,
+            (_, true) => &mut self.contended,
+            (_, false) => &mut self.alone,
+        };
+        row.latencies.push(latency);
+        if failed {
+            row.failed += 1;
+        }
+    }
+
+    pub fn print(&mut self, probe: &HolProbe, protocol: &str, unit: LatencyUnit) {
+        println!("\n{}", "--- Head-of-line Probe ---".cyan().bold());
+        render::print("Protocol:".white(), protocol);
+        let mut table = Table::new(["Requests", "Count", "Errors", "P50", "P99"]);
+        let mut p99s = [None, None];
+        let rows = [
+            ("Small, slow in flight", &mut self.contended),
+            ("Small, alone", &mut self.alone),
+            ("Slow", &mut self.slow),
+        ];
+        for (n, (label, row)) in rows.into_iter().enumerate() {
+            if row.latencies.is_empty() {
+                table.row([label.to_string(), "0".to_string(), "-".into(), "-".into(), "-".into()]);
+                continue;
+            }
+            let count = row.latencies.len();
+            let p = Percentiles::from_unsorted(&mut row.latencies);
+            if n < 2 {
+                p99s[n] = Some(p.p99);
+            }
+            let errors = format!("{:.2}%", row.failed as f64 / count as f64 * 100.0);
+            table.row([
+                label.to_string(),
+                count.to_string(),
+                if row.failed > 0 { errors.red().to_string() } else { errors },
+                unit.format(p.p50),
+                unit.format(p.p99).red().to_string(),
+            ]);
+        }
+        table.print();
+        match p99s {
+            [Some(contended), Some(alone)] => render::print(
+                "HOL cost:".white(),
+                format!(
+                    "small P99 {} with a slow transfer beside it ({:.2}x alone)",
+                    signed(contended, alone, unit),
+                    contended.as_secs_f64() / alone.as_secs_f64().max(f64::MIN_POSITIVE)
+                )
+                .yellow(),
+            ),
+            _ => render::print(
+                "HOL cost:".white(),
+                "needs small requests both with and without a slow transfer beside them".yellow(),
+            ),
+        }
+        render::print("Slow URL:".white(), probe.url(SLOW));
+        render::print("Small URL:".white(), probe.url(SMALL));
+    }
+}
+
+/// `a - b`, e.g. "+12.000ms" or "-0.400ms"
+fn signed(a: Duration, b: Duration, unit: LatencyUnit) -> String {
+    if a >= b {
+        format!("+{}", unit.format(a - b))
+    } else {
+        format!("-{}", unit.format(b - a))
+    }
+}
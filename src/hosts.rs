@@ -0,0 +1,185 @@
+//! Several targets in one run, from more than one URL on the command line or from
+//! `--hosts-file`: each host gets its own share of the connections, so its own pool,
+//! and every request a connection sends goes to its host. Connections are split by
+//! weight (evenly without weights), and so are the requests of a fixed-rate or `-n`
+//! run; a host that answers faster takes more of a work-stealing one.
+
+use crate::render::Table;
+use crate::units::LatencyUnit;
+use crate::Percentiles;
+use colored::Colorize;
+use std::path::Path;
+use std::time::Duration;
+
+/// The hosts of a run and which of them each connection sends to
+pub struct Hosts {
+    urls: Vec<String>,
+    weights: Vec<f64>,
+    /// The host each worker sends to, interleaved so that `--ramp-down` and the
+    /// worker-ordered reports treat the hosts alike
+    assignment: Vec<usize>,
+    /// Connections each host got
+    connections: Vec<usize>,
+}
+
+impl Hosts {
+    /// The URLs given on the command line, weighted equally
+    pub fn new(urls: Vec<String>, connections: usize) -> Result<Hosts, String> {
+        for url in &urls {
+            reqwest::Url::parse(url).map_err(|e| format!("invalid URL {:?}: {}", url, e))?;
+        }
+        let weights = vec![1.0; urls.len()];
+        Hosts::split(urls, weights, connections)
+    }
+
+    /// `--hosts-file`: one `URL [WEIGHT]` per line; blank lines and `#` comments are
+    /// skipped
+    pub fn load(path: &Path, connections: usize) -> Result<Hosts, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+
+        let mut urls = Vec::new();
+        let mut weights = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let (Some(url), weight, None) = (words.next(), words.next(), words.next()) else {
+                return Err(format!("{}:{}: expected URL [WEIGHT]", path.display(), n + 1));
+            };
+            reqwest::Url::parse(url).map_err(|e| format!("{}:{}: invalid URL {:?}: {}", path.display(), n + 1, url, e))?;
+            let weight = match weight.map(str::parse::<f64>) {
+                None => 1.0,
+                Some(Ok(weight)) if weight > 0.0 && weight.is_finite() => weight,
+                Some(_) => {
+                    return Err(format!("{}:{}: weight must be a positive number", path.display(), n + 1));
+                }
+            };
+            urls.push(url.to_string());
+            weights.push(weight);
+        }
+        if urls.is_empty() {
+            return Err(format!("{} lists no hosts", path.display()));
+        }
+        Hosts::split(urls, weights, connections)
+    }
+
+    /// Share `connections` out by weight, largest remainders first
+    fn split(urls: Vec<String>, weights: Vec<f64>, connections: usize) -> Result<Hosts, String> {
+        let total: f64 = weights.iter().sum();
+        let exact: Vec<f64> = weights.iter().map(|w| w / total * connections as f64).collect();
+        let mut counts: Vec<usize> = exact.iter().map(|share| share.floor() as usize).collect();
+        let mut order: Vec<usize> = (0..urls.len()).collect();
+        order.sort_by(|&a, &b| (exact[b] - exact[b].floor()).total_cmp(&(exact[a] - exact[a].floor())));
+        let left = connections - counts.iter().sum::<usize>();
+        for &host in order.iter().take(left) {
+            counts[host] += 1;
+        }
+        if let Some(host) = counts.iter().position(|&count| count == 0) {
+            return Err(format!(
+                "-c {} leaves {} without a connection; use more connections or give it more weight",
+                connections, urls[host]
+            ));
+        }
+
+        // Host h's k-th connection sits at (k + 0.5) / count along the worker order
+        let mut slots: Vec<(f64, usize)> = counts
+            .iter()
+            .enumerate()
+            .flat_map(|(host, &count)| (0..count).map(move |k| ((k as f64 + 0.5) / count as f64, host)))
+            .collect();
+        slots.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+        Ok(Hosts {
+            urls,
+            weights,
+            assignment: slots.into_iter().map(|(_, host)| host).collect(),
+            connections: counts,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.urls.len()
+    }
+
+    pub fn url(&self, index: usize) -> &str {
+        &self.urls[index]
+    }
+
+    /// The host worker `worker` sends to
+    pub fn of(&self, worker: usize) -> usize {
+        self.assignment[worker]
+    }
+
+    /// The `Hosts:` line of the banner
+    pub fn describe(&self) -> String {
+        let weighted = self.weights.iter().any(|&w| w != self.weights[0]);
+        let hosts: Vec<String> = self
+            .urls
+            .iter()
+            .zip(&self.connections)
+            .map(|(url, count)| format!("{} ({} conns)", url, count))
+            .collect();
+        format!("{}, {}", if weighted { "weighted" } else { "even" }, hosts.join(", "))
+    }
+}
+
+#[derive(Default)]
+struct HostEntry {
+    latencies: Vec<Duration>,
+    failed: u64,
+}
+
+/// Latencies and failures for each host, owned by the collector
+pub struct HostStats {
+    entries: Vec<HostEntry>,
+}
+
+impl HostStats {
+    pub fn new(hosts: usize) -> Self {
+        HostStats {
+            entries: (0..hosts).map(|_| HostEntry::default()).collect(),
+        }
+    }
+
+    pub fn record(&mut self, index: usize, latency: Duration, failed: bool) {
+        let entry = &mut self.entries[index];
+        entry.latencies.push(latency);
+        if failed {
+            entry.failed += 1;
+        }
+    }
+
+    /// The `--- Per Host ---` section: each host and all of them together, with rates
+    /// over the run's active time
+    pub fn print(&mut self, hosts: &Hosts, active_time: Duration, unit: LatencyUnit) {
+        println!("\n{}", "--- Per Host ---".cyan().bold());
+        let mut table = Table::new(["Conns", "Requests", "RPS", "Errors", "P50", "P99", "Host"]);
+        let secs = active_time.as_secs_f64();
+        let mut row = |conns: usize, latencies: &mut Vec<Duration>, failed: u64, host: &str| {
+            let p = Percentiles::from_unsorted(latencies);
+            let errors = format!("{:.2}%", failed as f64 / p.count.max(1) as f64 * 100.0);
+            table.row([
+                conns.to_string().white().to_string(),
+                p.count.to_string(),
+                format!("{:.1}", p.count as f64 / secs),
+                if failed > 0 { errors.red().to_string() } else { errors },
+                unit.format(p.p50),
+                unit.format(p.p99).red().to_string(),
+                host.to_string(),
+            ]);
+        };
+
+        let mut all = Vec::new();
+        let mut all_failed = 0;
+        for (index, entry) in self.entries.iter_mut().enumerate() {
+            all.extend_from_slice(&entry.latencies);
+            all_failed += entry.failed;
+            row(hosts.connections[index], &mut entry.latencies, entry.failed, hosts.url(index));
+        }
+        let conns = hosts.connections.iter().sum();
+        row(conns, &mut all, all_failed, &"combined".bold().to_string());
+        table.print();
+    }
+}
@@ -0,0 +1,165 @@
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Clone, Debug)]
+enum Op {
+    Exists,
+    Eq(Value),
+    Ne(Value),
+    Lt(f64),
+    Le(f64),
+    Gt(f64),
+    Ge(f64),
+}
+
+/// One `--assert-json` expression, compiled at startup:
+/// `$.path` (exists), `$.path == literal`, `$.path != literal`, or a numeric
+/// comparison with `<`, `<=`, `>` or `>=`. Paths use `.key`, `["key"]` and `[index]`.
+#[derive(Clone, Debug)]
+pub struct JsonAssertion {
+    source: String,
+    path: Vec<Segment>,
+    op: Op,
+}
+
+impl JsonAssertion {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let source = s.trim().to_string();
+        let rest = source.strip_prefix('$').ok_or("path must start with '$'")?;
+        let (path, rest) = parse_path(rest)?;
+
+        let rest = rest.trim();
+        let op = if rest.is_empty() {
+            Op::Exists
+        } else {
+            let (op, literal) = ["==", "!=", "<=", ">=", "<", ">"]
+                .iter()
+                .find_map(|op| rest.strip_prefix(op).map(|literal| (*op, literal.trim())))
+                .ok_or_else(|| format!("expected an operator after the path, got '{}'", rest))?;
+            let literal: Value =
+                serde_json::from_str(literal).map_err(|e| format!("invalid literal '{}': {}", literal, e))?;
+            let number = || literal.as_f64().ok_or_else(|| format!("'{}' needs a number", op));
+            match op {
+                "==" => Op::Eq(literal.clone()),
+                "!=" => Op::Ne(literal.clone()),
+                "<" => Op::Lt(number()?),
+                "<=" => Op::Le(number()?),
+                ">" => Op::Gt(number()?),
+                _ => Op::Ge(number()?),
+            }
+        };
+
+        Ok(JsonAssertion { source, path, op })
+    }
+
+    /// Describe why `doc` fails this assertion, if it does
+    pub fn check(&self, doc: &Value) -> Option<String> {
+        let found = self.path.iter().try_fold(doc, |value, segment| match segment {
+            Segment::Key(key) => value.get(key),
+            Segment::Index(i) => value.get(i),
+        });
+        let Some(found) = found else {
+            return Some(format!("{}: path not found", self.source));
+        };
+
+        let holds = match &self.op {
+            Op::Exists => true,
+            Op::Eq(expected) => found == expected,
+            Op::Ne(expected) => found != expected,
+            Op::Lt(n) => found.as_f64().is_some_and(|v| v < *n),
+            Op::Le(n) => found.as_f64().is_some_and(|v| v <= *n),
+            Op::Gt(n) => found.as_f64().is_some_and(|v| v > *n),
+            Op::Ge(n) => found.as_f64().is_some_and(|v| v >= *n),
+        };
+        (!holds).then(|| format!("{}: got {}", self.source, found))
+    }
+}
+
+/// Split a path off the front of `s`, returning the remainder
+fn parse_path(mut s: &str) -> Result<(Vec<Segment>, &str), String> {
+    let mut path = Vec::new();
+    loop {
+        if let Some(rest) = s.strip_prefix('.') {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return Err("empty key after '.'".to_string());
+            }
+            path.push(Segment::Key(rest[..end].to_string()));
+            s = &rest[end..];
+        } else if let Some(rest) = s.strip_prefix('[') {
+            let end = rest.find(']').ok_or("unclosed '['")?;
+            let inner = rest[..end].trim();
+            let segment = match inner.strip_prefix('"').and_then(|k| k.strip_suffix('"')) {
+                Some(key) => Segment::Key(key.to_string()),
+                None => Segment::Index(inner.parse().map_err(|_| format!("invalid index '{}'", inner))?),
+            };
+            path.push(segment);
+            s = &rest[end + 1..];
+        } else {
+            return Ok((path, s));
+        }
+    }
+}
+
+/// `--assert-json` assertions plus the sampling and failure counters
+pub struct JsonChecks {
+    assertions: Vec<JsonAssertion>,
+    /// Fraction of successful responses to validate
+    sample_rate: f64,
+    seen: AtomicU64,
+    pub parse_failures: AtomicU64,
+    pub assertion_failures: AtomicU64,
+}
+
+impl JsonChecks {
+    pub fn new(assertions: Vec<JsonAssertion>, sample_rate: f64) -> Self {
+        JsonChecks {
+            assertions,
+            sample_rate,
+            seen: AtomicU64::new(0),
+            parse_failures: AtomicU64::new(0),
+            assertion_failures: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether this response is part of the sample. Deterministic, so exactly
+    /// `rate` of the responses are validated.
+    pub fn sampled(&self) -> bool {
+        let n = self.seen.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * self.sample_rate).floor() > (n * self.sample_rate).floor()
+    }
+
+    /// Parse `body` and run every assertion, returning the first failure
+    pub fn check(&self, body: &[u8]) -> Option<String> {
+        let doc: Value = match serde_json::from_slice(body) {
+            Ok(doc) => doc,
+            Err(e) => {
+                self.parse_failures.fetch_add(1, Ordering::Relaxed);
+                return Some(format!("body is not valid JSON: {}", e));
+            }
+        };
+
+        let failure = self.assertions.iter().find_map(|a| a.check(&doc));
+        if failure.is_some() {
+            self.assertion_failures.fetch_add(1, Ordering::Relaxed);
+        }
+        failure
+    }
+}
+
+/// Parse a sample rate in (0, 1]
+pub fn parse_sample_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if rate <= 0.0 || rate > 1.0 {
+        return Err("expected a fraction in (0, 1]".to_string());
+    }
+    Ok(rate)
+}
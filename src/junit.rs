@@ -0,0 +1,91 @@
+use crate::output::RunSummary;
+use crate::thresholds::Evaluation;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Write `--junit PATH`: one testsuite for the run, one testcase per threshold, and
+/// the run's stats as suite properties
+pub fn write(path: &Path, summary: &RunSummary, evaluations: &[Evaluation]) -> std::io::Result<()> {
+    let time = summary.duration.as_secs_f64();
+    let failures = evaluations.iter().filter(|e| !e.passed).count();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites name=\"benchy\" tests=\"{}\" failures=\"{}\" errors=\"0\" time=\"{:.3}\">",
+        evaluations.len(),
+        failures,
+        time
+    );
+    let _ = writeln!(
+        xml,
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"0\" time=\"{:.3}\" timestamp=\"{}\">",
+        escape(summary.url),
+        evaluations.len(),
+        failures,
+        time,
+        humantime::format_rfc3339_seconds(summary.started)
+    );
+
+    xml.push_str("    <properties>\n");
+    let properties = [
+        ("url", summary.url.to_string()),
+        ("proto", summary.proto.to_string()),
+        ("conns", summary.conns.to_string()),
+        ("pipeline", summary.pipeline.to_string()),
+        ("reqs", summary.reqs.to_string()),
+        ("success", summary.success.to_string()),
+        ("failed", summary.failed.to_string()),
+        ("duration_s", format!("{:.3}", time)),
+        ("rps", format!("{:.2}", summary.rps)),
+        ("p50_us", summary.latency.p50.as_micros().to_string()),
+        ("p95_us", summary.latency.p95.as_micros().to_string()),
+        ("p99_us", summary.latency.p99.as_micros().to_string()),
+        ("err_rate", format!("{:.4}", summary.err_rate())),
+    ];
+    for (name, value) in properties {
+        let _ = writeln!(xml, "      <property name=\"{}\" value=\"{}\"/>", name, escape(&value));
+    }
+    xml.push_str("    </properties>\n");
+
+    for e in evaluations {
+        let name = escape(&format!("{} {}", e.name, e.required));
+        if e.passed {
+            let _ = writeln!(
+                xml,
+                "    <testcase classname=\"benchy.thresholds\" name=\"{}\" time=\"{:.3}\"/>",
+                name, time
+            );
+        } else {
+            let _ = writeln!(
+                xml,
+                "    <testcase classname=\"benchy.thresholds\" name=\"{}\" time=\"{:.3}\">",
+                name, time
+            );
+            let _ = writeln!(
+                xml,
+                "      <failure type=\"threshold\" message=\"{}\"/>",
+                escape(&format!("{} was {}, required {}", e.name, e.measured, e.required))
+            );
+            xml.push_str("    </testcase>\n");
+        }
+    }
+
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    std::fs::write(path, xml)
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
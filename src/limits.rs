@@ -0,0 +1,109 @@
+//! Client-side limits that a big `-c` runs into before the server does: open file
+//! descriptors (`ulimit -n`) and ephemeral ports. Failures they cause are counted as
+//! `client limits` errors rather than blamed on the server, with a hint after the
+//! results saying what to raise.
+
+use crate::render;
+use crate::thousands;
+use colored::Colorize;
+
+/// Descriptors to allow per connection: its socket, plus one for the connection a
+/// reconnecting worker opens before the old one is closed
+const FDS_PER_CONNECTION: u64 = 2;
+
+/// Descriptors benchy needs besides its connections: stdio, the runtime, DNS sockets
+const BASE_FDS: u64 = 64;
+
+/// The `ulimit -n` that `connections` should fit under
+pub fn recommended(connections: usize) -> u64 {
+    connections as u64 * FDS_PER_CONNECTION + BASE_FDS
+}
+
+/// The soft and hard RLIMIT_NOFILE
+#[cfg(unix)]
+pub fn nofile() -> Option<(u64, u64)> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: getrlimit only writes to the rlimit it is given
+    let ok = unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } == 0;
+    ok.then_some((limit.rlim_cur, limit.rlim_max))
+}
+
+#[cfg(not(unix))]
+pub fn nofile() -> Option<(u64, u64)> {
+    None
+}
+
+/// The local port range outgoing connections are given ports from, on Linux
+fn port_range() -> Option<(u32, u32)> {
+    let range = std::fs::read_to_string("/proc/sys/net/ipv4/ip_local_port_range").ok()?;
+    let mut ports = range.split_whitespace().map(str::parse);
+    match (ports.next(), ports.next()) {
+        (Some(Ok(low)), Some(Ok(high))) => Some((low, high)),
+        _ => None,
+    }
+}
+
+/// Before the run: warn when `connections` won't fit under `ulimit -n`
+pub fn warn(connections: usize) {
+    let Some((soft, hard)) = nofile() else {
+        return;
+    };
+    let needed = recommended(connections);
+    if soft >= needed {
+        return;
+    }
+    let fix = if hard >= needed {
+        format!("run `ulimit -n {}` first", needed)
+    } else {
+        format!("the hard limit is {}, so lower -c or raise it (e.g. in limits.conf)", hard)
+    };
+    eprintln!(
+        "{} ulimit -n is {}, too low for {} connections; {}",
+        "Warning:".yellow(),
+        soft,
+        connections,
+        fix
+    );
+}
+
+/// After the results: what ran out, for `exhausted` requests that failed for lack of
+/// descriptors or ports
+pub fn print_hint(exhausted: u64, connections: usize) {
+    if exhausted == 0 {
+        return;
+    }
+    render::print(
+        "Client:".white(),
+        format!(
+            "{} requests failed for lack of file descriptors or ephemeral ports on this machine, not at the server",
+            thousands(exhausted)
+        )
+        .red()
+        .bold(),
+    );
+    if let Some((soft, hard)) = nofile() {
+        let needed = recommended(connections);
+        let fix = if soft >= needed {
+            "enough for -c".to_string()
+        } else if hard >= needed {
+            format!("try `ulimit -n {}`", needed)
+        } else {
+            format!("hard limit {}, lower -c or raise it", hard)
+        };
+        render::print("ulimit -n:".white(), format!("{}, {}", soft, fix));
+    }
+    if let Some((low, high)) = port_range() {
+        render::print(
+            "Local ports:".white(),
+            format!(
+                "{}-{} ({} per destination; see net.ipv4.ip_local_port_range)",
+                low,
+                high,
+                thousands((high.saturating_sub(low) + 1) as u64)
+            ),
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! `--lint-responses`: cheap checks of each response against HTTP semantics, counted
+//! per rule with the first example of each, to catch a server's sloppiness while it is
+//! being benchmarked. Violations are only reported, unless `--lint-strict` fails the
+//! responses that have them.
+//!
+//! Only what hyper lets through can be checked. It refuses control characters in
+//! header values outright (an `other` error), and over HTTP/1.1 it never reads a body
+//! after a HEAD or 204 response; those bytes then surface as the next response on the
+//! connection failing to parse.
+
+use crate::render::{self, Table};
+use crate::thousands;
+use colored::Colorize;
+use reqwest::header::{HeaderMap, CONTENT_LENGTH, DATE, TRANSFER_ENCODING};
+use reqwest::StatusCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+/// A rule a response can break, in the order the section lists them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Rule {
+    /// No `Date` header, which an origin server with a clock must send
+    MissingDate,
+    /// A header value with bytes other than visible ASCII, space and tab (obs-text)
+    InvalidHeaderValue,
+    /// A 204 response announcing or carrying a body
+    NoContentBody,
+    /// A HEAD response carrying a body
+    HeadBody,
+    /// `Content-Length` and `Transfer-Encoding` together, a request smuggling vector
+    LengthAndEncoding,
+}
+
+impl Rule {
+    const ALL: [Rule; 5] = [
+        Rule::MissingDate,
+        Rule::InvalidHeaderValue,
+        Rule::NoContentBody,
+        Rule::HeadBody,
+        Rule::LengthAndEncoding,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Rule::MissingDate => "missing date",
+            Rule::InvalidHeaderValue => "invalid header value",
+            Rule::NoContentBody => "204 with body",
+            Rule::HeadBody => "HEAD with body",
+            Rule::LengthAndEncoding => "length and encoding",
+        }
+    }
+}
+
+/// One broken rule, with what broke it
+struct Violation {
+    rule: Rule,
+    example: String,
+}
+
+/// The rules each response's headers are held to
+fn header_violations(status: StatusCode, headers: &HeaderMap) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    // Interim responses are exempt, and hyper doesn't pass them on anyway
+    if !status.is_informational() && !headers.contains_key(DATE) {
+        violations.push(Violation {
+            rule: Rule::MissingDate,
+            example: format!("{} without Date", status.as_u16()),
+        });
+    }
+    if let Some((name, value)) = headers.iter().find(|(_, value)| value.to_str().is_err()) {
+        violations.push(Violation {
+            rule: Rule::InvalidHeaderValue,
+            example: format!("{}: {:?}", name, value),
+        });
+    }
+    let length = headers.get(CONTENT_LENGTH);
+    let encoding = headers.get(TRANSFER_ENCODING);
+    if status == StatusCode::NO_CONTENT {
+        let announced = match (length, encoding) {
+            (_, Some(encoding)) => {
+                Some(format!("Transfer-Encoding: {}", String::from_utf8_lossy(encoding.as_bytes())))
+            }
+            (Some(length), None) if length != "0" => {
+                Some(format!("Content-Length: {}", String::from_utf8_lossy(length.as_bytes())))
+            }
+            _ => None,
+        };
+        if let Some(announced) = announced {
+            violations.push(Violation {
+                rule: Rule::NoContentBody,
+                example: format!("204 with {}", announced),
+            });
+        }
+    }
+    if let Some((length, encoding)) = length.zip(encoding) {
+        violations.push(Violation {
+            rule: Rule::LengthAndEncoding,
+            example: format!(
+                "Content-Length: {} with Transfer-Encoding: {}",
+                String::from_utf8_lossy(length.as_bytes()),
+                String::from_utf8_lossy(encoding.as_bytes())
+            ),
+        });
+    }
+    violations
+}
+
+/// A body where there must be none: `len` bytes after a HEAD request or a 204
+fn body_violation(head: bool, status: StatusCode, len: u64) -> Option<Violation> {
+    if len == 0 {
+        return None;
+    }
+    let rule = if head {
+        Rule::HeadBody
+    } else if status == StatusCode::NO_CONTENT {
+        Rule::NoContentBody
+    } else {
+        return None;
+    };
+    Some(Violation {
+        rule,
+        example: format!("{} response with {} bytes of body", if head { "HEAD" } else { "204" }, len),
+    })
+}
+
+/// Violations per rule across the run, and the first of each
+pub struct Lint {
+    /// `--lint-strict`: responses with a violation fail
+    strict: bool,
+    checked: AtomicU64,
+    counts: [AtomicU64; Rule::ALL.len()],
+    examples: [OnceLock<String>; Rule::ALL.len()],
+}
+
+impl Lint {
+    pub fn new(strict: bool) -> Lint {
+        Lint {
+            strict,
+            checked: AtomicU64::new(0),
+            counts: Default::default(),
+            examples: Default::default(),
+        }
+    }
+
+    /// Check a response's headers, counting it. Under `--lint-strict`, returns why the
+    /// response fails.
+    pub fn check_headers(&self, status: StatusCode, headers: &HeaderMap) -> Option<String> {
+        self.checked.fetch_add(1, Ordering::Relaxed);
+        self.verdict(header_violations(status, headers))
+    }
+
+    /// Check the `len` bytes of body a response came with, once read
+    pub fn check_body(&self, head: bool, status: StatusCode, len: u64) -> Option<String> {
+        self.verdict(body_violation(head, status, len).into_iter().collect())
+    }
+
+    fn verdict(&self, violations: Vec<Violation>) -> Option<String> {
+        for violation in &violations {
+            self.counts[violation.rule as usize].fetch_add(1, Ordering::Relaxed);
+            let _ = self.examples[violation.rule as usize].set(violation.example.clone());
+        }
+        let first = violations.first().filter(|_| self.strict)?;
+        Some(format!("response lint: {}: {}", first.rule.name(), first.example))
+    }
+
+    /// The `Response Lint` section: each rule broken, how often, and the first example
+    pub fn print(&self) {
+        println!("\n{}", "--- Response Lint ---".cyan().bold());
+        let checked = thousands(self.checked.load(Ordering::Relaxed));
+        let broken: Vec<(Rule, u64)> = Rule::ALL
+            .into_iter()
+            .zip(&self.counts)
+            .map(|(rule, count)| (rule, count.load(Ordering::Relaxed)))
+            .filter(|&(_, count)| count > 0)
+            .collect();
+        if broken.is_empty() {
+            render::print("Checked:".white(), format!("{} responses, no rules broken", checked).green());
+            return;
+        }
+        render::print("Checked:".white(), format!("{} responses", checked));
+        let mut table = Table::new(["Rule", "Responses", "Example"]);
+        for (rule, count) in broken {
+            table.row([
+                format!("{}:", rule.name()).white().to_string(),
+                thousands(count).yellow().to_string(),
+                self.examples[rule as usize].get().cloned().unwrap_or_default(),
+            ]);
+        }
+        table.print();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderValue, CONTENT_TYPE};
+
+    /// Headers a well-behaved server would send, with `extra` added
+    fn headers(extra: &[(&'static str, &[u8])]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(DATE, HeaderValue::from_static("Thu, 15 Oct 2026 10:00:00 GMT"));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        for (name, value) in extra {
+            headers.insert(*name, HeaderValue::from_bytes(value).unwrap());
+        }
+        headers
+    }
+
+    fn rules(violations: &[Violation]) -> Vec<Rule> {
+        violations.iter().map(|violation| violation.rule).collect()
+    }
+
+    #[test]
+    fn a_clean_response_breaks_nothing() {
+        assert!(header_violations(StatusCode::OK, &headers(&[("content-length", b"12")])).is_empty());
+        assert!(header_violations(StatusCode::NO_CONTENT, &headers(&[("content-length", b"0")])).is_empty());
+        assert!(body_violation(false, StatusCode::OK, 12).is_none());
+        assert!(body_violation(true, StatusCode::OK, 0).is_none());
+    }
+
+    #[test]
+    fn missing_date() {
+        let mut without = headers(&[]);
+        without.remove(DATE);
+        let violations = header_violations(StatusCode::NOT_FOUND, &without);
+        assert_eq!(rules(&violations), [Rule::MissingDate]);
+        assert_eq!(violations[0].example, "404 without Date");
+        assert!(header_violations(StatusCode::CONTINUE, &without).is_empty());
+    }
+
+    #[test]
+    fn invalid_header_value() {
+        let violations = header_violations(StatusCode::OK, &headers(&[("x-name", b"caf\xe9")]));
+        assert_eq!(rules(&violations), [Rule::InvalidHeaderValue]);
+        assert_eq!(violations[0].example, r#"x-name: "caf\xe9""#);
+    }
+
+    #[test]
+    fn no_content_body() {
+        let violations = header_violations(StatusCode::NO_CONTENT, &headers(&[("content-length", b"5")]));
+        assert_eq!(rules(&violations), [Rule::NoContentBody]);
+        assert_eq!(violations[0].example, "204 with Content-Length: 5");
+        let violations = header_violations(StatusCode::NO_CONTENT, &headers(&[("transfer-encoding", b"chunked")]));
+        assert_eq!(rules(&violations), [Rule::NoContentBody]);
+
+        let body = body_violation(false, StatusCode::NO_CONTENT, 5).unwrap();
+        assert_eq!((body.rule, body.example.as_str()), (Rule::NoContentBody, "204 response with 5 bytes of body"));
+    }
+
+    #[test]
+    fn head_body() {
+        let body = body_violation(true, StatusCode::OK, 1000).unwrap();
+        assert_eq!((body.rule, body.example.as_str()), (Rule::HeadBody, "HEAD response with 1000 bytes of body"));
+    }
+
+    #[test]
+    fn length_and_encoding() {
+        let both = headers(&[("content-length", b"12"), ("transfer-encoding", b"chunked")]);
+        let violations = header_violations(StatusCode::OK, &both);
+        assert_eq!(rules(&violations), [Rule::LengthAndEncoding]);
+        assert_eq!(violations[0].example, "Content-Length: 12 with Transfer-Encoding: chunked");
+    }
+
+    #[test]
+    fn only_strict_fails_responses() {
+        let mut without = headers(&[]);
+        without.remove(DATE);
+        for strict in [false, true] {
+            let lint = Lint::new(strict);
+            assert_eq!(lint.check_headers(StatusCode::OK, &headers(&[])), None);
+            let verdict = lint.check_headers(StatusCode::OK, &without);
+            assert_eq!(verdict.as_deref(), strict.then_some("response lint: missing date: 200 without Date"));
+            lint.check_headers(StatusCode::ACCEPTED, &without);
+            assert_eq!(lint.checked.load(Ordering::Relaxed), 3);
+            assert_eq!(lint.counts[Rule::MissingDate as usize].load(Ordering::Relaxed), 2);
+            assert_eq!(lint.examples[Rule::MissingDate as usize].get().unwrap(), "200 without Date");
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use std::io::IsTerminal;
+use tracing_subscriber::EnvFilter;
+
+/// Install the stderr log subscriber for `-v`/`-vv`/`-vvv` or `RUST_LOG`.
+///
+/// Without either, no subscriber is installed and every event macro is a disabled
+/// callsite check, so the request path pays nothing.
+pub fn init(verbose: u8) {
+    let filter = match (EnvFilter::try_from_default_env(), verbose) {
+        (Ok(filter), _) => filter,
+        (Err(_), 0) => return,
+        // Our own lifecycle events: clients, connections, workers
+        (Err(_), 1) => EnvFilter::new("benchy=info"),
+        // Plus per-request outcomes, retries and backoff decisions
+        (Err(_), 2) => EnvFilter::new("benchy=debug"),
+        // Everything, including reqwest, hyper, h2 and quinn internals
+        (Err(_), _) => EnvFilter::new("trace"),
+    };
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .init();
+}
@@ -1,15 +1,152 @@
+mod affinity;
+mod alternate;
+mod altsvc;
+mod arrival;
+mod backoff;
+mod body;
+mod cacheanalysis;
+mod connect;
+mod cryptodrift;
+mod clock;
+mod compress;
+mod cors;
+mod dns;
+mod dryrun;
+mod dump;
+mod endpoint;
+mod errors;
+mod expect;
+mod fill;
+mod floor;
+mod handshake;
+mod headerfile;
+mod headers;
+mod hol;
+mod heatmap;
+mod hosts;
+mod junit;
+mod jsonassert;
+mod limits;
+mod lint;
+mod logging;
+mod manifest;
+mod merge;
+mod output;
+mod pause;
+mod payload;
+mod phases;
+mod preflight;
+mod prime;
+mod probe;
+mod processes;
+mod progress;
+mod prometheus;
+mod protocol;
+mod publish;
+mod quic;
+mod ratelimit;
+mod reachability;
+mod ready;
+mod render;
+mod report;
+mod requestid;
+mod requestlog;
+mod retry;
+mod results;
+mod resources;
+mod sanity;
+mod schedlag;
+mod script;
+mod selftest;
+mod sent;
+mod serve;
+mod sinks;
+mod thresholds;
+mod slowest;
+mod template;
+mod textreport;
+mod timeline;
+mod trailers;
+mod transport;
+mod units;
+mod upgrade;
+mod upload;
+mod urls;
+mod workers;
+mod workload;
+
+use affinity::{AffinityTally, Identity};
+use alternate::{SliceStats, Slicer};
+use altsvc::{AltSvcStats, Discovery};
+use arrival::{Arrival, Burst, BurstLatencies, Bursts, Pacer, PacingStats, Schedule};
+use backoff::{Backoff, BackoffStats};
+use body::{BodyChecks, BodyHasher, BodyRead, BodyStats};
 use clap::Parser;
+use compress::Compression;
+use connect::{ConnectStats, ConnectTimingLayer, ProtocolMix};
+use cryptodrift::{CryptoDrift, DriftReport};
+use cors::{CorsCheck, Preflight};
+use dns::{AddressOrder, BenchResolver, DnsStats};
+use dump::{ErrorDump, FailedExchange};
+use errors::ErrorCounts;
+use expect::{ContinueClient, ContinueStats};
+use fill::{Fill, Ramp};
+use floor::RpsFloor;
+use headerfile::{HeaderFile, HeaderOrder, SentGroups};
+use headers::{HeaderAssertion, HeaderTally, LatencyGroups};
+use heatmap::Heatmap;
+use hosts::{HostStats, Hosts};
+use jsonassert::{JsonAssertion, JsonChecks};
+use lint::Lint;
+use output::{OutputFormat, RunSummary};
+use pause::PauseControl;
+use payload::BodyPool;
+use progress::Throughput;
+use protocol::Protocol;
+use ratelimit::RateLimits;
+use reachability::Reachability;
+use render::Table;
+use requestid::{EchoStats, Ulids};
+use requestlog::RequestLog;
+use retry::StatusRetry;
+use script::{Script, WorkerScript};
+use sent::{RequestSize, SentBytes};
+use quic::{Congestion, H3Client, QuicTuning};
+use resources::ResourceUsage;
+use schedlag::LagProbe;
+use results::SavedRun;
+use sanity::Sanity;
+use thresholds::Thresholds;
+use slowest::Slowest;
+use template::{BodyGenerator, BodyTemplate};
+use textreport::TextDetails;
+use timeline::{StatusClasses, StatusTimeline};
+use trailers::Trailers;
+use transport::{BoxError, TlsStats};
+use units::LatencyUnit;
+use upgrade::{UpgradeClient, UpgradeStats};
+use upload::{TransferTimes, UploadBody};
+use urls::{UrlMix, UrlPicker, UrlStats};
+use workers::{Activity, Drain, Protocols, Quota, RequestCounts, WorkerReport};
 use colored::Colorize;
 use futures::stream::{FuturesUnordered, StreamExt};
-use reqwest::{Client, Version};
+use futures::FutureExt;
+use reqwest::header::{HeaderValue, ALT_SVC, CONTENT_ENCODING};
+use reqwest::{Client, RequestBuilder, Version};
+use std::cell::RefCell;
 use std::error::Error;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::io::IsTerminal;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Parser)]
 #[command(name = "benchy", about = "HTTP/2 and HTTP/3 benchmark tool")]
+#[command(after_help = "Release builds abort on a panic. Debug builds report a panicking worker and exit 1.")]
+#[command(group(clap::ArgGroup::new("body").args(["data", "data_file", "data_dir"])))]
 struct Args {
     /// Number of concurrent connections
     #[arg(short = 'c', default_value = "10")]
@@ -19,18 +156,153 @@ struct Args {
     #[arg(short = 'n', default_value = "100")]
     requests: u64,
 
-    /// POST body data
+    /// POST body data. `{name()}`, `{email()}`, `{int MIN MAX}`, `{uuid}` and
+    /// `{now_iso8601}` are filled in per request
     #[arg(short = 'd')]
     data: Option<String>,
 
+    /// POST this file's contents as the body, decompressed first if .zst or .gz
+    #[arg(long = "data-file", value_name = "FILE")]
+    data_file: Option<std::path::PathBuf>,
+
+    /// POST one file of this directory per request, picked at random (see --seed)
+    #[arg(long = "data-dir", value_name = "DIR")]
+    data_dir: Option<std::path::PathBuf>,
+
+    /// How --data-file and --data-dir files are compressed
+    #[arg(long = "data-file-encoding", value_name = "ENCODING", default_value = "auto")]
+    data_file_encoding: payload::Encoding,
+
+    /// Send request bodies compressed, with Content-Encoding: once at startup for a body
+    /// that is the same every time, per request for -d with placeholders or --script's
+    #[arg(long = "compress-body", value_name = "CODEC")]
+    compress_body: Option<compress::Codec>,
+
+    /// Request method (default: POST with -d, GET otherwise). HEAD responses' bodies
+    /// aren't read; their Content-Length is reported as a virtual size
+    #[arg(short = 'm', long, value_name = "METHOD", conflicts_with = "preflight_origin")]
+    method: Option<reqwest::Method>,
+
+    /// Seed for generated body values, so a run can be repeated (default: random)
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+
+    /// Print one generated body before the run starts
+    #[arg(long = "print-sample-body", requires = "data")]
+    print_sample_body: bool,
+
     /// Pipelining depth per connection (concurrent streams)
     #[arg(short = 'p', default_value = "10")]
     pipeline: usize,
 
+    /// Tasks that drive the -c connections, each taking its share in turn per request
+    /// (default: one per connection)
+    #[arg(long, value_name = "N", conflicts_with_all = ["identity", "affinity_header", "hol_probe", "h3_alt_svc", "handshake_only"])]
+    workers: Option<usize>,
+
+    /// How each connection fills its pipeline: `eager` sends -p requests at once,
+    /// `gradual[:DURATION]` ramps from 1 in flight to -p over DURATION (default 1s)
+    #[arg(long, value_name = "STRATEGY", default_value = "eager", value_parser = fill::parse)]
+    fill: Fill,
+
+    /// Pace requests at this many per second in total, split evenly over connections;
+    /// -p still caps how many are in flight
+    #[arg(long, value_name = "REQ_PER_SEC", value_parser = arrival::parse_rate)]
+    rate: Option<f64>,
+
+    /// How --rate spaces requests: `uniform` intervals or `poisson` (exponential gaps)
+    #[arg(long, value_enum, default_value_t = Arrival::Uniform, requires = "rate")]
+    arrival: Arrival,
+
+    /// Slow --rate to what the --ratelimit-headers advertise is left of the current
+    /// window, and send nothing for as long as a 429's Retry-After asks
+    #[arg(long = "auto-rate", requires_all = ["rate", "ratelimit_headers"])]
+    auto_rate: bool,
+
+    /// Also report latency from each request's --rate schedule rather than from when it
+    /// was sent, so time queued behind a full pipeline shows up (as in wrk2)
+    #[arg(long = "correct-latency", requires = "rate")]
+    correct_latency: bool,
+
+    /// Release SIZE requests together at the start of every INTERVAL (e.g. `50/1s`),
+    /// split over connections, and send nothing in between
+    #[arg(long, value_name = "SIZE/INTERVAL", value_parser = Burst::parse, conflicts_with = "rate")]
+    burst: Option<Burst>,
+
     /// Use HTTP/3 (QUIC) instead of HTTP/2
     #[arg(long = "h3")]
     http3: bool,
 
+    /// Protocol to use; `auto` lets ALPN decide and reports what was negotiated
+    #[arg(long, value_enum, default_value_t = Protocol::H2, conflicts_with = "http3")]
+    protocol: Protocol,
+
+    /// Count responses over any other HTTP version as failures and exit nonzero
+    #[arg(long = "require-protocol")]
+    require_protocol: bool,
+
+    /// Keep -p even when the server's SETTINGS_MAX_CONCURRENT_STREAMS is lower, letting
+    /// the extra streams queue inside the client
+    #[arg(long = "ignore-server-stream-limit")]
+    ignore_server_stream_limit: bool,
+
+    /// If the server can't speak the requested protocol, step down (h3 -> h2 -> h1);
+    /// DNS and connection failures still end the run
+    #[arg(long, conflicts_with_all = ["h3_alt_svc", "handshake_only"])]
+    fallback: bool,
+
+    /// Start on HTTP/2 and switch each connection to HTTP/3 as advertised by Alt-Svc
+    #[arg(long = "h3-alt-svc", conflicts_with_all = ["http3", "protocol"])]
+    h3_alt_svc: bool,
+
+    /// With --h3-alt-svc, fail instead of staying on HTTP/2 when no h3 is advertised
+    #[arg(long = "require-h3", requires = "h3_alt_svc")]
+    require_h3: bool,
+
+    /// With --h3, close the QUIC connection after this long without traffic (e.g. 10s)
+    #[arg(long = "h3-max-idle-timeout", value_name = "DURATION", value_parser = humantime::parse_duration)]
+    h3_max_idle_timeout: Option<Duration>,
+
+    /// With --h3, how many bidirectional streams the server may open towards us
+    #[arg(long = "h3-initial-max-streams", value_name = "N")]
+    h3_initial_max_streams: Option<u64>,
+
+    /// With --h3, the QUIC congestion controller
+    #[arg(long = "h3-congestion", value_enum)]
+    h3_congestion: Option<Congestion>,
+
+    /// With --h3, the largest UDP payload we accept, in bytes (1200-65527)
+    #[arg(long = "h3-max-udp-payload", value_name = "BYTES")]
+    h3_max_udp_payload: Option<u16>,
+
+    /// With --h3, send a QUIC PING after this long without traffic (e.g. 10s)
+    #[arg(long = "h3-keepalive", value_name = "DURATION", value_parser = humantime::parse_duration)]
+    h3_keepalive: Option<Duration>,
+
+    /// Send an HTTP/2 PING after this long without traffic, even while idle, and drop
+    /// the connection if none comes back within the same time (e.g. 10s)
+    #[arg(long = "h2-keepalive", value_name = "DURATION", value_parser = humantime::parse_duration)]
+    h2_keepalive: Option<Duration>,
+
+    /// Give up on a connection that isn't set up (TCP, TLS or QUIC handshake) within
+    /// this long, counting it as a connect timeout
+    #[arg(long = "connect-timeout", value_name = "DURATION", default_value = "10s", value_parser = humantime::parse_duration)]
+    connect_timeout: Duration,
+
+    /// Send `Expect: 100-continue` and hold the body back until the server answers 100
+    /// (or 1s passes); needs -d and --protocol h1
+    #[arg(long = "expect-continue", requires = "body")]
+    expect_continue: bool,
+
+    /// Reach HTTP/2 on an http:// target by upgrading each HTTP/1.1 connection
+    /// (Upgrade: h2c) instead of with prior knowledge
+    #[arg(long = "h2c-upgrade", conflicts_with_all = ["http3", "h3_alt_svc", "expect_continue", "proxy", "fallback", "handshake_only", "alternate", "url_file", "workload", "hosts_file", "more_urls"])]
+    h2c_upgrade: bool,
+
+    /// Send requests through this HTTP proxy; https:// targets are tunneled with CONNECT
+    #[arg(long, value_name = "URL")]
+    proxy: Option<String>,
+
     /// Skip TLS certificate verification
     #[arg(short = 'k', long = "insecure")]
     insecure: bool,
@@ -39,13 +311,582 @@ struct Args {
     #[arg(short = 'f', long = "fail-fast")]
     fail_fast: bool,
 
+    /// Throttle response body reads to this many bytes/sec per request
+    #[arg(long = "download-rate", value_name = "BYTES_PER_SEC")]
+    download_rate: Option<u64>,
+
+    /// Reconnect after this many requests on each connection (connection churn)
+    #[arg(long = "requests-per-connection", value_name = "N")]
+    requests_per_connection: Option<u64>,
+
+    /// Only open and close connections, measuring handshakes instead of requests
+    #[arg(long = "handshake-only")]
+    handshake_only: bool,
+
+    /// In --handshake-only mode, send one GET per connection to confirm liveness
+    #[arg(long = "handshake-liveness", requires = "handshake_only")]
+    handshake_liveness: bool,
+
+    /// Disable TLS session tickets so every new connection does a full handshake
+    #[arg(long = "no-session-resumption")]
+    no_session_resumption: bool,
+
+    /// Retry requests rejected by a GOAWAY or REFUSED_STREAM once on a new connection
+    #[arg(long = "retry-goaway", default_value_t = true, action = clap::ArgAction::Set, value_name = "BOOL")]
+    retry_goaway: bool,
+
+    /// Resend requests answered with one of these statuses (e.g. 502,503), within
+    /// --retry-budget
+    #[arg(long = "retry-on", value_name = "CODES", value_delimiter = ',', value_parser = clap::value_parser!(u16).range(100..600))]
+    retry_on: Vec<u16>,
+
+    /// Retries --retry-on may send in all, as a share of -n, e.g. 1% or 0.01
+    #[arg(long = "retry-budget", value_name = "RATE", default_value = "1%", value_parser = thresholds::parse_rate, requires = "retry_on")]
+    retry_budget: f64,
+
+    /// Resolve the target through this DNS server instead of the system resolver
+    #[arg(long = "dns-server", value_name = "IP[:PORT]", value_parser = dns::parse_server)]
+    dns_server: Option<SocketAddr>,
+
+    /// Re-resolve and reconnect at this interval (e.g. 30s), overriding record TTLs
+    #[arg(long = "dns-ttl-override", value_name = "DURATION", value_parser = humantime::parse_duration)]
+    dns_ttl_override: Option<Duration>,
+
+    /// Which address family to try first for dual-stack hosts
+    #[arg(long = "address-order", value_enum, default_value_t = AddressOrder::System)]
+    address_order: AddressOrder,
+
+    /// Give each connection a different one of the target's addresses, round-robin,
+    /// instead of the first for all
+    #[arg(long = "spread-dns", conflicts_with = "proxy")]
+    spread_dns: bool,
+
+    /// Require a response header: 'name: value' (exact) or 'name: /regex/' (repeatable)
+    #[arg(long = "assert-header", value_name = "HEADER", value_parser = HeaderAssertion::parse)]
+    assert_header: Vec<HeaderAssertion>,
+
+    /// Require a response trailer, like --assert-header; responses without trailers fail
+    /// (repeatable)
+    #[arg(long = "assert-trailer", value_name = "TRAILER", value_parser = HeaderAssertion::parse)]
+    assert_trailer: Vec<HeaderAssertion>,
+
+    /// Check each response against HTTP semantics (a Date header, header value
+    /// characters, no body on 204 or HEAD, not both Content-Length and
+    /// Transfer-Encoding) and report the rules broken
+    #[arg(long = "lint-responses")]
+    lint_responses: bool,
+
+    /// Fail responses that break a --lint-responses rule
+    #[arg(long = "lint-strict", requires = "lint_responses")]
+    lint_strict: bool,
+
+    /// Read the rate limit the service advertises (RateLimit, X-RateLimit-*,
+    /// X-Rate-Limit-*) and report it against the achieved rate and the 429s
+    #[arg(long = "ratelimit-headers")]
+    ratelimit_headers: bool,
+
+    /// Tally the values of this response header and print the most common (repeatable)
+    #[arg(long = "collect-header", value_name = "NAME")]
+    collect_header: Vec<reqwest::header::HeaderName>,
+
+    /// Report latency percentiles separately for each value of this response header
+    #[arg(long = "group-by-header", value_name = "NAME")]
+    group_by_header: Option<reqwest::header::HeaderName>,
+
+    /// Send a per-connection identity on every request, stable for the run: `header:NAME`
+    /// or `cookie:NAME`
+    #[arg(long = "identity", value_name = "KIND:NAME", value_parser = Identity::parse)]
+    identity: Option<Identity>,
+
+    /// Send one more header on every request from this file, a `NAME: VALUE` per line,
+    /// e.g. to spread the run over many API keys
+    #[arg(long = "header-file", value_name = "FILE")]
+    header_file: Option<std::path::PathBuf>,
+
+    /// Which --header-file line each request sends
+    #[arg(long = "header-order", value_enum, default_value = "round-robin", requires = "header_file")]
+    header_order: HeaderOrder,
+
+    /// Report requests, failures and latency per --header-file line sent
+    #[arg(long = "group-by-header-sent", requires = "header_file")]
+    group_by_header_sent: bool,
+
+    /// Warn before the run when a request's header block, as the server will measure
+    /// it, is larger than this
+    #[arg(long = "max-header-bytes", value_name = "BYTES", default_value_t = 8192)]
+    max_header_bytes: u64,
+
+    /// Send a unique ULID per request in this header, and check the server echoes it back
+    #[arg(long = "request-id-header", value_name = "NAME")]
+    request_id_header: Option<reqwest::header::HeaderName>,
+
+    /// Response header naming the backend; reports connections served by more than one
+    #[arg(long = "affinity-header", value_name = "NAME")]
+    affinity_header: Option<reqwest::header::HeaderName>,
+
+    /// Request a weighted mix of URLs: one `PATH [WEIGHT]` per line, resolved against URL
+    #[arg(long = "url-file", value_name = "FILE")]
+    url_file: Option<std::path::PathBuf>,
+
+    /// Switch the whole load between two targets every --slice and compare them slice by
+    /// slice; URL may be left out
+    #[arg(long, value_name = "A_URL,B_URL", value_parser = alternate::parse, conflicts_with_all = ["url_file", "h3_alt_svc", "hosts_file"])]
+    alternate: Option<[String; 2]>,
+
+    /// How long each --alternate slice sends to one target before draining and switching
+    #[arg(long, value_name = "DURATION", default_value = "10s", value_parser = humantime::parse_duration, requires = "alternate")]
+    slice: Duration,
+
+    /// Spread the connections over these hosts, one `URL [WEIGHT]` per line, and report
+    /// each; URL may be left out
+    #[arg(long = "hosts-file", value_name = "FILE", conflicts_with_all = ["url_file", "h3_alt_svc"])]
+    hosts_file: Option<std::path::PathBuf>,
+
+    /// Run the `on_request(ctx)` and `on_response(ctx, status, headers)` hooks of this
+    /// Rhai script around every request
+    #[arg(long, value_name = "FILE")]
+    script: Option<std::path::PathBuf>,
+
+    /// Report count, error rate and latency for each URL of --url-file
+    #[arg(long = "per-url-stats", requires = "url_file")]
+    per_url_stats: bool,
+
+    /// Send a weighted mix of requests: one JSON object per line with method, path and
+    /// optionally headers, body or body_file, and weight
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["url_file", "body", "method", "preflight_origin", "alternate", "hosts_file"])]
+    workload: Option<std::path::PathBuf>,
+
+    /// Report count, error rate and latency for each entry of --workload
+    #[arg(long = "per-entry-stats", requires = "workload")]
+    per_entry_stats: bool,
+
+    /// Keep one stream per connection on a slow object and the rest on a small one, and
+    /// report small-object latency with and without a slow transfer beside it
+    #[arg(long = "hol-probe", value_name = "slow_url=URL,small_url=URL", value_parser = hol::parse, conflicts_with_all = ["url_file", "workload", "alternate", "hosts_file", "more_urls", "h3_alt_svc", "h2c_upgrade", "requests_per_connection", "dns_ttl_override", "handshake_only"])]
+    hol_probe: Option<hol::Targets>,
+
+    /// Fingerprint every successful response body and report how many variants were served
+    #[arg(long = "verify-consistency")]
+    verify_consistency: bool,
+
+    /// Fail successful responses whose body doesn't have this SHA-256 digest
+    #[arg(long = "expect-body-sha256", value_name = "HEX", value_parser = body::parse_sha256)]
+    expect_body_sha256: Option<[u8; 32]>,
+
+    /// Fail successful responses whose body isn't exactly this many bytes
+    #[arg(long = "expect-size", value_name = "BYTES", conflicts_with = "expect_size_range")]
+    expect_size: Option<u64>,
+
+    /// Fail successful responses whose body length is outside MIN-MAX (inclusive)
+    #[arg(long = "expect-size-range", value_name = "MIN-MAX", value_parser = body::parse_size_range)]
+    expect_size_range: Option<std::ops::RangeInclusive<u64>>,
+
+    /// Require a JSON body field: '$.path', '$.path == LITERAL', or a numeric
+    /// comparison with <, <=, >, >= (repeatable)
+    #[arg(long = "assert-json", value_name = "EXPR", value_parser = JsonAssertion::parse)]
+    assert_json: Vec<JsonAssertion>,
+
+    /// Fraction of successful responses to check with --assert-json (e.g. 0.1)
+    #[arg(
+        long = "assert-json-sample",
+        value_name = "RATE",
+        default_value = "1",
+        value_parser = jsonassert::parse_sample_rate,
+        requires = "assert_json"
+    )]
+    assert_json_sample: f64,
+
+    /// Warn when the first responses are HTML where JSON is expected, or the same tiny
+    /// body from a URL that looks dynamic
+    #[arg(long = "sanity-checks", default_value_t = true, action = clap::ArgAction::Set, value_name = "BOOL")]
+    sanity_checks: bool,
+
+    /// Benchmark CORS preflights: send OPTIONS requests from this origin and require a
+    /// 200/204 with matching Access-Control-Allow-* headers
+    #[arg(long = "preflight-origin", value_name = "ORIGIN", conflicts_with_all = ["body", "cors_check"])]
+    preflight_origin: Option<String>,
+
+    /// Access-Control-Request-Method sent with --preflight-origin
+    #[arg(long = "preflight-method", value_name = "METHOD", default_value = "POST", requires = "preflight_origin")]
+    preflight_method: reqwest::Method,
+
+    /// Access-Control-Request-Headers sent with --preflight-origin (comma-separated)
+    #[arg(long = "preflight-headers", value_name = "NAMES", value_delimiter = ',', requires = "preflight_origin")]
+    preflight_headers: Vec<reqwest::header::HeaderName>,
+
+    /// Send this Origin with every request and require a matching Access-Control-Allow-Origin
+    #[arg(long = "cors-check", value_name = "ORIGIN")]
+    cors_check: Option<String>,
+
+    /// Write failing responses (request line, headers, body) to files in this directory
+    #[arg(long = "error-dump", value_name = "DIR")]
+    error_dump: Option<std::path::PathBuf>,
+
+    /// Maximum number of failing responses written by --error-dump
+    #[arg(long = "error-dump-limit", value_name = "N", default_value = "50", requires = "error_dump")]
+    error_dump_limit: u64,
+
+    /// List the N slowest requests after the run
+    #[arg(long, value_name = "N")]
+    slowest: Option<usize>,
+
+    /// Stop sending after this long (e.g. 10m), let in-flight requests finish and report
+    /// the partial run
+    #[arg(long = "max-duration", value_name = "DURATION", value_parser = humantime::parse_duration)]
+    max_duration: Option<Duration>,
+
+    /// Wind down over the last part of --max-duration (e.g. 30s), stopping workers one by
+    /// one so the load decreases linearly instead of ending all at once
+    #[arg(long = "ramp-down", value_name = "DURATION", value_parser = humantime::parse_duration, requires = "max_duration")]
+    ramp_down: Option<Duration>,
+
+    /// How long a worker waits for its in-flight requests once it has nothing left to send
+    #[arg(long = "drain-timeout", value_name = "DURATION", default_value = "30s", value_parser = humantime::parse_duration)]
+    drain_timeout: Duration,
+
+    /// Exit status when --max-duration cuts the run short
+    #[arg(long = "max-duration-exit-code", value_name = "CODE", default_value = "0", requires = "max_duration")]
+    max_duration_exit_code: i32,
+
+    /// Pause a worker with exponential backoff (100ms doubling to 10s) after connection
+    /// errors, resuming full speed on the first request that gets through
+    #[arg(long = "backoff-on-connect-errors")]
+    backoff_on_connect_errors: bool,
+
+    /// Unit for latencies in the results: ms, us, s, or auto (picked from the median)
+    #[arg(long = "latency-unit", value_enum, default_value_t = LatencyUnit::Auto)]
+    latency_unit: LatencyUnit,
+
+    /// Fail the run (exit 1) if P99 latency is above this, e.g. 250ms
+    #[arg(long = "max-p99", value_name = "DURATION", value_parser = humantime::parse_duration)]
+    max_p99: Option<Duration>,
+
+    /// Fail the run (exit 1) if more than this share of requests failed, e.g. 1% or 0.01
+    #[arg(long = "max-error-rate", value_name = "RATE", value_parser = thresholds::parse_rate)]
+    max_error_rate: Option<f64>,
+
+    /// Fail the run (exit 1) if throughput is below this many requests/sec
+    #[arg(long = "min-rps", value_name = "RPS")]
+    min_rps: Option<f64>,
+
+    /// Stop the run (exit 1) as soon as throughput stays below this many requests/sec
+    /// for --for
+    #[arg(long = "abort-if-rps-below", value_name = "RPS")]
+    abort_if_rps_below: Option<f64>,
+
+    /// How long throughput must stay below --abort-if-rps-below, in whole seconds
+    #[arg(long = "for", value_name = "DURATION", default_value = "60s", value_parser = humantime::parse_duration, requires = "abort_if_rps_below")]
+    rps_window: Duration,
+
+    /// Share of a second's responses that may be 5xx or errors before it counts towards
+    /// an error burst in the status timeline
+    #[arg(long = "flap-threshold", value_name = "PERCENT", default_value_t = 5.0)]
+    flap_threshold: f64,
+
+    /// Also write the results, with every latency, as JSON for `benchy merge`
+    #[arg(long = "save-results", value_name = "PATH", conflicts_with = "handshake_only")]
+    save_results: Option<std::path::PathBuf>,
+
+    /// Write a JSON line per completed request here, which `benchy report
+    /// --from-request-log` reads back
+    #[arg(long = "request-log", value_name = "PATH", conflicts_with_all = ["handshake_only", "cache_analysis"])]
+    request_log: Option<std::path::PathBuf>,
+
+    /// Write this run's configuration, with its seed, to a manifest that
+    /// `benchy run --replay` runs again
+    #[arg(long, value_name = "PATH")]
+    record: Option<std::path::PathBuf>,
+
+    /// Environment variable holding a secret used in the arguments or files; the
+    /// manifest stores `${NAME}` in its place
+    #[arg(long = "secret-env", value_name = "NAME", requires = "record")]
+    secret_env: Vec<String>,
+
+    /// Write response counts per second and latency bucket to this file, as JSON if it
+    /// ends in .json and CSV otherwise, and draw them with the results
+    #[arg(long, value_name = "PATH", conflicts_with = "handshake_only")]
+    heatmap: Option<std::path::PathBuf>,
+
+    /// Write a JUnit XML report with one testcase per threshold
+    #[arg(long, value_name = "PATH", conflicts_with = "handshake_only")]
+    junit: Option<std::path::PathBuf>,
+
+    /// After the run, send the JSON report to this http(s):// URL: POSTed, or PUT to a
+    /// presigned S3 or GCS URL
+    #[arg(long, value_name = "URL", conflicts_with = "handshake_only")]
+    publish: Option<String>,
+
+    /// Header for --publish (repeatable), e.g. "Authorization: Bearer $TOKEN"
+    #[arg(long = "publish-header", value_name = "NAME: VALUE", value_parser = publish::parse_header, requires = "publish")]
+    publish_header: Vec<(reqwest::header::HeaderName, HeaderValue)>,
+
+    /// How --publish sends the report: auto PUTs to presigned URLs and POSTs elsewhere
+    #[arg(long = "publish-method", value_enum, default_value_t = publish::PublishMethod::Auto, requires = "publish")]
+    publish_method: publish::PublishMethod,
+
+    /// Fail the run (exit 1) if --publish couldn't deliver the report
+    #[arg(long = "publish-required", requires = "publish")]
+    publish_required: bool,
+
+    /// Serve Prometheus metrics on http://ADDR/metrics while the run lasts (e.g. 0.0.0.0:9095)
+    #[arg(long = "prometheus-listen", value_name = "ADDR", conflicts_with_all = ["handshake_only", "cache_analysis"])]
+    prometheus_listen: Option<SocketAddr>,
+
+    /// Results as human-readable text, one tab-separated row per run, or markdown tables
+    #[arg(long = "output-format", value_enum, default_value_t = OutputFormat::Text, conflicts_with = "handshake_only")]
+    output_format: OutputFormat,
+
+    /// Where results go (repeatable): human, plain (no colors), json, csv (latency
+    /// percentiles), markdown, influx or table, to stdout or to PATH, e.g.
+    /// --output human --output json=results.json
+    #[arg(long, value_name = "SINK[=PATH]", value_parser = sinks::OutputSpec::parse, conflicts_with_all = ["output_format", "handshake_only"])]
+    output: Vec<sinks::OutputSpec>,
+
+    /// Leave out the header row of --output-format table, e.g. when appending to a file
+    #[arg(long = "no-header")]
+    no_header: bool,
+
+    /// Don't append the markdown results to $GITHUB_STEP_SUMMARY when it is set
+    #[arg(long = "no-github-summary")]
+    no_github_summary: bool,
+
+    /// Share -n through one counter all workers draw from, so fast connections take on
+    /// more requests than slow ones
+    #[arg(long = "work-stealing")]
+    work_stealing: bool,
+
+    /// Print requests sent, duration and response protocol per worker, flagging stragglers
+    #[arg(long = "per-worker-stats")]
+    per_worker_stats: bool,
+
+    /// Log to stderr: -v lifecycle, -vv per-request outcomes, -vvv everything including
+    /// reqwest/h2/quinn internals (RUST_LOG overrides)
+    #[arg(short = 'v', action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Establish every connection (one HEAD request each) before starting the timer
+    #[arg(long)]
+    preconnect: bool,
+
+    /// Before starting the timer, GET every URL of the run N times (1 if not given),
+    /// to fill caches, and report their cache status headers
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "1")]
+    prime: Option<u64>,
+
+    /// Instead of the usual run, measure a cache twice: a cold phase with a unique URL
+    /// per request, then a hot phase repeating the run's URLs, and report both with the
+    /// hot phase's hit ratio
+    #[arg(long = "cache-analysis", conflicts_with = "prime")]
+    cache_analysis: bool,
+
+    /// Response header that says whether the cache hit (values containing HIT) or
+    /// missed (MISS)
+    #[arg(long = "cache-status-header", value_name = "NAME", default_value = "x-cache", requires = "cache_analysis")]
+    cache_status_header: reqwest::header::HeaderName,
+
+    /// Requests in each --cache-analysis phase [default: -n]
+    #[arg(long = "phase-requests", value_name = "N", requires = "cache_analysis")]
+    phase_requests: Option<u64>,
+
+    /// Run on even when every connection fails in the first second, instead of
+    /// stopping with a diagnosis (for availability testing)
+    #[arg(long = "keep-going")]
+    keep_going: bool,
+
+    /// Skip the HEAD request that shows the remote address, TLS session and Server
+    /// header before the run
+    #[arg(long = "no-probe")]
+    no_probe: bool,
+
+    /// Check the configuration, load every file, print the resolved settings and a
+    /// memory estimate, and exit without sending anything (`=probe`: send one request)
+    #[arg(long = "dry-run", value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "config", conflicts_with = "handshake_only")]
+    dry_run: Option<dryrun::DryRun>,
+
+    /// Probe availability instead of generating load: one request per interval (default
+    /// 1s), a line per response, and an outage summary on Ctrl-C or --max-duration. Only
+    /// GET, HEAD, OPTIONS and TRACE, without a body
+    #[arg(long = "probe-mode", value_name = "INTERVAL", num_args = 0..=1, require_equals = true, default_missing_value = "1s", value_parser = humantime::parse_duration, conflicts_with_all = ["dry_run", "handshake_only", "processes", "fail_fast", "body", "script", "workload", "url_file", "alternate", "hosts_file", "more_urls", "hol_probe", "cache_analysis", "output", "output_format", "save_results", "request_log"])]
+    probe_mode: Option<Duration>,
+
+    /// Before starting, poll the target over HTTP/1.1 with backoff for up to this long
+    /// (e.g. 60s) until it is ready; the wait doesn't count toward the run
+    #[arg(long = "wait-ready", value_name = "DURATION", value_parser = humantime::parse_duration)]
+    wait_ready: Option<Duration>,
+
+    /// Poll this path on the target's origin instead of the target (e.g. /healthz)
+    #[arg(long = "ready-path", value_name = "PATH", requires = "wait_ready")]
+    ready_path: Option<String>,
+
+    /// Status the readiness poll waits for
+    #[arg(long = "ready-status", value_name = "CODE", default_value_t = 200, requires = "wait_ready")]
+    ready_status: u16,
+
+    /// Split the connections and requests over this many child processes and merge
+    /// their results, for when one process is the bottleneck
+    #[arg(long, value_name = "N", default_value_t = 1, conflicts_with = "handshake_only")]
+    processes: usize,
+
     /// Target URL
+    #[arg(required_unless_present_any = ["alternate", "hosts_file"], default_value = "")]
     url: String,
+
+    /// More target URLs, which share the connections evenly with the first
+    #[arg(value_name = "URL", conflicts_with_all = ["url_file", "workload", "alternate", "hosts_file", "h3_alt_svc"])]
+    more_urls: Vec<String>,
+
+    /// The command line, or what `benchy run --replay` made of its manifest
+    #[arg(skip)]
+    argv: Vec<std::ffi::OsString>,
 }
 
+impl Args {
+    /// Worker tasks: `--workers`, or one per connection
+    fn tasks(&self) -> usize {
+        self.workers.unwrap_or(self.connections)
+    }
+
+    fn quic_tuning(&self) -> QuicTuning {
+        QuicTuning {
+            max_idle_timeout: self.h3_max_idle_timeout,
+            initial_max_streams: self.h3_initial_max_streams,
+            congestion: self.h3_congestion.unwrap_or_default(),
+            max_udp_payload: self.h3_max_udp_payload,
+            keep_alive_interval: self.h3_keepalive,
+        }
+    }
+}
+
+#[derive(Default)]
 struct Stats {
     success: AtomicU64,
     failed: AtomicU64,
+    errors: ErrorCounts,
+    /// Connections that went away under in-flight requests (counted per worker)
+    goaway_connections: AtomicU64,
+    /// Connections `--h2-keepalive` found dead with requests in flight
+    keepalive_connections: AtomicU64,
+    /// Requests re-sent after a GOAWAY or REFUSED_STREAM
+    retried: AtomicU64,
+    /// Negotiated version per connection, only tracked with `--protocol auto`
+    protocols: ProtocolMix,
+    /// Only tracked with `--h3-alt-svc`
+    alt_svc: AltSvcStats,
+    /// TLS handshakes against warm-connection TTFBs, shared with the connectors
+    crypto: Arc<CryptoDrift>,
+    /// Responses over a different HTTP version than requests were pinned to
+    version_mismatches: AtomicU64,
+    /// Responses that failed an `--assert-header` check
+    header_assertion_failures: AtomicU64,
+    /// Responses that failed an `--assert-trailer` check
+    trailer_assertion_failures: AtomicU64,
+    /// Responses with missing or mismatching CORS headers
+    cors_failures: AtomicU64,
+    /// One per `--collect-header`
+    header_tallies: Vec<HeaderTally>,
+    body: BodyStats,
+    /// Request headers and bodies sent
+    sent: SentBytes,
+    /// Only tracked with `--backoff-on-connect-errors`
+    backoff: BackoffStats,
+    /// Sent, completed, cancelled and abandoned requests
+    requests: RequestCounts,
+    /// `--burst` requests dropped because the pipeline was still full when the next
+    /// burst started
+    backlogged: AtomicU64,
+    /// Only tracked with `--expect-continue`
+    expect: ContinueStats,
+    /// Only tracked with `--rate` or `--burst`
+    pacing: PacingStats,
+    /// Responses `--script`'s `on_response` marked failed
+    script_failures: AtomicU64,
+    /// Only tracked with `--request-id-header`
+    request_ids: EchoStats,
+    /// Only tracked with `--prometheus-listen`
+    prometheus: Option<prometheus::Recorder>,
+    /// Responses by status class, for the status timeline
+    status_classes: StatusClasses,
+}
+
+/// Per-run request settings shared by every worker
+struct RequestConfig {
+    url: String,
+    /// `data`, pre-split into static text and per-request generators, or `--data-file`
+    body: Option<BodyTemplate>,
+    /// `--data-dir`, one of which each request sends instead of `body`
+    bodies: Option<BodyPool>,
+    fail_fast: bool,
+    download_rate: Option<u64>,
+    retry_goaway: bool,
+    /// `--retry-on`, with its budget and counters
+    status_retry: Option<StatusRetry>,
+    /// Capture Alt-Svc and count requests per protocol (`--h3-alt-svc`)
+    alt_svc: bool,
+    /// Treat version mismatches as failures (`--require-protocol`)
+    require_protocol: bool,
+    assert_headers: Vec<HeaderAssertion>,
+    assert_trailers: Vec<HeaderAssertion>,
+    /// `--lint-responses`, with its counters
+    lint: Option<Lint>,
+    /// `--ratelimit-headers`, shared with the workers' schedules under `--auto-rate`
+    rate_limits: Option<Arc<RateLimits>>,
+    /// `--preflight-origin` or `--cors-check`
+    cors: Option<CorsCheck>,
+    group_by: Option<reqwest::header::HeaderName>,
+    affinity: Option<reqwest::header::HeaderName>,
+    body_checks: BodyChecks,
+    /// `--assert-json`, with its own parse and assertion failure counters
+    json_checks: Option<JsonChecks>,
+    /// `--sanity-checks`, which looks at the first responses' bodies
+    sanity: Option<Sanity>,
+    error_dump: Option<ErrorDump>,
+    /// `--url-file` or `--workload`; without either every request goes to `url`
+    urls: Option<UrlMix>,
+    /// `--alternate`'s A and B, which the URL index picks between
+    alternate: Option<[String; 2]>,
+    /// Several targets, each sent to by its own connections
+    hosts: Option<Hosts>,
+    /// `--hol-probe`'s slow and small URLs
+    hol: Option<hol::HolProbe>,
+    /// `-m`, or what the other flags imply: OPTIONS for preflights, POST with a body
+    method: reqwest::Method,
+    /// `--script`, which each worker runs on its own engine
+    script: Option<Script>,
+    /// `--request-id-header`
+    request_id: Option<reqwest::header::HeaderName>,
+    /// `--header-file`, one line of which each request sends
+    header_file: Option<HeaderFile>,
+    /// `--compress-body`, with the static bodies already compressed
+    compression: Option<Compression>,
+}
+
+impl RequestConfig {
+    fn url(&self, index: usize) -> &str {
+        match (&self.urls, &self.alternate, &self.hosts, &self.hol) {
+            (Some(mix), _, _, _) => mix.url(index),
+            (None, Some(targets), _, _) => &targets[index],
+            (None, None, Some(hosts), _) => hosts.url(index),
+            (None, None, None, Some(hol)) => hol.url(index),
+            (None, None, None, None) => &self.url,
+        }
+    }
+
+    /// Whether requests go to more than one URL, which reports then name
+    fn several_urls(&self) -> bool {
+        self.urls.as_ref().is_some_and(|mix| mix.len() > 1)
+            || self.hosts.as_ref().is_some_and(|hosts| hosts.len() > 1)
+            || self.alternate.is_some()
+            || self.hol.is_some()
+    }
+
+    /// The `--workload` entry at `index`, if that's where requests come from
+    fn entry(&self, index: usize) -> Option<&urls::EntryRequest> {
+        self.urls.as_ref()?.request(index)
+    }
+
+    fn method(&self, index: usize) -> &reqwest::Method {
+        self.entry(index).map_or(&self.method, |entry| &entry.method)
+    }
 }
 
 #[derive(Debug)]
@@ -54,284 +895,2792 @@ struct ErrorDetails {
     status: Option<u16>,
     headers: Option<String>,
     body: Option<String>,
+    /// Only captured with `--assert-trailer`
+    trailers: Option<String>,
+}
+
+/// Whether worker `i` talks to an https:// target
+fn https_target(args: &Args, config: &RequestConfig, i: usize) -> bool {
+    match &config.hosts {
+        Some(hosts) => hosts.url(hosts.of(i)).starts_with("https://"),
+        None => args.url.starts_with("https://"),
+    }
 }
 
-fn build_client(http3: bool, insecure: bool, is_https: bool) -> Result<Client, reqwest::Error> {
+/// Worker `i`'s client, with a TLS config (and so a session cache) of its own
+fn worker_client(
+    args: &Args,
+    client_opts: &ClientOptions,
+    tls_stats: &Arc<TlsStats>,
+    is_https: bool,
+    i: usize,
+) -> Result<WorkerClient, String> {
+    let tls = build_tls(args.protocol, args.insecure, is_https, !args.no_session_resumption, tls_stats)
+        .map_err(|e| e.to_string())?;
+    let opts = ClientOptions {
+        worker: Some(i),
+        resolver: client_opts.resolver.for_worker(i),
+        ..client_opts.clone()
+    };
+    WorkerClient::new(opts, tls).map_err(|e| e.to_string())
+}
+
+/// Build the rustls config for one worker, or `None` for plaintext h2c.
+///
+/// Clients rebuilt by the same worker share this config and so its session
+/// cache, which is what lets churned connections resume.
+fn build_tls(
+    protocol: Protocol,
+    insecure: bool,
+    is_https: bool,
+    resumption: bool,
+    tls_stats: &Arc<TlsStats>,
+) -> Result<Option<Arc<rustls::ClientConfig>>, transport::BoxError> {
+    if protocol != Protocol::H3 && !is_https {
+        return Ok(None);
+    }
+
+    transport::tls_config(protocol.alpn(), insecure, resumption, tls_stats).map(Some)
+}
+
+/// Client settings shared by every worker
+#[derive(Clone)]
+struct ClientOptions {
+    protocol: Protocol,
+    conn_stats: Arc<ConnectStats>,
+    resolver: Arc<BenchResolver>,
+    /// Dial this address for the host instead of resolving it (Alt-Svc alternatives)
+    resolve_override: Option<(String, SocketAddr)>,
+    /// Replace the client after this many requests (`--requests-per-connection`)
+    requests_per_connection: Option<u64>,
+    /// Replace the client once it is this old (`--dns-ttl-override`)
+    max_client_age: Option<Duration>,
+    /// Send over our own QUIC connection with these parameters instead of reqwest's (`--h3`)
+    quic: Option<QuicTuning>,
+    /// `--proxy`
+    proxy: Option<String>,
+    /// Send over our own HTTP/1.1 connections with `Expect: 100-continue` (`--expect-continue`)
+    expect_continue: bool,
+    /// HTTP/2 PING interval and timeout (`--h2-keepalive`)
+    h2_keepalive: Option<Duration>,
+    /// Give up on setting up a connection after this long (`--connect-timeout`)
+    connect_timeout: Duration,
+    /// Send over our own connections upgraded to HTTP/2 with `Upgrade: h2c`, counted
+    /// here (`--h2c-upgrade`)
+    h2c_upgrade: Option<Arc<UpgradeStats>>,
+    /// The worker a client is for, logged with its connections; `None` for probes
+    worker: Option<usize>,
+}
+
+/// Milliseconds to stall every client's construction for; lets tests/phases.rs make
+/// setup slow without a slow server. Debug builds only.
+#[cfg(debug_assertions)]
+const SLOW_CLIENT_ENV: &str = "BENCHY_TEST_SLOW_CLIENT_MS";
+
+fn build_client(
+    opts: &ClientOptions,
+    tls: Option<&Arc<rustls::ClientConfig>>,
+) -> Result<Client, reqwest::Error> {
+    #[cfg(debug_assertions)]
+    if let Some(ms) = std::env::var(SLOW_CLIENT_ENV).ok().and_then(|ms| ms.parse().ok()) {
+        std::thread::sleep(Duration::from_millis(ms));
+    }
+    tracing::info!(
+        protocol = opts.protocol.name(),
+        resolve_override = ?opts.resolve_override,
+        "building client"
+    );
     let mut builder = Client::builder()
         .pool_max_idle_per_host(1)
-        .pool_idle_timeout(Duration::from_secs(30));
+        .pool_idle_timeout(Duration::from_secs(30))
+        // Retries are ours to make and count (see --retry-goaway)
+        .retry(reqwest::retry::never())
+        .dns_resolver(opts.resolver.clone())
+        .connect_timeout(opts.connect_timeout)
+        .connector_layer(ConnectTimingLayer::new(
+            opts.conn_stats.clone(),
+            opts.proxy.is_some(),
+            opts.worker,
+            opts.protocol == Protocol::H2 && tls.is_none(),
+            tls.is_some() && matches!(opts.protocol, Protocol::H2 | Protocol::Auto),
+        ));
+
+    if let Some(proxy) = &opts.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+
+    if let Some((host, addr)) = &opts.resolve_override {
+        builder = builder.resolve(host, *addr);
+    }
+
+    // Only takes effect on HTTP/2 connections
+    if let Some(keepalive) = opts.h2_keepalive {
+        builder = builder
+            .http2_keep_alive_interval(keepalive)
+            .http2_keep_alive_timeout(keepalive)
+            .http2_keep_alive_while_idle(true);
+    }
+
+    if let Some(tls) = tls {
+        // Our own rustls config carries -k and the handshake counters
+        builder = builder.use_preconfigured_tls(rustls::ClientConfig::clone(tls));
+    }
 
-    if http3 {
+    match opts.protocol {
+        // Requests go out over quic.rs; this client only builds them
+        Protocol::H3 if opts.quic.is_some() => {}
         // HTTP/3 always uses QUIC (encrypted)
-        builder = builder.http3_prior_knowledge();
-    } else if is_https {
+        Protocol::H3 => builder = builder.http3_prior_knowledge(),
+        Protocol::H1 => builder = builder.http1_only(),
         // HTTPS: prefer HTTP/2 via ALPN, enable adaptive window for better performance
-        builder = builder
-            .use_rustls_tls()
-            .http2_adaptive_window(true);
-    } else {
+        _ if tls.is_some() => builder = builder.http2_adaptive_window(true),
+        // Plain HTTP without prior knowledge: HTTP/1.1
+        Protocol::Auto => {}
         // Plain HTTP: use h2c (HTTP/2 over cleartext)
-        builder = builder.http2_prior_knowledge();
+        Protocol::H2 => builder = builder.http2_prior_knowledge(),
     }
 
-    if insecure {
-        builder = builder
-            .danger_accept_invalid_certs(true)
-            .danger_accept_invalid_hostnames(true);
+    builder.build()
+}
+
+/// A reqwest client, plus our own HTTP/3 connection when requests go over quic.rs,
+/// HTTP/1.1 connections when they go over expect.rs, or an upgraded HTTP/2 connection
+/// when they go over upgrade.rs. Requests are always built with the reqwest client.
+#[derive(Clone)]
+struct HttpClient {
+    reqwest: Client,
+    h3: Option<Arc<H3Client>>,
+    expect: Option<Arc<ContinueClient>>,
+    upgrade: Option<Arc<UpgradeClient>>,
+}
+
+impl HttpClient {
+    fn new(opts: &ClientOptions, tls: Option<&Arc<rustls::ClientConfig>>) -> Result<Self, BoxError> {
+        let h3 = match (&opts.quic, tls) {
+            (Some(tuning), Some(tls)) => {
+                Some(Arc::new(H3Client::new(
+                    tls,
+                    tuning,
+                    opts.resolver.clone(),
+                    opts.conn_stats.clone(),
+                    opts.connect_timeout,
+                )?))
+            }
+            _ => None,
+        };
+        let expect = opts.expect_continue.then(|| {
+            Arc::new(ContinueClient::new(
+                tls.cloned(),
+                opts.resolver.clone(),
+                opts.conn_stats.clone(),
+                opts.connect_timeout,
+            ))
+        });
+        let upgrade = opts.h2c_upgrade.as_ref().map(|stats| {
+            Arc::new(UpgradeClient::new(
+                opts.resolver.clone(),
+                opts.conn_stats.clone(),
+                stats.clone(),
+                opts.connect_timeout,
+            ))
+        });
+        Ok(HttpClient {
+            reqwest: build_client(opts, tls)?,
+            h3,
+            expect,
+            upgrade,
+        })
     }
 
-    builder.build()
+    async fn send(&self, request: RequestBuilder) -> Result<reqwest::Response, BoxError> {
+        self.execute(request.build()?).await
+    }
+
+    async fn execute(&self, request: reqwest::Request) -> Result<reqwest::Response, BoxError> {
+        match (&self.h3, &self.expect, &self.upgrade) {
+            (Some(h3), _, _) => h3.send(request).await,
+            (_, Some(expect), _) => expect.send(request).await,
+            (_, _, Some(upgrade)) => upgrade.send(request).await,
+            _ => Ok(self.reqwest.execute(request).await?),
+        }
+    }
+}
+
+/// A worker's current client.
+///
+/// The client is replaced (and with it the connection and its DNS lookup) for
+/// connection churn, `--dns-ttl-override`, or the switch to HTTP/3 after Alt-Svc
+/// discovery. The old client is dropped once its in-flight requests complete.
+struct WorkerClient {
+    opts: ClientOptions,
+    tls: Option<Arc<rustls::ClientConfig>>,
+    client: HttpClient,
+    built: Instant,
+    /// Requests taken from this client so far
+    sent: u64,
+    /// The last replacement failed, so the next request tries again
+    rebuild: bool,
+}
+
+/// A worker's client couldn't be replaced mid-run, e.g. out of file descriptors for a
+/// new UDP socket; the request that needed it fails as a client resources error
+#[derive(Debug)]
+struct RebuildError(BoxError);
+
+impl std::fmt::Display for RebuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "could not replace the worker's client")
+    }
+}
+
+impl std::error::Error for RebuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.0.as_ref())
+    }
+}
+
+impl WorkerClient {
+    fn new(opts: ClientOptions, tls: Option<Arc<rustls::ClientConfig>>) -> Result<Self, BoxError> {
+        let client = HttpClient::new(&opts, tls.as_ref())?;
+        Ok(WorkerClient {
+            opts,
+            tls,
+            client,
+            built: Instant::now(),
+            sent: 0,
+            rebuild: false,
+        })
+    }
+
+    /// The client for the next request, or why it couldn't be replaced, and the version
+    /// to pin it to
+    fn next(&mut self) -> (Result<HttpClient, RebuildError>, Option<Version>) {
+        let sent = self.sent;
+        self.sent += 1;
+        let quota_reached = self
+            .opts
+            .requests_per_connection
+            .is_some_and(|n| sent > 0 && sent.is_multiple_of(n));
+        let too_old = self.opts.max_client_age.is_some_and(|age| self.built.elapsed() >= age);
+
+        if quota_reached || too_old || self.rebuild {
+            // The configuration built at startup, but a new client needs new sockets
+            match HttpClient::new(&self.opts, self.tls.as_ref()) {
+                Ok(client) => {
+                    self.client = client;
+                    self.built = Instant::now();
+                    self.rebuild = false;
+                }
+                Err(e) => {
+                    tracing::info!(error = %e, "could not replace the worker's client");
+                    self.rebuild = true;
+                    return (Err(RebuildError(e)), self.opts.protocol.version());
+                }
+            }
+        }
+        (Ok(self.client.clone()), self.opts.protocol.version())
+    }
+}
+
+/// Run-wide connection indexes `0..connections` in contiguous runs for `workers`, the
+/// first `connections % workers` one larger
+fn deal(connections: usize, workers: usize) -> Vec<std::ops::Range<usize>> {
+    let (base, remainder) = (connections / workers, connections % workers);
+    let mut first = 0;
+    (0..workers)
+        .map(|worker| {
+            let len = base + usize::from(worker < remainder);
+            first += len;
+            first - len..first
+        })
+        .collect()
+}
+
+/// `connections`' share of -n, given each connection's and how many of the first
+/// connections take one more
+fn quota(connections: std::ops::Range<usize>, per_connection: u64, remainder: u64) -> u64 {
+    let extra = connections.clone().filter(|&c| (c as u64) < remainder).count() as u64;
+    per_connection * connections.len() as u64 + extra
+}
+
+/// One worker's connections, which its requests take in turn
+struct ClientPool {
+    clients: Vec<WorkerClient>,
+    /// Index of the first among the run's connections
+    first: usize,
+    turn: usize,
+}
+
+impl ClientPool {
+    /// Deal `clients` out to `workers` in contiguous runs (see [`deal`])
+    fn split(clients: Vec<WorkerClient>, workers: usize) -> Vec<ClientPool> {
+        let ranges = deal(clients.len(), workers);
+        let mut clients = clients.into_iter();
+        ranges
+            .into_iter()
+            .map(|range| ClientPool {
+                first: range.start,
+                clients: clients.by_ref().take(range.len()).collect(),
+                turn: 0,
+            })
+            .collect()
+    }
+
+    fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Run-wide indexes of the pool's connections
+    fn connections(&self) -> std::ops::Range<usize> {
+        self.first..self.first + self.len()
+    }
+
+    /// The pool's share of -n (see [`quota`])
+    fn quota(&self, per_connection: u64, remainder: u64) -> u64 {
+        quota(self.connections(), per_connection, remainder)
+    }
+
+    /// The run-wide index of the connection the next request goes out on
+    fn current(&self) -> usize {
+        self.first + self.turn
+    }
+
+    fn next(&mut self) -> (Result<HttpClient, RebuildError>, Option<Version>) {
+        let next = self.clients[self.turn].next();
+        self.turn = (self.turn + 1) % self.clients.len();
+        next
+    }
+
+    /// The only client, for Alt-Svc discovery, which `--workers` is rejected with
+    fn only(&mut self) -> &mut WorkerClient {
+        &mut self.clients[0]
+    }
+}
+
+/// Timing of a single completed request
+struct Sample {
+    /// Time until the response body was fully consumed
+    latency: Duration,
+    /// Time until response headers arrived (server TTFB)
+    ttfb: Duration,
+    /// Time until the request body was fully written, for requests with a body
+    upload: Option<Duration>,
+    /// From the worker queueing the request to it going out (`Outgoing::pushed`)
+    client_queue: Duration,
+    /// An attempt at this request hit a GOAWAY
+    goaway: bool,
+    /// The response's Alt-Svc header, only captured with `--h3-alt-svc`
+    alt_svc: Option<HeaderValue>,
+    /// Value of the `--group-by-header` header, "(none)" when absent
+    group: Option<String>,
+    /// Value of the `--affinity-header` header, if asked for and present
+    backend: Option<String>,
+    sent_at: Instant,
+    /// `None` when no response arrived
+    status: Option<u16>,
+    /// Why no response arrived
+    error: Option<errors::ErrorKind>,
+    /// Index of the worker that sent the request, filled in by the worker
+    worker: usize,
+    /// The worker's submission index, filled in by the worker
+    seq: u64,
+    /// `--request-id-header` value sent, filled in by the worker
+    request_id: Option<HeaderValue>,
+    /// Place in its burst under `--burst`, filled in by the worker
+    burst: Option<arrival::Position>,
+    /// How long after its `--rate` schedule the request was sent, filled in by the worker
+    queued: Duration,
+    /// Index into `--url-file`, `--alternate`'s target, or the host; 0 without any
+    url: usize,
+    /// `--alternate` slice the request was sent in, filled in by the worker
+    slice: Option<u64>,
+    /// A `--hol-probe` small request overlapped a slow transfer on its connection,
+    /// filled in by the worker
+    contended: bool,
+    /// Index into `--data-dir` of the body sent
+    body_file: Option<usize>,
+    /// Line of `--header-file` sent
+    header: Option<usize>,
+    /// Time until 100 Continue arrived, with `--expect-continue`
+    continued: Option<Duration>,
+    /// What kept a failed request from reaching the server, with its error chain
+    unreachable: Option<String>,
+    /// `None` when no response arrived
+    version: Option<Version>,
+}
+
+/// What a worker sends next
+struct Outgoing<'a> {
+    /// Index into `--url-file`, `--alternate`'s target, or the host; 0 without any
+    url: usize,
+    body: Option<bytes::Bytes>,
+    /// Index into `--data-dir` of the body
+    body_file: Option<usize>,
+    /// The request as `--script`'s `on_request` left it, and the worker's engine to run
+    /// `on_response` on
+    script: Option<(&'a Mutex<WorkerScript>, script::Request)>,
+    /// `--request-id-header` value
+    request_id: Option<HeaderValue>,
+    /// Line of `--header-file` to send
+    header: Option<usize>,
+    /// When the worker queued the request
+    pushed: Instant,
 }
 
 enum RequestResult {
-    Success(Duration),
-    Failed(Duration),
+    Success(Sample),
+    Failed(Sample),
     Error(ErrorDetails),
 }
 
+struct Percentiles {
+    count: usize,
+    avg: Duration,
+    p50: Duration,
+    p95: Duration,
+    p99: Duration,
+}
+
+impl Percentiles {
+    fn from_unsorted(samples: &mut [Duration]) -> Self {
+        samples.sort_unstable();
+
+        let len = samples.len();
+        let avg = if len > 0 {
+            samples.iter().sum::<Duration>() / len as u32
+        } else {
+            Duration::ZERO
+        };
+
+        Percentiles {
+            count: len,
+            avg,
+            p50: samples.get(len / 2).copied().unwrap_or_default(),
+            p95: samples.get(len * 95 / 100).copied().unwrap_or_default(),
+            p99: samples.get(len * 99 / 100).copied().unwrap_or_default(),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+    // `benchy merge`, `benchy report`, `benchy serve`, `benchy selftest` and `benchy run`
+    // are the only subcommands; everything else takes a URL
+    let argv = match std::env::args_os().nth(1) {
+        Some(arg) if arg == "merge" => return merge::run(),
+        Some(arg) if arg == "report" => return report::run(),
+        Some(arg) if arg == "serve" => return serve::run().await,
+        Some(arg) if arg == "selftest" => return selftest::run().await,
+        Some(arg) if arg == "run" => manifest::replay()?,
+        _ => std::env::args_os().collect(),
+    };
+    let mut args = Args::parse_from(&argv);
+    args.argv = argv;
+    logging::init(args.verbose);
+    let child = processes::Slot::from_env()?;
+    if let Some(slot) = &child {
+        slot.apply(&mut args);
+    }
+    if args.http3 {
+        args.protocol = Protocol::H3;
+    }
+    args.http3 = args.protocol == Protocol::H3;
+
+    preflight::check(&args)?;
+    let outputs = sinks::specs(&args.output, args.output_format);
+    // Everything but the text report keeps stdout to the results
+    let text = outputs.iter().any(sinks::OutputSpec::is_text);
+    if outputs.iter().any(|output| output.kind == sinks::SinkKind::Plain) {
+        colored::control::set_override(false);
+    }
+    if args.h2c_upgrade {
+        args.protocol = Protocol::H2;
+    }
+    if let Some([a, _]) = &args.alternate {
+        // The banner, the probes and --wait-ready look at A
+        args.url = a.clone();
+    }
+    let hosts = match &args.hosts_file {
+        Some(path) => Some(Hosts::load(path, args.connections)?),
+        None if !args.more_urls.is_empty() => {
+            let urls = std::iter::once(args.url.clone()).chain(args.more_urls.iter().cloned()).collect();
+            Some(Hosts::new(urls, args.connections)?)
+        }
+        None => None,
+    };
+    if let Some(hosts) = &hosts {
+        // The banner, the probes and --wait-ready look at the first host
+        args.url = hosts.url(0).to_string();
+    }
+    // Pinned here, before --processes splits it between children, so the manifest
+    // has the seed the run used
+    if let Some(path) = args.record.as_ref().filter(|_| args.dry_run.is_none()) {
+        args.seed = Some(args.seed.unwrap_or_else(rand::random));
+        manifest::record(path, &args)?;
+        let notice = render::line("Recorded:".white(), path.display());
+        if text {
+            println!("{}", notice);
+        } else {
+            eprintln!("{}", notice);
+        }
+    }
+
+    // Before anything else touches the target, including the protocol and stream
+    // limit probes
+    if let Some(timeout) = args.wait_ready.filter(|_| args.dry_run.is_none()) {
+        match ready::wait(&args, timeout).await {
+            Ok(ready) => {
+                let notice = render::line(
+                    "Ready:".white(),
+                    format!("{} after {:.1?} ({} attempts)", ready.url, ready.elapsed, ready.attempts),
+                );
+                if text {
+                    println!("{}", notice);
+                } else {
+                    eprintln!("{}", notice);
+                }
+            }
+            Err(message) => {
+                print_error_details(&ErrorDetails {
+                    message,
+                    status: None,
+                    headers: None,
+                    body: None,
+                    trailers: None,
+                });
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Children warn for their own share of -c
+    if args.processes <= 1 {
+        limits::warn(args.connections);
+    }
+    if args.handshake_only {
+        return handshake::run(args).await;
+    }
+    // A dry run checks the children's configuration in this process
+    if args.processes > 1 && args.dry_run.is_none() {
+        return processes::run(args).await;
+    }
+
+    // Step down until a protocol the server actually speaks is found
+    let requested = args.protocol;
+    if args.fallback && args.dry_run.is_none() {
+        loop {
+            let Err(failure) = protocol::probe(&args).await else {
+                break;
+            };
+            let reason = failure.reason;
+            if !failure.negotiation {
+                // A lower protocol would fail to reach the server just the same
+                print_error_details(&ErrorDetails {
+                    message: format!("{} probe failed before negotiating: {}", args.protocol.label(), reason),
+                    status: None,
+                    headers: None,
+                    body: None,
+                    trailers: None,
+                });
+                std::process::exit(1);
+            }
+            let Some(next) = args.protocol.fallback() else {
+                print_error_details(&ErrorDetails {
+                    message: format!("no protocol could be negotiated, {} failed: {}", args.protocol.label(), reason),
+                    status: None,
+                    headers: None,
+                    body: None,
+                    trailers: None,
+                });
+                std::process::exit(1);
+            };
+            let notice = render::line(
+                "Fallback:".yellow(),
+                format!("{} unavailable, trying {}\n  caused by: {}", args.protocol.label(), next.label(), reason),
+            );
+            if text {
+                println!("{}", notice);
+            } else {
+                eprintln!("{}", notice);
+            }
+            args.protocol = next;
+        }
+        args.http3 = args.protocol == Protocol::H3;
+    }
+    // Streams beyond the server's limit would queue inside h2 and show up as latency
+    let requested_pipeline = args.pipeline;
+    // The probe dials the target directly, so it's skipped behind a proxy, and with
+    // prior knowledge, which an --h2c-upgrade target may not speak
+    if args.protocol == Protocol::H2
+        && !args.ignore_server_stream_limit
+        && args.proxy.is_none()
+        && !args.h2c_upgrade
+        && args.dry_run.is_none()
+    {
+        match server_stream_limit(&args).await {
+            Ok(Some(limit)) if limit < args.pipeline => args.pipeline = limit.max(1),
+            Ok(_) => {}
+            Err(e) => eprintln!("{} could not read the server's stream limit: {}", "Warning:".yellow(), e),
+        }
+    }
+
+    let protocol = if args.h3_alt_svc {
+        "HTTP/2 -> HTTP/3 via Alt-Svc"
+    } else {
+        args.protocol.label()
+    };
 
-    let protocol = if args.http3 { "HTTP/3" } else { "HTTP/2" };
-    let expected_version = if args.http3 { Version::HTTP_3 } else { Version::HTTP_2 };
+    let is_https = args.url.starts_with("https://");
 
+    let conn_stats = Arc::new(ConnectStats::default());
     let stats = Arc::new(Stats {
-        success: AtomicU64::new(0),
-        failed: AtomicU64::new(0),
+        header_tallies: args.collect_header.iter().cloned().map(HeaderTally::new).collect(),
+        prometheus: args.prometheus_listen.map(|_| prometheus::Recorder::new()),
+        crypto: conn_stats.crypto.clone(),
+        ..Default::default()
     });
 
-    let (tx, mut rx) = mpsc::unbounded_channel::<RequestResult>();
-    let abort_flag = Arc::new(AtomicBool::new(false));
+    let tls_stats = Arc::new(TlsStats::default());
+    let dns_stats = Arc::new(DnsStats::default());
+    let upgrade_stats = args.h2c_upgrade.then(|| Arc::new(UpgradeStats::default()));
+
+    let client_opts = ClientOptions {
+        protocol: args.protocol,
+        conn_stats: conn_stats.clone(),
+        resolver: Arc::new(BenchResolver::new(
+            args.dns_server,
+            args.dns_ttl_override,
+            args.address_order,
+            args.spread_dns,
+            dns_stats.clone(),
+        )),
+        resolve_override: None,
+        requests_per_connection: args.requests_per_connection,
+        max_client_age: args.dns_ttl_override,
+        quic: args.http3.then(|| args.quic_tuning()),
+        proxy: args.proxy.clone(),
+        expect_continue: args.expect_continue,
+        h2_keepalive: args.h2_keepalive,
+        connect_timeout: args.connect_timeout,
+        h2c_upgrade: upgrade_stats.clone(),
+        // Set for each worker's clients
+        worker: None,
+    };
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<RequestResult>();
+    let cancel = CancellationToken::new();
+
+    let error_dump = match &args.error_dump {
+        Some(dir) => Some(
+            ErrorDump::create(dir, args.error_dump_limit)
+                .map_err(|e| format!("--error-dump {}: {}", dir.display(), e))?,
+        ),
+        None => None,
+    };
+
+    let mut request_log = match &args.request_log {
+        Some(path) => {
+            Some(RequestLog::create(path).map_err(|e| format!("--request-log {}: {}", path.display(), e))?)
+        }
+        None => None,
+    };
+
+    let urls = match (&args.url_file, &args.workload) {
+        (Some(path), _) => Some(UrlMix::load(path, &args.url)?),
+        (None, Some(path)) => Some(workload::load(path, &args.url)?),
+        (None, None) => None,
+    };
+    let script = match &args.script {
+        Some(path) => Some(Script::load(path)?),
+        None => None,
+    };
+    let body = match (&args.data, &args.data_file) {
+        (Some(data), _) => Some(BodyTemplate::parse(data)),
+        (None, Some(path)) => {
+            let body = payload::load(path, args.data_file_encoding).map_err(|e| format!("--data-file {}", e))?;
+            payload::warn_size("--data-file", body.len() as u64);
+            Some(BodyTemplate::fixed(body))
+        }
+        (None, None) => None,
+    };
+    let bodies = match &args.data_dir {
+        Some(dir) => {
+            let pool = BodyPool::load(dir, args.data_file_encoding).map_err(|e| format!("--data-dir {}", e))?;
+            payload::warn_size("--data-dir", pool.size());
+            Some(pool)
+        }
+        None => None,
+    };
+
+    let compression = args
+        .compress_body
+        .map(|codec| Compression::new(codec, body.as_ref(), bodies.as_ref(), urls.as_ref()));
+
+    let sanity = args.sanity_checks.then(|| {
+        let body = body.as_ref().map(|template| template.render(&mut template::BodyGenerator::new(0, 0)));
+        Sanity::new(!args.assert_json.is_empty(), body.as_deref())
+    });
+
+    let header_file = match &args.header_file {
+        Some(path) => Some(HeaderFile::load(path, args.header_order).map_err(|e| format!("--header-file {}", e))?),
+        None => None,
+    };
+
+    let cors = match (&args.preflight_origin, &args.cors_check) {
+        (Some(origin), _) => Some(CorsCheck::new(
+            origin,
+            Some(Preflight {
+                method: args.preflight_method.clone(),
+                headers: args.preflight_headers.clone(),
+            }),
+        )?),
+        (None, Some(origin)) => Some(CorsCheck::new(origin, None)?),
+        (None, None) => None,
+    };
+
+    let config = Arc::new(RequestConfig {
+        url: args.url.clone(),
+        body,
+        bodies,
+        // So a failed --dry-run=probe comes with its details
+        fail_fast: args.fail_fast || args.dry_run == Some(dryrun::DryRun::Probe),
+        download_rate: args.download_rate,
+        retry_goaway: args.retry_goaway,
+        status_retry: (!args.retry_on.is_empty())
+            .then(|| StatusRetry::new(args.retry_on.clone(), args.retry_budget, args.requests)),
+        alt_svc: args.h3_alt_svc,
+        require_protocol: args.require_protocol,
+        assert_headers: args.assert_header.clone(),
+        assert_trailers: args.assert_trailer.clone(),
+        lint: args.lint_responses.then(|| Lint::new(args.lint_strict)),
+        rate_limits: args
+            .ratelimit_headers
+            .then(|| Arc::new(RateLimits::new(args.rate.filter(|_| args.auto_rate), args.tasks()))),
+        cors,
+        group_by: args.group_by_header.clone(),
+        affinity: args.affinity_header.clone(),
+        body_checks: BodyChecks {
+            consistency: args.verify_consistency,
+            expect_sha256: args.expect_body_sha256,
+            expect_size: args.expect_size.map(|n| n..=n).or(args.expect_size_range.clone()),
+        },
+        json_checks: (!args.assert_json.is_empty())
+            .then(|| JsonChecks::new(args.assert_json.clone(), args.assert_json_sample)),
+        sanity,
+        error_dump,
+        urls,
+        alternate: args.alternate.clone(),
+        hosts,
+        hol: match &args.hol_probe {
+            Some(targets) => Some(hol::HolProbe::new(targets, &args.url)?),
+            None => None,
+        },
+        method: match &args.method {
+            Some(method) => method.clone(),
+            None if args.preflight_origin.is_some() => reqwest::Method::OPTIONS,
+            None if args.data.is_some() || args.data_file.is_some() || args.data_dir.is_some() => {
+                reqwest::Method::POST
+            }
+            None => reqwest::Method::GET,
+        },
+        script,
+        request_id: args.request_id_header.clone(),
+        header_file,
+        compression,
+    });
+    let seed = args.seed.unwrap_or_else(rand::random);
+    // Before the banner, so a request reqwest won't build doesn't fail once per worker
+    preflight::request(&args, &config, seed)?;
+    if let Some(warning) = preflight::header_size(&args, &config, seed) {
+        eprintln!("{} {}", "Warning:".yellow(), warning);
+    }
+
+    let discovery = if args.h3_alt_svc {
+        let url = reqwest::Url::parse(&args.url)?;
+        Some(Arc::new(Discovery {
+            origin_host: url.host_str().unwrap_or_default().to_string(),
+            insecure: args.insecure,
+            resumption: !args.no_session_resumption,
+            require_h3: args.require_h3,
+            tls_stats: tls_stats.clone(),
+        }))
+    } else {
+        None
+    };
+
+    if text {
+        let details = match args.probe_mode {
+            Some(interval) => format!(
+                "({}) probing every {} until interrupted",
+                protocol.magenta(),
+                humantime::format_duration(interval).to_string().green()
+            ),
+            None => format!(
+                "({}) with {} connections x {} streams = {} concurrency, {} total requests",
+                protocol.magenta(),
+                args.connections.to_string().green(),
+                args.pipeline.to_string().green(),
+                (args.connections * args.pipeline).to_string().green().bold(),
+                args.requests.to_string().green()
+            ),
+        };
+        println!("{}", render::banner(&args.url, details));
+        if let Some(tuning) = &client_opts.quic {
+            render::print("QUIC:".white(), tuning.describe());
+        }
+        if let Some(proxy) = &args.proxy {
+            render::print("Proxy:".white(), proxy);
+        }
+        if let (Some(mix), Some(path)) = (&config.urls, &args.url_file) {
+            render::print("URLs:".white(), format!("{} weighted from {}", mix.len(), path.display()));
+        }
+        if let (Some(mix), Some(path)) = (&config.urls, &args.workload) {
+            render::print("Workload:".white(), format!("{} entries weighted from {}", mix.len(), path.display()));
+        }
+        if let Some(hosts) = &config.hosts {
+            render::print("Hosts:".white(), hosts.describe());
+        }
+        if let Some(hol) = &config.hol {
+            render::print("HOL probe:".white(), hol.describe());
+        }
+        if let Some(workers) = args.workers {
+            let (each, extra) = (args.connections / workers, args.connections % workers);
+            let each = if extra == 0 { each.to_string() } else { format!("{}-{}", each, each + 1) };
+            render::print(
+                "Topology:".white(),
+                format!(
+                    "{} workers driving {} connections, {} each in turn",
+                    workers.to_string().green(),
+                    args.connections,
+                    each
+                ),
+            );
+        }
+        if args.fill != Fill::Eager {
+            render::print("Fill:".white(), args.fill.describe());
+        }
+        if let Some([a, b]) = &args.alternate {
+            render::print(
+                "Alternate:".white(),
+                format!("A {} and B {}, switching every {}", a, b, humantime::format_duration(args.slice)),
+            );
+        }
+        if let (Some(pool), Some(dir)) = (&config.bodies, &args.data_dir) {
+            render::print(
+                "Bodies:".white(),
+                format!("{} files from {}, {} in memory", pool.len(), dir.display(), resources::mib(pool.size())),
+            );
+        }
+        if let (Some(file), Some(path)) = (&config.header_file, &args.header_file) {
+            render::print("Headers:".white(), format!("{} from {}", file.describe(), path.display()));
+        }
+        if let Some(path) = &args.script {
+            render::print("Script:".white(), path.display());
+        }
+        if let Some(codec) = args.compress_body {
+            render::print("Compression:".white(), format!("Content-Encoding: {}", codec.name()));
+        }
+        match (&args.identity, &args.affinity_header) {
+            (Some(identity), Some(header)) => {
+                render::print(
+                    "Affinity:".white(),
+                    format!("{} per connection, backend from {}", identity.describe(), header),
+                )
+            }
+            (Some(identity), None) => render::print(
+                "Identity:".white(),
+                format!("{} per connection", identity.describe()),
+            ),
+            (None, Some(header)) => render::print("Affinity:".white(), format!("backend from {}", header)),
+            (None, None) => {}
+        }
+        if args.dry_run.is_none() {
+            endpoint::print(&args).await;
+        }
+    }
+    if args.pipeline < requested_pipeline {
+        let note = render::wrapped(
+            "Note:".yellow().bold(),
+            format!(
+                "server limits concurrent streams to {}; effective concurrency reduced from {} to {} per connection",
+                args.pipeline, requested_pipeline, args.pipeline
+            )
+            .yellow()
+            .bold(),
+        );
+        if text {
+            println!("{}", note);
+        } else {
+            eprintln!("{}", note);
+        }
+    }
+
+    // Static bodies were compressed above; these are compressed again for every request
+    let generated = config.body.as_ref().is_some_and(|template| !template.is_static()) || config.script.is_some();
+    if config.compression.is_some() && generated {
+        let warning = render::wrapped(
+            "Warning:".yellow().bold(),
+            "--compress-body compresses each generated body as it is sent, which costs client CPU per request; \
+             see Per request under Body Compression"
+                .yellow(),
+        );
+        if text {
+            println!("{}", warning);
+        } else {
+            eprintln!("{}", warning);
+        }
+    }
+
+    if args.print_sample_body {
+        if let Some(template) = &config.body {
+            // Worker 0 starts from the same seed, so this is its first body
+            let sample = template.render(&mut BodyGenerator::new(seed, 0));
+            let sample = render::line("Sample body:".white(), String::from_utf8_lossy(&sample));
+            let seed = (!template.is_static()).then(|| render::line("Seed:".white(), seed));
+            for line in std::iter::once(sample).chain(seed) {
+                if text {
+                    println!("{}", line);
+                } else {
+                    eprintln!("{}", line);
+                }
+            }
+        }
+    }
+
+    if let Some(mode) = args.dry_run {
+        return dryrun::run(mode, &args, &config, &client_opts, seed).await;
+    }
+    if let Some(interval) = args.probe_mode {
+        return probe::run(interval, &args, &config, &client_opts, seed).await;
+    }
+    let mut sinks = sinks::Sinks::open(&outputs, &args.url, args.no_header)?;
+
+    // Nothing from here until the first send counts towards throughput
+    let setup = phases::Setup::begin();
+
+    // Each worker gets its own TLS config (and so its own session cache) and client
+    let mut workers = Vec::with_capacity(args.connections);
+    for i in 0..args.connections {
+        workers.push(worker_client(&args, &client_opts, &tls_stats, https_target(&args, &config, i), i)?);
+    }
+
+    if args.preconnect {
+        match preconnect(&workers, &config).await {
+            Ok(elapsed) if text => render::print("Preconnect:".white(), format!("{:?}", elapsed)),
+            Ok(_) => {}
+            Err(err) => {
+                print_error_details(&err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if args.cache_analysis {
+        let requests = args.phase_requests.unwrap_or(args.requests);
+        let mut analysis = cacheanalysis::run(
+            &workers,
+            &config,
+            &args.cache_status_header,
+            requests,
+            args.connections * args.pipeline,
+        )
+        .await;
+        if let Some(path) = &args.save_results {
+            if let Err(e) = std::fs::write(path, analysis.to_json().to_string()) {
+                eprintln!("{} could not write {}: {}", "Warning:".yellow(), path.display(), e);
+            }
+        }
+        analysis.print(args.latency_unit);
+        return Ok(());
+    }
+
+    if let Some(per_url) = args.prime.filter(|&n| n > 0) {
+        match prime::run(&workers, &config, per_url, args.rate).await {
+            Ok(primed) => {
+                for line in primed.lines() {
+                    if text {
+                        println!("{}", line);
+                    } else {
+                        eprintln!("{}", line);
+                    }
+                }
+            }
+            Err(err) => {
+                print_error_details(&err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let metrics = match args.prometheus_listen {
+        Some(addr) => {
+            let endpoint = prometheus::Endpoint::start(addr).await?;
+            if let Some(recorder) = &stats.prometheus {
+                endpoint.publish(recorder, prometheus::Gauges::of(&stats));
+            }
+            if text {
+                render::print("Metrics:".white(), format!("http://{}/metrics", endpoint.addr()));
+            }
+            Some(endpoint)
+        }
+        None => None,
+    };
 
-    let is_https = args.url.starts_with("https://");
-    let url: Arc<str> = args.url.into();
-    let data: Option<Arc<str>> = args.data.map(|s| s.into());
-
-    println!(
-        "{} {} ({}) with {} connections x {} streams = {} concurrency, {} total requests",
-        "Benchmarking".cyan().bold(),
-        url.yellow(),
-        protocol.magenta(),
-        args.connections.to_string().green(),
-        args.pipeline.to_string().green(),
-        (args.connections * args.pipeline).to_string().green().bold(),
-        args.requests.to_string().green()
-    );
+    let pause = Arc::new(PauseControl::default());
+    pause::listen(pause.clone())?;
+    cancel_on_sigterm(cancel.clone())?;
 
     let start = Instant::now();
+    let clock = setup.clock();
+    let deadline = args.max_duration.map(|d| start + d);
+    // When SIGTERM or --abort-if-rps-below stopped the run, for the measured window
+    let cancelled_at = {
+        let cancel = cancel.clone();
+        tokio::spawn(async move {
+            cancel.cancelled().await;
+            Instant::now()
+        })
+    };
 
-    let reqs_per_worker = args.requests / args.connections as u64;
-    let remainder = args.requests % args.connections as u64;
+    // --alternate's slices start with the run
+    let slicer = args.alternate.as_ref().map(|_| Arc::new(Slicer::new(args.slice)));
+    let slicing = slicer.clone().map(|slicer| tokio::spawn(async move { slicer.run(start).await }));
+
+    // Samples throughput once a second for the live ETA (on a terminal), the
+    // best/worst window summary and --abort-if-rps-below, and benchy's own CPU and
+    // memory use. A few seconds in, it also checks that any connection got through.
+    // It publishes --prometheus-listen's metrics too, and stops their server at the end.
+    let (stop_sampler, mut sampler_stopped) = tokio::sync::oneshot::channel::<()>();
+    let sampler = {
+        let stats = stats.clone();
+        let conn_stats = conn_stats.clone();
+        let pause = pause.clone();
+        let cancel = cancel.clone();
+        let target = args.requests;
+        let flap_threshold = args.flap_threshold;
+        // While --fill gradual ramps up, the live line shows the requests in flight climb
+        let filled_at = match args.fill {
+            Fill::Gradual(over) => Some(start + over),
+            Fill::Eager => None,
+        };
+        let depth = args.connections * args.pipeline;
+        let mut floor = args.abort_if_rps_below.map(|min| RpsFloor::new(min, args.rps_window));
+        // Throughput falls on purpose from here
+        let ramp_from = deadline.zip(args.ramp_down).map(|(deadline, ramp)| deadline - ramp);
+        // Children leave the terminal to the parent
+        let live = std::io::stderr().is_terminal() && child.is_none();
+        let snapshots = std::env::var_os(serve::PROGRESS_ENV).is_some();
+        // A blackholed port fails nothing until --connect-timeout, so say why it's quiet
+        let mut unreached_hint = child.is_none().then(|| args.connect_timeout.min(Duration::from_secs(3)));
+        tokio::spawn(async move {
+            let mut throughput = Throughput::default();
+            // Paced sends per window, for --rate and --burst
+            let mut submissions = Throughput::default();
+            let mut timeline = StatusTimeline::new(flap_threshold);
+            let mut resources = ResourceUsage::start();
+            let lag = LagProbe::start();
+            let mut toggles = pause.toggles();
+            let mut breach = None;
+            let second = Duration::from_secs(1);
+            let mut interval = tokio::time::interval_at(tokio::time::Instant::from_std(start) + second, second);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = &mut sampler_stopped => break,
+                }
+                // A window counts as paused if the run was paused at any point in it
+                let paused = pause.is_paused() || pause.toggles() != toggles;
+                toggles = pause.toggles();
+                let total = stats.success.load(Ordering::Relaxed) + stats.failed.load(Ordering::Relaxed);
+                throughput.tick(total, paused);
+                timeline.tick(&stats.status_classes, clock.wall(Instant::now()), paused);
+                sinks.interval(&sinks::Interval {
+                    at: clock.wall(Instant::now()),
+                    completed: throughput.series().last().copied().unwrap_or(0),
+                    total,
+                });
+                submissions.tick(stats.pacing.submitted(), paused);
+                resources.tick();
+                let checking = breach.is_none()
+                    && !cancel.is_cancelled()
+                    && ramp_from.is_none_or(|ramp_from| Instant::now() < ramp_from)
+                    && stats.requests.submitted() < target;
+                if let Some(floor) = floor.as_mut().filter(|_| checking) {
+                    if let Some(found) = floor.check(&throughput, start.elapsed()) {
+                        if live {
+                            eprint!("\r\x1b[2K");
+                        }
+                        eprintln!("{} {}", "Aborting:".red().bold(), found.message());
+                        // Drained and reported like SIGTERM, then failed as a threshold
+                        cancel.cancel();
+                        breach = Some(found);
+                    }
+                }
+                if unreached_hint.is_some_and(|after| start.elapsed() >= after) {
+                    unreached_hint = None;
+                    // DNS failures and error statuses are problems of their own
+                    let failed = stats.failed.load(Ordering::Relaxed);
+                    let unconnected = stats.errors.get(errors::ErrorKind::Connect)
+                        + stats.errors.get(errors::ErrorKind::ConnectTimeout);
+                    if conn_stats.established.load(Ordering::Relaxed) == 0
+                        && stats.success.load(Ordering::Relaxed) == 0
+                        && failed == unconnected
+                        && !cancel.is_cancelled()
+                    {
+                        if live {
+                            eprint!("\r\x1b[2K");
+                        }
+                        eprintln!(
+                            "{} no connections established after {} — is the port reachable?",
+                            "Warning:".yellow(),
+                            humantime::format_duration(Duration::from_secs(start.elapsed().as_secs()))
+                        );
+                    }
+                }
+                if live {
+                    let until_deadline = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+                    let mut status = throughput.status(target, until_deadline);
+                    if filled_at.is_some_and(|filled_at| Instant::now() < filled_at) {
+                        status.push_str(&format!("  filling {}/{} in flight", stats.requests.in_flight(), depth));
+                    }
+                    eprint!("\r\x1b[2K{} {}", "Progress:".white(), status);
+                }
+                if snapshots {
+                    let mut snapshot = throughput.snapshot(target, start.elapsed(), clock.wall(Instant::now()));
+                    snapshot["in_flight"] = stats.requests.in_flight().into();
+                    eprintln!("{}", snapshot);
+                }
+                if let Some((endpoint, recorder)) = metrics.as_ref().zip(stats.prometheus.as_ref()) {
+                    endpoint.publish(recorder, prometheus::Gauges::of(&stats));
+                }
+            }
+            if live {
+                eprint!("\r\x1b[2K");
+            }
+            // One last time with the run's totals, for a scrape already under way
+            if let Some((endpoint, recorder)) = metrics.zip(stats.prometheus.as_ref()) {
+                endpoint.publish(recorder, prometheus::Gauges::of(&stats));
+                endpoint.shutdown().await;
+            }
+            (throughput, submissions, timeline, resources.finish(), lag.finish().await, floor, breach, sinks)
+        })
+    };
 
-    let mut handles = Vec::with_capacity(args.connections);
+    let tasks = args.tasks();
+    // -n is split by connection, so a worker with more of them gets more requests
+    let reqs_per_connection = args.requests / args.connections as u64;
+    let remainder = args.requests % args.connections as u64;
+    let shared_quota = Arc::new(AtomicU64::new(args.requests));
 
-    for i in 0..args.connections {
-        let client = build_client(args.http3, args.insecure, is_https)?;
+    let mut handles = Vec::with_capacity(tasks);
 
-        let url = url.clone();
-        let data = data.clone();
+    for (i, mut pool) in ClientPool::split(workers, tasks).into_iter().enumerate() {
+        let config = config.clone();
         let stats = stats.clone();
         let tx = tx.clone();
-        let pipeline = args.pipeline;
-        let abort_flag = abort_flag.clone();
-        let fail_fast = args.fail_fast;
+        // Each of the worker's connections gets -p streams
+        let pipeline = args.pipeline * pool.len();
+        let fill = args.fill;
+        let cancel = cancel.clone();
+        let discovery = discovery.clone();
+        let pause = pause.clone();
+        let slicer = slicer.clone();
+        let mut backoff = args.backoff_on_connect_errors.then(Backoff::default);
+        let drain_timeout = args.drain_timeout;
+        let identity = args.identity.as_ref().map(|identity| identity.for_worker(seed, i));
+        let mut pacer = match (args.rate, args.burst) {
+            (Some(rate), _) => Some(Pacer::Rate(Schedule::new(
+                args.arrival,
+                rate * pool.len() as f64 / args.connections as f64,
+                seed,
+                i,
+                config.rate_limits.clone().filter(|_| args.auto_rate),
+            ))),
+            (_, Some(burst)) => Some(Pacer::Burst(Bursts::new(burst, pool.connections().map(|c| burst.share(args.connections, c)).sum(), start))),
+            _ => None,
+        };
+
+        // --ramp-down staggers when workers stop: worker 0 runs to the deadline, the
+        // last one stops first, so active workers decrease linearly
+        let stop_at = match (deadline, args.ramp_down) {
+            (Some(deadline), Some(ramp)) => {
+                let share = (tasks - i) as f64 / tasks as f64;
+                Some(deadline - ramp.min(deadline - start) + ramp.min(deadline - start).mul_f64(share))
+            }
+            (deadline, _) => deadline,
+        };
 
-        let my_reqs = reqs_per_worker + if (i as u64) < remainder { 1 } else { 0 };
+        let quota = if args.work_stealing {
+            Quota::Shared(shared_quota.clone())
+        } else {
+            Quota::Fixed(pool.quota(reqs_per_connection, remainder))
+        };
 
         handles.push(tokio::spawn(async move {
+            tracing::info!(worker = i, "worker started");
+            // Borrowed by the requests in flight, so declared before them
+            let script = config.script.as_ref().map(|script| Mutex::new(script.worker(i)));
             let mut in_flight = FuturesUnordered::new();
             let mut sent = 0u64;
 
-            while sent < my_reqs && in_flight.len() < pipeline && !abort_flag.load(Ordering::Relaxed) {
-                in_flight.push(send_request(&client, &url, &data, &stats, expected_version, fail_fast));
-                sent += 1;
-            }
+            // Past --max-duration no new requests go out, but in-flight ones are drained
+            let accepting = || {
+                !cancel.is_cancelled() && stop_at.is_none_or(|stop_at| Instant::now() < stop_at)
+            };
+
+            // Tag each request with its submission index so GOAWAY failures can be
+            // attributed to the connection they were sent on. Once the run is cancelled,
+            // requests still in flight resolve to `None` instead of running on.
+            let mut generator = BodyGenerator::new(seed, i);
+            let mut picker = UrlPicker::new(seed, i);
+            let mut body_picker = payload::picker(seed, i);
+            let mut header_picker = headerfile::picker(seed, i);
+            let mut ulids = config.request_id.as_ref().map(|_| Ulids::new());
+            let mut protocols = Protocols::default();
+            let mut activity = Activity::default();
+            let mut connection = config.hol.as_ref().map(|_| hol::Connection::default());
+            let mut encoder = config.compression.as_ref().map(|compression| compress::Encoder::new(compression.codec()));
+            let submit = |seq: u64, (client, version): (Result<HttpClient, RebuildError>, Option<Version>), outgoing, request_id, position, queued| {
+                let request = send_request(client, version, outgoing, identity.as_ref(), &config, &stats);
+                let cancelled = cancel.clone().cancelled_owned();
+                async move {
+                    tokio::select! {
+                        biased;
+                        _ = cancelled => None,
+                        result = request => Some(result),
+                    }
+                }
+                .map(move |result| (seq, position, queued, request_id, result))
+            };
+
+            // Requests submitted before this index were sent on a connection already
+            // known to have gone away
+            let mut goaway_boundary = 0u64;
+            // Likewise for connections dropped by --h2-keepalive
+            let mut keepalive_boundary = 0u64;
+
+            // Alt-Svc discovery sends the first request alone, like a browser would
+            let mut limit = if discovery.is_some() { 1 } else { pipeline };
+            // --fill gradual holds the depth below the limit until it has ramped up
+            let ramp = fill.ramp(pipeline, start);
+
+            // Set once this worker has nothing left to send: in-flight requests are awaited
+            // until then, and counted as abandoned if they haven't finished
+            let mut drain_deadline: Option<Instant> = None;
+
+            loop {
+                // Drained our part of a closed --alternate slice: wait for the next one.
+                // Nothing due meanwhile is made up afterwards.
+                if let Some(slicer) = slicer.as_ref().filter(|slicer| !slicer.is_open()) {
+                    if in_flight.is_empty() && accepting() && quota.has_more(sent) {
+                        tokio::select! {
+                            _ = slicer.wait_open() => {}
+                            _ = cancel.cancelled() => {}
+                        }
+                        if let Some(pacer) = &mut pacer {
+                            pacer.restart();
+                        }
+                    }
+                }
+                // Bursts that started while the pipeline was still full are not made up,
+                // so one slow burst doesn't bleed into the next
+                if let Some(pacer) = &mut pacer {
+                    let backlogged = pacer.roll();
+                    if backlogged > 0 && !pause.is_paused() && accepting() && quota.has_more(sent) {
+                        stats.backlogged.fetch_add(backlogged, Ordering::Relaxed);
+                    }
+                }
+                while in_flight.len() < limit.min(ramp.as_ref().map_or(pipeline, Ramp::depth))
+                    && accepting()
+                    && slicer.as_ref().is_none_or(|slicer| slicer.is_open())
+                    && pacer.as_ref().is_none_or(Pacer::is_due)
+                    && quota.claim(sent)
+                {
+                    let (position, queued) = match pacer.as_mut() {
+                        Some(pacer) => {
+                            let lag = pacer.lag();
+                            let position = pacer.advance();
+                            // Behind schedule, the period runs until now rather than the next due time
+                            let until = pacer.next_due().max(Instant::now());
+                            stats.pacing.record(lag, until.saturating_duration_since(start));
+                            (position, lag)
+                        }
+                        None => (None, Duration::ZERO),
+                    };
+                    let url = match (&slicer, &config.hosts, &connection) {
+                        (Some(slicer), _, _) => alternate::target(slicer.begin()),
+                        (None, Some(hosts), _) => hosts.of(pool.current()),
+                        (None, None, Some(connection)) => connection.pick(),
+                        (None, None, None) => config.urls.as_ref().map_or(0, |mix| mix.pick(&mut picker)),
+                    };
+                    let body_file = config.bodies.as_ref().map(|pool| pool.pick(&mut body_picker));
+                    let (body, mut source) = match (config.entry(url), &config.bodies, body_file) {
+                        (Some(entry), _, _) => (entry.body.clone(), compress::Source::Entry(url)),
+                        (None, Some(pool), Some(index)) => (Some(pool.body(index)), compress::Source::File(index)),
+                        _ => match &config.body {
+                            Some(template) if template.is_static() => {
+                                (Some(template.render(&mut generator)), compress::Source::Fixed)
+                            }
+                            template => (
+                                template.as_ref().map(|template| template.render(&mut generator)),
+                                compress::Source::Generated,
+                            ),
+                        },
+                    };
+                    let mut outgoing = Outgoing {
+                        url,
+                        body,
+                        body_file,
+                        script: None,
+                        request_id: None,
+                        header: config.header_file.as_ref().map(|file| file.pick(&mut header_picker)),
+                        pushed: Instant::now(),
+                    };
+                    if let Some(script) = &script {
+                        let request = script.lock().expect("script lock poisoned").on_request(
+                            sent,
+                            config.method(url),
+                            config.url(url),
+                            outgoing.body.as_ref(),
+                        );
+                        match request {
+                            Ok(Some(mut request)) => {
+                                outgoing.body = request.body.take();
+                                source = compress::Source::Generated;
+                                outgoing.script = Some((script, request));
+                            }
+                            // Skipped by the script: its share of -n is used up, but nothing goes out
+                            Ok(None) => {
+                                sent += 1;
+                                stats.requests.skipped.fetch_add(1, Ordering::Relaxed);
+                                if let Some(slicer) = &slicer {
+                                    slicer.finish();
+                                }
+                                continue;
+                            }
+                            Err(message) => {
+                                let _ = tx.send(RequestResult::Error(ErrorDetails {
+                                    message,
+                                    status: None,
+                                    headers: None,
+                                    body: None,
+                                    trailers: None,
+                                }));
+                                cancel.cancel();
+                                break;
+                            }
+                        }
+                    }
+                    if let Some(ulids) = &mut ulids {
+                        outgoing.request_id = Some(ulids.next());
+                        stats.request_ids.sent.fetch_add(1, Ordering::Relaxed);
+                    }
+                    if let (Some(compression), Some(encoder)) = (&config.compression, &mut encoder) {
+                        outgoing.body = outgoing.body.map(|body| compression.encode(source, body, encoder));
+                    }
+                    let request_id = outgoing.request_id.clone();
+                    // After on_request, which is the script's time rather than queueing
+                    outgoing.pushed = Instant::now();
+                    if let Some(connection) = &mut connection {
+                        connection.sent(sent, url);
+                    }
+                    in_flight.push(submit(sent, pool.next(), outgoing, request_id, position, queued));
+                    sent += 1;
+                    stats.requests.submitted.fetch_add(1, Ordering::Relaxed);
+                }
+                activity.update(in_flight.len());
 
-            while let Some(result) = in_flight.next().await {
-                if abort_flag.load(Ordering::Relaxed) {
+                if drain_deadline.is_none() && !(accepting() && quota.has_more(sent)) {
+                    drain_deadline = Some(Instant::now() + drain_timeout);
+                }
+                // With --rate or --burst, also wake when the next request is due and has room
+                let due = pacer
+                    .as_ref()
+                    .filter(|_| drain_deadline.is_none() && in_flight.len() < limit)
+                    .map(Pacer::next_due);
+                // And when the ramp next lets one more in
+                let step = ramp.as_ref().filter(|_| drain_deadline.is_none()).and_then(Ramp::next_step);
+
+                let next = tokio::select! {
+                    biased;
+                    next = in_flight.next(), if !in_flight.is_empty() => next,
+                    _ = tokio::time::sleep_until(due.unwrap_or_else(Instant::now).into()), if due.is_some() => continue,
+                    _ = tokio::time::sleep_until(step.unwrap_or_else(Instant::now).into()), if step.is_some() => continue,
+                    _ = tokio::time::sleep_until(drain_deadline.unwrap_or_else(Instant::now).into()), if drain_deadline.is_some() && !in_flight.is_empty() => {
+                        tracing::info!(worker = i, in_flight = in_flight.len(), "drain timed out");
+                        stats.requests.abandoned.fetch_add(in_flight.len() as u64, Ordering::Relaxed);
+                        break;
+                    }
+                    else => break,
+                };
+                let Some((seq, position, queued, request_id, result)) = next else {
                     break;
+                };
+                activity.update(in_flight.len());
+                let contended = connection.as_mut().is_some_and(|connection| connection.done(seq));
+                let Some(mut result) = result else {
+                    stats.requests.cancelled.fetch_add(1, Ordering::Relaxed);
+                    if let Some(slicer) = &slicer {
+                        slicer.finish();
+                    }
+                    continue;
+                };
+                stats.requests.completed.fetch_add(1, Ordering::Relaxed);
+
+                if let RequestResult::Success(sample) | RequestResult::Failed(sample) = &mut result {
+                    sample.worker = i;
+                    sample.seq = seq;
+                    sample.request_id = request_id.clone();
+                    sample.burst = position;
+                    sample.queued = queued;
+                    sample.contended = contended;
+                    // The slice can't move on while this request is counted in it
+                    sample.slice = slicer.as_ref().map(|slicer| slicer.index());
+                    if let Some(version) = sample.version {
+                        protocols.record(version);
+                    }
+                    if let Some(recorder) = &stats.prometheus {
+                        recorder.record(sample.status, sample.latency);
+                    }
+                    stats.status_classes.record(sample.status);
+                    if let (Some(compression), Some(status)) = (&config.compression, sample.status) {
+                        compression.record_status(status);
+                    }
+                    tracing::debug!(
+                        worker = i,
+                        seq,
+                        request_id = request_id.as_ref().and_then(|id| id.to_str().ok()),
+                        body_file = sample.body_file.and_then(|index| Some(config.bodies.as_ref()?.name(index))),
+                        status = ?sample.status,
+                        latency = ?sample.latency,
+                        error = ?sample.error,
+                        "request completed"
+                    );
                 }
+                if let Some(slicer) = &slicer {
+                    slicer.finish();
+                }
+
+                if let RequestResult::Success(sample) | RequestResult::Failed(sample) = &result {
+                    if sample.goaway && seq >= goaway_boundary {
+                        stats.goaway_connections.fetch_add(1, Ordering::Relaxed);
+                        goaway_boundary = sent;
+                    }
+                    if sample.error == Some(errors::ErrorKind::KeepAlive) && seq >= keepalive_boundary {
+                        stats.keepalive_connections.fetch_add(1, Ordering::Relaxed);
+                        keepalive_boundary = sent;
+                    }
+                }
+
+                if let (Some(backoff), RequestResult::Success(sample) | RequestResult::Failed(sample)) =
+                    (&mut backoff, &result)
+                {
+                    backoff.record(
+                        matches!(sample.error, Some(errors::ErrorKind::Connect | errors::ErrorKind::ConnectTimeout)),
+                        &stats.backoff,
+                    );
+                }
+
+                let mut should_abort = matches!(&result, RequestResult::Error(_));
 
-                let should_abort = matches!(&result, RequestResult::Error(_));
+                let switch = match (&discovery, seq) {
+                    (Some(discovery), 0) => Some(discovery.switch(pool.only(), &result, &stats.alt_svc, start).await),
+                    _ => None,
+                };
                 let _ = tx.send(result);
+                if let Some(Err(details)) = switch {
+                    let _ = tx.send(RequestResult::Error(details));
+                    should_abort = true;
+                }
 
+                // Nothing new goes out, and what's in flight is cancelled and drained
                 if should_abort {
-                    break;
+                    cancel.cancel();
+                    continue;
                 }
 
-                if sent < my_reqs && !abort_flag.load(Ordering::Relaxed) {
-                    in_flight.push(send_request(&client, &url, &data, &stats, expected_version, fail_fast));
-                    sent += 1;
-                }
+                // While backing off, let in-flight requests finish, then pause and send a
+                // single request to see whether the target is back
+                limit = match &mut backoff {
+                    // Paused: drain, then park until resumed. Nothing missed while parked is
+                    // made up afterwards.
+                    _ if pause.is_paused() => {
+                        if in_flight.is_empty() {
+                            tokio::select! {
+                                _ = pause.wait_resumed() => {}
+                                _ = cancel.cancelled() => {}
+                            }
+                            if let Some(pacer) = &mut pacer {
+                                pacer.restart();
+                            }
+                            pipeline
+                        } else {
+                            0
+                        }
+                    }
+                    Some(backoff) if backoff.active() => {
+                        if in_flight.is_empty() && quota.has_more(sent) && accepting() {
+                            backoff.wait(&stats.backoff).await;
+                            if let Some(pacer) = &mut pacer {
+                                pacer.restart();
+                            }
+                        }
+                        1
+                    }
+                    _ => pipeline,
+                };
+            }
+            tracing::info!(worker = i, sent, "worker finished");
+            WorkerReport {
+                sent,
+                elapsed: start.elapsed(),
+                busy: activity.finish(),
+                script_metrics: script
+                    .as_ref()
+                    .map(|script| script.lock().expect("script lock poisoned").take_metrics())
+                    .unwrap_or_default(),
+                protocols,
             }
         }));
     }
 
     drop(tx);
 
-    let abort_flag_collector = abort_flag.clone();
-    let fail_fast = args.fail_fast;
+    let cancel_collector = cancel.clone();
+    let mut url_stats = config
+        .urls
+        .as_ref()
+        .filter(|_| args.per_url_stats || args.per_entry_stats)
+        .map(|mix| UrlStats::new(mix.len()));
+    let mut slice_stats = args.alternate.as_ref().map(|_| SliceStats::default());
+    let mut host_stats = config.hosts.as_ref().map(|hosts| HostStats::new(hosts.len()));
+    let mut hol_stats = config.hol.as_ref().map(|_| hol::HolStats::default());
+    let mut affinity = args
+        .affinity_header
+        .clone()
+        .map(|header| AffinityTally::new(header, args.connections));
+    let mut reachability = (!args.keep_going).then(|| Reachability::new(args.tasks(), args.requests));
+    let mut heatmap = args.heatmap.as_ref().map(|_| Heatmap::default());
+    let mut sent_groups = config
+        .header_file
+        .as_ref()
+        .filter(|_| args.group_by_header_sent)
+        .map(|file| SentGroups::new(file.len()));
+    let target = args.url.clone();
+    let mut transfers = TransferTimes::new(config.method == reqwest::Method::HEAD);
+    let (log_config, log_group) = (config.clone(), args.group_by_header.clone());
     let collector = tokio::spawn(async move {
         let mut latencies = Vec::with_capacity(args.requests as usize);
+        let mut ttfbs = Vec::with_capacity(args.requests as usize);
+        let mut client_queues = Vec::with_capacity(args.requests as usize);
+        let mut groups = LatencyGroups::default();
+        let mut burst_latencies = BurstLatencies::default();
+        let mut slowest = Slowest::new(args.slowest.unwrap_or(0));
         let mut first_error: Option<ErrorDetails> = None;
+        let mut last_sent: Option<Instant> = None;
+        let mut completions = Vec::with_capacity(args.requests as usize);
+        let mut continue_waits = Vec::new();
+        let mut response_latencies = args.correct_latency.then(|| Vec::with_capacity(args.requests as usize));
 
         while let Some(result) = rx.recv().await {
+            let failed = matches!(result, RequestResult::Failed(_));
             match result {
-                RequestResult::Success(d) | RequestResult::Failed(d) => {
-                    latencies.push(d);
+                RequestResult::Success(sample) | RequestResult::Failed(sample) => {
+                    last_sent = last_sent.max(Some(sample.sent_at));
+                    completions.push(sample.sent_at + sample.latency);
+                    latencies.push(sample.latency);
+                    ttfbs.push(sample.ttfb);
+                    client_queues.push(sample.client_queue);
+                    transfers.record(sample.latency, sample.ttfb, sample.upload, sample.error.is_none());
+                    if let Some(log) = &mut request_log {
+                        let entry = requestlog::Entry {
+                            sent: clock.wall(sample.sent_at),
+                            offset: sample.sent_at.saturating_duration_since(start),
+                            url: log_config.url(sample.url),
+                            version: sample.version,
+                            status: sample.status,
+                            error: sample.error,
+                            ok: !failed,
+                            latency: sample.latency,
+                            ttfb: sample.ttfb,
+                            worker: sample.worker,
+                            request_id: sample.request_id.as_ref().and_then(|id| id.to_str().ok()),
+                            body_file: sample.body_file.and_then(|index| Some(log_config.bodies.as_ref()?.name(index))),
+                            group: log_group.as_ref().zip(sample.group.as_deref()).map(|(h, v)| (h.as_str(), v)),
+                        };
+                        if let Err(e) = log.write(&entry) {
+                            eprintln!("{} could not write --request-log: {}", "Warning:".yellow(), e);
+                            request_log = None;
+                        }
+                    }
+                    slowest.record(
+                        sample.latency,
+                        sample.sent_at.saturating_duration_since(start),
+                        sample.worker,
+                        sample.status,
+                        sample.url,
+                        sample.request_id.as_ref(),
+                    );
+                    if let Some(group) = sample.group {
+                        groups.record(group, sample.latency);
+                    }
+                    if let (Some(sent_groups), Some(line)) = (&mut sent_groups, sample.header) {
+                        sent_groups.record(line, failed, sample.latency);
+                    }
+                    if let Some(position) = sample.burst {
+                        burst_latencies.record(position, sample.latency);
+                    }
+                    if let Some(url_stats) = &mut url_stats {
+                        url_stats.record(sample.url, sample.latency, failed);
+                    }
+                    if let (Some(slice_stats), Some(slice)) = (&mut slice_stats, sample.slice) {
+                        slice_stats.record(slice, sample.latency, failed);
+                    }
+                    if let Some(host_stats) = &mut host_stats {
+                        host_stats.record(sample.url, sample.latency, failed);
+                    }
+                    if let Some(hol_stats) = &mut hol_stats {
+                        hol_stats.record(sample.url, sample.contended, sample.latency, failed);
+                    }
+                    if let Some(affinity) = affinity.as_mut().filter(|_| sample.status.is_some()) {
+                        affinity.record(sample.worker, sample.backend);
+                    }
+                    continue_waits.extend(sample.continued);
+                    if let Some(response_latencies) = &mut response_latencies {
+                        response_latencies.push(sample.queued + sample.latency);
+                    }
+
+                    let elapsed = sample.sent_at.saturating_duration_since(start) + sample.latency;
+                    if let Some(heatmap) = &mut heatmap {
+                        heatmap.record(elapsed, sample.latency);
+                    }
+                    let unreachable = reachability
+                        .as_mut()
+                        .and_then(|r| r.record(elapsed, sample.worker, sample.seq, sample.unreachable.as_deref()));
+                    if let Some(cause) = unreachable.filter(|_| first_error.is_none()) {
+                        first_error = Some(ErrorDetails {
+                            message: format!(
+                                "{} is unreachable, every connection failed in the first second (--keep-going runs anyway)\n  {}",
+                                target, cause
+                            ),
+                            status: None,
+                            headers: None,
+                            body: None,
+                            trailers: None,
+                        });
+                        cancel_collector.cancel();
+                    }
                 }
                 RequestResult::Error(details) => {
-                    if fail_fast && first_error.is_none() {
+                    // Only produced by -f, or by conditions that end the run like --require-h3
+                    if first_error.is_none() {
                         first_error = Some(details);
-                        abort_flag_collector.store(true, Ordering::Relaxed);
+                        cancel_collector.cancel();
                     }
                 }
             }
         }
-        (latencies, first_error)
+        if let Some(Err(e)) = request_log.map(RequestLog::finish) {
+            eprintln!("{} could not write --request-log: {}", "Warning:".yellow(), e);
+        }
+        let drain = Drain::measure(last_sent, &completions);
+        (
+            latencies,
+            ttfbs,
+            client_queues,
+            transfers,
+            continue_waits,
+            groups,
+            sent_groups,
+            burst_latencies,
+            url_stats,
+            slice_stats,
+            host_stats,
+            hol_stats,
+            affinity,
+            slowest,
+            first_error,
+            drain,
+            response_latencies,
+            heatmap,
+        )
     });
 
-    for h in handles {
-        let _ = h.await;
+    let (mut worker_reports, panics) = join_workers(handles, &cancel).await;
+    let mut script_metrics = script::Metrics::new();
+    for report in &mut worker_reports {
+        script::merge(&mut script_metrics, std::mem::take(&mut report.script_metrics));
+    }
+    let _ = stop_sampler.send(());
+    if let Some(slicing) = slicing {
+        slicing.abort();
     }
+    let (throughput, mut submissions, mut timeline, resources, scheduler_lag, floor, breach, mut sinks) =
+        sampler.await?;
+    submissions.trim_idle_tail();
+    timeline.trim_idle_tail();
+
+    let (
+        mut latencies,
+        mut ttfbs,
+        mut client_queues,
+        mut transfers,
+        mut continue_waits,
+        groups,
+        sent_groups,
+        burst_latencies,
+        mut url_stats,
+        slice_stats,
+        host_stats,
+        hol_stats,
+        affinity,
+        slowest,
+        first_error,
+        drain,
+        response_latencies,
+        heatmap,
+    ) = collector.await?;
+    // Requests stuck in flight don't shorten the window below when sending stopped
+    let stopped = match deadline.filter(|&deadline| Instant::now() >= deadline) {
+        Some(deadline) => Some(deadline),
+        None if cancelled_at.is_finished() => cancelled_at.await.ok(),
+        None => {
+            cancelled_at.abort();
+            None
+        }
+    };
+    let run_phases = setup.end(start, drain.as_ref().map(|drain| drain.ended).max(stopped));
+    let total_time = run_phases.measured();
+    // Time paused with SIGUSR2 doesn't count towards throughput
+    let paused_time = pause.paused_time().min(total_time);
+    let active_time = run_phases.active(paused_time);
+    let utilization = workers::utilization(&worker_reports, active_time);
 
-    let (mut latencies, first_error) = collector.await?;
-    let total_time = start.elapsed();
+    // A panic means the results are missing that worker's share, so none are shown
+    if !panics.is_empty() {
+        print_error_details(&ErrorDetails {
+            message: panics.join("\n"),
+            status: None,
+            headers: None,
+            body: None,
+            trailers: None,
+        });
+        stats.requests.print(args.requests);
+        std::process::exit(1);
+    }
 
     // Show error details if we aborted
     if let Some(err) = first_error {
-        println!("\n{}", "--- Error Details ---".red().bold());
-        println!("{:<14} {}", "Error:".white(), err.message.red());
-        if let Some(status) = err.status {
-            println!("{:<14} {}", "Status:".white(), status.to_string().yellow());
+        print_error_details(&err);
+        stats.requests.print(args.requests);
+        std::process::exit(1);
+    }
+
+    let success = stats.success.load(Ordering::Relaxed);
+    let failed = stats.failed.load(Ordering::Relaxed);
+
+    let latency = Percentiles::from_unsorted(&mut latencies);
+    let ttfb = Percentiles::from_unsorted(&mut ttfbs);
+
+    // Completed rather than requested, so a run cut short by --max-duration isn't inflated
+    let completed = latencies.len() as u64;
+    let rps = completed as f64 / active_time.as_secs_f64();
+    let stopped_early = deadline.is_some_and(|deadline| Instant::now() >= deadline) && completed < args.requests;
+    // Cancelled by SIGTERM or --abort-if-rps-below; an abort would have exited above
+    let cancelled = cancel.is_cancelled();
+    // Without an abort or a deadline, every request of -n goes out and comes back
+    debug_assert!(stats.requests.settled(), "requests unaccounted for");
+    debug_assert!(
+        stopped_early || cancelled || stats.requests.submitted() + stats.requests.skipped() == args.requests,
+        "{} of {} requests sent, {} skipped",
+        stats.requests.submitted(),
+        args.requests,
+        stats.requests.skipped()
+    );
+
+    // One unit for every latency in the results, so columns line up
+    let unit = args.latency_unit.resolve(latency.p50);
+    let dns = Percentiles::from_unsorted(&mut dns_stats.take_lookup_times());
+    let connect = Percentiles::from_unsorted(&mut conn_stats.take_setup_times());
+    // Only --proxy with an https:// target splits Connect into these
+    let tunnel = Percentiles::from_unsorted(&mut conn_stats.take_tunnel_times());
+    let tls_handshake = Percentiles::from_unsorted(&mut conn_stats.take_tls_times());
+    let mut phases = vec![("DNS", &dns), ("Connect", &connect), ("Tunnel", &tunnel), ("TLS", &tls_handshake)];
+    // Only --expect-continue requests have this
+    let continue_wait = Percentiles::from_unsorted(&mut continue_waits);
+    phases.push(("100 Continue", &continue_wait));
+    phases.push(("TTFB", &ttfb));
+    // Upload and Server wait only have samples for requests with a body
+    let upload = Percentiles::from_unsorted(&mut transfers.upload);
+    let server_wait = Percentiles::from_unsorted(&mut transfers.server_wait);
+    let download = Percentiles::from_unsorted(&mut transfers.download);
+    phases.extend([("Upload", &upload), ("Server wait", &server_wait), ("Download", &download)]);
+    let crypto_drift = conn_stats.crypto.finish(start, &clock);
+    let url_rows = match (&mut url_stats, &config.urls) {
+        (Some(url_stats), Some(mix)) => url_stats.rows(mix),
+        _ => Vec::new(),
+    };
+    let group_rows = groups.percentiles();
+
+    let summary = RunSummary {
+        url: &args.url,
+        proto: if args.h3_alt_svc { "h3-alt-svc" } else { args.protocol.name() },
+        conns: args.connections,
+        pipeline: args.pipeline,
+        reqs: completed,
+        started: run_phases.started(),
+        duration: total_time,
+        rps,
+        success,
+        failed,
+        errors: &stats.errors,
+        latency: &latency,
+        phases: phases.clone(),
+        urls: &url_rows,
+        resources,
+        scheduler_lag,
+        status_timeline: Some(&timeline),
+        crypto_drift: crypto_drift.as_ref(),
+    };
+    if let Some(sanity) = &config.sanity {
+        sanity.print(text && child.is_none());
+    }
+    if child.is_some() || args.save_results.is_some() {
+        let mut saved = SavedRun::new(&summary, args.requests, active_time, &latencies, &ttfbs);
+        saved.mismatches = stats.version_mismatches.load(Ordering::Relaxed);
+        saved.stopped_early = stopped_early || cancelled;
+        saved.script_metrics = script_metrics.clone();
+        saved.rps_breach = breach.clone();
+        saved.worker_utilization = utilization.clone();
+        saved.phases = Some(run_phases.clone());
+        if child.is_some() {
+            serde_json::to_writer(std::io::stdout().lock(), &saved.to_json())?;
+            return Ok(());
+        }
+        if let Some(path) = &args.save_results {
+            if let Err(e) = saved.save(path) {
+                eprintln!("{} could not write {}: {}", "Warning:".yellow(), path.display(), e);
+            }
+        }
+    }
+    let mut evaluations = Thresholds::of(&args).evaluate(&summary, unit);
+    if let Some(floor) = &floor {
+        evaluations.push(floor.evaluation(breach.as_ref()));
+    }
+    let thresholds_failed = evaluations.iter().any(|e| !e.passed);
+
+    if !args.no_github_summary {
+        if let Some(path) = std::env::var_os("GITHUB_STEP_SUMMARY") {
+            if let Err(e) = output::append_step_summary(path.as_ref(), &summary.markdown(unit, &evaluations)) {
+                eprintln!("{} could not write GITHUB_STEP_SUMMARY: {}", "Warning:".yellow(), e);
+            }
+        }
+    }
+    if let Some(path) = &args.junit {
+        if let Err(e) = junit::write(path, &summary, &evaluations) {
+            eprintln!("{} could not write {}: {}", "Warning:".yellow(), path.display(), e);
+        }
+    }
+    if let (Some(path), Some(heatmap)) = (&args.heatmap, &heatmap) {
+        if let Err(e) = heatmap.write(path, run_phases.started()) {
+            eprintln!("{} could not write {}: {}", "Warning:".yellow(), path.display(), e);
+        }
+    }
+    let protocols = stats.protocols.counts();
+    let response = response_latencies.map(|mut latencies| Percentiles::from_unsorted(&mut latencies));
+    let client_queue = Percentiles::from_unsorted(&mut client_queues);
+    let details = textreport::RunDetails {
+        args: &args,
+        config: &config,
+        stats: &stats,
+        conn_stats: &conn_stats,
+        dns_stats: &dns_stats,
+        tls_stats: &tls_stats,
+        upgrade_stats: upgrade_stats.as_deref(),
+        requested,
+        is_https,
+        seed,
+        paused_time,
+        active_time,
+        stopped_early,
+        cancelled,
+        throughput: &throughput,
+        submissions: &submissions,
+        drain: drain.as_ref(),
+        worker_reports: &worker_reports,
+        response: response.as_ref(),
+        client_queue: &client_queue,
+        continue_wait: &continue_wait,
+        affinity: affinity.as_ref(),
+        heatmap: heatmap.as_ref(),
+        slicer: slicer.as_deref(),
+        slowest: RefCell::new(Some(slowest)),
+        burst_latencies: RefCell::new(burst_latencies),
+        sent_groups: RefCell::new(sent_groups),
+        slice_stats: RefCell::new(slice_stats),
+        host_stats: RefCell::new(host_stats),
+        hol_stats: RefCell::new(hol_stats),
+    };
+    let report = sinks::BenchReport {
+        summary: &summary,
+        evaluations: &evaluations,
+        unit,
+        latencies: &latencies,
+        ttfbs: &ttfbs,
+        phases: Some(&run_phases),
+        groups: args.group_by_header.as_ref().map(|header| (header.as_str(), &group_rows[..])),
+        script_metrics: &script_metrics,
+        rps_breach: breach.as_ref(),
+        utilization: &utilization,
+        protocols: &protocols,
+        text: TextDetails::Run(&details),
+    };
+    sinks.render(&report);
+    let published = publish::publish(&args, &sinks::to_json(&report)).await;
+    if !text {
+        if resources.as_ref().is_some_and(|r| r.saturated()) {
+            eprintln!(
+                "{} client saturated — results may understate server capacity",
+                "Warning:".yellow()
+            );
         }
-        if let Some(headers) = err.headers {
-            println!("\n{}:", "Headers".white().bold());
-            println!("{}", headers.dimmed());
+        if scheduler_lag.is_some_and(|lag| lag.overloaded()) {
+            eprintln!("{} {}", "Warning:".yellow(), schedlag::OVERLOADED_WARNING);
         }
-        if let Some(body) = err.body {
-            println!("\n{}:", "Body".white().bold());
-            println!("{}", body);
+        if let Some(warning) = crypto_drift.as_ref().and_then(DriftReport::warning) {
+            eprintln!("{} {}", "Warning:".yellow(), warning);
         }
+    }
+    if let Some(published) = &published {
+        published.print(text);
+    }
+
+    exit_on_failed_checks(
+        &args,
+        stats.version_mismatches.load(Ordering::Relaxed),
+        stopped_early,
+        thresholds_failed,
+        published.as_ref(),
+    );
+    Ok(())
+}
+
+/// Exit with the status `--require-protocol`, the thresholds, `--publish-required` or
+/// `--max-duration-exit-code` call for, if any
+fn exit_on_failed_checks(
+    args: &Args,
+    mismatches: u64,
+    stopped_early: bool,
+    thresholds_failed: bool,
+    published: Option<&publish::Published>,
+) {
+    let publish_failed = args.publish_required && published.is_some_and(publish::Published::failed);
+    if (args.require_protocol && mismatches > 0) || thresholds_failed || publish_failed {
         std::process::exit(1);
     }
+    if stopped_early && args.max_duration_exit_code != 0 {
+        std::process::exit(args.max_duration_exit_code);
+    }
+}
+
+/// Cancel the run on SIGTERM, as `benchy serve` does to abort one. Unlike `-f`, the
+/// partial results are still reported.
+#[cfg(unix)]
+fn cancel_on_sigterm(cancel: CancellationToken) -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut signals = signal(SignalKind::terminate())?;
+    tokio::spawn(async move {
+        if signals.recv().await.is_some() {
+            cancel.cancel();
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn cancel_on_sigterm(_cancel: CancellationToken) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Join the workers as they finish, so a panicking one cancels the rest straight away:
+/// the reports of those that finished, in worker order, and what the others panicked
+/// with. Only debug builds get here on a panic; the release profile has
+/// `panic = "abort"`, so there the process aborts with the panic message instead.
+async fn join_workers<T>(handles: Vec<tokio::task::JoinHandle<T>>, cancel: &CancellationToken) -> (Vec<T>, Vec<String>) {
+    let mut joins: FuturesUnordered<_> = handles
+        .into_iter()
+        .enumerate()
+        .map(|(i, h)| h.map(move |joined| (i, joined)))
+        .collect();
+    let mut reports = Vec::with_capacity(joins.len());
+    let mut panics = Vec::new();
+    while let Some((i, joined)) = joins.next().await {
+        match joined {
+            Ok(report) => reports.push((i, report)),
+            Err(e) => {
+                cancel.cancel();
+                panics.push(format!("worker {} {}", i, panic_message(e)));
+            }
+        }
+    }
+    reports.sort_by_key(|&(i, _)| i);
+    (reports.into_iter().map(|(_, report)| report).collect(), panics)
+}
 
-    let success = stats.success.load(Ordering::Relaxed);
-    let failed = stats.failed.load(Ordering::Relaxed);
+/// What a worker panicked with, e.g. "panicked: attempt to divide by zero"
+fn panic_message(e: tokio::task::JoinError) -> String {
+    match e.try_into_panic() {
+        Ok(payload) => match payload.downcast_ref::<&str>() {
+            Some(message) => format!("panicked: {}", message),
+            None => match payload.downcast_ref::<String>() {
+                Some(message) => format!("panicked: {}", message),
+                None => "panicked".to_string(),
+            },
+        },
+        Err(e) => e.to_string(),
+    }
+}
+
+fn print_error_details(err: &ErrorDetails) {
+    println!("\n{}", "--- Error Details ---".red().bold());
+    render::print_wrapped("Error:".white(), err.message.red());
+    if let Some(status) = err.status {
+        render::print("Status:".white(), status.to_string().yellow());
+    }
+    if let Some(headers) = &err.headers {
+        println!("\n{}:", "Headers".white().bold());
+        println!("{}", headers.dimmed());
+    }
+    if let Some(body) = &err.body {
+        println!("\n{}:", "Body".white().bold());
+        println!("{}", body);
+    }
+    if let Some(trailers) = &err.trailers {
+        println!("\n{}:", "Trailers".white().bold());
+        println!("{}", trailers.dimmed());
+    }
+}
+
+/// Trailers captured for `--assert-trailer`, formatted for the error details
+fn received_trailers(trailers: &Option<Trailers>) -> Option<String> {
+    let trailers = trailers.as_ref()?;
+    Some(match trailers.get() {
+        Some(received) => format!("{:#?}", received),
+        None => "(none)".to_string(),
+    })
+}
+
+/// Render an error and its full `source()` chain
+fn error_chain(e: &(dyn Error + 'static)) -> String {
+    let mut msg = e.to_string();
+    let mut source = e.source();
+    while let Some(src) = source {
+        msg.push_str(&format!("\n  caused by: {}", src));
+        source = src.source();
+    }
+    msg
+}
+
+/// Format a count with thousands separators, e.g. 412,345
+fn thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
 
-    latencies.sort_unstable();
+fn print_percentiles(title: &str, p: &Percentiles, unit: LatencyUnit) {
+    println!("\n{}", format!("--- {} ---", title).cyan().bold());
+    render::print("Avg:".white(), unit.format(p.avg));
+    render::print("P50:".white(), unit.format(p.p50));
+    render::print("P95:".white(), unit.format(p.p95).yellow());
+    render::print("P99:".white(), unit.format(p.p99).red());
+}
 
-    let len = latencies.len();
-    let p50 = latencies.get(len / 2).copied().unwrap_or_default();
-    let p95 = latencies.get(len * 95 / 100).copied().unwrap_or_default();
-    let p99 = latencies.get(len * 99 / 100).copied().unwrap_or_default();
-    let avg = if len > 0 {
-        latencies.iter().sum::<Duration>() / len as u32
+/// The server's SETTINGS_MAX_CONCURRENT_STREAMS, read from a probe connection before
+/// the run
+async fn server_stream_limit(args: &Args) -> Result<Option<usize>, transport::BoxError> {
+    let target = transport::Target::resolve(&args.url).await?;
+    let tls = if target.is_https {
+        Some(transport::tls_config(&[b"h2"], args.insecure, false, &Arc::new(TlsStats::default()))?)
     } else {
-        Duration::ZERO
+        None
     };
+    tokio::time::timeout(protocol::PROBE_TIMEOUT, transport::h2_stream_limit(&target, tls.as_ref()))
+        .await
+        .map_err(|_| "timed out")?
+}
+
+/// Open every client's connection with a HEAD request so the measured phase starts
+/// against warm connections. Returns how long that took.
+///
+/// Any status counts as connected; only transport errors abort the run.
+async fn preconnect(workers: &[WorkerClient], config: &RequestConfig) -> Result<Duration, ErrorDetails> {
+    let start = Instant::now();
 
-    let rps = args.requests as f64 / total_time.as_secs_f64();
+    let primes = workers.iter().enumerate().map(|(i, worker)| {
+        let url = match &config.hosts {
+            Some(hosts) => hosts.url(hosts.of(i)),
+            None => &config.url,
+        };
+        let mut request = worker.client.reqwest.head(url);
+        if let Some(version) = worker.opts.protocol.version() {
+            request = request.version(version);
+        }
+        worker.client.send(request)
+    });
 
-    println!("\n{}", "--- Results ---".cyan().bold());
-    println!("{:<14} {:?}", "Total time:".white(), total_time);
-    println!("{:<14} {}", "Requests/sec:".white(), format!("{:.2}", rps).green().bold());
-    println!("{:<14} {}", "Success:".white(), success.to_string().green());
-    if failed > 0 {
-        println!("{:<14} {}", "Failed:".white(), failed.to_string().red().bold());
-    } else {
-        println!("{:<14} {}", "Failed:".white(), "0".dimmed());
+    for result in futures::future::join_all(primes).await {
+        if let Err(e) = result {
+            return Err(ErrorDetails {
+                message: format!("preconnect failed: {}", error_chain(e.as_ref())),
+                status: None,
+                headers: None,
+                body: None,
+                trailers: None,
+            });
+        }
     }
 
-    println!("\n{}", "--- Latency ---".cyan().bold());
-    println!("{:<14} {:?}", "Avg:".white(), avg);
-    println!("{:<14} {:?}", "P50:".white(), p50);
-    println!("{:<14} {}", "P95:".white(), format!("{:?}", p95).yellow());
-    println!("{:<14} {}", "P99:".white(), format!("{:?}", p99).red());
+    Ok(start.elapsed())
+}
 
-    Ok(())
+/// Print a compact percentile table, one row per request phase.
+///
+/// Phases without samples (e.g. no new connections were opened) are skipped.
+fn print_phases(phases: &[(&str, &Percentiles)], unit: LatencyUnit) {
+    println!("\n{}", "--- Phase Breakdown ---".cyan().bold());
+    let mut table = Table::new(["Phase", "Avg", "P50", "P95", "P99"]);
+    for (name, p) in phases {
+        if p.count == 0 {
+            continue;
+        }
+        table.row([
+            format!("{}:", name).white().to_string(),
+            unit.format(p.avg),
+            unit.format(p.p50),
+            unit.format(p.p95).yellow().to_string(),
+            unit.format(p.p99).red().to_string(),
+        ]);
+    }
+    table.print();
+}
+
+/// Consume the response body, optionally throttled to `rate` bytes/sec.
+///
+/// Throttling is a per-request token bucket: after each chunk we sleep until
+/// the bytes read so far are within budget, so slow reads hold the stream open.
+async fn read_body(
+    resp: &mut reqwest::Response,
+    rate: Option<u64>,
+    mut hasher: Option<&mut BodyHasher>,
+    mut capture: Option<&mut Vec<u8>>,
+) -> BodyRead {
+    let start = Instant::now();
+    let mut total = 0u64;
+
+    loop {
+        let chunk = match resp.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                return BodyRead {
+                    len: total,
+                    error: Some(e),
+                }
+            }
+        };
+        total += chunk.len() as u64;
+        if let Some(hasher) = hasher.as_deref_mut() {
+            hasher.update(&chunk);
+        }
+        if let Some(capture) = capture.as_deref_mut() {
+            capture.extend_from_slice(&chunk);
+        }
+
+        if let Some(rate) = rate.filter(|r| *r > 0) {
+            let due = Duration::from_secs_f64(total as f64 / rate as f64);
+            let elapsed = start.elapsed();
+            if due > elapsed {
+                tokio::time::sleep(due - elapsed).await;
+            }
+        }
+    }
+
+    BodyRead {
+        len: total,
+        error: None,
+    }
+}
+
+/// `request` with everything a worker adds to it besides its body: CORS headers, the
+/// identity, the request id, the `--header-file` line, `--script`'s headers and the
+/// pinned version
+fn with_headers(
+    request: RequestBuilder,
+    config: &RequestConfig,
+    identity: Option<&(reqwest::header::HeaderName, HeaderValue)>,
+    request_id: Option<&HeaderValue>,
+    header: Option<usize>,
+    scripted: Option<&script::Request>,
+    version: Option<Version>,
+) -> RequestBuilder {
+    let request = match &config.cors {
+        Some(cors) => cors.apply(request),
+        None => request,
+    };
+    let request = match identity {
+        Some((name, value)) => request.header(name, value),
+        None => request,
+    };
+    let request = match config.request_id.as_ref().zip(request_id) {
+        Some((name, id)) => request.header(name, id),
+        None => request,
+    };
+    let request = match config.header_file.as_ref().zip(header) {
+        Some((file, line)) => {
+            let (name, value) = file.header(line);
+            request.header(name, value)
+        }
+        None => request,
+    };
+    let request = match scripted {
+        Some(scripted) => scripted
+            .headers
+            .iter()
+            .fold(request, |request, (name, value)| request.header(name, value)),
+        None => request,
+    };
+    match version {
+        Some(version) => request.version(version),
+        None => request,
+    }
 }
 
 #[inline]
 async fn send_request(
-    client: &Client,
-    url: &str,
-    data: &Option<Arc<str>>,
+    client: Result<HttpClient, RebuildError>,
+    version: Option<Version>,
+    outgoing: Outgoing<'_>,
+    identity: Option<&(reqwest::header::HeaderName, HeaderValue)>,
+    config: &RequestConfig,
     stats: &Stats,
-    expected_version: Version,
-    fail_fast: bool,
 ) -> RequestResult {
+    let Outgoing {
+        url,
+        body,
+        body_file,
+        script,
+        request_id,
+        header,
+        pushed,
+    } = outgoing;
+    let (method, target) = match &script {
+        Some((_, request)) => (&request.method, request.url.as_str()),
+        None => (config.method(url), config.url(url)),
+    };
+    // HEAD responses end with their headers, whatever Content-Length says
+    let is_head = method == reqwest::Method::HEAD;
     let req_start = Instant::now();
+    let mut goaway = false;
+    let mut retried = false;
+    let mut status_retries = 0;
+    let mut uploaded = None;
 
-    let result = if let Some(ref body) = data {
-        client
-            .post(url)
-            .version(expected_version)
-            .body(body.to_string())
-            .send()
-            .await
-    } else {
-        client.get(url).version(expected_version).send().await
+    let result = match client {
+        // The worker's client couldn't be replaced, so this request has nothing to go out on
+        Err(e) => Err(e.into()),
+        Ok(client) => loop {
+            let request = client.reqwest.request(method.clone(), target);
+            let request = match &body {
+                Some(body) => {
+                    let (body, done) = UploadBody::new(body.clone());
+                    uploaded = Some(done);
+                    let request = request.body(reqwest::Body::wrap(body));
+                    match &config.compression {
+                        Some(compression) => request.header(CONTENT_ENCODING, compression.codec().name()),
+                        None => request,
+                    }
+                }
+                None => request,
+            };
+            // Before the rest, so --header-file and --script can still override an entry's
+            let request = match config.entry(url) {
+                Some(entry) => entry
+                    .headers
+                    .iter()
+                    .fold(request, |request, (name, value)| request.header(name, value)),
+                None => request,
+            };
+            let scripted = script.as_ref().map(|(_, scripted)| scripted);
+            let request = with_headers(request, config, identity, request_id.as_ref(), header, scripted, version);
+            // Built here rather than by send(), to be measured first
+            let result = match request.build() {
+                Ok(request) => {
+                    let size = RequestSize::of(&request);
+                    stats.sent.observe(&size);
+                    let result = client.execute(request).await;
+                    if let Ok(resp) = &result {
+                        stats.sent.record(&size, resp.version());
+                    }
+                    result
+                }
+                Err(e) => Err(e.into()),
+            };
+
+            if let Err(e) = &result {
+                goaway |= errors::classify(e.as_ref()) == errors::ErrorKind::GoAway;
+
+                // The server never saw this request, so one more try on a new connection is free
+                if config.retry_goaway && !retried && errors::is_safe_to_retry(e.as_ref()) {
+                    retried = true;
+                    stats.retried.fetch_add(1, Ordering::Relaxed);
+                    tracing::debug!(error = %e, "retrying request the server did not process");
+                    continue;
+                }
+            }
+
+            let retry_status = match (&result, &config.status_retry) {
+                (Ok(resp), Some(retry)) => retry.retry(resp.status().as_u16(), status_retries),
+                _ => false,
+            };
+            if retry_status {
+                status_retries += 1;
+                if let Ok(resp) = result {
+                    tracing::debug!(status = resp.status().as_u16(), "retrying request on its status");
+                    // Read to the end, so the connection can be reused
+                    let _ = resp.bytes().await;
+                }
+                continue;
+            }
+
+            break result;
+        },
     };
 
-    let elapsed = req_start.elapsed();
+    let ttfb = req_start.elapsed();
+    // Read when the sample is taken: a server may answer before it has read the whole body
+    let upload = || uploaded.as_ref()?.get().map(|at| at.saturating_duration_since(req_start));
+    // Only until this function first ran: past that, reqwest doesn't say whether the
+    // request waited for a stream or for its connection to be set up
+    let client_queue = || req_start.saturating_duration_since(pushed);
+    let fail_fast = config.fail_fast;
+    let download_rate = config.download_rate;
 
     match result {
         Ok(resp) => {
-            if resp.version() != expected_version {
-                eprintln!(
-                    "{} {:?} not {:?}",
-                    "Warning:".yellow(),
-                    resp.version(),
-                    expected_version
-                );
+            stats.crypto.record_response(&resp, req_start, ttfb);
+            let mut resp = if config.assert_trailers.is_empty() { resp } else { trailers::capture(resp) };
+            let served = resp.version();
+            let trailers = resp.extensions().get::<Trailers>().cloned();
+            let mismatch = match version {
+                Some(expected) => resp.version() != expected,
+                None => {
+                    stats.protocols.record(&resp);
+                    false
+                }
+            };
+            if mismatch {
+                stats.version_mismatches.fetch_add(1, Ordering::Relaxed);
             }
 
+            let alt_svc = if config.alt_svc {
+                stats.alt_svc.record_request(resp.version());
+                resp.headers().get(ALT_SVC).cloned()
+            } else {
+                None
+            };
+
             let status = resp.status();
-            if status.is_success() {
-                stats.success.fetch_add(1, Ordering::Relaxed);
-                let _ = resp.bytes().await;
-                RequestResult::Success(elapsed)
+            let wrong_protocol = mismatch && config.require_protocol;
+            let continued = resp
+                .extensions()
+                .get::<expect::Continue>()
+                .and_then(|&outcome| stats.expect.record(outcome, status.as_u16()));
+
+            for tally in &stats.header_tallies {
+                tally.record(resp.headers());
+            }
+            if let Some((name, id)) = config.request_id.as_ref().zip(request_id.as_ref()) {
+                stats.request_ids.check(name, id, resp.headers());
+            }
+            let header_failure = config
+                .assert_headers
+                .iter()
+                .find_map(|assertion| assertion.check(resp.headers()));
+            if header_failure.is_some() {
+                stats.header_assertion_failures.fetch_add(1, Ordering::Relaxed);
+            }
+            let header_failure = header_failure.or_else(|| {
+                let reason = config.cors.as_ref()?.check(status, resp.headers())?;
+                stats.cors_failures.fetch_add(1, Ordering::Relaxed);
+                Some(reason)
+            });
+            // Every response is linted, whatever else failed it
+            let lint_failure = config.lint.as_ref().and_then(|lint| lint.check_headers(status, resp.headers()));
+            let header_failure = header_failure.or(lint_failure);
+            if let Some(rate_limits) = &config.rate_limits {
+                rate_limits.record(status.as_u16(), resp.headers());
+            }
+            // Called for every response, so its metrics see the failures too
+            let script_failure = match &script {
+                Some((engine, scripted)) => {
+                    let verdict = engine.lock().expect("script lock poisoned").on_response(
+                        scripted,
+                        status.as_u16(),
+                        resp.headers(),
+                    );
+                    match verdict {
+                        Ok(reason) => reason,
+                        Err(message) => {
+                            stats.failed.fetch_add(1, Ordering::Relaxed);
+                            return RequestResult::Error(ErrorDetails {
+                                message,
+                                status: Some(status.as_u16()),
+                                headers: Some(format!("{:#?}", resp.headers())),
+                                body: None,
+                                trailers: None,
+                            });
+                        }
+                    }
+                }
+                None => None,
+            };
+            let header_failure = header_failure.or_else(|| {
+                script_failure.inspect(|_| {
+                    stats.script_failures.fetch_add(1, Ordering::Relaxed);
+                })
+            });
+            let group = config.group_by.as_ref().map(|name| match resp.headers().get(name) {
+                Some(value) => String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                None => "(none)".to_string(),
+            });
+            let backend = config
+                .affinity
+                .as_ref()
+                .and_then(|name| resp.headers().get(name))
+                .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned());
+
+            if status.is_success() && !wrong_protocol && header_failure.is_none() {
+                // A HEAD response ends with its headers; its Content-Length describes the
+                // GET body, so reading would only trip the truncation check
+                let (body_failure, captured) = if is_head {
+                    stats.body.record_declared(resp.headers());
+                    // Never any over HTTP/1.1, where hyper doesn't look, but HTTP/2 and
+                    // HTTP/3 would pass it on as data
+                    let lint_failure = match &config.lint {
+                        Some(lint) => {
+                            let len = resp.chunk().await.ok().flatten().map_or(0, |chunk| chunk.len() as u64);
+                            lint.check_body(true, status, len)
+                        }
+                        None => None,
+                    };
+                    (lint_failure, None)
+                } else {
+                    let content_length = body::content_length(resp.headers());
+                    let mut hasher = BodyHasher::new(&config.body_checks);
+                    // Only bodies sampled for --assert-json or --sanity-checks are buffered
+                    let json_checks = config.json_checks.as_ref().filter(|checks| checks.sampled());
+                    let sanity = config.sanity.as_ref().filter(|sanity| sanity.claim());
+                    let mut captured = (json_checks.is_some() || sanity.is_some()).then(Vec::new);
+                    let read = read_body(&mut resp, download_rate, hasher.as_mut(), captured.as_mut()).await;
+                    stats.body.record_size(read.len);
+                    if let (Some(sanity), Some(body)) = (sanity, &captured) {
+                        let content_type = resp.headers().get(reqwest::header::CONTENT_TYPE);
+                        sanity.record(target, content_type.and_then(|value| value.to_str().ok()), body);
+                    }
+                    let body_failure = match stats.body.check_length(&read, content_length, target) {
+                        Some(reason) => {
+                            stats.errors.record(errors::ErrorKind::Truncated);
+                            Some(reason)
+                        }
+                        None => stats
+                            .body
+                            .check(read.len, hasher, &config.body_checks)
+                            .or_else(|| {
+                                json_checks.zip(captured.as_deref()).and_then(|(checks, body)| checks.check(body))
+                            })
+                            .or_else(|| {
+                                let received = trailers.as_ref().and_then(Trailers::get);
+                                let reason = config.assert_trailers.iter().find_map(|a| a.check_trailers(received))?;
+                                stats.trailer_assertion_failures.fetch_add(1, Ordering::Relaxed);
+                                Some(reason)
+                            })
+                            .or_else(|| config.lint.as_ref()?.check_body(false, status, read.len)),
+                    };
+                    (body_failure, captured)
+                };
+                let sample = Sample {
+                    latency: if is_head { ttfb } else { req_start.elapsed() },
+                    ttfb,
+                    upload: upload(),
+                    client_queue: client_queue(),
+                    goaway,
+                    alt_svc,
+                    group,
+                    backend,
+                    sent_at: req_start,
+                    status: Some(status.as_u16()),
+                    error: None,
+                    worker: 0,
+                    seq: 0,
+                    request_id: None,
+                    burst: None,
+                    queued: Duration::ZERO,
+                    url,
+                    slice: None,
+                    contended: false,
+                    body_file,
+                    header,
+                    continued,
+                    unreachable: None,
+                    version: Some(served),
+                };
+
+                match body_failure {
+                    None => {
+                        stats.success.fetch_add(1, Ordering::Relaxed);
+                        if let Some(retry) = config.status_retry.as_ref().filter(|_| status_retries > 0) {
+                            retry.recovered();
+                        }
+                        RequestResult::Success(sample)
+                    }
+                    Some(reason) => {
+                        stats.failed.fetch_add(1, Ordering::Relaxed);
+                        if fail_fast {
+                            RequestResult::Error(ErrorDetails {
+                                message: reason,
+                                status: Some(status.as_u16()),
+                                headers: Some(format!("{:#?}", resp.headers())),
+                                body: captured.map(|body| String::from_utf8_lossy(&body).into_owned()),
+                                trailers: received_trailers(&trailers),
+                            })
+                        } else {
+                            // Bodies buffered for --assert-json are kept for --error-dump
+                            let dump_slot = captured
+                                .as_ref()
+                                .and(config.error_dump.as_ref())
+                                .and_then(|dump| dump.reserve());
+                            if let (Some(dump), Some(slot), Some(body)) = (&config.error_dump, dump_slot, &captured) {
+                                let exchange = FailedExchange {
+                                    method: method.as_str(),
+                                    url: target,
+                                    request_id: config.request_id.as_ref().zip(request_id.as_ref()),
+                                    version: resp.version(),
+                                    status,
+                                    headers: resp.headers(),
+                                    body,
+                                    trailers: trailers.as_ref().and_then(Trailers::get),
+                                };
+                                if let Err(e) = dump.write(slot, &exchange).await {
+                                    eprintln!("{} could not write error dump: {}", "Warning:".yellow(), e);
+                                }
+                            }
+                            RequestResult::Failed(sample)
+                        }
+                    }
+                }
             } else {
                 stats.failed.fetch_add(1, Ordering::Relaxed);
+                if status == reqwest::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE {
+                    stats.errors.record(errors::ErrorKind::HeadersTooLarge);
+                }
 
                 if fail_fast {
+                    let message = if wrong_protocol {
+                        format!("{:?} response, expected {:?}", resp.version(), version.unwrap_or_default())
+                    } else if let Some(reason) = header_failure {
+                        reason
+                    } else {
+                        format!("HTTP {} {}", status.as_u16(), status.canonical_reason().unwrap_or(""))
+                    };
                     let headers = format!("{:#?}", resp.headers());
-                    let body = resp.text().await.ok();
+                    let body = if is_head { None } else { resp.text().await.ok() };
                     RequestResult::Error(ErrorDetails {
-                        message: format!("HTTP {} {}", status.as_u16(), status.canonical_reason().unwrap_or("")),
+                        message,
                         status: Some(status.as_u16()),
                         headers: Some(headers),
                         body,
+                        trailers: received_trailers(&trailers),
                     })
                 } else {
-                    let _ = resp.bytes().await;
-                    RequestResult::Failed(elapsed)
+                    // Failures picked for --error-dump keep their body instead of discarding it
+                    if is_head {
+                        stats.body.record_declared(resp.headers());
+                    }
+                    let dump_slot = config.error_dump.as_ref().and_then(|dump| dump.reserve());
+                    let version = resp.version();
+                    let headers = dump_slot.is_some().then(|| resp.headers().clone());
+                    let mut body = dump_slot.is_some().then(Vec::new);
+                    if !is_head {
+                        // The request has failed already; a body at odds with its
+                        // Content-Length is only counted
+                        let content_length = body::content_length(resp.headers());
+                        let read = read_body(&mut resp, download_rate, None, body.as_mut()).await;
+                        stats.body.check_length(&read, content_length, target);
+                        stats.body.record_size(read.len);
+                    }
+                    let captured = headers.zip(body).map(|(headers, body)| (version, headers, body));
+
+                    let sample = Sample {
+                        latency: if is_head { ttfb } else { req_start.elapsed() },
+                        ttfb,
+                        upload: upload(),
+                        client_queue: client_queue(),
+                        goaway,
+                        alt_svc,
+                        group,
+                        backend,
+                        sent_at: req_start,
+                        status: Some(status.as_u16()),
+                        error: None,
+                        worker: 0,
+                        seq: 0,
+                        request_id: None,
+                        burst: None,
+                        queued: Duration::ZERO,
+                        url,
+                        slice: None,
+                        contended: false,
+                        body_file,
+                        header,
+                        continued,
+                        unreachable: None,
+                        version: Some(served),
+                    };
+
+                    // Written after the latency is taken so disk I/O doesn't skew it
+                    if let (Some(dump), Some(slot), Some((version, headers, body))) =
+                        (&config.error_dump, dump_slot, captured)
+                    {
+                        let exchange = FailedExchange {
+                            method: method.as_str(),
+                            url: target,
+                            request_id: config.request_id.as_ref().zip(request_id.as_ref()),
+                            version,
+                            status,
+                            headers: &headers,
+                            body: &body,
+                            trailers: trailers.as_ref().and_then(Trailers::get),
+                        };
+                        if let Err(e) = dump.write(slot, &exchange).await {
+                            eprintln!("{} could not write error dump: {}", "Warning:".yellow(), e);
+                        }
+                    }
+
+                    RequestResult::Failed(sample)
                 }
             }
         }
         Err(e) => {
             stats.failed.fetch_add(1, Ordering::Relaxed);
+            let kind = errors::classify(e.as_ref());
+            tracing::debug!(kind = kind.label(), error = %error_chain(e.as_ref()), "request failed");
+            if kind == errors::ErrorKind::Protocol {
+                stats.version_mismatches.fetch_add(1, Ordering::Relaxed);
+            }
+            stats.errors.record(kind);
 
             if fail_fast {
-                // Build full error chain
-                let mut error_msg = e.to_string();
-                let mut source = e.source();
-                while let Some(src) = source {
-                    error_msg.push_str(&format!("\n  caused by: {}", src));
-                    source = src.source();
-                }
-
                 RequestResult::Error(ErrorDetails {
-                    message: error_msg,
-                    status: e.status().map(|s| s.as_u16()),
+                    message: error_chain(e.as_ref()),
+                    status: errors::status(e.as_ref()),
                     headers: None,
                     body: None,
+                    trailers: None,
                 })
             } else {
-                RequestResult::Failed(elapsed)
+                RequestResult::Failed(Sample {
+                    latency: ttfb,
+                    ttfb,
+                    upload: upload(),
+                    client_queue: client_queue(),
+                    goaway,
+                    alt_svc: None,
+                    group: None,
+                    backend: None,
+                    sent_at: req_start,
+                    status: None,
+                    error: Some(kind),
+                    worker: 0,
+                    seq: 0,
+                    request_id: None,
+                    burst: None,
+                    queued: Duration::ZERO,
+                    url,
+                    slice: None,
+                    contended: false,
+                    body_file,
+                    header,
+                    continued: None,
+                    unreachable: errors::connection_failure(kind, e.as_ref())
+                        .map(|what| format!("{}: {}", what, error_chain(e.as_ref()))),
+                    version: None,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_request_of_n_is_dealt_out_once() {
+        for connections in 1..=9 {
+            for workers in 1..=connections {
+                let ranges = deal(connections, workers);
+                assert_eq!(ranges.iter().map(|range| range.len()).sum::<usize>(), connections);
+                assert!(ranges.windows(2).all(|pair| pair[0].end == pair[1].start));
+                for requests in [0, 1, 2, 7, 10, 99, 100] {
+                    let (per_connection, remainder) = (requests / connections as u64, requests % connections as u64);
+                    let quotas: Vec<u64> = ranges.iter().map(|range| quota(range.clone(), per_connection, remainder)).collect();
+                    assert_eq!(quotas.iter().sum::<u64>(), requests, "-n {} -c {} --workers {}", requests, connections, workers);
+                }
             }
         }
     }
+
+    #[test]
+    fn the_remainder_goes_to_the_first_connections() {
+        // -n 7 -c 3: 3, 2, 2 per connection
+        assert_eq!(deal(3, 3), [0..1, 1..2, 2..3]);
+        assert_eq!([0..1, 1..2, 2..3].map(|range| quota(range, 2, 1)), [3, 2, 2]);
+        // -n 10 -c 4 --workers 3: connections 0-1, 2 and 3, each with 2 and the first two 3
+        assert_eq!(deal(4, 3), [0..2, 2..3, 3..4]);
+        assert_eq!([0..2, 2..3, 3..4].map(|range| quota(range, 2, 2)), [6, 2, 2]);
+        // -n 2 -c 3: the last connection sends nothing
+        assert_eq!([0..1, 1..2, 2..3].map(|range| quota(range, 0, 2)), [1, 1, 0]);
+    }
+
+    #[tokio::test]
+    async fn a_panicking_worker_cancels_the_rest_and_is_reported() {
+        let cancel = CancellationToken::new();
+        let waiting = cancel.clone();
+        let handles = vec![
+            tokio::spawn(async move {
+                waiting.cancelled().await;
+                "cancelled"
+            }),
+            tokio::spawn(async {
+                let sent = 5;
+                if sent == 5 {
+                    panic!("injected at {}", sent);
+                }
+                "finished"
+            }),
+            tokio::spawn(async { "finished" }),
+        ];
+        let (reports, panics) = join_workers(handles, &cancel).await;
+        assert!(cancel.is_cancelled());
+        assert_eq!(reports, ["cancelled", "finished"]);
+        assert_eq!(panics, ["worker 1 panicked: injected at 5"]);
+    }
 }
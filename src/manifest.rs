@@ -0,0 +1,246 @@
+//! `--record` and `benchy run --replay`: a run's configuration as a manifest, so the
+//! same benchmark can be run again on another machine or months later.
+//!
+//! benchy is configured by its arguments alone, so the manifest is the arguments with
+//! the random seed pinned, and copies of the `--url-file`, `--workload`, `--hosts-file`
+//! and `--script` files they name. Secrets named with `--secret-env` are stored as `${NAME}` and read
+//! from the environment again on replay.
+
+use crate::Args;
+use clap::Parser;
+use reqwest::Url;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Bumped when the manifest's fields change meaning
+const FORMAT_VERSION: u64 = 1;
+
+/// Bumped whenever a default that changes what a run does changes (`-c`, `-n`, `-p`,
+/// timeouts, the `--retry-goaway` policy, ...), so a manifest recorded under the old
+/// defaults is refused rather than quietly run as a different benchmark
+const DEFAULTS_VERSION: u64 = 1;
+
+/// Flags whose file is copied into the manifest
+const FILE_FLAGS: [&str; 4] = ["--url-file", "--workload", "--hosts-file", "--script"];
+
+/// Flags that only control the recording, left out of it
+const RECORD_FLAGS: [&str; 2] = ["--record", "--secret-env"];
+
+#[derive(Parser)]
+#[command(name = "benchy run", bin_name = "benchy run", about = "Run a benchmark recorded with --record")]
+struct RunArgs {
+    /// Manifest written by --record
+    #[arg(long, value_name = "FILE")]
+    replay: PathBuf,
+}
+
+/// A file one of `FILE_FLAGS` names
+struct File {
+    flag: String,
+    name: String,
+    content: String,
+}
+
+/// Write the manifest for a run with `args`; `args.seed` is already settled
+pub fn record(path: &Path, args: &Args) -> Result<(), String> {
+    let mut argv = args
+        .argv
+        .iter()
+        .skip(1)
+        .map(|arg| arg.to_str().map(str::to_string))
+        .collect::<Option<Vec<String>>>()
+        .ok_or("--record: arguments must be valid UTF-8")?;
+    argv = without_flags(argv, &RECORD_FLAGS);
+    if values(&argv, "--seed").is_empty() {
+        let seed = args.seed.expect("the seed is settled before recording");
+        argv.splice(0..0, ["--seed".to_string(), seed.to_string()]);
+    }
+
+    let mut files = Vec::new();
+    for flag in FILE_FLAGS {
+        for index in values(&argv, flag) {
+            let (prefix, value) = split(&argv[index], flag);
+            let path = Path::new(value);
+            let content = std::fs::read_to_string(path).map_err(|e| format!("--record: {} {}: {}", flag, value, e))?;
+            // Saved under its own name, so the manifest doesn't carry this machine's paths
+            let name = path
+                .file_name()
+                .map_or_else(|| value.to_string(), |name| name.to_string_lossy().into_owned());
+            argv[index] = format!("{}{}", prefix, name);
+            files.push(File {
+                flag: flag.to_string(),
+                name,
+                content,
+            });
+        }
+    }
+
+    for name in &args.secret_env {
+        let secret = std::env::var(name)
+            .ok()
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| format!("--secret-env {}: not set", name))?;
+        let reference = format!("${{{}}}", name);
+        for arg in &mut argv {
+            *arg = arg.replace(&secret, &reference);
+        }
+        for file in &mut files {
+            file.content = file.content.replace(&secret, &reference);
+        }
+    }
+    // Credentials in a URL are the one secret benchy can recognize on its own
+    if let Some(url) = argv
+        .iter()
+        .filter(|arg| !arg.contains("${"))
+        .filter_map(|arg| Url::parse(arg.split_once('=').map_or(arg, |(_, value)| value)).ok())
+        .find(|url| url.password().is_some())
+    {
+        return Err(format!(
+            "--record: {} carries a password; pass it in an environment variable named with --secret-env",
+            url.host_str().unwrap_or_default()
+        ));
+    }
+
+    let recorded = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let manifest = json!({
+        "format": FORMAT_VERSION,
+        "defaults": DEFAULTS_VERSION,
+        "benchy": env!("CARGO_PKG_VERSION"),
+        "recorded_ms": recorded.as_millis() as u64,
+        "args": argv,
+        "files": files
+            .iter()
+            .map(|file| json!({"flag": file.flag, "name": file.name, "content": file.content}))
+            .collect::<Vec<Value>>(),
+        "secrets": args.secret_env,
+    });
+    let text = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+    std::fs::write(path, text).map_err(|e| format!("--record {}: {}", path.display(), e))
+}
+
+/// The command line `benchy run --replay FILE` stands for, with its secrets filled in
+/// from the environment and its files written to a directory of their own
+pub fn replay() -> Result<Vec<OsString>, Box<dyn Error>> {
+    // Skips `run` itself
+    let run = RunArgs::parse_from(std::env::args_os().skip(1));
+    let path = &run.replay;
+    let text = std::fs::read(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let manifest: Value = serde_json::from_slice(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let malformed = || format!("{}: malformed manifest", path.display());
+
+    let format = manifest.get("format").and_then(Value::as_u64);
+    if format != Some(FORMAT_VERSION) {
+        return Err(format!("{}: unsupported manifest format {:?}, expected {}", path.display(), format, FORMAT_VERSION).into());
+    }
+    let defaults = manifest.get("defaults").and_then(Value::as_u64);
+    if defaults != Some(DEFAULTS_VERSION) {
+        let recorded_by = manifest.get("benchy").and_then(Value::as_str).unwrap_or("unknown");
+        return Err(format!(
+            "{} was recorded by benchy {} with defaults version {}; this benchy {} has defaults version {}, \
+             so the run wouldn't be the same. Record it again",
+            path.display(),
+            recorded_by,
+            defaults.map_or("unknown".to_string(), |defaults| defaults.to_string()),
+            env!("CARGO_PKG_VERSION"),
+            DEFAULTS_VERSION
+        )
+        .into());
+    }
+
+    let strings = |key: &str| -> Option<Vec<String>> {
+        manifest
+            .get(key)?
+            .as_array()?
+            .iter()
+            .map(|value| value.as_str().map(str::to_string))
+            .collect()
+    };
+    let mut argv = strings("args").ok_or_else(malformed)?;
+    let mut files = manifest
+        .get("files")
+        .and_then(Value::as_array)
+        .ok_or_else(malformed)?
+        .iter()
+        .map(|file| {
+            Some(File {
+                flag: file.get("flag")?.as_str()?.to_string(),
+                name: file.get("name")?.as_str()?.to_string(),
+                content: file.get("content")?.as_str()?.to_string(),
+            })
+        })
+        .collect::<Option<Vec<File>>>()
+        .ok_or_else(malformed)?;
+
+    for name in strings("secrets").ok_or_else(malformed)? {
+        let secret = std::env::var(&name).map_err(|_| format!("{} needs ${} set", path.display(), name))?;
+        let reference = format!("${{{}}}", name);
+        for arg in &mut argv {
+            *arg = arg.replace(&reference, &secret);
+        }
+        for file in &mut files {
+            file.content = file.content.replace(&reference, &secret);
+        }
+    }
+
+    if !files.is_empty() {
+        let dir = std::env::temp_dir().join(format!("benchy-replay-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        for (n, file) in files.iter().enumerate() {
+            // Numbered, in case two flags named files with the same name
+            let written = dir.join(format!("{}-{}", n, file.name));
+            std::fs::write(&written, &file.content).map_err(|e| format!("{}: {}", written.display(), e))?;
+            let index = values(&argv, &file.flag)
+                .into_iter()
+                .find(|&index| split(&argv[index], &file.flag).1 == file.name)
+                .ok_or_else(malformed)?;
+            let (prefix, _) = split(&argv[index], &file.flag);
+            argv[index] = format!("{}{}", prefix, written.display());
+        }
+    }
+
+    Ok(std::iter::once("benchy".to_string()).chain(argv).map(OsString::from).collect())
+}
+
+/// Indexes of the arguments holding `flag`'s values, whether given as `--flag VALUE` or
+/// `--flag=VALUE`
+fn values(argv: &[String], flag: &str) -> Vec<usize> {
+    let joined = format!("{}=", flag);
+    let mut indexes = Vec::new();
+    for (i, arg) in argv.iter().enumerate() {
+        if arg == "--" {
+            break;
+        }
+        if arg == flag && i + 1 < argv.len() {
+            indexes.push(i + 1);
+        } else if arg.starts_with(&joined) {
+            indexes.push(i);
+        }
+    }
+    indexes
+}
+
+/// `--flag=` and the value for an argument of that form, or nothing and the whole
+/// argument for a value given on its own
+fn split<'a>(arg: &'a str, flag: &str) -> (&'a str, &'a str) {
+    match arg.strip_prefix(flag).and_then(|rest| rest.strip_prefix('=')) {
+        Some(value) => arg.split_at(arg.len() - value.len()),
+        None => ("", arg),
+    }
+}
+
+/// `argv` without `flags` and their values
+fn without_flags(argv: Vec<String>, flags: &[&str]) -> Vec<String> {
+    let mut kept = Vec::with_capacity(argv.len());
+    let mut args = argv.into_iter();
+    while let Some(arg) = args.next() {
+        if flags.contains(&arg.as_str()) {
+            args.next();
+        } else if !flags.iter().any(|flag| arg.starts_with(&format!("{}=", flag))) {
+            kept.push(arg);
+        }
+    }
+    kept
+}
@@ -0,0 +1,84 @@
+//! `benchy merge`: combine `--save-results` files from sharded runs into one report.
+
+use crate::output::{OutputFormat, RunSummary};
+use crate::results::SavedRun;
+use crate::units::LatencyUnit;
+use crate::Percentiles;
+use clap::Parser;
+use colored::Colorize;
+use std::error::Error;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(name = "benchy merge", bin_name = "benchy merge", about = "Combine results saved with --save-results into one report")]
+struct MergeArgs {
+    /// Results files written by --save-results
+    #[arg(required = true, num_args = 1.., value_name = "FILE")]
+    files: Vec<PathBuf>,
+
+    /// Write the merged results here, in the same format, so merges can be merged
+    #[arg(short = 'o', long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Results as human-readable text, one tab-separated row, or markdown tables
+    #[arg(long = "output-format", value_enum, default_value_t = OutputFormat::Text)]
+    output_format: OutputFormat,
+
+    /// Leave out the header row of --output-format table
+    #[arg(long = "no-header")]
+    no_header: bool,
+
+    /// Unit for latencies; `auto` picks one from the median
+    #[arg(long = "latency-unit", value_enum, default_value_t = LatencyUnit::Auto)]
+    latency_unit: LatencyUnit,
+}
+
+pub fn run() -> Result<(), Box<dyn Error>> {
+    // Skips `merge` itself, which clap would take for the first file
+    let args = MergeArgs::parse_from(std::env::args_os().skip(1));
+
+    let mut merged = SavedRun::default();
+    for path in &args.files {
+        let run = SavedRun::load(path)?;
+        if !merged.targets.is_empty() && run.targets.iter().any(|t| !merged.targets.contains(t)) {
+            eprintln!(
+                "{} {} targets {}, unlike {}; reporting mixed targets",
+                "Warning:".yellow(),
+                path.display(),
+                run.targets.join(", "),
+                merged.targets.join(", ")
+            );
+        }
+        merged.merge(run);
+    }
+
+    if let Some(path) = &args.output {
+        merged
+            .save(path)
+            .map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+    }
+
+    let latency = Percentiles::from_unsorted(&mut merged.latencies);
+    let ttfb = Percentiles::from_unsorted(&mut merged.ttfbs);
+    let unit = args.latency_unit.resolve(latency.p50);
+    let summary = merged.summary(&latency, &ttfb);
+    match args.output_format {
+        OutputFormat::Text => {
+            println!(
+                "{} {} results from {}",
+                "Merged".cyan().bold(),
+                args.files.len().to_string().green(),
+                merged.url.yellow()
+            );
+            merged.print(&latency, &ttfb, unit, &format!("{} runs", args.files.len()), false);
+        }
+        OutputFormat::Table => {
+            if !args.no_header {
+                println!("{}", RunSummary::table_header());
+            }
+            println!("{}", summary.table_row());
+        }
+        OutputFormat::Markdown => print!("{}", summary.markdown(unit, &[])),
+    }
+    Ok(())
+}
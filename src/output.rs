@@ -0,0 +1,184 @@
+use crate::cryptodrift::DriftReport;
+use crate::errors::{ErrorCounts, ErrorKind};
+use crate::resources::{self, ResourceSummary};
+use crate::schedlag::SchedulerLag;
+use crate::thresholds::Evaluation;
+use crate::timeline::StatusTimeline;
+use crate::units::LatencyUnit;
+use crate::urls::{self, UrlRow};
+use crate::Percentiles;
+use clap::ValueEnum;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// How results are printed (`--output-format`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable results blocks
+    #[default]
+    Text,
+    /// One tab-separated row per run, for awk/sort/column -t
+    Table,
+    /// GitHub-flavored markdown tables, e.g. for PR comments
+    Markdown,
+}
+
+/// Column names of `--output-format table`. Append-only: scripts index these by
+/// position, so never reorder or remove one.
+const COLUMNS: &[&str] = &[
+    "url",
+    "proto",
+    "conns",
+    "pipeline",
+    "reqs",
+    "duration_s",
+    "rps",
+    "p50_us",
+    "p95_us",
+    "p99_us",
+    "err_rate",
+    "client_cpu_pct",
+    "client_rss_mib",
+];
+
+/// One run's results, for the machine- and markdown-oriented output formats
+pub struct RunSummary<'a> {
+    pub url: &'a str,
+    pub proto: &'a str,
+    pub conns: usize,
+    pub pipeline: usize,
+    /// Completed requests
+    pub reqs: u64,
+    /// When the measured window began, and how long it lasted
+    pub started: SystemTime,
+    pub duration: Duration,
+    pub rps: f64,
+    pub success: u64,
+    pub failed: u64,
+    pub errors: &'a ErrorCounts,
+    pub latency: &'a Percentiles,
+    /// Request phases with samples, e.g. DNS, Connect, TTFB
+    pub phases: Vec<(&'a str, &'a Percentiles)>,
+    /// `--per-url-stats`, empty otherwise
+    pub urls: &'a [UrlRow<'a>],
+    /// benchy's own CPU and memory use; `None` off Linux
+    pub resources: Option<ResourceSummary>,
+    /// How late benchy's runtime ran its tasks
+    pub scheduler_lag: Option<SchedulerLag>,
+    /// Status classes per second; a merged run's aren't kept
+    pub status_timeline: Option<&'a StatusTimeline>,
+    /// TLS handshake and warm TTFB P50s per second; `None` without TLS, or merged
+    pub crypto_drift: Option<&'a DriftReport>,
+}
+
+impl RunSummary<'_> {
+    pub fn err_rate(&self) -> f64 {
+        if self.reqs == 0 {
+            0.0
+        } else {
+            self.failed as f64 / self.reqs as f64
+        }
+    }
+
+    pub fn table_header() -> String {
+        COLUMNS.join("\t")
+    }
+
+    pub fn table_row(&self) -> String {
+        // Empty where they couldn't be measured, so the column count never changes
+        let (cpu, rss) = match &self.resources {
+            Some(r) => (format!("{:.1}", r.cpu_avg * 100.0), format!("{:.1}", r.rss_peak as f64 / (1024.0 * 1024.0))),
+            None => (String::new(), String::new()),
+        };
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{:.3}\t{:.2}\t{}\t{}\t{}\t{:.4}\t{}\t{}",
+            self.url,
+            self.proto,
+            self.conns,
+            self.pipeline,
+            self.reqs,
+            self.duration.as_secs_f64(),
+            self.rps,
+            self.latency.p50.as_micros(),
+            self.latency.p95.as_micros(),
+            self.latency.p99.as_micros(),
+            self.err_rate(),
+            cpu,
+            rss
+        )
+    }
+
+    /// Results, latency and threshold tables as GitHub-flavored markdown
+    pub fn markdown(&self, unit: LatencyUnit, evaluations: &[Evaluation]) -> String {
+        let mut md = format!("### benchy: `{}`\n\n", self.url);
+        md.push_str("| Metric | Value |\n|---|---:|\n");
+        let _ = writeln!(md, "| Protocol | {} |", self.proto);
+        // Not known to a report rebuilt from a request log
+        if self.conns > 0 {
+            let _ = writeln!(md, "| Concurrency | {} × {} |", self.conns, self.pipeline);
+        }
+        let _ = writeln!(md, "| Requests | {} |", self.reqs);
+        let _ = writeln!(md, "| Total time | {:.3}s |", self.duration.as_secs_f64());
+        let _ = writeln!(md, "| Requests/sec | {:.2} |", self.rps);
+        let _ = writeln!(md, "| Success | {} |", self.success);
+        let _ = writeln!(md, "| Failed | {} |", self.failed);
+        let _ = writeln!(md, "| Error rate | {:.2}% |", self.err_rate() * 100.0);
+        for kind in ErrorKind::ALL {
+            let count = self.errors.get(kind);
+            if count > 0 {
+                let _ = writeln!(md, "| Errors: {} | {} |", kind.label(), count);
+            }
+        }
+        if let Some(r) = &self.resources {
+            let saturated = if r.saturated() { " ⚠️ saturated" } else { "" };
+            let _ = writeln!(
+                md,
+                "| Client CPU | {:.1}% avg, {:.1}% peak of {}{} |",
+                r.cpu_avg * 100.0,
+                r.cpu_peak * 100.0,
+                r.cores(),
+                saturated
+            );
+            let _ = writeln!(md, "| Client RSS | {} peak |", resources::mib(r.rss_peak));
+        }
+        if let Some(lag) = &self.scheduler_lag {
+            let overloaded = if lag.overloaded() { " ⚠️ overloaded" } else { "" };
+            let _ = writeln!(md, "| Client lag | {:.1?} p99, {:.1?} max{} |", lag.p99, lag.max, overloaded);
+        }
+
+        md.push_str("\n| Phase | Avg | P50 | P95 | P99 |\n|---|---:|---:|---:|---:|\n");
+        let rows = std::iter::once(("Latency", self.latency)).chain(self.phases.iter().copied());
+        for (name, p) in rows.filter(|(_, p)| p.count > 0) {
+            let _ = writeln!(
+                md,
+                "| {} | {} | {} | {} | {} |",
+                name,
+                unit.format(p.avg),
+                unit.format(p.p50),
+                unit.format(p.p95),
+                unit.format(p.p99)
+            );
+        }
+
+        if !self.urls.is_empty() {
+            md.push_str(&urls::markdown(self.urls, unit));
+        }
+
+        if !evaluations.is_empty() {
+            md.push_str("\n| Threshold | Measured | Required | |\n|---|---:|---:|:---:|\n");
+            for e in evaluations {
+                let verdict = if e.passed { "✅" } else { "❌" };
+                let _ = writeln!(md, "| {} | {} | {} | {} |", e.name, e.measured, e.required, verdict);
+            }
+        }
+        md
+    }
+}
+
+/// Append `markdown` to the GitHub Actions job summary file
+pub fn append_step_summary(path: &Path, markdown: &str) -> std::io::Result<()> {
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", markdown)
+}
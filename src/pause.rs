@@ -0,0 +1,84 @@
+use colored::Colorize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// Run-wide pause switch, toggled by SIGUSR2. Paused workers let in-flight requests
+/// finish, then park until resumed.
+#[derive(Default)]
+pub struct PauseControl {
+    paused: AtomicBool,
+    /// Bumped on every toggle, so samplers can tell a window saw a pause
+    toggles: AtomicU64,
+    resumed: Notify,
+    /// Start of the current pause, and the length of all finished ones
+    time: Mutex<(Option<Instant>, Duration)>,
+}
+
+impl PauseControl {
+    pub fn toggle(&self) {
+        let mut time = self.time.lock().unwrap();
+        let paused = !self.paused.load(Ordering::Relaxed);
+        if paused {
+            time.0 = Some(Instant::now());
+        } else if let Some(since) = time.0.take() {
+            time.1 += since.elapsed();
+        }
+        self.paused.store(paused, Ordering::Relaxed);
+        self.toggles.fetch_add(1, Ordering::Relaxed);
+        drop(time);
+
+        if paused {
+            eprintln!("{} no new requests until the next SIGUSR2", "Paused:".yellow().bold());
+        } else {
+            eprintln!("{} sending requests again", "Resumed:".green().bold());
+            self.resumed.notify_waiters();
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn toggles(&self) -> u64 {
+        self.toggles.load(Ordering::Relaxed)
+    }
+
+    /// Park until the run is resumed; returns at once if it isn't paused
+    pub async fn wait_resumed(&self) {
+        loop {
+            // Registered before the check so a resume in between isn't missed
+            let resumed = self.resumed.notified();
+            if !self.is_paused() {
+                return;
+            }
+            resumed.await;
+        }
+    }
+
+    /// Wall-clock time spent paused, including a pause still in progress
+    pub fn paused_time(&self) -> Duration {
+        let time = self.time.lock().unwrap();
+        time.1 + time.0.map_or(Duration::ZERO, |since| since.elapsed())
+    }
+}
+
+/// Toggle `control` on every SIGUSR2 for the rest of the process
+#[cfg(unix)]
+pub fn listen(control: Arc<PauseControl>) -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut signals = signal(SignalKind::user_defined2())?;
+    tokio::spawn(async move {
+        while signals.recv().await.is_some() {
+            control.toggle();
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn listen(_control: Arc<PauseControl>) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,130 @@
+//! `--data-file` and `--data-dir`: request bodies read from disk, decompressed once at
+//! startup when stored as zstd or gzip, and shared by every request that sends them.
+
+use crate::resources::mib;
+use bytes::Bytes;
+use clap::ValueEnum;
+use colored::Colorize;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// Mixed into the seed so body picks don't shift generated values or URL picks
+const BODY_STREAM: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Bodies held in memory past this size get a warning before the run
+const MEMORY_WARNING: u64 = 256 * 1024 * 1024;
+
+/// How body files are stored (`--data-file-encoding`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Encoding {
+    /// From each file's extension: .zst or .zstd, .gz, anything else as is
+    #[default]
+    Auto,
+    /// Sent as stored
+    Identity,
+    Gzip,
+    Zstd,
+}
+
+impl Encoding {
+    fn resolve(self, path: &Path) -> Encoding {
+        match (self, path.extension().and_then(|ext| ext.to_str())) {
+            (Encoding::Auto, Some("zst" | "zstd")) => Encoding::Zstd,
+            (Encoding::Auto, Some("gz")) => Encoding::Gzip,
+            (Encoding::Auto, _) => Encoding::Identity,
+            (encoding, _) => encoding,
+        }
+    }
+}
+
+/// Read `path`, decompressing it as `encoding` says
+pub fn load(path: &Path, encoding: Encoding) -> Result<Bytes, String> {
+    let file = File::open(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let mut body = Vec::new();
+    let (read, what) = match encoding.resolve(path) {
+        Encoding::Zstd => (zstd::stream::read::Decoder::new(file).and_then(|mut r| r.read_to_end(&mut body)), "zstd"),
+        // Multi-member, like gunzip, so concatenated .gz files decompress whole
+        Encoding::Gzip => (flate2::read::MultiGzDecoder::new(BufReader::new(file)).read_to_end(&mut body), "gzip"),
+        Encoding::Identity | Encoding::Auto => (BufReader::new(file).read_to_end(&mut body), "file"),
+    };
+    read.map_err(|e| format!("{}: corrupt or truncated {}: {}", path.display(), what, e))?;
+    Ok(Bytes::from(body))
+}
+
+/// Warn before the run when `bytes` of bodies are about to be held in memory
+pub fn warn_size(flag: &str, bytes: u64) {
+    if bytes > MEMORY_WARNING {
+        eprintln!(
+            "{} {} holds {} of decompressed bodies in memory",
+            "Warning:".yellow(),
+            flag,
+            mib(bytes)
+        );
+    }
+}
+
+/// `--data-dir`: one body per file, each request sending one picked at random
+pub struct BodyPool {
+    names: Vec<String>,
+    bodies: Vec<Bytes>,
+}
+
+impl BodyPool {
+    /// Every file directly in `dir`, in name order so seeded picks repeat; dotfiles and
+    /// subdirectories are skipped
+    pub fn load(dir: &Path, encoding: Encoding) -> Result<BodyPool, String> {
+        let entries = std::fs::read_dir(dir).map_err(|e| format!("could not read {}: {}", dir.display(), e))?;
+        let mut paths = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("could not read {}: {}", dir.display(), e))?;
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if !hidden && entry.file_type().is_ok_and(|kind| kind.is_file()) {
+                paths.push(entry.path());
+            }
+        }
+        if paths.is_empty() {
+            return Err(format!("{} has no files to send", dir.display()));
+        }
+        paths.sort();
+
+        let mut pool = BodyPool {
+            names: Vec::with_capacity(paths.len()),
+            bodies: Vec::with_capacity(paths.len()),
+        };
+        for path in paths {
+            pool.bodies.push(load(&path, encoding)?);
+            pool.names.push(path.file_name().unwrap_or_default().to_string_lossy().into_owned());
+        }
+        Ok(pool)
+    }
+
+    pub fn len(&self) -> usize {
+        self.bodies.len()
+    }
+
+    /// Decompressed size of every body
+    pub fn size(&self) -> u64 {
+        self.bodies.iter().map(|body| body.len() as u64).sum()
+    }
+
+    pub fn name(&self, index: usize) -> &str {
+        &self.names[index]
+    }
+
+    pub fn body(&self, index: usize) -> Bytes {
+        self.bodies[index].clone()
+    }
+
+    /// Index of the next body to send, uniformly
+    pub fn pick(&self, rng: &mut SmallRng) -> usize {
+        rng.gen_range(0..self.bodies.len())
+    }
+}
+
+/// One worker's body picks, seeded from `--seed` plus the worker index
+pub fn picker(seed: u64, worker: usize) -> SmallRng {
+    SmallRng::seed_from_u64(seed.wrapping_add(worker as u64) ^ BODY_STREAM)
+}
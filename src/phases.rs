@@ -0,0 +1,143 @@
+//! Where a run's time went. Setup comes before the first send: the workers' clients
+//! built (a TLS config each), `--preconnect` and `--prime`. The measured window runs
+//! from the first send to the last completion, and throughput is computed over it
+//! alone. Teardown follows, while workers wind down and `--drain-timeout` gives up on
+//! requests that never completed.
+
+use crate::clock::RunClock;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The boundaries of a run's phases
+#[derive(Clone)]
+pub struct Phases {
+    /// Setup's start, which the other boundaries are placed by
+    clock: RunClock,
+    setup: Instant,
+    measured: Instant,
+    measured_end: Instant,
+    end: Instant,
+}
+
+/// Setup's start, before anything is measured
+pub struct Setup {
+    clock: RunClock,
+}
+
+impl Setup {
+    pub fn begin() -> Setup {
+        Setup {
+            clock: RunClock::start(),
+        }
+    }
+
+    /// For wall-clock times of what happens from here on
+    pub fn clock(&self) -> RunClock {
+        self.clock
+    }
+
+    /// The run is over: it was measured from `measured` until `measured_end`, the last
+    /// completion or when sending stopped, and now it has wound down. Without either
+    /// the window runs to now.
+    pub fn end(&self, measured: Instant, measured_end: Option<Instant>) -> Phases {
+        let end = Instant::now();
+        Phases {
+            clock: self.clock,
+            setup: self.clock.began(),
+            measured,
+            measured_end: measured_end.map_or(end, |last| last.clamp(measured, end)),
+            end,
+        }
+    }
+}
+
+impl Phases {
+    pub fn setup(&self) -> Duration {
+        self.measured - self.setup
+    }
+
+    pub fn measured(&self) -> Duration {
+        self.measured_end - self.measured
+    }
+
+    /// What throughput is computed over: the measured window, less `paused` (SIGUSR2)
+    pub fn active(&self, paused: Duration) -> Duration {
+        self.measured() - paused.min(self.measured())
+    }
+
+    pub fn teardown(&self) -> Duration {
+        self.end - self.measured_end
+    }
+
+    pub fn total(&self) -> Duration {
+        self.end - self.setup
+    }
+
+    /// When the measured window began
+    pub fn started(&self) -> SystemTime {
+        self.clock.wall(self.measured)
+    }
+
+    /// When the measured window ended
+    pub fn ended(&self) -> SystemTime {
+        self.clock.wall(self.measured_end)
+    }
+
+    /// e.g. `setup 1.2s, measured 10.0s, teardown 0.3s`
+    pub fn describe(&self) -> String {
+        format!(
+            "setup {:.1?}, measured {:.1?}, teardown {:.1?}",
+            self.setup(),
+            self.measured(),
+            self.teardown()
+        )
+    }
+
+    /// For `--save-results`: each boundary in milliseconds since the Unix epoch
+    pub fn to_json(&self) -> Value {
+        let ms = |at: Instant| self.clock.wall(at).duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        json!({
+            "setup_start_ms": ms(self.setup),
+            "measured_start_ms": ms(self.measured),
+            "measured_end_ms": ms(self.measured_end),
+            "teardown_end_ms": ms(self.end),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A run of `requests` sent `gap` apart, after clients that take `build` to construct
+    fn run(build: Duration, requests: u32, gap: Duration) -> (Phases, f64) {
+        let setup = Setup::begin();
+        // Stands in for the workers' clients, each with a TLS config to build
+        std::thread::sleep(build);
+        let start = Instant::now();
+        for _ in 0..requests {
+            std::thread::sleep(gap);
+        }
+        let phases = setup.end(start, Some(Instant::now()));
+        let rps = requests as f64 / phases.active(Duration::ZERO).as_secs_f64();
+        (phases, rps)
+    }
+
+    #[test]
+    fn slow_setup_leaves_throughput_alone() {
+        let (_, fast) = run(Duration::ZERO, 20, Duration::from_millis(5));
+        let (phases, slow) = run(Duration::from_millis(500), 20, Duration::from_millis(5));
+        assert!(phases.setup() >= Duration::from_millis(500), "{}", phases.describe());
+        assert!(phases.measured() < Duration::from_millis(500), "{}", phases.describe());
+        // Counted over the whole run, the slow clients would cut it to under a fifth
+        assert!(slow > fast * 0.6, "{:.1}/s after slow setup, {:.1}/s after none", slow, fast);
+    }
+
+    #[test]
+    fn pauses_are_not_active() {
+        let (phases, _) = run(Duration::ZERO, 1, Duration::from_millis(20));
+        assert_eq!(phases.active(Duration::ZERO), phases.measured());
+        assert_eq!(phases.active(Duration::from_millis(5)), phases.measured() - Duration::from_millis(5));
+        assert_eq!(phases.active(Duration::from_secs(60)), Duration::ZERO);
+    }
+}
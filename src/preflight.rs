@@ -0,0 +1,360 @@
+//! Everything that can be known to be wrong before a connection is opened: flags that
+//! don't go together, URLs that can't be requested, files that can't be read, and a
+//! request reqwest won't build. Every problem is reported at once, instead of the first
+//! one alone, or the same error once per worker after the run has started.
+
+use crate::protocol::Protocol;
+use crate::requestid::Ulids;
+use crate::sent::RequestSize;
+use crate::sinks;
+use crate::template::BodyGenerator;
+use crate::{error_chain, Args, RequestConfig};
+use reqwest::Version;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+/// What's wrong with the arguments, one sentence each
+pub struct Problems(Vec<String>);
+
+impl fmt::Display for Problems {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.as_slice() {
+            [problem] => write!(f, "{}", problem),
+            problems => {
+                write!(f, "{} problems with the arguments:", problems.len())?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// main() reports errors with their Debug, which would quote and escape the list
+impl fmt::Debug for Problems {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for Problems {}
+
+/// Check `args` as parsed, before `--alternate` or `--hosts-file` fill in the URL and
+/// before `--h2c-upgrade` pins the protocol
+pub fn check(args: &Args) -> Result<(), Problems> {
+    let mut problems = Vec::new();
+    let mut problem = |message: String| problems.push(message);
+
+    let quic_flags = args.h3_max_idle_timeout.is_some()
+        || args.h3_initial_max_streams.is_some()
+        || args.h3_congestion.is_some()
+        || args.h3_max_udp_payload.is_some()
+        || args.h3_keepalive.is_some();
+    if quic_flags && !args.http3 {
+        problem("--h3-max-idle-timeout, --h3-initial-max-streams, --h3-congestion, --h3-max-udp-payload and --h3-keepalive require --h3".into());
+    }
+
+    // The targets, as far as they are known before --hosts-file is read
+    let urls: Vec<&str> = match (&args.alternate, &args.hosts_file) {
+        (Some(targets), _) => targets.iter().map(String::as_str).collect(),
+        (None, Some(_)) => Vec::new(),
+        (None, None) => std::iter::once(&args.url).chain(&args.more_urls).map(String::as_str).collect(),
+    };
+    for url in &urls {
+        if let Err(reason) = target(url) {
+            problem(format!("{}: {}", url, reason));
+        }
+    }
+    let all_https = urls.iter().all(|url| url.starts_with("https://"));
+    // --fallback may step down to a protocol that can use it
+    if args.http3 && !args.fallback && !all_https {
+        problem("HTTP/3 runs over QUIC, which is always encrypted, so it needs an https:// URL".into());
+    }
+    if args.h3_alt_svc && !all_https {
+        problem("--h3-alt-svc requires an https:// URL".into());
+    }
+
+    if args.h2c_upgrade {
+        if urls.iter().any(|url| !url.starts_with("http://")) {
+            problem("--h2c-upgrade is for http:// targets; https:// negotiates HTTP/2 with ALPN".into());
+        }
+        if !matches!(args.protocol, Protocol::Auto | Protocol::H2) {
+            problem("--h2c-upgrade upgrades to HTTP/2, so it can't be combined with --protocol h1 or h3".into());
+        }
+    }
+    // Only hyper's HTTP/1.1 client reports interim responses
+    if args.expect_continue && (args.protocol != Protocol::H1 || args.fallback || args.proxy.is_some()) {
+        problem("--expect-continue needs --protocol h1, without --fallback or --proxy".into());
+    }
+    if args.alternate.is_some() {
+        // Our QUIC and 100-continue connections are only ever dialed to one host
+        if args.http3 || args.expect_continue {
+            problem("--alternate can't be combined with --h3 or --expect-continue".into());
+        }
+        if args.slice.is_zero() {
+            problem("--slice must be longer than 0s".into());
+        }
+        if !args.url.is_empty() {
+            problem("--alternate names both targets, so leave out URL".into());
+        }
+    }
+    if args.hol_probe.is_some() {
+        // Over HTTP/1.1 each stream is a connection of its own, with nothing to block
+        if args.protocol == Protocol::H1 {
+            problem("--hol-probe needs streams that share a connection: --protocol h2 or h3".into());
+        }
+        if args.pipeline < 2 {
+            problem("--hol-probe needs -p 2 or more: one stream for the slow object, the rest for small ones".into());
+        }
+    }
+    let has_body = args.data.is_some() || args.data_file.is_some() || args.data_dir.is_some();
+    if args.compress_body.is_some() && !(has_body || args.workload.is_some() || args.script.is_some()) {
+        problem("--compress-body needs a body to compress: -d, --data-file, --data-dir, --workload or --script".into());
+    }
+    if args.hosts_file.is_some() && !args.url.is_empty() {
+        problem("--hosts-file names the targets, so leave out URL".into());
+    }
+    if let Some(reason) = args.publish.as_deref().and_then(crate::publish::problem) {
+        problem(reason);
+    }
+    // Nothing would be sent: the requests are split over the connections, and each
+    // connection's over its streams
+    if args.connections == 0 {
+        problem("-c must be at least 1".into());
+    }
+    if args.pipeline == 0 {
+        problem("-p must be at least 1".into());
+    }
+    if args.workers.is_some_and(|workers| workers == 0 || workers > args.connections) {
+        problem(format!("--workers must be from 1 up to -c ({})", args.connections));
+    }
+    if !(0.0..100.0).contains(&args.flap_threshold) {
+        problem(format!("--flap-threshold must be a percentage from 0 up to 100, got {}", args.flap_threshold));
+    }
+    if args.abort_if_rps_below.is_some() && args.rps_window < Duration::from_secs(1) {
+        problem("--for must be at least 1s".into());
+    }
+    if args.probe_mode.is_some_and(|interval| interval.is_zero()) {
+        problem("--probe-mode needs an interval above zero".into());
+    }
+    // A probe may be repeated for days against production, so it must be safe to
+    if let (Some(_), Some(method)) = (args.probe_mode, &args.method) {
+        if !matches!(*method, reqwest::Method::GET | reqwest::Method::HEAD | reqwest::Method::OPTIONS | reqwest::Method::TRACE) {
+            problem(format!("--probe-mode only sends safe methods (GET, HEAD, OPTIONS or TRACE), not -m {}", method));
+        }
+    }
+    if args.method == Some(reqwest::Method::HEAD) {
+        let body_flags = [
+            (args.data.is_some(), "-d"),
+            (args.data_file.is_some(), "--data-file"),
+            (args.data_dir.is_some(), "--data-dir"),
+            (args.verify_consistency, "--verify-consistency"),
+            (args.expect_body_sha256.is_some(), "--expect-body-sha256"),
+            (args.expect_size.is_some() || args.expect_size_range.is_some(), "--expect-size"),
+            (!args.assert_json.is_empty(), "--assert-json"),
+            (!args.assert_trailer.is_empty(), "--assert-trailer"),
+            (args.download_rate.is_some(), "--download-rate"),
+        ];
+        let set: Vec<&str> = body_flags.iter().filter_map(|&(set, flag)| set.then_some(flag)).collect();
+        if !set.is_empty() {
+            problem(format!("-m HEAD has no bodies, so {} can't be used", set.join(", ")));
+        }
+    }
+    if let Some(proxy) = &args.proxy {
+        // HTTP/3 and the handshake benchmark dial the target themselves
+        if args.http3 || args.h3_alt_svc || args.handshake_only {
+            problem("--proxy can't be combined with HTTP/3 or --handshake-only".into());
+        }
+        if let Err(e) = reqwest::Proxy::all(proxy) {
+            problem(format!("--proxy {}: {}", proxy, e));
+        }
+    }
+    if args.processes == 0 {
+        problem("--processes must be at least 1".into());
+    }
+    // A connection with no share of the burst would never send anything
+    if args.burst.is_some_and(|burst| burst.size < args.connections as u64) {
+        problem("--burst SIZE must be at least -c, so every connection sends part of each burst".into());
+    }
+    for output in sinks::problems(&args.output) {
+        problem(output);
+    }
+
+    // Only whether they can be opened; what's in them is checked as they are loaded
+    let files = [
+        ("--data-file", args.data_file.as_deref()),
+        ("--url-file", args.url_file.as_deref()),
+        ("--workload", args.workload.as_deref()),
+        ("--hosts-file", args.hosts_file.as_deref()),
+        ("--header-file", args.header_file.as_deref()),
+        ("--script", args.script.as_deref()),
+    ];
+    for (flag, path) in files {
+        if let Some(Err(e)) = path.map(std::fs::File::open) {
+            problem(format!("{} {}: {}", flag, path.unwrap_or(Path::new("")).display(), e));
+        }
+    }
+    if let Some(dir) = &args.data_dir {
+        if let Err(e) = std::fs::read_dir(dir) {
+            problem(format!("--data-dir {}: {}", dir.display(), e));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Problems(problems))
+    }
+}
+
+/// Why `url` can't be benchmarked, if it can't
+fn target(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| e.to_string())?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("scheme {} isn't http or https", parsed.scheme()));
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err("no host".into());
+    }
+    Ok(())
+}
+
+/// Build worker 0's first request, body, identity and headers included, so whatever
+/// reqwest won't put in a request fails once, here
+pub fn request(args: &Args, config: &RequestConfig, seed: u64) -> Result<(), Problems> {
+    build(args, config, seed, 0, config.header_file.as_ref().map(|_| 0))
+        .map(|_| ())
+        .map_err(|e| Problems(vec![format!("the request can't be built: {}", error_chain(&e))]))
+}
+
+/// A warning if the largest header block a request will carry is over
+/// `--max-header-bytes`: every `--workload` entry's, with the longest `--header-file`
+/// line. `--script` headers aren't known until the run
+pub fn header_size(args: &Args, config: &RequestConfig, seed: u64) -> Option<String> {
+    let line = config.header_file.as_ref().map(|file| {
+        (0..file.len())
+            .max_by_key(|&line| {
+                let (name, value) = file.header(line);
+                name.as_str().len() + value.len()
+            })
+            .unwrap_or(0)
+    });
+    let version = args.protocol.version();
+    let (size, url) = (0..config.urls.as_ref().map_or(1, |mix| mix.len()))
+        .filter_map(|index| {
+            let request = build(args, config, seed, index, line).ok()?;
+            Some((RequestSize::of(&request).headers(version), config.url(index)))
+        })
+        .max_by_key(|&(size, _)| size)?;
+    if size <= args.max_header_bytes {
+        return None;
+    }
+    let measured = match version {
+        Some(Version::HTTP_11) => "an HTTP/1.1 header block",
+        Some(_) => "a header list (names, values and 32 B per field)",
+        None => "a header block or list, whichever is larger",
+    };
+    Some(format!(
+        "requests to {} carry {} B of headers as {}, over --max-header-bytes {}; servers commonly answer 431 or \
+         reset the stream",
+        url, size, measured, args.max_header_bytes
+    ))
+}
+
+/// Request `index` of worker 0, sending `--header-file` line `header`
+fn build(
+    args: &Args,
+    config: &RequestConfig,
+    seed: u64,
+    index: usize,
+    header: Option<usize>,
+) -> reqwest::Result<reqwest::Request> {
+    let identity = args.identity.as_ref().map(|identity| identity.for_worker(seed, 0));
+    let request_id = config.request_id.as_ref().map(|_| Ulids::new().next());
+    let request = reqwest::Client::new().request(config.method(index).clone(), config.url(index));
+    let request = match (config.entry(index), &config.bodies, &config.body) {
+        (Some(entry), _, _) => {
+            let request = entry.headers.iter().fold(request, |request, (name, value)| request.header(name, value));
+            match &entry.body {
+                Some(body) => request.body(body.clone()),
+                None => request,
+            }
+        }
+        (None, Some(pool), _) => request.body(pool.body(0)),
+        (None, None, Some(template)) => request.body(template.render(&mut BodyGenerator::new(seed, 0))),
+        (None, None, None) => request,
+    };
+    crate::with_headers(request, config, identity.as_ref(), request_id.as_ref(), header, None, args.protocol.version())
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn problems(argv: &[&str]) -> Vec<String> {
+        let args = Args::parse_from(std::iter::once("benchy").chain(argv.iter().copied()));
+        match check(&args) {
+            Ok(()) => Vec::new(),
+            Err(Problems(problems)) => problems,
+        }
+    }
+
+    #[test]
+    fn valid_configurations_pass() {
+        for argv in [
+            &["http://localhost:8080/"][..],
+            &["-c", "1", "-p", "1", "-n", "1", "https://localhost:8443/"],
+            &["--h3", "--h3-keepalive", "5s", "https://localhost:8443/"],
+            &["--protocol", "h1", "--expect-continue", "-d", "x", "http://localhost:8080/"],
+            &["--probe-mode=5s", "-m", "OPTIONS", "http://localhost:8080/"],
+        ] {
+            assert_eq!(problems(argv), Vec::<String>::new(), "{:?}", argv);
+        }
+    }
+
+    #[test]
+    fn invalid_configurations_are_reported() {
+        let cases: &[(&[&str], &str)] = &[
+            (&["-c", "0", "-n", "10", "http://localhost/"], "-c must be at least 1"),
+            (&["-c", "1", "-p", "0", "-n", "10", "http://localhost/"], "-p must be at least 1"),
+            (&["--processes", "0", "http://localhost/"], "--processes must be at least 1"),
+            (&["-c", "4", "--workers", "5", "http://localhost/"], "--workers must be from 1 up to -c (4)"),
+            (&["--workers", "0", "http://localhost/"], "--workers must be from 1 up to -c (10)"),
+            (&["--h3-keepalive", "5s", "https://localhost/"], "require --h3"),
+            (&["--h3", "http://localhost/"], "needs an https:// URL"),
+            (&["--h3-alt-svc", "http://localhost/"], "--h3-alt-svc requires an https:// URL"),
+            (&["--h2c-upgrade", "https://localhost/"], "--h2c-upgrade is for http:// targets"),
+            (&["--expect-continue", "-d", "x", "http://localhost/"], "--expect-continue needs --protocol h1"),
+            (&["--hol-probe", "slow_url=http://localhost/a,small_url=http://localhost/b", "-p", "1", "http://localhost/"], "--hol-probe needs -p 2"),
+            (&["--compress-body", "gzip", "http://localhost/"], "--compress-body needs a body"),
+            (&["--flap-threshold", "100", "http://localhost/"], "--flap-threshold must be a percentage"),
+            (&["--abort-if-rps-below", "10", "--for", "500ms", "http://localhost/"], "--for must be at least 1s"),
+            (&["--probe-mode=0s", "http://localhost/"], "--probe-mode needs an interval above zero"),
+            (&["--probe-mode", "-m", "POST", "http://localhost/"], "only sends safe methods"),
+            (&["-m", "HEAD", "-d", "x", "http://localhost/"], "-m HEAD has no bodies, so -d can't be used"),
+            (&["--burst", "2/1s", "-c", "4", "http://localhost/"], "--burst SIZE must be at least -c"),
+            (&["ftp://localhost/"], "scheme ftp isn't http or https"),
+            (&["--data-file", "/nonexistent/benchy-body", "http://localhost/"], "--data-file /nonexistent/benchy-body"),
+        ];
+        for (argv, expected) in cases {
+            let problems = problems(argv);
+            assert!(
+                problems.iter().any(|problem| problem.contains(expected)),
+                "{:?}: expected {:?}, got {:?}",
+                argv,
+                expected,
+                problems
+            );
+        }
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let problems = problems(&["-c", "0", "-p", "0", "--processes", "0", "ftp://localhost/"]);
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+    }
+}
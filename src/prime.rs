@@ -0,0 +1,165 @@
+//! `--prime N`: untimed GETs to every URL of the workload before the measured phase,
+//! so a CDN benchmark measures cache hits rather than the first requests' origin
+//! fetches, with what the cache said about the last of them.
+
+use crate::render;
+use crate::{error_chain, ErrorDetails, RequestConfig, WorkerClient};
+use colored::Colorize;
+use futures::stream::{self, StreamExt};
+use std::collections::{BTreeMap, HashSet};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::time::{Interval, MissedTickBehavior};
+
+/// Response headers caches report hits and misses in
+const CACHE_HEADERS: [&str; 3] = ["x-cache", "cf-cache-status", "x-cache-status"];
+
+/// What priming found
+pub struct Primed {
+    urls: usize,
+    per_url: u64,
+    elapsed: Duration,
+    /// Priming responses that weren't 2xx
+    unsuccessful: u64,
+    /// Values of each of `CACHE_HEADERS` on the last priming response for each URL
+    cache: BTreeMap<&'static str, BTreeMap<String, u64>>,
+}
+
+/// The distinct URLs the workload requests, with their URL indexes, in the order they
+/// were given
+pub fn urls(config: &RequestConfig) -> Vec<(usize, &str)> {
+    let count = match (&config.urls, &config.alternate, &config.hosts, &config.hol) {
+        (Some(mix), _, _, _) => mix.len(),
+        (None, Some(targets), _, _) => targets.len(),
+        (None, None, Some(hosts), _) => hosts.len(),
+        (None, None, None, Some(_)) => 2,
+        (None, None, None, None) => 1,
+    };
+    let mut seen = HashSet::new();
+    (0..count).map(|i| (i, config.url(i))).filter(|(_, url)| seen.insert(*url)).collect()
+}
+
+/// Send `per_url` GETs to each URL one after another, spread over the workers' clients
+/// so their connections are warm too, and at no more than `rate` req/s in all
+pub async fn run(
+    workers: &[WorkerClient],
+    config: &RequestConfig,
+    per_url: u64,
+    rate: Option<f64>,
+) -> Result<Primed, ErrorDetails> {
+    let start = Instant::now();
+    let urls = urls(config);
+    let pacer = rate.map(|rate| {
+        let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / rate));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Mutex::new(interval)
+    });
+
+    // URLs are primed side by side, but each URL's requests in turn, so only the first
+    // of them goes to the origin
+    let primed = stream::iter(urls.iter())
+        .map(|&(i, url)| {
+            // Each host is primed over its own connections
+            let pool: Vec<&WorkerClient> = match &config.hosts {
+                Some(hosts) => workers.iter().enumerate().filter(|(w, _)| hosts.of(*w) == i).map(|(_, w)| w).collect(),
+                None => workers.iter().collect(),
+            };
+            prime(pool, url, i, per_url, pacer.as_ref())
+        })
+        .buffer_unordered(workers.len().max(1))
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut summary = Primed {
+        urls: urls.len(),
+        per_url,
+        elapsed: Duration::ZERO,
+        unsuccessful: 0,
+        cache: BTreeMap::new(),
+    };
+    for result in primed {
+        let (unsuccessful, cache) = result?;
+        summary.unsuccessful += unsuccessful;
+        for (name, value) in cache {
+            *summary.cache.entry(name).or_default().entry(value).or_default() += 1;
+        }
+    }
+    summary.elapsed = start.elapsed();
+    Ok(summary)
+}
+
+/// Prime one URL, the `index`th: how many responses weren't 2xx, and the last
+/// response's cache headers
+async fn prime(
+    workers: Vec<&WorkerClient>,
+    url: &str,
+    index: usize,
+    per_url: u64,
+    pacer: Option<&Mutex<Interval>>,
+) -> Result<(u64, Vec<(&'static str, String)>), ErrorDetails> {
+    let mut unsuccessful = 0;
+    let mut cache = Vec::new();
+    for n in 0..per_url {
+        if let Some(pacer) = pacer {
+            pacer.lock().await.tick().await;
+        }
+        let worker = workers[(index + n as usize) % workers.len()];
+        let mut request = worker.client.reqwest.get(url);
+        if let Some(version) = worker.opts.protocol.version() {
+            request = request.version(version);
+        }
+        let failed = |e: &(dyn std::error::Error + 'static)| ErrorDetails {
+            message: format!("priming {} failed: {}", url, error_chain(e)),
+            status: None,
+            headers: None,
+            body: None,
+            trailers: None,
+        };
+        let resp = worker.client.send(request).await.map_err(|e| failed(e.as_ref()))?;
+        if !resp.status().is_success() {
+            unsuccessful += 1;
+        }
+        cache = CACHE_HEADERS
+            .iter()
+            .filter_map(|&name| {
+                let value = resp.headers().get(name)?;
+                Some((name, String::from_utf8_lossy(value.as_bytes()).into_owned()))
+            })
+            .collect();
+        // Read to the end, so the object is cached whole and the connection can be reused
+        resp.bytes().await.map_err(|e| failed(&e))?;
+    }
+    Ok((unsuccessful, cache))
+}
+
+impl Primed {
+    pub fn lines(&self) -> Vec<String> {
+        let mut prime = format!(
+            "{} to {} in {:.1?}",
+            if self.per_url == 1 { "1 GET".to_string() } else { format!("{} GETs", self.per_url) },
+            if self.urls == 1 { "the URL".to_string() } else { format!("each of {} URLs", self.urls) },
+            self.elapsed
+        );
+        if self.unsuccessful > 0 {
+            prime.push_str(&format!(", {}", format!("{} not 2xx", self.unsuccessful).yellow()));
+        }
+        let cache = if self.cache.is_empty() {
+            format!("no {} header on the priming responses", CACHE_HEADERS.join(", ")).dimmed().to_string()
+        } else {
+            let values = self
+                .cache
+                .iter()
+                .map(|(name, values)| {
+                    let values: Vec<String> = values.iter().map(|(value, count)| format!("{} {}", value, count)).collect();
+                    format!("{} {}", name, values.join(", "))
+                })
+                .collect::<Vec<String>>()
+                .join("; ");
+            format!("{} {}", values, "(last priming GET per URL)".dimmed())
+        };
+        vec![
+            render::line("Prime:".white(), prime),
+            render::line("Cache:".white(), cache),
+        ]
+    }
+}
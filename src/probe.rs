@@ -0,0 +1,210 @@
+//! `--probe-mode`: benchy as an availability prober rather than a load generator. One
+//! request goes out per interval, on one client, and each response (or failure) is
+//! printed as it comes back, with a running availability. Nothing is collected for
+//! percentiles, so it can run for days; on Ctrl-C, SIGTERM or `--max-duration` it
+//! stops with a summary of the outages it saw. A failure is anything the run would count
+//! as one: an error of any kind, or a response the status and body checks reject.
+
+use crate::clock::{self, RunClock};
+use crate::render;
+use crate::units::LatencyUnit;
+use crate::{send_request, worker_client, Args, ClientOptions, Outgoing, RequestConfig, RequestResult, Stats};
+use colored::Colorize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+
+/// The probes so far, and the outages among them
+struct Availability {
+    start: Instant,
+    total: u64,
+    successes: u64,
+    outages: u64,
+    /// When the outage still going started, if the last probe failed; an outage is the
+    /// failed probes in a row, from the first up to the next success
+    current: Option<Instant>,
+    /// The longest that has ended, and when it started
+    longest: Option<(Duration, Instant)>,
+    /// Time spent in outages that have ended
+    down: Duration,
+}
+
+impl Availability {
+    fn new(start: Instant) -> Availability {
+        Availability {
+            start,
+            total: 0,
+            successes: 0,
+            outages: 0,
+            current: None,
+            longest: None,
+            down: Duration::ZERO,
+        }
+    }
+
+    /// A probe sent at `sent`
+    fn record(&mut self, sent: Instant, ok: bool) {
+        self.total += 1;
+        if ok {
+            self.successes += 1;
+            self.end_outage(sent);
+        } else if self.current.is_none() {
+            self.outages += 1;
+            self.current = Some(sent);
+        }
+    }
+
+    /// The outage going on until `at`, if any, is over
+    fn end_outage(&mut self, at: Instant) {
+        if let Some(start) = self.current.take() {
+            let lasted = at.saturating_duration_since(start);
+            self.down += lasted;
+            if self.longest.is_none_or(|(longest, _)| lasted > longest) {
+                self.longest = Some((lasted, start));
+            }
+        }
+    }
+
+    fn percent(&self) -> f64 {
+        if self.total == 0 {
+            return 100.0;
+        }
+        self.successes as f64 * 100.0 / self.total as f64
+    }
+
+    /// Closes any outage still going at `end`, and prints the summary
+    fn print(mut self, end: Instant, clock: &RunClock) {
+        let ongoing = self.current.is_some();
+        self.end_outage(end);
+        let elapsed = end.saturating_duration_since(self.start);
+        let round = |d: Duration| humantime::format_duration(Duration::from_secs(d.as_secs_f64().round() as u64));
+
+        println!("\n{}", "--- Availability ---".cyan().bold());
+        render::print("Duration:".white(), round(elapsed));
+        render::print("Probes:".white(), self.total);
+        render::print("Successes:".white(), self.successes.to_string().green());
+        let failures = self.total - self.successes;
+        if failures > 0 {
+            render::print("Failures:".white(), failures.to_string().red().bold());
+        } else {
+            render::print("Failures:".white(), "0".dimmed());
+        }
+        let percent = format!("{:.3}%", self.percent());
+        render::print("Availability:".white(), if failures > 0 { percent.yellow().bold() } else { percent.green().bold() });
+        if self.outages == 0 {
+            render::print("Outages:".white(), "none".dimmed());
+            return;
+        }
+        let outages = if ongoing { format!("{} (the last still going)", self.outages) } else { self.outages.to_string() };
+        render::print("Outages:".white(), outages.red());
+        if let Some((longest, at)) = self.longest {
+            render::print(
+                "Longest:".white(),
+                format!("{} from {}", round(longest), clock::rfc3339(clock.wall(at))),
+            );
+        }
+        // Time up, per outage that ended it
+        render::print("MTBF:".white(), round(elapsed.saturating_sub(self.down) / self.outages as u32));
+    }
+}
+
+/// Probe every `interval` until interrupted or `--max-duration` is up
+pub async fn run(
+    interval: Duration,
+    args: &Args,
+    config: &RequestConfig,
+    client_opts: &ClientOptions,
+    seed: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tls_stats = Arc::new(crate::transport::TlsStats::default());
+    let mut worker = worker_client(args, client_opts, &tls_stats, crate::https_target(args, config, 0), 0)?;
+    let identity = args.identity.as_ref().map(|identity| identity.for_worker(seed, 0));
+    let stats = Stats::default();
+
+    let cancel = CancellationToken::new();
+    crate::cancel_on_sigterm(cancel.clone())?;
+    let interrupt = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            interrupt.cancel();
+        }
+    });
+    let clock = RunClock::start();
+    let deadline = args.max_duration.map(|d| clock.began() + d);
+    let mut availability = Availability::new(clock.began());
+    let mut ticks = tokio::time::interval(interval);
+    // One probe at a time: a slow one pushes the next back rather than doubling up
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let expired = async {
+        match deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(expired);
+
+    println!("\n{}", "--- Probes ---".cyan().bold());
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            _ = cancel.cancelled() => break,
+            _ = &mut expired => break,
+        }
+        let sent = Instant::now();
+        let outgoing = Outgoing {
+            url: 0,
+            body: None,
+            body_file: None,
+            script: None,
+            request_id: None,
+            header: config.header_file.as_ref().map(|_| 0),
+            pushed: sent,
+        };
+        let (client, version) = worker.next();
+        let result = tokio::select! {
+            result = send_request(client, version, outgoing, identity.as_ref(), config, &stats) => result,
+            _ = cancel.cancelled() => break,
+        };
+        let (ok, line) = describe(result, args.latency_unit);
+        availability.record(sent, ok);
+        println!(
+            "{}  {}  {}",
+            clock::rfc3339(clock.wall(sent)).dimmed(),
+            line,
+            format!("{:.2}% of {}", availability.percent(), availability.total).dimmed()
+        );
+    }
+    availability.print(Instant::now(), &clock);
+    Ok(())
+}
+
+/// Whether the probe succeeded, and its status, latency and protocol, or why it failed
+fn describe(result: RequestResult, unit: LatencyUnit) -> (bool, String) {
+    match result {
+        RequestResult::Success(sample) => {
+            let version = sample.version.map(|version| format!("{:?}", version)).unwrap_or_default();
+            let line = format!(
+                "{}  {:>9}  {}",
+                format!("{:<7}", sample.status.unwrap_or_default()).green(),
+                unit.resolve(sample.latency).format(sample.latency),
+                version
+            );
+            (true, line)
+        }
+        RequestResult::Failed(sample) => {
+            let what = match (sample.error, sample.status) {
+                (Some(kind), Some(status)) => format!("{} {}", status, kind.label()),
+                (Some(kind), None) => kind.label().to_string(),
+                (None, Some(status)) => status.to_string(),
+                (None, None) => "failed".to_string(),
+            };
+            let version = sample.version.map(|version| format!("{:?}", version)).unwrap_or_default();
+            let line = format!("{}  {:>9}  {}", format!("{:<7}", what).red().bold(), unit.resolve(sample.latency).format(sample.latency), version);
+            (false, line)
+        }
+        // -f is rejected with --probe-mode, but the details are the most there is
+        RequestResult::Error(details) => (false, details.message.red().bold().to_string()),
+    }
+}
@@ -0,0 +1,288 @@
+//! `--processes`: one run split over child benchy processes, for when a single process
+//! runs into client-side limits (one tokio runtime, one epoll instance) before the
+//! server does.
+//!
+//! The parent re-runs its own executable with the same arguments and a slot in
+//! [`CHILD_ENV`]. Each child runs its share of the connections and requests, then
+//! writes its results to stdout in the `--save-results` format, which the parent
+//! merges into a single report.
+
+use crate::output::{self, OutputFormat};
+use crate::sinks::{self, BenchReport, OutputSpec, Sinks};
+use crate::results::SavedRun;
+use crate::textreport::TextDetails;
+use crate::thresholds::Thresholds;
+use crate::{endpoint, exit_on_failed_checks, junit, publish, render, Args, Percentiles};
+use colored::Colorize;
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde_json::Value;
+use std::error::Error;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::Stdio;
+
+/// Set on child processes to `INDEX/COUNT/SEED`
+const CHILD_ENV: &str = "BENCHY_PROCESS";
+
+/// A child's place in the run
+pub struct Slot {
+    index: usize,
+    count: usize,
+    seed: u64,
+}
+
+impl Slot {
+    /// This process's slot, if it was started by `--processes`
+    pub fn from_env() -> Result<Option<Slot>, String> {
+        let Some(value) = std::env::var_os(CHILD_ENV) else {
+            return Ok(None);
+        };
+        let invalid = || format!("invalid {}: {:?}", CHILD_ENV, value);
+        let value = value.to_str().ok_or_else(invalid)?;
+        let mut parts = value.split('/').map(str::parse::<u64>);
+        let (Some(Ok(index)), Some(Ok(count)), Some(Ok(seed)), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        Ok(Some(Slot {
+            index: index as usize,
+            count: count as usize,
+            seed,
+        }))
+    }
+
+    /// Narrow `args` to this child's connections and requests. Workers are numbered as
+    /// in a single process (the seed is shifted by the first one's index), so bodies,
+    /// URL picks and `--identity` values don't repeat across children.
+    pub fn apply(&self, args: &mut Args) {
+        let (first, connections) = split(args.connections, self.count, self.index);
+        // Exactly the requests these workers would get from -n in one process
+        let per_worker = args.requests / args.connections as u64;
+        let remainder = args.requests % args.connections as u64;
+        let extra = (remainder.saturating_sub(first as u64)).min(connections as u64);
+
+        args.rate = args.rate.map(|rate| rate * connections as f64 / args.connections as f64);
+        args.requests = per_worker * connections as u64 + extra;
+        args.connections = connections;
+        // The same share of the workers, at least one for the child's connections
+        args.workers = args.workers.map(|workers| split(workers, self.count, self.index).1.clamp(1, connections));
+        args.seed = Some(self.seed.wrapping_add(first as u64));
+        args.processes = 1;
+        // The parent prints the results and writes the reports
+        args.output_format = OutputFormat::Table;
+        args.output = Vec::new();
+        args.no_github_summary = true;
+        args.junit = None;
+        args.save_results = None;
+        args.publish = None;
+        args.record = None;
+        // The parent already waited
+        args.wait_ready = None;
+        args.print_sample_body &= self.index == 0;
+    }
+}
+
+/// Index of the first of `index`'s share of `total`, and the size of the share; the
+/// first `total % parts` get one more
+fn split(total: usize, parts: usize, index: usize) -> (usize, usize) {
+    let base = total / parts;
+    let remainder = total % parts;
+    (index * base + index.min(remainder), base + usize::from(index < remainder))
+}
+
+/// Flags whose results only one process would see, or that children would trip over
+fn unsupported(args: &Args) -> Vec<&'static str> {
+    [
+        (args.burst.is_some(), "--burst"),
+        (args.h3_alt_svc, "--h3-alt-svc"),
+        (args.error_dump.is_some(), "--error-dump"),
+        (args.request_log.is_some(), "--request-log"),
+        (args.slowest.is_some(), "--slowest"),
+        (args.per_worker_stats, "--per-worker-stats"),
+        (args.per_url_stats, "--per-url-stats"),
+        (args.per_entry_stats, "--per-entry-stats"),
+        (args.hol_probe.is_some(), "--hol-probe"),
+        (args.compress_body.is_some(), "--compress-body"),
+        (args.group_by_header.is_some(), "--group-by-header"),
+        (args.group_by_header_sent, "--group-by-header-sent"),
+        (args.lint_responses, "--lint-responses"),
+        (args.ratelimit_headers, "--ratelimit-headers"),
+        (args.cache_analysis, "--cache-analysis"),
+        (args.prometheus_listen.is_some(), "--prometheus-listen"),
+        (!args.collect_header.is_empty(), "--collect-header"),
+        (args.affinity_header.is_some(), "--affinity-header"),
+        (args.request_id_header.is_some(), "--request-id-header"),
+        (args.alternate.is_some(), "--alternate"),
+        (args.abort_if_rps_below.is_some(), "--abort-if-rps-below"),
+        (!args.retry_on.is_empty(), "--retry-on"),
+        (args.heatmap.is_some(), "--heatmap"),
+        (args.h2c_upgrade, "--h2c-upgrade"),
+        (args.hosts_file.is_some() || !args.more_urls.is_empty(), "--hosts-file or several URLs"),
+    ]
+    .into_iter()
+    .filter_map(|(set, flag)| set.then_some(flag))
+    .collect()
+}
+
+/// A benchy child in `index` of `count` slots, running `args` and writing its results
+/// to stdout; killed if its handle is dropped
+pub fn child_command<I, S>(exe: &Path, args: I, index: usize, count: usize, seed: u64) -> tokio::process::Command
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = tokio::process::Command::new(exe);
+    command
+        .args(args)
+        .env(CHILD_ENV, format!("{}/{}/{}", index, count, seed))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        // Dropping a child's future, on failure or Ctrl-C, stops it
+        .kill_on_drop(true);
+    command
+}
+
+/// Run the benchmark over `args.processes` children and print the merged results
+pub async fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let processes = args.processes;
+    if processes > args.connections {
+        return Err("--processes can't be more than -c, every process needs a connection".into());
+    }
+    let unsupported = unsupported(&args);
+    if !unsupported.is_empty() {
+        return Err(format!("--processes doesn't merge the results of {}", unsupported.join(", ")).into());
+    }
+
+    let outputs = sinks::specs(&args.output, args.output_format);
+    let text = outputs.iter().any(OutputSpec::is_text);
+    let mut sinks = Sinks::open(&outputs, &args.url, args.no_header)?;
+    if text {
+        let (_, most) = split(args.connections, processes, 0);
+        let (_, least) = split(args.connections, processes, processes - 1);
+        let connections = if most == least { most.to_string() } else { format!("{}-{}", least, most) };
+        let details = format!(
+            "({}) with {} processes x {} connections x {} streams = {} concurrency, {} total requests",
+            args.protocol.label().magenta(),
+            processes.to_string().green(),
+            connections.green(),
+            args.pipeline.to_string().green(),
+            (args.connections * args.pipeline).to_string().green().bold(),
+            args.requests.to_string().green()
+        );
+        println!("{}", render::banner(&args.url, details));
+        endpoint::print(&args).await;
+    }
+
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let exe = std::env::current_exe()?;
+    let mut children = FuturesUnordered::new();
+    for index in 0..processes {
+        let child = child_command(&exe, args.argv.iter().skip(1), index, processes, seed)
+            .spawn()
+            .map_err(|e| format!("could not start process {}: {}", index, e))?;
+        children.push(async move { (index, child.wait_with_output().await) });
+    }
+
+    let mut merged = SavedRun::default();
+    loop {
+        let next = tokio::select! {
+            next = children.next() => next,
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("\n{} interrupted, stopping {} processes", "Warning:".yellow(), children.len());
+                drop(children);
+                std::process::exit(130);
+            }
+        };
+        let Some((index, output)) = next else {
+            break;
+        };
+
+        let output = output.map_err(|e| format!("process {}: {}", index, e))?;
+        let report = output
+            .status
+            .success()
+            .then(|| serde_json::from_slice::<Value>(&output.stdout).ok())
+            .flatten()
+            .and_then(|report| SavedRun::from_json(&report).ok());
+        if let Some(report) = report {
+            merged.merge(report);
+            continue;
+        }
+
+        // Whatever the child printed instead, e.g. -f error details
+        print!("{}", String::from_utf8_lossy(&output.stdout));
+        let reason = if output.status.success() {
+            "sent no results".to_string()
+        } else {
+            format!("failed ({})", output.status)
+        };
+        eprintln!(
+            "{} process {} {}, stopping {} others",
+            "Error:".red().bold(),
+            index,
+            reason,
+            children.len()
+        );
+        drop(children);
+        std::process::exit(1);
+    }
+
+    let latency = Percentiles::from_unsorted(&mut merged.latencies);
+    let ttfb = Percentiles::from_unsorted(&mut merged.ttfbs);
+    let unit = args.latency_unit.resolve(latency.p50);
+    let summary = merged.summary(&latency, &ttfb);
+    let evaluations = Thresholds {
+        max_p99: args.max_p99,
+        max_error_rate: args.max_error_rate,
+        min_rps: args.min_rps,
+    }
+    .evaluate(&summary, unit);
+    let thresholds_failed = evaluations.iter().any(|e| !e.passed);
+
+    if !args.no_github_summary {
+        if let Some(path) = std::env::var_os("GITHUB_STEP_SUMMARY") {
+            if let Err(e) = output::append_step_summary(path.as_ref(), &summary.markdown(unit, &evaluations)) {
+                eprintln!("{} could not write GITHUB_STEP_SUMMARY: {}", "Warning:".yellow(), e);
+            }
+        }
+    }
+    if let Some(path) = &args.junit {
+        if let Err(e) = junit::write(path, &summary, &evaluations) {
+            eprintln!("{} could not write {}: {}", "Warning:".yellow(), path.display(), e);
+        }
+    }
+    if let Some(path) = &args.save_results {
+        if let Err(e) = merged.save(path) {
+            eprintln!("{} could not write {}: {}", "Warning:".yellow(), path.display(), e);
+        }
+    }
+    let runs = format!("{} processes", processes);
+    let report = BenchReport {
+        summary: &summary,
+        evaluations: &evaluations,
+        unit,
+        latencies: &merged.latencies,
+        ttfbs: &merged.ttfbs,
+        phases: None,
+        groups: None,
+        script_metrics: &merged.script_metrics,
+        rps_breach: merged.rps_breach.as_ref(),
+        utilization: &merged.worker_utilization,
+        protocols: &[],
+        text: TextDetails::Merged {
+            run: &merged,
+            ttfb: &ttfb,
+            runs: &runs,
+            require_protocol: args.require_protocol,
+        },
+    };
+    sinks.render(&report);
+    let published = publish::publish(&args, &sinks::to_json(&report)).await;
+    if let Some(published) = &published {
+        published.print(text);
+    }
+
+    exit_on_failed_checks(&args, merged.mismatches, merged.stopped_early, thresholds_failed, published.as_ref());
+    Ok(())
+}
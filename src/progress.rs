@@ -0,0 +1,124 @@
+use crate::clock;
+use crate::thousands;
+use serde_json::{json, Value};
+use std::time::{Duration, SystemTime};
+
+/// Seconds of recent throughput the ETA is based on
+const ROLLING_WINDOWS: usize = 5;
+
+/// Completions per one-second window, sampled while the run is going
+#[derive(Default)]
+pub struct Throughput {
+    /// Requests completed in each full window, oldest first
+    windows: Vec<u64>,
+    /// Whether the run was paused at some point during each window
+    paused: Vec<bool>,
+    last_total: u64,
+    last_eta: Option<Duration>,
+}
+
+impl Throughput {
+    /// Close a window, given the running total of completed requests
+    pub fn tick(&mut self, total: u64, paused: bool) {
+        self.windows.push(total.saturating_sub(self.last_total));
+        self.paused.push(paused);
+        self.last_total = total;
+    }
+
+    /// Windows the run wasn't paused in
+    fn active_windows(&self) -> impl Iterator<Item = u64> + '_ {
+        self.windows.iter().zip(&self.paused).filter(|(_, paused)| !**paused).map(|(n, _)| *n)
+    }
+
+    /// The last `n` windows, if the run wasn't paused in any of them and none is the
+    /// first, which includes opening the connections
+    pub fn last_active(&self, n: usize) -> Option<&[u64]> {
+        let first = self.windows.len().checked_sub(n)?;
+        if first == 0 || self.paused[first..].contains(&true) {
+            return None;
+        }
+        Some(&self.windows[first..])
+    }
+
+    /// Completions in every window so far, oldest first
+    pub fn series(&self) -> &[u64] {
+        &self.windows
+    }
+
+    /// Requests/sec over the last few windows the run wasn't paused in
+    fn rolling_rate(&self) -> Option<f64> {
+        let recent: Vec<u64> = self.active_windows().collect();
+        let recent = &recent[recent.len().saturating_sub(ROLLING_WINDOWS)..];
+        if recent.is_empty() {
+            return None;
+        }
+        Some(recent.iter().sum::<u64>() as f64 / recent.len() as f64)
+    }
+
+    /// One-line status: progress towards `target`, rolling rate, ETA, and with
+    /// `--max-duration` the total projected by the deadline
+    pub fn status(&mut self, target: u64, until_deadline: Option<Duration>) -> String {
+        let total = self.last_total;
+        if self.paused.last() == Some(&true) {
+            return format!("{}/{}  PAUSED", thousands(total), thousands(target));
+        }
+        let Some(rate) = self.rolling_rate() else {
+            return String::new();
+        };
+
+        let mut line = format!(
+            "{}/{} ({:.1}%)  {:.0} req/s",
+            thousands(total),
+            thousands(target),
+            total as f64 * 100.0 / target.max(1) as f64,
+            rate
+        );
+
+        if rate > 0.0 {
+            let eta = Duration::from_secs((target.saturating_sub(total) as f64 / rate).ceil() as u64);
+            line.push_str(&format!("  ETA {}", humantime::format_duration(eta)));
+            // Compared tick to tick, so a steady run doesn't flap on rounding
+            if self.last_eta.is_some_and(|last| eta > last + Duration::from_secs(1)) {
+                line.push_str(" (slowing)");
+            }
+            self.last_eta = Some(eta);
+        } else {
+            line.push_str("  ETA unknown (stalled)");
+        }
+
+        if let Some(remaining) = until_deadline {
+            let projected = (total + (rate * remaining.as_secs_f64()) as u64).min(target);
+            line.push_str(&format!("  projected {} by max duration", thousands(projected)));
+        }
+        line
+    }
+
+    /// Progress towards `target` as JSON, for `benchy serve`
+    pub fn snapshot(&self, target: u64, elapsed: Duration, at: SystemTime) -> Value {
+        json!({
+            "at": clock::rfc3339(at),
+            "completed": self.last_total,
+            "target": target,
+            "elapsed_secs": elapsed.as_secs_f64(),
+            "req_per_sec": self.rolling_rate(),
+            "paused": self.paused.last() == Some(&true),
+        })
+    }
+
+    /// Drop the windows after the last one anything happened in, e.g. paced sends while
+    /// the last responses drain
+    pub fn trim_idle_tail(&mut self) {
+        while self.windows.last() == Some(&0) {
+            self.windows.pop();
+            self.paused.pop();
+        }
+    }
+
+    /// Best and worst full-window rates, once there are at least two unpaused windows
+    pub fn range(&self) -> Option<(u64, u64)> {
+        if self.active_windows().count() < 2 {
+            return None;
+        }
+        Some((self.active_windows().max()?, self.active_windows().min()?))
+    }
+}
@@ -0,0 +1,250 @@
+//! `--prometheus-listen`: a `/metrics` endpoint to scrape benchy itself during a soak
+//! test.
+//!
+//! Workers only bump the atomic counters of [`Recorder`]. Once a second the throughput
+//! sampler renders them, with the in-flight and connection gauges, into the exposition
+//! text and swaps it in; the server hands out the latest text and never touches the
+//! run's own state.
+
+use crate::Stats;
+use arc_swap::ArcSwap;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::body::Incoming;
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio_util::sync::CancellationToken;
+
+/// Upper bounds of the latency histogram's buckets, in seconds
+const BUCKETS: [f64; 15] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// `status_class` label values: a status's first digit, or a transport error
+const CLASSES: [&str; 6] = ["1xx", "2xx", "3xx", "4xx", "5xx", "error"];
+
+/// Counters the workers update as requests complete
+pub struct Recorder {
+    requests: [AtomicU64; CLASSES.len()],
+    /// Per bucket, not cumulative; the last is past the largest bound
+    buckets: [AtomicU64; BUCKETS.len() + 1],
+    duration_ns: AtomicU64,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder {
+            requests: Default::default(),
+            buckets: Default::default(),
+            duration_ns: AtomicU64::new(0),
+        }
+    }
+
+    /// A completed request, with its status unless it failed before getting one
+    pub fn record(&self, status: Option<u16>, latency: Duration) {
+        let class = match status {
+            Some(status @ 100..=599) => status as usize / 100 - 1,
+            _ => CLASSES.len() - 1,
+        };
+        self.requests[class].fetch_add(1, Ordering::Relaxed);
+        let seconds = latency.as_secs_f64();
+        let bucket = BUCKETS.iter().position(|&le| seconds <= le).unwrap_or(BUCKETS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.duration_ns.fetch_add(latency.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// The exposition text for the counters as they are now and the gauges given
+    fn render(&self, gauges: &Gauges) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP benchy_requests_total Requests completed, by status class\n");
+        out.push_str("# TYPE benchy_requests_total counter\n");
+        for (class, count) in CLASSES.iter().zip(&self.requests) {
+            let _ = writeln!(out, "benchy_requests_total{{status_class=\"{}\"}} {}", class, count.load(Ordering::Relaxed));
+        }
+
+        out.push_str("# HELP benchy_request_duration_seconds Latency of completed requests\n");
+        out.push_str("# TYPE benchy_request_duration_seconds histogram\n");
+        let mut cumulative = 0;
+        for (le, count) in BUCKETS.iter().map(f64::to_string).chain(["+Inf".to_string()]).zip(&self.buckets) {
+            cumulative += count.load(Ordering::Relaxed);
+            let _ = writeln!(out, "benchy_request_duration_seconds_bucket{{le=\"{}\"}} {}", le, cumulative);
+        }
+        let sum = self.duration_ns.load(Ordering::Relaxed) as f64 / 1e9;
+        let _ = writeln!(out, "benchy_request_duration_seconds_sum {}", sum);
+        let _ = writeln!(out, "benchy_request_duration_seconds_count {}", cumulative);
+
+        out.push_str("# HELP benchy_inflight_requests Requests sent and not yet completed\n");
+        out.push_str("# TYPE benchy_inflight_requests gauge\n");
+        let _ = writeln!(out, "benchy_inflight_requests {}", gauges.in_flight);
+        if let Some(open) = gauges.connections {
+            out.push_str("# HELP benchy_connections_open TCP connections benchy has open to the target\n");
+            out.push_str("# TYPE benchy_connections_open gauge\n");
+            let _ = writeln!(out, "benchy_connections_open {}", open);
+        }
+        out.push_str("# HELP benchy_bytes_received_total Response body bytes read\n");
+        out.push_str("# TYPE benchy_bytes_received_total counter\n");
+        let _ = writeln!(out, "benchy_bytes_received_total {}", gauges.bytes_received);
+        out
+    }
+}
+
+/// What the sampler reads off the run's stats each second
+pub struct Gauges {
+    in_flight: u64,
+    bytes_received: u64,
+    /// Linux only
+    connections: Option<u64>,
+}
+
+impl Gauges {
+    pub fn of(stats: &Stats) -> Gauges {
+        let requests = &stats.requests;
+        let settled = requests.completed.load(Ordering::Relaxed) + requests.cancelled() + requests.abandoned();
+        Gauges {
+            in_flight: requests.submitted().saturating_sub(settled),
+            bytes_received: stats.body.received(),
+            connections: None,
+        }
+    }
+}
+
+/// The `/metrics` server, serving whatever was last published
+pub struct Endpoint {
+    addr: SocketAddr,
+    text: Arc<ArcSwap<String>>,
+    stop: CancellationToken,
+    server: JoinHandle<()>,
+}
+
+impl Endpoint {
+    /// Listen on `addr` and serve `/metrics` until [`Endpoint::shutdown`]
+    pub async fn start(addr: SocketAddr) -> Result<Endpoint, String> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .map_err(|e| format!("--prometheus-listen: could not listen on {}: {}", addr, e))?;
+        let addr = listener.local_addr().map_err(|e| e.to_string())?;
+        let text = Arc::new(ArcSwap::from_pointee(String::new()));
+        let stop = CancellationToken::new();
+        let server = tokio::spawn(serve(listener, text.clone(), stop.clone()));
+        Ok(Endpoint {
+            addr,
+            text,
+            stop,
+            server,
+        })
+    }
+
+    /// Where it ended up listening, with any port 0 picked
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Swap in the metrics as they are now
+    pub fn publish(&self, recorder: &Recorder, mut gauges: Gauges) {
+        gauges.connections = open_connections(self.addr.port());
+        self.text.store(Arc::new(recorder.render(&gauges)));
+    }
+
+    /// Stop accepting scrapes and wait for those being answered
+    pub async fn shutdown(self) {
+        self.stop.cancel();
+        let _ = self.server.await;
+    }
+}
+
+async fn serve(listener: TcpListener, text: Arc<ArcSwap<String>>, stop: CancellationToken) {
+    let mut connections = JoinSet::new();
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    tracing::info!(error = %e, "metrics accept failed");
+                    continue;
+                }
+            },
+            // Scrapes already answered, so a soak test's don't pile up
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+            _ = stop.cancelled() => break,
+        };
+        let text = text.clone();
+        let stop = stop.clone();
+        connections.spawn(async move {
+            let service = service_fn(move |req| {
+                let text = text.clone();
+                async move { Ok::<_, Infallible>(respond(&req, &text)) }
+            });
+            let conn = http1::Builder::new().serve_connection(TokioIo::new(stream), service);
+            tokio::pin!(conn);
+            // An idle keep-alive connection closes at once; a scrape being answered finishes
+            let result = tokio::select! {
+                result = conn.as_mut() => result,
+                _ = stop.cancelled() => {
+                    conn.as_mut().graceful_shutdown();
+                    conn.await
+                }
+            };
+            if let Err(e) = result {
+                tracing::info!(%peer, error = %e, "metrics connection failed");
+            }
+        });
+    }
+    while connections.join_next().await.is_some() {}
+}
+
+fn respond(req: &Request<Incoming>, text: &ArcSwap<String>) -> Response<Full<Bytes>> {
+    let (status, body) = match (req.method(), req.uri().path()) {
+        (&Method::GET | &Method::HEAD, "/metrics") => (StatusCode::OK, Bytes::from(text.load().as_bytes().to_vec())),
+        (_, "/metrics") => (StatusCode::METHOD_NOT_ALLOWED, Bytes::from_static(b"GET only\n")),
+        _ => (StatusCode::NOT_FOUND, Bytes::from_static(b"metrics are at /metrics\n")),
+    };
+    let mut resp = Response::new(Full::new(body));
+    *resp.status_mut() = status;
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain; version=0.0.4; charset=utf-8"));
+    resp
+}
+
+/// Established TCP connections of this process, except scrapes of `metrics_port`: the
+/// sockets among /proc/self/fd looked up in /proc/self/net/tcp{,6}. Connections are
+/// pooled inside reqwest, out of sight, so this is the only count of those still open.
+/// HTTP/3 runs over UDP and isn't counted.
+fn open_connections(metrics_port: u16) -> Option<u64> {
+    let sockets: HashSet<u64> = std::fs::read_dir("/proc/self/fd")
+        .ok()?
+        .filter_map(|entry| {
+            let target = std::fs::read_link(entry.ok()?.path()).ok()?;
+            target.to_str()?.strip_prefix("socket:[")?.strip_suffix(']')?.parse().ok()
+        })
+        .collect();
+    let mut open = 0;
+    for table in ["/proc/self/net/tcp", "/proc/self/net/tcp6"] {
+        let Ok(table) = std::fs::read_to_string(table) else {
+            continue;
+        };
+        // sl local_address rem_address st tx_queue:rx_queue tr:tm->when retrnsmt uid timeout inode
+        for line in table.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (Some(local), Some(&"01"), Some(inode)) = (fields.get(1), fields.get(3), fields.get(9)) else {
+                continue;
+            };
+            let port = local.rsplit(':').next().and_then(|port| u16::from_str_radix(port, 16).ok());
+            if port != Some(metrics_port) && inode.parse().is_ok_and(|inode: u64| sockets.contains(&inode)) {
+                open += 1;
+            }
+        }
+    }
+    Some(open)
+}
@@ -0,0 +1,155 @@
+use crate::connect::ConnectStats;
+use crate::dns::{BenchResolver, DnsStats};
+use crate::errors::{self, ErrorKind};
+use crate::transport::TlsStats;
+use crate::{build_client, build_tls, error_chain, Args, ClientOptions};
+use reqwest::Version;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Upper bound on probes made before the run
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// HTTP version to benchmark, selected with `--protocol` (or `--h3`)
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum Protocol {
+    /// HTTP/1.1 only
+    H1,
+    /// HTTP/2 (h2c prior knowledge on http://, ALPN on https://)
+    H2,
+    /// HTTP/3 over QUIC
+    H3,
+    /// Let ALPN decide and report what was negotiated
+    Auto,
+}
+
+impl Protocol {
+    /// The `--protocol` value, for machine-readable output
+    pub fn name(self) -> &'static str {
+        match self {
+            Protocol::H1 => "h1",
+            Protocol::H2 => "h2",
+            Protocol::H3 => "h3",
+            Protocol::Auto => "auto",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Protocol::H1 => "HTTP/1.1",
+            Protocol::H2 => "HTTP/2",
+            Protocol::H3 => "HTTP/3",
+            Protocol::Auto => "auto",
+        }
+    }
+
+    /// Version requests are pinned to; `None` lets ALPN decide
+    pub fn version(self) -> Option<Version> {
+        match self {
+            Protocol::H1 => Some(Version::HTTP_11),
+            Protocol::H2 => Some(Version::HTTP_2),
+            Protocol::H3 => Some(Version::HTTP_3),
+            Protocol::Auto => None,
+        }
+    }
+
+    /// ALPN protocol ids to offer over TLS
+    pub fn alpn(self) -> &'static [&'static [u8]] {
+        match self {
+            Protocol::H1 => &[b"http/1.1"],
+            Protocol::H3 => &[b"h3"],
+            Protocol::H2 | Protocol::Auto => &[b"h2", b"http/1.1"],
+        }
+    }
+
+    /// The next protocol down for `--fallback`
+    pub fn fallback(self) -> Option<Protocol> {
+        match self {
+            Protocol::H3 => Some(Protocol::H2),
+            Protocol::H2 => Some(Protocol::H1),
+            Protocol::H1 | Protocol::Auto => None,
+        }
+    }
+}
+
+/// Why a protocol probe failed
+#[derive(Debug)]
+pub struct ProbeError {
+    pub reason: String,
+    /// The server was reached but didn't speak the protocol, so `--fallback` may step
+    /// down; anything else (DNS, a refused connection) would fail the same way lower down
+    pub negotiation: bool,
+}
+
+impl ProbeError {
+    fn other(reason: impl ToString) -> ProbeError {
+        ProbeError {
+            reason: reason.to_string(),
+            negotiation: false,
+        }
+    }
+}
+
+/// Check that the server actually speaks `args.protocol` by sending one HEAD request
+/// on a throwaway client, so the probe doesn't show up in the run's statistics.
+pub async fn probe(args: &Args) -> Result<(), ProbeError> {
+    let protocol = args.protocol;
+    let url = args.url.as_str();
+    let is_https = url.starts_with("https://");
+    let tls = build_tls(protocol, args.insecure, is_https, false, &Arc::new(TlsStats::default()))
+        .map_err(ProbeError::other)?;
+    let resolver = BenchResolver::new(
+        args.dns_server,
+        None,
+        args.address_order,
+        false,
+        Arc::new(DnsStats::default()),
+    );
+    let opts = ClientOptions {
+        protocol,
+        conn_stats: Arc::new(ConnectStats::default()),
+        resolver: Arc::new(resolver),
+        resolve_override: None,
+        requests_per_connection: None,
+        max_client_age: None,
+        quic: None,
+        proxy: args.proxy.clone(),
+        expect_continue: false,
+        h2_keepalive: None,
+        connect_timeout: args.connect_timeout,
+        h2c_upgrade: None,
+        worker: None,
+    };
+    let client = build_client(&opts, tls.as_ref()).map_err(|e| ProbeError::other(error_chain(&e)))?;
+
+    // A QUIC handshake to a port nobody listens on only fails at the idle timeout
+    let mut request = client.head(url).timeout(PROBE_TIMEOUT);
+    if let Some(version) = protocol.version() {
+        request = request.version(version);
+    }
+    let resp = request.send().await.map_err(|e| ProbeError {
+        reason: error_chain(&e),
+        negotiation: is_negotiation_failure(protocol, is_https, errors::classify(&e)),
+    })?;
+
+    match protocol.version() {
+        Some(expected) if resp.version() != expected => Err(ProbeError {
+            reason: format!("server answered with {:?}", resp.version()),
+            negotiation: true,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Whether a probe of `protocol` that failed with `kind` says the server doesn't speak
+/// it: ALPN or the connection settled on another version, a QUIC handshake went
+/// unanswered, which only ends in a timeout, or a cleartext server took the HTTP/2
+/// preface badly once connected (an HTTP/1.1 server answers it, or hangs up on it)
+fn is_negotiation_failure(protocol: Protocol, is_https: bool, kind: ErrorKind) -> bool {
+    match kind {
+        ErrorKind::Protocol => true,
+        ErrorKind::Timeout | ErrorKind::ConnectTimeout => protocol == Protocol::H3,
+        ErrorKind::Other => protocol == Protocol::H2 && !is_https,
+        _ => false,
+    }
+}
@@ -0,0 +1,166 @@
+//! `--publish URL`: send the JSON report to an HTTP(S) endpoint after the run, e.g. a
+//! result archive's ingest API or an object store. benchy signs nothing, so for S3 or
+//! GCS the caller passes a presigned URL, which is PUT to.
+//!
+//! The upload goes over a plain HTTP/1.1 client of its own, like `--wait-ready`'s, with
+//! the system's proxy settings rather than the run's `--proxy` and `-k`. A failed
+//! upload is reported but only fails the run with `--publish-required`.
+
+use crate::protocol::Protocol;
+use crate::render;
+use crate::transport::{BoxError, TlsStats};
+use crate::{build_tls, error_chain, Args};
+use clap::ValueEnum;
+use colored::Colorize;
+use reqwest::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use reqwest::{Client, Method, StatusCode, Url};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Tries before giving up, and the wait before the first retry, doubled after each
+const ATTEMPTS: u32 = 3;
+const FIRST_DELAY: Duration = Duration::from_secs(1);
+
+/// Per attempt, since an archive that hangs shouldn't hold the pipeline up
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Query parameters only a presigned S3 or GCS URL carries
+const SIGNATURES: [&str; 2] = ["X-Amz-Signature", "X-Goog-Signature"];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PublishMethod {
+    /// PUT to presigned S3 and GCS URLs, POST anywhere else
+    #[default]
+    Auto,
+    Post,
+    Put,
+}
+
+/// `--publish-header NAME: VALUE`
+pub fn parse_header(s: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = s.split_once(':').ok_or_else(|| format!("expected NAME: VALUE, got {:?}", s))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|e| format!("{:?}: {}", name, e))?;
+    let value = HeaderValue::from_str(value.trim()).map_err(|e| format!("{:?}: {}", value, e))?;
+    Ok((name, value))
+}
+
+/// What's wrong with `--publish`'s URL, for the preflight
+pub fn problem(url: &str) -> Option<String> {
+    match Url::parse(url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => None,
+        Ok(url) if matches!(url.scheme(), "s3" | "gs") => Some(format!(
+            "--publish: benchy doesn't sign requests, so give a presigned https:// URL for {}:// \
+             (e.g. from `aws s3 presign` or `gcloud storage sign-url`)",
+            url.scheme()
+        )),
+        Ok(_) => Some(format!("--publish {}: expected an http:// or https:// URL", url)),
+        Err(e) => Some(format!("--publish {}: {}", url, e)),
+    }
+}
+
+/// `url` without its query, which for a presigned URL is the credential
+pub fn redacted(url: &Url) -> String {
+    let mut url = url.clone();
+    url.set_query(None);
+    url.to_string()
+}
+
+/// How the upload ended
+pub struct Published {
+    method: Method,
+    /// Redacted
+    url: String,
+    attempts: u32,
+    result: Result<StatusCode, String>,
+}
+
+impl Published {
+    pub fn failed(&self) -> bool {
+        self.result.is_err()
+    }
+
+    /// One line, to stdout with the text report and to stderr otherwise
+    pub fn print(&self, text: bool) {
+        let tries = match self.attempts {
+            1 => String::new(),
+            n => format!(" after {} attempts", n),
+        };
+        let line = match &self.result {
+            Ok(status) => render::line(
+                "Published:".white(),
+                format!("{} {} {}{}", self.method, self.url, status.to_string().green(), tries),
+            ),
+            Err(e) => render::wrapped(
+                "Publish:".white(),
+                format!("{} {} failed{}: {}", self.method, self.url, tries, e).red().bold(),
+            ),
+        };
+        if text {
+            println!("{}", line);
+        } else {
+            eprintln!("{}", line);
+        }
+    }
+}
+
+/// Upload `report` to `--publish`, retrying on errors and 408, 429 and 5xx responses
+pub async fn publish(args: &Args, report: &Value) -> Option<Published> {
+    let url = Url::parse(args.publish.as_deref()?).ok()?;
+    let method = match args.publish_method {
+        PublishMethod::Post => Method::POST,
+        PublishMethod::Put => Method::PUT,
+        PublishMethod::Auto if url.query_pairs().any(|(key, _)| SIGNATURES.contains(&key.as_ref())) => Method::PUT,
+        PublishMethod::Auto => Method::POST,
+    };
+    let mut published = Published {
+        method: method.clone(),
+        url: redacted(&url),
+        attempts: 0,
+        result: Err(String::new()),
+    };
+    let client = match client(url.scheme() == "https") {
+        Ok(client) => client,
+        Err(e) => {
+            published.result = Err(error_chain(e.as_ref()));
+            return Some(published);
+        }
+    };
+    let body = serde_json::to_vec_pretty(report).expect("the report serializes");
+    let mut delay = FIRST_DELAY;
+    loop {
+        published.attempts += 1;
+        let request = args
+            .publish_header
+            .iter()
+            .fold(client.request(method.clone(), url.clone()), |request, (name, value)| request.header(name, value))
+            .header(CONTENT_TYPE, "application/json")
+            .timeout(TIMEOUT)
+            .body(body.clone());
+        let (result, retry) = match request.send().await {
+            Ok(resp) if resp.status().is_success() => (Ok(resp.status()), false),
+            Ok(resp) => {
+                let status = resp.status();
+                let retry = status.is_server_error()
+                    || matches!(status, StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS);
+                (Err(format!("HTTP {}", status)), retry)
+            }
+            Err(e) => (Err(error_chain(&e)), true),
+        };
+        published.result = result;
+        if !retry || published.attempts == ATTEMPTS {
+            return Some(published);
+        }
+        tracing::info!(attempt = published.attempts, error = ?published.result.as_ref().err(), "retrying --publish");
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+    }
+}
+
+fn client(is_https: bool) -> Result<Client, BoxError> {
+    let mut builder = Client::builder().http1_only().retry(reqwest::retry::never());
+    if let Some(tls) = build_tls(Protocol::H1, false, is_https, false, &Arc::new(TlsStats::default()))? {
+        builder = builder.use_preconfigured_tls(rustls::ClientConfig::clone(&tls));
+    }
+    Ok(builder.build()?)
+}
@@ -0,0 +1,330 @@
+//! HTTP/3 over our own quinn endpoint for `--h3` runs.
+//!
+//! reqwest builds its QUIC transport config internally and only exposes a few knobs,
+//! so `--h3` requests go through here instead. Requests are still assembled with
+//! reqwest's builder and responses handed back as `reqwest::Response`, so everything
+//! downstream treats them like any other response.
+
+use crate::connect::{self, ConnectStats};
+use crate::dns::BenchResolver;
+use crate::transport::BoxError;
+use bytes::{Buf, Bytes};
+use clap::ValueEnum;
+use h3::client::{RequestStream, SendRequest};
+use http_body::{Body, Frame};
+use http_body_util::BodyExt;
+use quinn::congestion::{BbrConfig, CubicConfig, NewRenoConfig};
+use quinn::crypto::rustls::QuicClientConfig;
+use quinn::{ClientConfig, EndpointConfig, IdleTimeout, TransportConfig, VarInt};
+use reqwest::dns::{Name, Resolve};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+
+/// QUIC congestion controller (`--h3-congestion`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Congestion {
+    Bbr,
+    /// quinn's default
+    #[default]
+    Cubic,
+    #[value(name = "newreno")]
+    NewReno,
+}
+
+impl Congestion {
+    pub fn name(self) -> &'static str {
+        match self {
+            Congestion::Bbr => "bbr",
+            Congestion::Cubic => "cubic",
+            Congestion::NewReno => "newreno",
+        }
+    }
+}
+
+/// QUIC transport parameters set with the `--h3-*` flags; `None` keeps quinn's default
+#[derive(Clone, Debug, Default)]
+pub struct QuicTuning {
+    pub max_idle_timeout: Option<Duration>,
+    /// Bidirectional streams the server may open towards us
+    pub initial_max_streams: Option<u64>,
+    pub congestion: Congestion,
+    pub max_udp_payload: Option<u16>,
+    /// Send a PING after this long without traffic, so a dead path is noticed while idle
+    pub keep_alive_interval: Option<Duration>,
+}
+
+impl QuicTuning {
+    /// The congestion controller and any parameters that were set, for the banner,
+    /// e.g. "congestion bbr, idle timeout 10s"
+    pub fn describe(&self) -> String {
+        let mut parts = vec![format!("congestion {}", self.congestion.name())];
+        if let Some(timeout) = self.max_idle_timeout {
+            parts.push(format!("idle timeout {}", humantime::format_duration(timeout)));
+        }
+        if let Some(streams) = self.initial_max_streams {
+            parts.push(format!("max streams {}", streams));
+        }
+        if let Some(payload) = self.max_udp_payload {
+            parts.push(format!("max UDP payload {} B", payload));
+        }
+        if let Some(interval) = self.keep_alive_interval {
+            parts.push(format!("keepalive {}", humantime::format_duration(interval)));
+        }
+        parts.join(", ")
+    }
+
+    fn transport_config(&self) -> Result<TransportConfig, BoxError> {
+        let mut config = TransportConfig::default();
+        if let Some(timeout) = self.max_idle_timeout {
+            let timeout = IdleTimeout::try_from(timeout).map_err(|_| "--h3-max-idle-timeout is too large")?;
+            config.max_idle_timeout(Some(timeout));
+        }
+        if let Some(streams) = self.initial_max_streams {
+            let streams = VarInt::from_u64(streams).map_err(|_| "--h3-initial-max-streams is too large")?;
+            config.max_concurrent_bidi_streams(streams);
+        }
+        config.keep_alive_interval(self.keep_alive_interval);
+        match self.congestion {
+            Congestion::Bbr => config.congestion_controller_factory(Arc::new(BbrConfig::default())),
+            Congestion::Cubic => config.congestion_controller_factory(Arc::new(CubicConfig::default())),
+            Congestion::NewReno => config.congestion_controller_factory(Arc::new(NewRenoConfig::default())),
+        };
+        Ok(config)
+    }
+
+    fn endpoint_config(&self) -> Result<EndpointConfig, BoxError> {
+        let mut config = EndpointConfig::default();
+        if let Some(payload) = self.max_udp_payload {
+            config
+                .max_udp_payload_size(payload)
+                .map_err(|_| "--h3-max-udp-payload must be between 1200 and 65527")?;
+        }
+        Ok(config)
+    }
+
+    /// A quinn client config carrying these parameters
+    pub fn client_config(&self, tls: Arc<rustls::ClientConfig>) -> Result<ClientConfig, BoxError> {
+        let mut config = ClientConfig::new(Arc::new(QuicClientConfig::try_from(tls)?));
+        config.transport_config(Arc::new(self.transport_config()?));
+        Ok(config)
+    }
+
+    /// A client endpoint on `bind` that advertises these parameters
+    pub fn endpoint(&self, bind: SocketAddr) -> Result<quinn::Endpoint, BoxError> {
+        let socket = std::net::UdpSocket::bind(bind)?;
+        let runtime = quinn::default_runtime().ok_or("no async runtime found")?;
+        Ok(quinn::Endpoint::new(self.endpoint_config()?, None, socket, runtime)?)
+    }
+}
+
+/// Resolving the host, the QUIC handshake or HTTP/3 setup failed, so no request was sent
+#[derive(Debug, Clone)]
+pub struct ConnectError(Arc<dyn std::error::Error + Send + Sync>);
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HTTP/3 connection failed")
+    }
+}
+
+impl std::error::Error for ConnectError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.0.as_ref())
+    }
+}
+
+type Sender = SendRequest<h3_quinn::OpenStreams, Bytes>;
+
+#[derive(Default)]
+struct ConnState {
+    live: Option<(quinn::Connection, Sender)>,
+    /// The last failed connect, shared with requests that were waiting on it
+    failure: Option<(Instant, ConnectError)>,
+}
+
+/// One worker's HTTP/3 connection, opened on first use and reopened once it closes
+pub struct H3Client {
+    tuning: QuicTuning,
+    client_config: ClientConfig,
+    resolver: Arc<BenchResolver>,
+    stats: Arc<ConnectStats>,
+    connect_timeout: Duration,
+    conn: tokio::sync::Mutex<ConnState>,
+}
+
+impl H3Client {
+    pub fn new(
+        tls: &Arc<rustls::ClientConfig>,
+        tuning: &QuicTuning,
+        resolver: Arc<BenchResolver>,
+        stats: Arc<ConnectStats>,
+        connect_timeout: Duration,
+    ) -> Result<H3Client, BoxError> {
+        // Checked here so out-of-range values fail at startup rather than on first use
+        tuning.endpoint_config()?;
+        Ok(H3Client {
+            tuning: tuning.clone(),
+            client_config: tuning.client_config(tls.clone())?,
+            resolver,
+            stats,
+            connect_timeout,
+            conn: tokio::sync::Mutex::default(),
+        })
+    }
+
+    /// The live connection's request sender, connecting first if there is none.
+    ///
+    /// Held under the lock so pipelined requests share one connection instead of
+    /// each opening their own. Requests that waited on a connect that failed fail
+    /// with it rather than each retrying the handshake in turn.
+    async fn sender(&self, uri: &http::Uri) -> Result<Sender, BoxError> {
+        let asked = Instant::now();
+        let mut conn = self.conn.lock().await;
+        if let Some((quic, sender)) = &conn.live {
+            if quic.close_reason().is_none() {
+                return Ok(sender.clone());
+            }
+        }
+        if let Some((failed, e)) = &conn.failure {
+            if *failed > asked {
+                return Err(e.clone().into());
+            }
+        }
+
+        // Counted with the TCP connector's connections: resolving, the QUIC handshake
+        // and HTTP/3 setup
+        let start = Instant::now();
+        match self.connect(uri).await {
+            Ok((quic, sender)) => {
+                self.stats.record(start.elapsed(), Some(canonical(quic.remote_address())));
+                conn.live = Some((quic, sender.clone()));
+                Ok(sender)
+            }
+            Err(e) => {
+                self.stats.failed.fetch_add(1, Ordering::Relaxed);
+                let e = ConnectError(e.into());
+                conn.failure = Some((Instant::now(), e.clone()));
+                Err(e.into())
+            }
+        }
+    }
+
+    async fn connect(&self, uri: &http::Uri) -> Result<(quinn::Connection, Sender), BoxError> {
+        let host = uri.host().ok_or("URL has no host")?.trim_start_matches('[').trim_end_matches(']');
+        let port = uri.port_u16().unwrap_or(443);
+        let addr = match IpAddr::from_str(host) {
+            Ok(ip) => SocketAddr::new(ip, port),
+            Err(_) => {
+                let mut addr = self
+                    .resolver
+                    .resolve(Name::from_str(host)?)
+                    .await?
+                    .next()
+                    .ok_or_else(|| format!("{} did not resolve to any address", host))?;
+                addr.set_port(port);
+                addr
+            }
+        };
+
+        // Dual-stack, like reqwest's own HTTP/3 endpoint
+        let endpoint = self.tuning.endpoint("[::]:0".parse()?)?;
+        let quic = connect::counted(async {
+            let connecting = endpoint.connect_with(self.client_config.clone(), addr, host)?;
+            connect::within(self.connect_timeout, connecting).await
+        });
+        let quic = match quic.await {
+            Ok(quic) => quic,
+            Err(e) => {
+                tracing::info!(error = %e, "QUIC connection failed");
+                return Err(e);
+            }
+        };
+        tracing::info!(remote = ?addr, "QUIC connection established");
+
+        let (mut driver, sender) = h3::client::new(h3_quinn::Connection::new(quic.clone())).await?;
+        tokio::spawn(async move {
+            std::future::poll_fn(|cx| driver.poll_close(cx)).await;
+        });
+        Ok((quic, sender))
+    }
+
+    /// Where the live connection goes, if there is one. The endpoint is dual-stack, so
+    /// IPv4 peers come back as mapped IPv6 addresses until canonicalized.
+    pub async fn remote(&self) -> Option<SocketAddr> {
+        Some(canonical(self.conn.lock().await.live.as_ref()?.0.remote_address()))
+    }
+
+    pub async fn send(&self, request: reqwest::Request) -> Result<reqwest::Response, BoxError> {
+        let (parts, mut body) = http::Request::<reqwest::Body>::try_from(request)?.into_parts();
+        let mut sender = self.sender(&parts.uri).await?;
+
+        let mut stream = sender.send_request(http::Request::from_parts(parts, ())).await?;
+        while let Some(frame) = body.frame().await {
+            if let Ok(data) = frame?.into_data() {
+                stream.send_data(data).await?;
+            }
+        }
+        stream.finish().await?;
+
+        let response = stream.recv_response().await?;
+        let body = ResponseBody {
+            stream,
+            data_done: false,
+            trailers_done: false,
+        };
+        Ok(reqwest::Response::from(response.map(|()| reqwest::Body::wrap(body))))
+    }
+}
+
+/// `remote` with an IPv4-mapped IPv6 address, as the dual-stack endpoint sees IPv4
+/// peers, turned back into IPv4
+fn canonical(remote: SocketAddr) -> SocketAddr {
+    SocketAddr::new(remote.ip().to_canonical(), remote.port())
+}
+
+impl Drop for H3Client {
+    fn drop(&mut self) {
+        if let Some((quic, _)) = self.conn.get_mut().live.take() {
+            quic.close(0u32.into(), b"");
+        }
+    }
+}
+
+/// The response half of a request stream, read by reqwest as the response body
+struct ResponseBody {
+    stream: RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    /// The data is all read, so the trailers (if any) come next
+    data_done: bool,
+    trailers_done: bool,
+}
+
+impl Body for ResponseBody {
+    type Data = Bytes;
+    type Error = h3::error::StreamError;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        if !self.data_done {
+            match ready!(self.stream.poll_recv_data(cx)) {
+                Ok(Some(mut data)) => return Poll::Ready(Some(Ok(Frame::data(data.copy_to_bytes(data.remaining()))))),
+                Ok(None) => self.data_done = true,
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+        if self.trailers_done {
+            return Poll::Ready(None);
+        }
+        let trailers = ready!(self.stream.poll_recv_trailers(cx));
+        self.trailers_done = true;
+        match trailers {
+            Ok(Some(trailers)) => Poll::Ready(Some(Ok(Frame::trailers(trailers)))),
+            Ok(None) => Poll::Ready(None),
+            Err(e) => Poll::Ready(Some(Err(e))),
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//! `--ratelimit-headers`: the rate limit a service advertises in its responses, set
+//! against what the run achieved and how often it was throttled. `--auto-rate` also
+//! slows `--rate` to what the headers say is left of the current window, and holds
+//! sends for as long as a 429's `Retry-After` asks.
+//!
+//! Understood are the IETF `RateLimit-Limit`/`-Remaining`/`-Reset` headers and their
+//! structured `RateLimit`/`RateLimit-Policy` successors, and the `X-RateLimit-*`
+//! (GitHub, GitLab and most others) and `X-Rate-Limit-*` variants. Services that only
+//! answer 429, like Stripe, are still counted as throttling.
+
+use crate::render;
+use crate::thousands;
+use colored::Colorize;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Prefixes of the separate limit, remaining and reset headers, in order of preference
+const PREFIXES: [&str; 3] = ["ratelimit-", "x-ratelimit-", "x-rate-limit-"];
+
+/// A reset above this is a Unix time rather than seconds from now
+const EPOCH_SECONDS: f64 = 1e9;
+
+/// What one response said about the limit
+#[derive(Default, Debug)]
+struct Advertised {
+    /// Requests allowed per window
+    limit: Option<u64>,
+    remaining: Option<u64>,
+    /// Seconds until the window resets
+    reset: Option<f64>,
+    /// Length of the window, when the policy gives it
+    window: Option<f64>,
+    /// Where it came from, e.g. `x-ratelimit-*`
+    source: Option<&'static str>,
+}
+
+impl Advertised {
+    fn parse(headers: &HeaderMap, now: SystemTime) -> Advertised {
+        let mut advertised = Advertised::default();
+        let text = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        for prefix in PREFIXES {
+            let limit = text(&format!("{}limit", prefix));
+            let remaining = text(&format!("{}remaining", prefix));
+            let reset = text(&format!("{}reset", prefix));
+            if limit.is_none() && remaining.is_none() && reset.is_none() {
+                continue;
+            }
+            // Drafts allow a policy after the number: `100, 100;w=60`
+            if let Some(limit) = limit {
+                advertised.limit = leading(limit).map(|n| n as u64);
+                advertised.window = param(limit, &["w"]);
+            }
+            advertised.remaining = remaining.and_then(leading).map(|n| n as u64);
+            advertised.reset = reset.and_then(leading).map(|reset| seconds_from(reset, now));
+            advertised.source = Some(match prefix {
+                "ratelimit-" => "RateLimit-*",
+                "x-ratelimit-" => "X-RateLimit-*",
+                _ => "X-Rate-Limit-*",
+            });
+            return advertised;
+        }
+        // Structured: `RateLimit-Policy: "default";q=100;w=60` and `RateLimit: "default";r=50;t=30`,
+        // or the older combined `RateLimit: limit=100, remaining=50, reset=30`
+        if let Some(policy) = text("ratelimit-policy") {
+            advertised.limit = param(policy, &["q", "limit"]).map(|n| n as u64);
+            advertised.window = param(policy, &["w", "window"]);
+            advertised.source = Some("RateLimit-Policy");
+        }
+        if let Some(state) = text("ratelimit") {
+            advertised.limit = advertised.limit.or(param(state, &["limit"]).map(|n| n as u64));
+            advertised.remaining = param(state, &["r", "remaining"]).map(|n| n as u64);
+            advertised.reset = param(state, &["t", "reset"]).map(|reset| seconds_from(reset, now));
+            advertised.source = Some("RateLimit");
+        }
+        advertised
+    }
+}
+
+/// The number a header value starts with
+fn leading(value: &str) -> Option<f64> {
+    let end = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    value[..end].parse().ok()
+}
+
+/// The first of `keys` among a value's `key=value` parameters, split on `;` and `,`
+fn param(value: &str, keys: &[&str]) -> Option<f64> {
+    value.split([';', ',']).find_map(|part| {
+        let (key, value) = part.split_once('=')?;
+        keys.contains(&key.trim().to_ascii_lowercase().as_str()).then(|| value.trim().trim_matches('"').parse().ok())?
+    })
+}
+
+/// A reset in seconds from now, given either that way or as a Unix time (in seconds,
+/// as GitHub does, or milliseconds)
+fn seconds_from(reset: f64, now: SystemTime) -> f64 {
+    if reset < EPOCH_SECONDS {
+        return reset;
+    }
+    let reset = if reset >= EPOCH_SECONDS * 1000.0 { reset / 1000.0 } else { reset };
+    let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+    (reset - now).max(0.0)
+}
+
+/// `Retry-After` as seconds or an HTTP date
+fn retry_after(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = UNIX_EPOCH + Duration::from_secs(http_date(value)?);
+    Some(at.duration_since(now).unwrap_or_default())
+}
+
+/// Seconds since the epoch of an IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+fn http_date(value: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let mut parts = value.split_whitespace().skip(1);
+    let day: i64 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|&m| m == month)? as i64 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut clock = parts.next()?.split(':').map(|n| n.parse::<i64>().ok());
+    let (h, m, s) = (clock.next()??, clock.next()??, clock.next()??);
+    // Days from 1970-01-01 to the date, in the proleptic Gregorian calendar
+    let (y, mp) = if month > 2 { (year, month - 3) } else { (year - 1, month + 9) };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let days = era * 146_097 + yoe * 365 + yoe / 4 - yoe / 100 + doy - 719_468;
+    u64::try_from(days * 86_400 + h * 3600 + m * 60 + s).ok()
+}
+
+/// Advertised limits across the run, 429s, and the `--auto-rate` ceiling workers'
+/// schedules read
+pub struct RateLimits {
+    /// `--auto-rate`: `--rate`, as the most the ceiling rises to
+    auto: Option<f64>,
+    workers: usize,
+    start: Instant,
+    /// Responses with any rate limit header
+    advertising: AtomicU64,
+    source: OnceLock<&'static str>,
+    /// `u64::MAX` until a limit is seen
+    min_limit: AtomicU64,
+    /// Milliseconds: the policy's window when given, and the longest reset otherwise
+    policy_window: AtomicU64,
+    longest_reset: AtomicU64,
+    throttled: AtomicU64,
+    longest_retry_after: AtomicU64,
+    /// `--auto-rate`: the compliant rate across all workers as f64 bits (which order
+    /// like the values, for positive ones), the lowest it went, and nanoseconds into
+    /// the run before which nothing is sent
+    rate: AtomicU64,
+    lowest_rate: AtomicU64,
+    hold_until: AtomicU64,
+}
+
+impl RateLimits {
+    /// `auto` is `--rate` under `--auto-rate`
+    pub fn new(auto: Option<f64>, workers: usize) -> RateLimits {
+        let rate = auto.unwrap_or(0.0).to_bits();
+        RateLimits {
+            auto,
+            workers,
+            start: Instant::now(),
+            advertising: AtomicU64::new(0),
+            source: OnceLock::new(),
+            min_limit: AtomicU64::new(u64::MAX),
+            policy_window: AtomicU64::new(0),
+            longest_reset: AtomicU64::new(0),
+            throttled: AtomicU64::new(0),
+            longest_retry_after: AtomicU64::new(0),
+            rate: AtomicU64::new(rate),
+            lowest_rate: AtomicU64::new(rate),
+            hold_until: AtomicU64::new(0),
+        }
+    }
+
+    /// Read a response's headers
+    pub fn record(&self, status: u16, headers: &HeaderMap) {
+        let now = SystemTime::now();
+        let advertised = Advertised::parse(headers, now);
+        let retry = retry_after(headers, now).filter(|_| status == 429 || status == 503);
+        if status == 429 {
+            self.throttled.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(retry) = retry {
+            self.longest_retry_after.fetch_max(retry.as_millis() as u64, Ordering::Relaxed);
+            self.hold(retry);
+        }
+        let Some(source) = advertised.source else {
+            return;
+        };
+        self.advertising.fetch_add(1, Ordering::Relaxed);
+        let _ = self.source.set(source);
+        if let Some(limit) = advertised.limit {
+            self.min_limit.fetch_min(limit, Ordering::Relaxed);
+        }
+        if let Some(window) = advertised.window {
+            self.policy_window.fetch_max((window * 1000.0) as u64, Ordering::Relaxed);
+        }
+        if let Some(reset) = advertised.reset {
+            self.longest_reset.fetch_max((reset * 1000.0) as u64, Ordering::Relaxed);
+        }
+        let (Some(max), Some(remaining), Some(reset)) = (self.auto, advertised.remaining, advertised.reset) else {
+            return;
+        };
+        if remaining == 0 {
+            // Nothing left of this window: wait for the next rather than be refused
+            self.hold(Duration::from_secs_f64(reset));
+        } else if reset > 0.0 {
+            let rate = (remaining as f64 / reset).min(max);
+            self.rate.store(rate.to_bits(), Ordering::Relaxed);
+            self.lowest_rate.fetch_min(rate.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    /// Send nothing for `wait` from now, under `--auto-rate`
+    fn hold(&self, wait: Duration) {
+        if self.auto.is_some() {
+            let until = (self.start.elapsed() + wait).as_nanos() as u64;
+            self.hold_until.fetch_max(until, Ordering::Relaxed);
+        }
+    }
+
+    /// A worker's mean gap between sends: its own under `--rate`, or longer to keep the
+    /// workers together to the compliant rate
+    pub fn gap(&self, own: f64) -> f64 {
+        let rate = f64::from_bits(self.rate.load(Ordering::Relaxed));
+        if rate > 0.0 {
+            own.max(self.workers as f64 / rate)
+        } else {
+            own
+        }
+    }
+
+    /// When sends may go out again after a `Retry-After` or an exhausted window
+    pub fn held_until(&self) -> Instant {
+        self.start + Duration::from_nanos(self.hold_until.load(Ordering::Relaxed))
+    }
+
+    /// The `Rate Limits` section: the advertised limit against the `successful`
+    /// requests over `active`, and the `completed` ones that were throttled
+    pub fn print(&self, successful: u64, completed: u64, active: Duration) {
+        println!("\n{}", "--- Rate Limits ---".cyan().bold());
+        let achieved = successful as f64 / active.as_secs_f64().max(f64::EPSILON);
+        let limit = self.min_limit.load(Ordering::Relaxed);
+        // In whole seconds, as resets are given; a Unix time reset rounded up puts the
+        // longest a fraction past the window
+        let window = match self.policy_window.load(Ordering::Relaxed) {
+            0 => self.longest_reset.load(Ordering::Relaxed),
+            policy => policy,
+        } / 1000;
+        let advertised_rate = (limit != u64::MAX && window > 0).then(|| limit as f64 / window as f64);
+        match (self.source.get(), limit) {
+            (None, _) => render::print_wrapped(
+                "Note:".yellow().bold(),
+                "no response carried rate limit headers (RateLimit, X-RateLimit-*, X-Rate-Limit-*)".yellow(),
+            ),
+            (Some(source), u64::MAX) => {
+                render::print("Advertised:".white(), format!("no limit, only what is left, in {}", source))
+            }
+            (Some(source), limit) => {
+                let per = match advertised_rate {
+                    Some(rate) => format!(
+                        "per {} ({:.1} req/s)",
+                        humantime::format_duration(Duration::from_secs(window)),
+                        rate
+                    ),
+                    None => "per window of unknown length".to_string(),
+                };
+                let seen = thousands(self.advertising.load(Ordering::Relaxed));
+                let line = format!("{} requests {}, from {} on {} responses", thousands(limit), per, source, seen);
+                render::print("Advertised:".white(), line);
+            }
+        }
+        let mut line = format!("{:.1} successful req/s", achieved);
+        if let Some(rate) = advertised_rate {
+            line += &format!(", {:.0}% of the advertised rate", achieved / rate * 100.0);
+        }
+        render::print("Achieved:".white(), line);
+
+        let throttled = self.throttled.load(Ordering::Relaxed);
+        let mut line = format!(
+            "{} responses with 429 ({:.2}% of completed)",
+            thousands(throttled),
+            throttled as f64 / completed.max(1) as f64 * 100.0
+        );
+        let retry = self.longest_retry_after.load(Ordering::Relaxed);
+        if retry > 0 {
+            let longest = humantime::format_duration(Duration::from_secs(retry.div_ceil(1000)));
+            line += &format!(", Retry-After up to {}", longest);
+        }
+        render::print("Throttled:".white(), if throttled > 0 { line.yellow() } else { line.green() });
+
+        if let Some(max) = self.auto {
+            let rate = f64::from_bits(self.rate.load(Ordering::Relaxed));
+            let lowest = f64::from_bits(self.lowest_rate.load(Ordering::Relaxed));
+            render::print(
+                "Auto rate:".white(),
+                format!("{:.1} req/s at the end, {:.1} at the lowest, of --rate {}", rate, lowest, max),
+            );
+        }
+    }
+}
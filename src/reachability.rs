@@ -0,0 +1,74 @@
+//! Early abort for a target that can't be reached at all, so a down server gives one
+//! diagnosis instead of every worker failing its whole share of `-n`.
+//!
+//! The collector feeds each result in until the target has proved reachable or the
+//! first second is over. The target is unreachable once every worker's first few
+//! requests have all failed to connect and nothing else came back. Judging each worker by the requests it sent first, rather than by the
+//! results that arrive first, keeps fast refusals from part of the target (one dead
+//! backend, one bad `--url-file` host) from outrunning slower successes.
+
+use std::time::Duration;
+
+/// Only failures this early in the run count
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Requests per worker that must fail, unless the worker's share of `-n` is smaller
+const MIN_FAILURES: u64 = 10;
+
+pub struct Reachability {
+    /// Settled one way or the other, or past the window
+    done: bool,
+    /// How many of each worker's first requests must fail
+    needed: u64,
+    /// Connection failures among each worker's first `needed` requests
+    failures: Vec<u64>,
+    /// The first failure's diagnosis
+    cause: Option<String>,
+}
+
+impl Reachability {
+    pub fn new(workers: usize, requests: u64) -> Self {
+        // Workers beyond -n have nothing to send, so never get to fail
+        let workers = workers.min(requests.try_into().unwrap_or(usize::MAX)).max(1);
+        let share = requests / workers as u64;
+        Reachability {
+            done: false,
+            needed: MIN_FAILURES.min(share).max(1),
+            failures: vec![0; workers],
+            cause: None,
+        }
+    }
+
+    /// Feed one result: request `seq` of `worker`, finished `elapsed` into the run.
+    /// `cause` is the diagnosis of a connection failure, `None` for anything else.
+    /// Returns the first failure's diagnosis once the target is judged unreachable.
+    pub fn record(&mut self, elapsed: Duration, worker: usize, seq: u64, cause: Option<&str>) -> Option<String> {
+        if self.done {
+            return None;
+        }
+        let Some(cause) = cause else {
+            // Anything else, even an error status, means requests are getting through
+            self.done = true;
+            return None;
+        };
+        if elapsed > WINDOW {
+            self.done = true;
+            return None;
+        }
+        if seq >= self.needed {
+            return None;
+        }
+
+        if let Some(failures) = self.failures.get_mut(worker) {
+            *failures += 1;
+        }
+        if self.cause.is_none() {
+            self.cause = Some(cause.to_string());
+        }
+        if self.failures.iter().any(|&failures| failures < self.needed) {
+            return None;
+        }
+        self.done = true;
+        self.cause.take()
+    }
+}
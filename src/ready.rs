@@ -0,0 +1,92 @@
+//! `--wait-ready`: poll a readiness URL until the service under test answers, so a
+//! benchmark started alongside the service (as in CI) doesn't race it.
+//!
+//! The polls go over a plain HTTP/1.1 client of their own, so they neither warm up
+//! nor count against the workers' connections, and the wait happens before the timer
+//! starts.
+
+use crate::protocol::{Protocol, PROBE_TIMEOUT};
+use crate::transport::{BoxError, TlsStats};
+use crate::{build_tls, error_chain, Args};
+use reqwest::{Client, StatusCode, Url};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Delay after the first failed poll, doubled after each one up to `MAX_DELAY`
+const FIRST_DELAY: Duration = Duration::from_millis(100);
+const MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// How the wait ended well
+pub struct Ready {
+    pub url: Url,
+    pub elapsed: Duration,
+    pub attempts: u32,
+}
+
+/// `--ready-path` on the target's origin, or the target itself
+pub fn url(args: &Args) -> Result<Url, String> {
+    let mut url = Url::parse(&args.url).map_err(|e| format!("{}: {}", args.url, e))?;
+    if let Some(path) = &args.ready_path {
+        url = url.join(path).map_err(|e| format!("--ready-path {}: {}", path, e))?;
+    }
+    Ok(url)
+}
+
+/// Poll until the readiness URL returns `--ready-status` or `timeout` runs out; the
+/// error carries the last poll's failure
+pub async fn wait(args: &Args, timeout: Duration) -> Result<Ready, String> {
+    let url = url(args)?;
+    let client = client(args, url.scheme() == "https").map_err(|e| error_chain(e.as_ref()))?;
+    let expected = StatusCode::from_u16(args.ready_status).map_err(|e| format!("--ready-status: {}", e))?;
+
+    let start = Instant::now();
+    let deadline = start + timeout;
+    let mut delay = FIRST_DELAY;
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let last = match client.get(url.clone()).timeout(remaining.min(PROBE_TIMEOUT)).send().await {
+            Ok(resp) if resp.status() == expected => {
+                return Ok(Ready {
+                    url,
+                    elapsed: start.elapsed(),
+                    attempts,
+                })
+            }
+            Ok(resp) => format!("HTTP {}, waiting for {}", resp.status(), expected),
+            Err(e) => error_chain(&e),
+        };
+        tracing::info!(attempts, error = %last, "target not ready");
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(format!(
+                "{} not ready after {} ({} attempts), last: {}",
+                url,
+                humantime::format_duration(timeout),
+                attempts,
+                last
+            ));
+        }
+        tokio::time::sleep(delay.min(remaining)).await;
+        delay = (delay * 2).min(MAX_DELAY);
+    }
+}
+
+/// HTTP/1.1 only, with the same `-k` and `--proxy` as the run but nothing else of its
+/// client: no connection timing, no session resumption, the system resolver
+fn client(args: &Args, is_https: bool) -> Result<Client, BoxError> {
+    let mut builder = Client::builder()
+        .http1_only()
+        .pool_max_idle_per_host(0)
+        .retry(reqwest::retry::never());
+    if let Some(proxy) = &args.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    if let Some(tls) = build_tls(Protocol::H1, args.insecure, is_https, false, &Arc::new(TlsStats::default()))? {
+        builder = builder.use_preconfigured_tls(rustls::ClientConfig::clone(&tls));
+    }
+    Ok(builder.build()?)
+}
@@ -0,0 +1,413 @@
+//! Laying out the results: `Label:` lines, tables and the banner, aligned by what a
+//! terminal shows rather than by bytes, so ANSI colors and labels wider than the label
+//! column don't push values out of line.
+//!
+//! On a terminal, lines are fit to its width: values and a table's last column are
+//! ellipsized (`…`), while notes and errors wrap under their value instead of losing
+//! their end. The banner shortens its URL in the middle. Piped output is left whole.
+
+use colored::Colorize;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+/// Columns taken by a line's label, which values start after
+pub const LABEL_WIDTH: usize = 14;
+
+/// Narrower than this, a value or URL is left whole and wraps
+const MIN_VALUE_WIDTH: usize = 16;
+
+/// Between a table's columns
+const GAP: &str = "  ";
+
+const ELLIPSIS: char = '…';
+
+/// Turns colors back off after a value cut short inside a colored span
+const RESET: &str = "\x1b[0m";
+
+/// Columns of the terminal stdout is, if it is one; looked up once, as results are
+/// printed all at the end
+pub fn terminal_width() -> Option<usize> {
+    static WIDTH: OnceLock<Option<usize>> = OnceLock::new();
+    *WIDTH.get_or_init(|| {
+        use std::io::IsTerminal;
+        if !std::io::stdout().is_terminal() {
+            return None;
+        }
+        #[cfg(unix)]
+        {
+            let mut size = libc::winsize {
+                ws_row: 0,
+                ws_col: 0,
+                ws_xpixel: 0,
+                ws_ypixel: 0,
+            };
+            // SAFETY: TIOCGWINSZ only writes to the winsize it is given
+            let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+            if ok && size.ws_col > 0 {
+                return Some(size.ws_col as usize);
+            }
+        }
+        std::env::var("COLUMNS").ok()?.parse().ok()
+    })
+}
+
+/// `s` split into escape sequences, which take no columns, and visible characters
+fn pieces(s: &str) -> impl Iterator<Item = (&str, bool)> {
+    let mut rest = s;
+    std::iter::from_fn(move || {
+        let mut chars = rest.char_indices();
+        let (_, first) = chars.next()?;
+        let end = if first == '\x1b' && rest[1..].starts_with('[') {
+            // CSI: parameters, then a final byte from @ to ~
+            rest[2..]
+                .find(|c: char| ('@'..='~').contains(&c))
+                .map_or(rest.len(), |at| at + 3)
+        } else {
+            first.len_utf8()
+        };
+        let (piece, tail) = rest.split_at(end);
+        rest = tail;
+        Some((piece, first != '\x1b'))
+    })
+}
+
+/// Columns `s` takes on a terminal, color codes aside
+pub fn width(s: &str) -> usize {
+    pieces(s).filter(|&(_, visible)| visible).count()
+}
+
+/// `s` followed by enough spaces to take `columns`
+fn pad(s: &str, columns: usize) -> String {
+    format!("{}{}", s, " ".repeat(columns.saturating_sub(width(s))))
+}
+
+/// `s` cut to `columns`, ending in `…` if anything was cut
+pub fn ellipsize(s: &str, columns: usize) -> String {
+    if width(s) <= columns {
+        return s.to_string();
+    }
+    let mut out = String::new();
+    let mut shown = 0;
+    let mut colored = false;
+    for (piece, visible) in pieces(s) {
+        if !visible {
+            colored = true;
+            out.push_str(piece);
+        } else if shown + 1 < columns {
+            shown += 1;
+            out.push_str(piece);
+        } else {
+            break;
+        }
+    }
+    out.push(ELLIPSIS);
+    if colored {
+        out.push_str(RESET);
+    }
+    out
+}
+
+/// `s` broken at spaces into lines of at most `columns`, each after the first indented
+/// by `indent`; a word longer than a line is broken where it has to be
+fn wrap(s: &str, columns: usize, indent: usize) -> String {
+    let mut out = String::new();
+    let mut line = 0;
+    let mut word = String::new();
+    let mut word_width = 0;
+    let flush = |out: &mut String, line: &mut usize, word: &mut String, word_width: &mut usize| {
+        if *line > 0 && *line + 1 + *word_width > columns {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent));
+            *line = 0;
+        } else if *line > 0 {
+            out.push(' ');
+            *line += 1;
+        }
+        out.push_str(word);
+        *line += *word_width;
+        word.clear();
+        *word_width = 0;
+    };
+    for (piece, visible) in pieces(s) {
+        match piece {
+            " " => flush(&mut out, &mut line, &mut word, &mut word_width),
+            "\n" => {
+                flush(&mut out, &mut line, &mut word, &mut word_width);
+                out.push('\n');
+                out.push_str(&" ".repeat(indent));
+                line = 0;
+            }
+            _ => {
+                if visible && word_width == columns {
+                    flush(&mut out, &mut line, &mut word, &mut word_width);
+                }
+                word.push_str(piece);
+                word_width += visible as usize;
+            }
+        }
+    }
+    flush(&mut out, &mut line, &mut word, &mut word_width);
+    out
+}
+
+/// `label` padded to the label column (or followed by one space if it's wider) and
+/// `value` after it, as `fit` makes it for a terminal `terminal` columns wide
+fn lay_out(
+    terminal: Option<usize>,
+    label: impl Display,
+    value: impl Display,
+    fit: fn(&str, usize, usize) -> String,
+) -> String {
+    let label = label.to_string();
+    let head = format!("{} ", pad(&label, LABEL_WIDTH));
+    let indent = width(&head);
+    let value = value.to_string();
+    match terminal.and_then(|w| w.checked_sub(indent)).filter(|&w| w >= MIN_VALUE_WIDTH) {
+        Some(columns) => format!("{}{}", head, fit(&value, columns, indent)),
+        None => format!("{}{}", head, value),
+    }
+}
+
+/// A `Label:  value` line, the value ellipsized to the terminal
+pub fn line(label: impl Display, value: impl Display) -> String {
+    line_for(terminal_width(), label, value)
+}
+
+fn line_for(terminal: Option<usize>, label: impl Display, value: impl Display) -> String {
+    lay_out(terminal, label, value, |value, columns, _| {
+        value.lines().map(|line| ellipsize(line, columns)).collect::<Vec<_>>().join("\n")
+    })
+}
+
+pub fn print(label: impl Display, value: impl Display) {
+    println!("{}", line(label, value));
+}
+
+/// A `Label:  value` line whose value wraps under itself instead of being cut short,
+/// for notes, warnings and errors, where the end matters
+pub fn wrapped(label: impl Display, value: impl Display) -> String {
+    wrapped_for(terminal_width(), label, value)
+}
+
+fn wrapped_for(terminal: Option<usize>, label: impl Display, value: impl Display) -> String {
+    lay_out(terminal, label, value, wrap)
+}
+
+pub fn print_wrapped(label: impl Display, value: impl Display) {
+    println!("{}", wrapped(label, value));
+}
+
+/// The `Benchmarking URL ...` banner; the URL loses its middle if the line would
+/// otherwise not fit the terminal
+pub fn banner(url: &str, details: impl Display) -> String {
+    banner_for(terminal_width(), url, details)
+}
+
+fn banner_for(terminal: Option<usize>, url: &str, details: impl Display) -> String {
+    let details = details.to_string();
+    let lead = "Benchmarking".cyan().bold();
+    // When the rest doesn't fit either, the URL still gets no more than half the line
+    let room = terminal
+        .map(|w| w.saturating_sub(width(&lead) + 1 + 1 + width(&details)).max(w / 2))
+        .filter(|&room| room >= MIN_VALUE_WIDTH);
+    let url = match room {
+        Some(room) if url.chars().count() > room => {
+            // The scheme and host on the left, the end of the path on the right
+            let tail = (room - 1) / 2;
+            let head = room - 1 - tail;
+            let chars: Vec<char> = url.chars().collect();
+            let mut short: String = chars[..head].iter().collect();
+            short.push(ELLIPSIS);
+            short.extend(&chars[chars.len() - tail..]);
+            short
+        }
+        _ => url.to_string(),
+    };
+    format!("{} {} {}", lead, url.yellow(), details)
+}
+
+/// Rows of cells printed with each column as wide as its widest cell. The first
+/// column is at least as wide as a line's label, so tables line up with the lines
+/// around them; the last is ellipsized to the terminal.
+pub struct Table {
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// A table whose first row is `header`, its first cell colored like a label
+    pub fn new<I, S>(header: I) -> Table
+    where
+        I: IntoIterator<Item = S>,
+        S: Display,
+    {
+        let mut header = header.into_iter().map(|cell| cell.to_string());
+        let first = header.next().map(|cell| cell.white().to_string());
+        Table {
+            rows: vec![first.into_iter().chain(header).collect()],
+        }
+    }
+
+    pub fn row<I, S>(&mut self, cells: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Display,
+    {
+        self.rows.push(cells.into_iter().map(|cell| cell.to_string()).collect());
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.lines_for(terminal_width())
+    }
+
+    fn lines_for(&self, terminal: Option<usize>) -> Vec<String> {
+        let columns = self.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let mut widths: Vec<usize> = (0..columns)
+            .map(|c| self.rows.iter().filter_map(|row| row.get(c)).map(|cell| width(cell)).max().unwrap_or(0))
+            .collect();
+        if let Some(first) = widths.first_mut() {
+            *first = (*first).max(LABEL_WIDTH);
+        }
+
+        self.rows
+            .iter()
+            .map(|row| {
+                let mut line = String::new();
+                for (c, cell) in row.iter().enumerate() {
+                    if c + 1 == row.len() {
+                        let room = terminal
+                            .and_then(|w| w.checked_sub(width(&line)))
+                            .filter(|&room| room >= MIN_VALUE_WIDTH);
+                        line.push_str(&room.map_or_else(|| cell.clone(), |room| ellipsize(cell, room)));
+                    } else {
+                        line.push_str(&pad(cell, widths[c]));
+                        line.push_str(if c == 0 { " " } else { GAP });
+                    }
+                }
+                line
+            })
+            .collect()
+    }
+
+    pub fn print(&self) {
+        for line in self.lines() {
+            println!("{}", line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A results section as main prints it, laid out for a terminal `terminal` columns wide
+    fn report(terminal: Option<usize>) -> String {
+        colored::control::set_override(false);
+        let mut out = vec![banner_for(
+            terminal,
+            "https://api.example.com/v1/accounts/12345/transactions",
+            "(HTTP/2) with 32 concurrency, 10000 total requests",
+        )];
+        out.push(line_for(terminal, "Requests/sec:", "1,234.56"));
+        out.push(line_for(terminal, "Remote:", "10.0.0.1 ×2, 10.0.0.2 ×1, 10.0.0.3 ×1, 2001:db8::1 ×4"));
+        out.push(line_for(terminal, "Connection errors:", "3"));
+        out.push(wrapped_for(
+            terminal,
+            "Note:",
+            "header sizes are before HPACK/QPACK compression, which the transport doesn't report",
+        ));
+        let mut table = Table::new(["Phase", "Avg", "P50", "P99", "Share"]);
+        table.row(["Connect:", "0.617ms", "0.601ms", "1.020ms", "0.3%, mostly the first request per connection"]);
+        table.row(["TTFB:", "202.202ms", "198.040ms", "402.042ms", "99.5%"]);
+        out.extend(table.lines_for(terminal));
+        out.join("\n")
+    }
+
+    fn assert_layout(terminal: Option<usize>, expected: &[&str]) {
+        let report = report(terminal);
+        assert_eq!(report.lines().collect::<Vec<_>>(), expected, "laid out for {:?}:\n{}", terminal, report);
+    }
+
+    #[test]
+    fn lays_out_piped() {
+        assert_layout(
+            None,
+            &[
+                "Benchmarking https://api.example.com/v1/accounts/12345/transactions (HTTP/2) with 32 concurrency, 10000 total requests",
+                "Requests/sec:  1,234.56",
+                "Remote:        10.0.0.1 ×2, 10.0.0.2 ×1, 10.0.0.3 ×1, 2001:db8::1 ×4",
+                "Connection errors: 3",
+                "Note:          header sizes are before HPACK/QPACK compression, which the transport doesn't report",
+                "Phase          Avg        P50        P99        Share",
+                "Connect:       0.617ms    0.601ms    1.020ms    0.3%, mostly the first request per connection",
+                "TTFB:          202.202ms  198.040ms  402.042ms  99.5%",
+            ],
+        );
+    }
+
+    #[test]
+    fn lays_out_at_80_columns() {
+        assert_layout(
+            Some(80),
+            &[
+                "Benchmarking https://api.example.…/12345/transactions (HTTP/2) with 32 concurrency, 10000 total requests",
+                "Requests/sec:  1,234.56",
+                "Remote:        10.0.0.1 ×2, 10.0.0.2 ×1, 10.0.0.3 ×1, 2001:db8::1 ×4",
+                "Connection errors: 3",
+                "Note:          header sizes are before HPACK/QPACK compression, which the",
+                "               transport doesn't report",
+                "Phase          Avg        P50        P99        Share",
+                "Connect:       0.617ms    0.601ms    1.020ms    0.3%, mostly the first request …",
+                "TTFB:          202.202ms  198.040ms  402.042ms  99.5%",
+            ],
+        );
+    }
+
+    #[test]
+    fn lays_out_at_60_columns() {
+        assert_layout(
+            Some(60),
+            &[
+                "Benchmarking https://api.exa…5/transactions (HTTP/2) with 32 concurrency, 10000 total requests",
+                "Requests/sec:  1,234.56",
+                "Remote:        10.0.0.1 ×2, 10.0.0.2 ×1, 10.0.0.3 ×1, 2001:…",
+                "Connection errors: 3",
+                "Note:          header sizes are before HPACK/QPACK",
+                "               compression, which the transport doesn't",
+                "               report",
+                "Phase          Avg        P50        P99        Share",
+                "Connect:       0.617ms    0.601ms    1.020ms    0.3%, mostly the first request per connection",
+                "TTFB:          202.202ms  198.040ms  402.042ms  99.5%",
+            ],
+        );
+    }
+
+    #[test]
+    fn lays_out_at_40_columns() {
+        assert_layout(
+            Some(40),
+            &[
+                "Benchmarking https://ap…nsactions (HTTP/2) with 32 concurrency, 10000 total requests",
+                "Requests/sec:  1,234.56",
+                "Remote:        10.0.0.1 ×2, 10.0.0.2 ×1…",
+                "Connection errors: 3",
+                "Note:          header sizes are before",
+                "               HPACK/QPACK compression,",
+                "               which the transport",
+                "               doesn't report",
+                "Phase          Avg        P50        P99        Share",
+                "Connect:       0.617ms    0.601ms    1.020ms    0.3%, mostly the first request per connection",
+                "TTFB:          202.202ms  198.040ms  402.042ms  99.5%",
+            ],
+        );
+    }
+
+    #[test]
+    fn colors_take_no_columns() {
+        let value = "\x1b[32m10.0.0.1 ×2, 10.0.0.2 ×1, 10.0.0.3 ×1\x1b[0m";
+        assert_eq!(line_for(Some(80), "Remote:", value), format!("Remote:        {}", value));
+        assert_eq!(
+            line_for(Some(40), "Remote:", value),
+            "Remote:        \x1b[32m10.0.0.1 ×2, 10.0.0.2 ×1…\x1b[0m"
+        );
+    }
+}
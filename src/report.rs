@@ -0,0 +1,516 @@
+//! `benchy report`: percentiles and CDF tables from the `latency_histogram` in a
+//! `--save-results` file, at any percentile rather than only those a run prints.
+//!
+//! `--from-request-log` rebuilds the results from a `--request-log` instead, over any
+//! part of the run and split any way, in any `--output` format.
+
+use crate::errors::ErrorCounts;
+use crate::headers::{self, LatencyGroups};
+use crate::render;
+use crate::requestlog::Record;
+use crate::results::{self, SavedRun};
+use crate::output::OutputFormat;
+use crate::sinks::{self, BenchReport, OutputSpec, SinkKind, Sinks};
+use crate::textreport::TextDetails;
+use crate::units::LatencyUnit;
+use crate::Percentiles;
+use clap::{CommandFactory, Parser, ValueEnum};
+use colored::Colorize;
+use hdrhistogram::Histogram;
+use serde_json::Value;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Percentiles printed when none are asked for
+const DEFAULT_PERCENTILES: [f64; 7] = [50.0, 90.0, 95.0, 99.0, 99.9, 99.99, 100.0];
+
+#[derive(Parser)]
+#[command(
+    name = "benchy report",
+    bin_name = "benchy report",
+    about = "Query the latency histogram in a --save-results or benchy merge file, or the requests in a --request-log"
+)]
+struct ReportArgs {
+    /// Results file written by --save-results or benchy merge -o
+    #[arg(value_name = "FILE", required_unless_present = "from_request_log")]
+    file: Option<PathBuf>,
+
+    /// Rebuild the results from a log written by --request-log
+    #[arg(long = "from-request-log", value_name = "LOG")]
+    from_request_log: Option<PathBuf>,
+
+    /// Only requests sent from then on: an offset into the run, e.g. 30s, or a time,
+    /// e.g. 2026-10-15T09:30:00Z
+    #[arg(long, value_name = "TIME", value_parser = parse_bound, requires = "from_request_log")]
+    from: Option<Bound>,
+
+    /// Only requests sent before then, as --from
+    #[arg(long, value_name = "TIME", value_parser = parse_bound, requires = "from_request_log")]
+    to: Option<Bound>,
+
+    /// Also report latency percentiles separately for each value of this
+    #[arg(long = "group-by", value_enum, requires = "from_request_log")]
+    group_by: Option<GroupBy>,
+
+    /// Write the results as SINK (human, plain, json, csv, markdown, influx, table), to
+    /// stdout or to PATH; repeatable. Text by default
+    #[arg(long = "output", value_name = "SINK[=PATH]", value_parser = OutputSpec::parse, requires = "from_request_log")]
+    output: Vec<OutputSpec>,
+
+    /// Print the latency at this percentile, e.g. 99.95; repeatable
+    #[arg(short = 'p', long = "percentile", value_name = "P", value_parser = parse_percentile)]
+    percentiles: Vec<f64>,
+
+    /// Also print the full CDF: value, percentile and count at each step
+    #[arg(long)]
+    cdf: bool,
+
+    /// CDF steps per halving of the distance to 100%
+    #[arg(long = "cdf-ticks", value_name = "N", default_value_t = 5, requires = "cdf")]
+    cdf_ticks: u32,
+
+    /// Unit for latencies; `auto` picks one from the median
+    #[arg(long = "latency-unit", value_enum, default_value_t = LatencyUnit::Auto)]
+    latency_unit: LatencyUnit,
+}
+
+/// What `--group-by` splits a request log's latencies by
+#[derive(Clone, Copy, ValueEnum)]
+enum GroupBy {
+    /// The response status, or the error when none arrived
+    Status,
+    Url,
+    /// The value of the --group-by-header the run logged
+    Header,
+    /// The --data-dir file sent as the body
+    BodyFile,
+}
+
+/// Either end of `--from` and `--to`
+#[derive(Clone, Copy)]
+enum Bound {
+    /// Into the run
+    Offset(Duration),
+    At(SystemTime),
+}
+
+impl Bound {
+    /// `record` was sent at or after this
+    fn reached(self, record: &Record) -> bool {
+        match self {
+            Bound::Offset(offset) => record.offset >= offset,
+            Bound::At(at) => record.sent >= at,
+        }
+    }
+}
+
+fn parse_bound(s: &str) -> Result<Bound, String> {
+    if let Ok(offset) = humantime::parse_duration(s) {
+        return Ok(Bound::Offset(offset));
+    }
+    humantime::parse_rfc3339_weak(s).map(Bound::At).map_err(|_| {
+        format!("expected an offset into the run, e.g. 30s, or a time, e.g. 2026-10-15T09:30:00Z, not {:?}", s)
+    })
+}
+
+fn parse_percentile(s: &str) -> Result<f64, String> {
+    let p: f64 = s.trim_end_matches('%').parse().map_err(|_| format!("invalid percentile {:?}", s))?;
+    if !(0.0..=100.0).contains(&p) {
+        return Err(format!("percentile {} is not between 0 and 100", p));
+    }
+    Ok(p)
+}
+
+/// e.g. "P99.95:", "P50:"
+fn label(percentile: f64) -> String {
+    format!("P{}:", percentile)
+}
+
+pub fn run() -> Result<(), Box<dyn Error>> {
+    // Skips `report` itself, which clap would take for the file
+    let args = ReportArgs::parse_from(std::env::args_os().skip(1));
+    match (&args.from_request_log, &args.file) {
+        // Checked here rather than by clap, which would then waive the flags that
+        // require --from-request-log when FILE is given
+        (Some(_), Some(_)) => ReportArgs::command()
+            .error(clap::error::ErrorKind::ArgumentConflict, "FILE can't be used with --from-request-log")
+            .exit(),
+        (Some(log), None) => from_request_log(&args, log),
+        (None, Some(file)) => from_results(&args, file),
+        (None, None) => unreachable!("clap requires FILE without --from-request-log"),
+    }
+}
+
+/// The latency at each percentile asked for, and the average
+fn print_latencies(args: &ReportArgs, histogram: &Histogram<u64>, unit: LatencyUnit) {
+    let percentiles = if args.percentiles.is_empty() {
+        &DEFAULT_PERCENTILES[..]
+    } else {
+        &args.percentiles[..]
+    };
+    for &p in percentiles {
+        render::print(label(p).white(), at(histogram, p / 100.0, unit).green());
+    }
+    render::print("Avg:".white(), unit.format(Duration::from_nanos(histogram.mean().round() as u64)));
+}
+
+fn from_results(args: &ReportArgs, path: &Path) -> Result<(), Box<dyn Error>> {
+    let text = std::fs::read(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let value: Value = serde_json::from_slice(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let encoded = value
+        .get("latency_histogram")
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("{}: no latency_histogram, save it again with this benchy", path.display()))?;
+    let histogram = results::decode_histogram(encoded).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if histogram.is_empty() {
+        return Err(format!("{}: no latencies were recorded", path.display()).into());
+    }
+
+    let unit = args
+        .latency_unit
+        .resolve(Duration::from_nanos(histogram.value_at_quantile(0.5)));
+    println!(
+        "{} {} ({} latencies)",
+        "Report".cyan().bold(),
+        value.get("url").and_then(Value::as_str).unwrap_or("?").yellow(),
+        histogram.len().to_string().green()
+    );
+    print_latencies(args, &histogram, unit);
+
+    if args.cdf {
+        print_cdf(&histogram, args.cdf_ticks, unit);
+    }
+    Ok(())
+}
+
+/// A request log's requests between `--from` and `--to`, as one run's results
+struct Replay {
+    run: SavedRun,
+    /// What `groups` are by, with `--group-by`
+    grouped_by: Option<String>,
+    groups: LatencyGroups,
+    /// Lines that weren't a request, skipped
+    malformed: u64,
+}
+
+/// Stream `log`, keeping the requests sent in `from..to`
+fn replay(log: impl BufRead, from: Option<Bound>, to: Option<Bound>, group_by: Option<GroupBy>) -> io::Result<Replay> {
+    let errors = ErrorCounts::default();
+    let mut run = SavedRun::default();
+    let mut grouped_by = group_by.map(|by| match by {
+        GroupBy::Status => "status".to_string(),
+        GroupBy::Url => "url".to_string(),
+        // Named after the header once a line has it
+        GroupBy::Header => "header".to_string(),
+        GroupBy::BodyFile => "body file".to_string(),
+    });
+    let mut groups = LatencyGroups::default();
+    let mut malformed = 0;
+    let (mut url, mut version): (Option<String>, Option<String>) = (None, None);
+    let (mut first_sent, mut last_done): (Option<SystemTime>, Option<SystemTime>) = (None, None);
+    for line in log.split(b'\n') {
+        let line = line?;
+        if line.trim_ascii().is_empty() {
+            continue;
+        }
+        let Some(record) = Record::parse(&line) else {
+            malformed += 1;
+            continue;
+        };
+        if from.is_some_and(|from| !from.reached(&record)) || to.is_some_and(|to| to.reached(&record)) {
+            continue;
+        }
+
+        if record.ok {
+            run.success += 1;
+        } else {
+            run.failed += 1;
+        }
+        if let Some(kind) = record.error {
+            errors.add(kind, 1);
+        }
+        run.latencies.push(record.latency);
+        run.ttfbs.push(record.ttfb);
+        first_sent = Some(first_sent.map_or(record.sent, |first| first.min(record.sent)));
+        last_done = last_done.max(Some(record.sent + record.latency));
+        same(&mut url, &record.url, "(mixed)");
+        if let Some(this) = &record.version {
+            same(&mut version, this, "mixed");
+        }
+
+        let group = match group_by {
+            Some(GroupBy::Status) => record
+                .status
+                .map(|status| status.to_string())
+                .or_else(|| record.error.map(|kind| kind.label().to_string())),
+            Some(GroupBy::Url) => Some(record.url),
+            Some(GroupBy::Header) => {
+                if let (Some((name, _)), Some(grouped_by)) = (&record.header, &mut grouped_by) {
+                    grouped_by.clone_from(name);
+                }
+                record.header.map(|(_, value)| value)
+            }
+            Some(GroupBy::BodyFile) => record.body_file,
+            None => continue,
+        };
+        groups.record(group.unwrap_or_else(|| "(none)".to_string()), record.latency);
+    }
+
+    run.url = url.unwrap_or_default();
+    run.proto = version.unwrap_or_else(|| "?".to_string());
+    run.requested = run.latencies.len() as u64;
+    run.errors = errors;
+    if let (Some(first), Some(last)) = (first_sent, last_done) {
+        let started = first.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        run.spans = vec![(started, last.duration_since(first).unwrap_or_default())];
+    }
+    Ok(Replay { run, grouped_by, groups, malformed })
+}
+
+/// Keep `seen` while every value is `this`, and `mixed` once one isn't
+fn same(seen: &mut Option<String>, this: &str, mixed: &str) {
+    match seen {
+        Some(seen) if seen != this => *seen = mixed.to_string(),
+        Some(_) => {}
+        None => *seen = Some(this.to_string()),
+    }
+}
+
+fn from_request_log(args: &ReportArgs, path: &Path) -> Result<(), Box<dyn Error>> {
+    let problems = sinks::problems(&args.output);
+    if !problems.is_empty() {
+        return Err(problems.join("\n").into());
+    }
+    if args.output.iter().any(|output| output.kind == SinkKind::Plain) {
+        colored::control::set_override(false);
+    }
+
+    let log = File::open(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let Replay { mut run, grouped_by, groups, malformed } =
+        replay(BufReader::new(log), args.from, args.to, args.group_by)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    if malformed > 0 {
+        eprintln!("{} skipped {} malformed lines of {}", "Warning:".yellow(), malformed, path.display());
+    }
+    if run.latencies.is_empty() {
+        let window = if args.from.is_some() || args.to.is_some() { " between --from and --to" } else { "" };
+        return Err(format!("{}: no requests{}", path.display(), window).into());
+    }
+
+    let histogram = results::histogram(&run.latencies);
+    let latency = Percentiles::from_unsorted(&mut run.latencies);
+    let ttfb = Percentiles::from_unsorted(&mut run.ttfbs);
+    let unit = args.latency_unit.resolve(latency.p50);
+    let group_rows = groups.percentiles();
+
+    let mut sinks = Sinks::open(&sinks::specs(&args.output, OutputFormat::Text), &run.url, false)?;
+    sinks.render(&BenchReport {
+        summary: &run.summary(&latency, &ttfb),
+        evaluations: &[],
+        unit,
+        latencies: &run.latencies,
+        ttfbs: &run.ttfbs,
+        phases: None,
+        groups: grouped_by.as_deref().map(|by| (by, &group_rows[..])),
+        script_metrics: &run.script_metrics,
+        rps_breach: None,
+        utilization: &[],
+        protocols: &[],
+        text: TextDetails::Replayed(&LogText {
+            args,
+            path,
+            histogram: &histogram,
+            malformed,
+        }),
+    });
+    Ok(())
+}
+
+/// What the text report of a replayed log shows besides the results
+pub struct LogText<'a> {
+    args: &'a ReportArgs,
+    path: &'a Path,
+    histogram: &'a Histogram<u64>,
+    /// Lines skipped as not what `--request-log` writes
+    malformed: u64,
+}
+
+/// The text report of a replayed log
+pub fn print_log(report: &BenchReport, log: &LogText) {
+    let (summary, unit) = (report.summary, report.unit);
+    println!(
+        "{} {} ({} requests from {})",
+        "Report".cyan().bold(),
+        summary.url.yellow(),
+        summary.reqs.to_string().green(),
+        log.path.display()
+    );
+    if log.malformed > 0 {
+        render::print("Skipped:".white(), format!("{} malformed lines", log.malformed).yellow());
+    }
+    print_latencies(log.args, log.histogram, unit);
+    render::print("Requests/sec:".white(), format!("{:.2}", summary.rps).green().bold());
+    render::print("Success:".white(), summary.success.to_string().green());
+    if summary.failed > 0 {
+        render::print("Failed:".white(), summary.failed.to_string().red().bold());
+    } else {
+        render::print("Failed:".white(), "0".dimmed());
+    }
+    summary.errors.print();
+    if let Some((by, rows)) = report.groups {
+        headers::print_groups(by, rows, unit);
+    }
+    if log.args.cdf {
+        print_cdf(log.histogram, log.args.cdf_ticks, unit);
+    }
+}
+
+fn at(histogram: &Histogram<u64>, quantile: f64, unit: LatencyUnit) -> String {
+    unit.format(Duration::from_nanos(histogram.value_at_quantile(quantile)))
+}
+
+/// HdrHistogram's percentile distribution: each row is the latency that many requests
+/// finished within
+fn print_cdf(histogram: &Histogram<u64>, ticks: u32, unit: LatencyUnit) {
+    println!("\n{}", "--- Latency CDF ---".cyan().bold());
+    println!("{:>12} {:>12} {:>12} {:>14}", "Value", "Percentile", "Count", "1/(1-P)");
+    let mut count = 0;
+    for step in histogram.iter_quantiles(ticks.max(1)) {
+        count += step.count_since_last_iteration();
+        let quantile = step.quantile_iterated_to();
+        let inverse = if quantile < 1.0 {
+            format!("{:.2}", 1.0 / (1.0 - quantile))
+        } else {
+            "inf".to_string()
+        };
+        println!(
+            "{:>12} {:>12} {:>12} {:>14}",
+            unit.format(Duration::from_nanos(step.value_iterated_to())),
+            format!("{:.6}", quantile * 100.0),
+            count,
+            inverse
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorKind;
+    use std::io::Cursor;
+
+    /// A `--request-log` line, `offset_ms` into a run that started at 1,700,000,000s
+    fn line(offset_ms: u64, url: &str, status: Option<u16>, latency_ms: u64, backend: &str) -> String {
+        let sent = UNIX_EPOCH + Duration::from_secs(1_700_000_000) + Duration::from_millis(offset_ms);
+        serde_json::json!({
+            "sent": crate::clock::rfc3339(sent),
+            "offset_s": offset_ms as f64 / 1000.0,
+            "url": url,
+            "version": status.map(|_| "HTTP/2.0"),
+            "status": status,
+            "error": if status.is_none() { Some("timeout") } else { None },
+            "ok": status == Some(200),
+            "latency_us": latency_ms as f64 * 1000.0,
+            "ttfb_us": latency_ms as f64 * 500.0,
+            "worker": 0,
+            "request_id": null,
+            "body_file": format!("{}.json", backend),
+            "headers": { "x-backend": backend },
+        })
+        .to_string()
+    }
+
+    fn log() -> String {
+        [
+            line(0, "http://a/x", Some(200), 10, "a"),
+            "{\"sent\": truncated".to_string(),
+            line(1000, "http://a/y", Some(503), 20, "b"),
+            line(2000, "http://a/x", None, 5000, "a"),
+            "not json at all".to_string(),
+            String::new(),
+            line(3000, "http://a/x", Some(200), 30, "b"),
+        ]
+        .join("\n")
+    }
+
+    fn rows(replay: Replay) -> Vec<(String, usize)> {
+        replay.groups.percentiles().into_iter().map(|(value, p)| (value, p.count)).collect()
+    }
+
+    #[test]
+    fn malformed_lines_are_counted_and_skipped() {
+        let replay = replay(Cursor::new(log()), None, None, None).unwrap();
+        assert_eq!(replay.malformed, 2);
+        let run = &replay.run;
+        assert_eq!((run.requested, run.success, run.failed), (4, 2, 2));
+        assert_eq!(run.errors.get(ErrorKind::Timeout), 1);
+        assert_eq!((run.url.as_str(), run.proto.as_str()), ("(mixed)", "HTTP/2.0"));
+        // From the first request sent to the last one done: the timeout at 2s + 5s
+        assert_eq!(run.spans, vec![(1_700_000_000_000, Duration::from_secs(7))]);
+        assert_eq!(run.ttfbs[1], Duration::from_millis(10));
+        assert!(replay.grouped_by.is_none());
+    }
+
+    #[test]
+    fn from_and_to_keep_the_requests_sent_between() {
+        let offsets = replay(
+            Cursor::new(log()),
+            Some(parse_bound("1s").unwrap()),
+            Some(parse_bound("3s").unwrap()),
+            None,
+        )
+        .unwrap();
+        assert_eq!(offsets.run.latencies, [Duration::from_millis(20), Duration::from_secs(5)]);
+
+        let times = replay(
+            Cursor::new(log()),
+            Some(parse_bound("2023-11-14T22:13:22Z").unwrap()),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(times.run.latencies, [Duration::from_secs(5), Duration::from_millis(30)]);
+        assert!(parse_bound("yesterday").is_err());
+    }
+
+    #[test]
+    fn from_is_inclusive_and_to_exclusive() {
+        let sent = |from: &str, to: &str| {
+            let (from, to) = (parse_bound(from).unwrap(), parse_bound(to).unwrap());
+            replay(Cursor::new(log()), Some(from), Some(to), None).unwrap().run.latencies
+        };
+        // Requests were sent at exactly 0s, 1s, 2s and 3s into the run
+        assert_eq!(sent("1s", "2s"), [Duration::from_millis(20)]);
+        assert_eq!(sent("1s", "1s"), []);
+        assert_eq!(sent("3s", "1h"), [Duration::from_millis(30)]);
+        assert_eq!(sent("0s", "3s").len(), 3);
+        // The same at the times they were sent
+        assert_eq!(sent("2023-11-14T22:13:21Z", "2023-11-14T22:13:22Z"), [Duration::from_millis(20)]);
+        assert_eq!(sent("2023-11-14T22:13:21Z", "2023-11-14T22:13:21Z"), []);
+        assert_eq!(sent("2023-11-14T22:13:23Z", "2023-11-14T22:13:24Z"), [Duration::from_millis(30)]);
+    }
+
+    #[test]
+    fn grouped_by_status_url_or_header() {
+        let by_status = replay(Cursor::new(log()), None, None, Some(GroupBy::Status)).unwrap();
+        assert_eq!(by_status.grouped_by.as_deref(), Some("status"));
+        assert_eq!(
+            rows(by_status),
+            [("200".to_string(), 2), ("503".to_string(), 1), ("timeout".to_string(), 1)]
+        );
+
+        let by_url = replay(Cursor::new(log()), None, None, Some(GroupBy::Url)).unwrap();
+        assert_eq!(rows(by_url), [("http://a/x".to_string(), 3), ("http://a/y".to_string(), 1)]);
+
+        let by_header = replay(Cursor::new(log()), None, None, Some(GroupBy::Header)).unwrap();
+        assert_eq!(by_header.grouped_by.as_deref(), Some("x-backend"));
+        assert_eq!(rows(by_header), [("a".to_string(), 2), ("b".to_string(), 2)]);
+
+        let by_body = replay(Cursor::new(log()), None, None, Some(GroupBy::BodyFile)).unwrap();
+        assert_eq!(by_body.grouped_by.as_deref(), Some("body file"));
+        assert_eq!(rows(by_body), [("a.json".to_string(), 2), ("b.json".to_string(), 2)]);
+    }
+}
@@ -0,0 +1,112 @@
+//! `--request-id-header`: a unique ULID on every request, checked against the server's
+//! echo of it, to catch requests that a proxy or at-least-once gateway duplicated,
+//! dropped or mixed up on the way.
+//!
+//! Each worker makes its own ids, so generating one takes no lock. Ids are monotonic
+//! within a worker: a second id in the same millisecond increments the random part of
+//! the previous one instead of drawing a new one.
+
+use crate::render;
+use crate::thousands;
+use colored::Colorize;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Crockford's base32, as ULIDs are written
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Bits of randomness below the 48-bit millisecond timestamp
+const RANDOM_BITS: u32 = 80;
+
+/// One worker's ULIDs
+pub struct Ulids {
+    rng: SmallRng,
+    millis: u64,
+    random: u128,
+}
+
+impl Ulids {
+    /// Seeded from the OS rather than `--seed`: ids from a repeated run must not
+    /// collide with the first run's in the server's logs
+    pub fn new() -> Self {
+        Ulids {
+            rng: SmallRng::from_entropy(),
+            millis: 0,
+            random: 0,
+        }
+    }
+
+    pub fn next(&mut self) -> HeaderValue {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        if now > self.millis {
+            self.millis = now;
+            self.random = self.rng.gen::<u128>() >> (128 - RANDOM_BITS);
+        } else {
+            // Same millisecond, or the clock stepped back: stay ahead of the last id
+            self.random += 1;
+            if self.random >> RANDOM_BITS != 0 {
+                self.millis += 1;
+                self.random = 0;
+            }
+        }
+
+        let value = (self.millis as u128) << RANDOM_BITS | self.random;
+        let mut text = [0u8; 26];
+        for (i, c) in text.iter_mut().enumerate() {
+            *c = ALPHABET[(value >> (125 - 5 * i)) as usize & 31];
+        }
+        HeaderValue::from_bytes(&text).expect("base32 is a valid header value")
+    }
+}
+
+/// What came back of the ids sent
+#[derive(Default)]
+pub struct EchoStats {
+    /// One per request sent, each a different id
+    pub sent: AtomicU64,
+    /// Responses with the header set to the request's id
+    echoed: AtomicU64,
+    /// Responses without the header
+    missing: AtomicU64,
+    /// Responses with the header set to something else, such as another request's id
+    mismatched: AtomicU64,
+}
+
+impl EchoStats {
+    /// Check a response to the request sent with `id`
+    pub fn check(&self, name: &HeaderName, id: &HeaderValue, headers: &HeaderMap) {
+        let counter = match headers.get(name) {
+            Some(echo) if echo == id => &self.echoed,
+            Some(_) => &self.mismatched,
+            None => &self.missing,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn print(&self, name: &HeaderName) {
+        let sent = format!("{} unique sent", thousands(self.sent.load(Ordering::Relaxed)));
+        let echoed = self.echoed.load(Ordering::Relaxed);
+        let missing = self.missing.load(Ordering::Relaxed);
+        let mismatched = self.mismatched.load(Ordering::Relaxed);
+        if echoed == 0 && mismatched == 0 {
+            let note = format!("the server didn't echo {}", name);
+            render::print("Request IDs:".white(), format!("{}, {}", sent, note.dimmed()));
+            return;
+        }
+        let mismatches = format!("{} mismatched", thousands(mismatched));
+        let not_echoed = format!("{} not echoed", thousands(missing));
+        render::print(
+            "Request IDs:".white(),
+            format!(
+                "{}, {} echoed, {}, {}",
+                sent,
+                thousands(echoed).green(),
+                if mismatched > 0 { mismatches.red().bold() } else { mismatches.normal() },
+                if missing > 0 { not_echoed.yellow() } else { not_echoed.normal() }
+            ),
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//! `--request-log PATH`: a JSON line per completed request, so a run's requests can be
+//! sliced again after it's over; `benchy report --from-request-log` reads them back.
+
+use crate::clock;
+use crate::errors::ErrorKind;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Writes a line per request to `--request-log`; owned by the collector
+pub struct RequestLog(BufWriter<File>);
+
+/// A completed request, as logged
+pub struct Entry<'a> {
+    /// When the request was sent
+    pub sent: SystemTime,
+    /// How long after the run started it was sent
+    pub offset: Duration,
+    pub url: &'a str,
+    /// `None` when no response arrived
+    pub version: Option<reqwest::Version>,
+    pub status: Option<u16>,
+    /// Why no response arrived
+    pub error: Option<ErrorKind>,
+    /// Counted as a success: a response that passed every check
+    pub ok: bool,
+    pub latency: Duration,
+    pub ttfb: Duration,
+    pub worker: usize,
+    /// `--request-id-header` value sent
+    pub request_id: Option<&'a str>,
+    /// `--data-dir` file sent as the body
+    pub body_file: Option<&'a str>,
+    /// `--group-by-header` and the response's value of it
+    pub group: Option<(&'a str, &'a str)>,
+}
+
+impl Entry<'_> {
+    pub fn to_json(&self) -> Value {
+        let headers: serde_json::Map<String, Value> = self
+            .group
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value.into()))
+            .collect();
+        json!({
+            "sent": clock::rfc3339(self.sent),
+            "offset_s": self.offset.as_secs_f64(),
+            "url": self.url,
+            "version": self.version.map(|version| format!("{:?}", version)),
+            "status": self.status,
+            "error": self.error.map(ErrorKind::label),
+            "ok": self.ok,
+            "latency_us": micros(self.latency),
+            "ttfb_us": micros(self.ttfb),
+            "worker": self.worker,
+            "request_id": self.request_id,
+            "body_file": self.body_file,
+            "headers": headers,
+        })
+    }
+}
+
+/// A line of a request log, read back
+pub struct Record {
+    pub sent: SystemTime,
+    pub offset: Duration,
+    pub url: String,
+    /// e.g. "HTTP/2.0"; `None` when no response arrived
+    pub version: Option<String>,
+    pub status: Option<u16>,
+    pub error: Option<ErrorKind>,
+    pub ok: bool,
+    pub latency: Duration,
+    pub ttfb: Duration,
+    /// `--data-dir` file sent as the body
+    pub body_file: Option<String>,
+    /// The `--group-by-header` header and its value, if the run logged one
+    pub header: Option<(String, String)>,
+}
+
+impl Record {
+    /// `None` for anything but a line `--request-log` writes
+    pub fn parse(line: &[u8]) -> Option<Record> {
+        let value: Value = serde_json::from_slice(line).ok()?;
+        let micros = |key: &str| Duration::try_from_secs_f64(value.get(key)?.as_f64()? / 1e6).ok();
+        let nullable = |key: &str| value.get(key).map(|v| (!v.is_null()).then_some(v));
+        let error = match nullable("error")? {
+            Some(label) => {
+                let label = label.as_str()?;
+                Some(ErrorKind::ALL.into_iter().find(|kind| kind.label() == label)?)
+            }
+            None => None,
+        };
+        let version = match nullable("version")? {
+            Some(version) => Some(version.as_str()?.to_string()),
+            None => None,
+        };
+        let status = match nullable("status")? {
+            Some(status) => Some(u16::try_from(status.as_u64()?).ok()?),
+            None => None,
+        };
+        // Absent from logs written before --data-dir files were logged
+        let body_file = match value.get("body_file").filter(|v| !v.is_null()) {
+            Some(name) => Some(name.as_str()?.to_string()),
+            None => None,
+        };
+        let header = match value.get("headers")?.as_object()?.iter().next() {
+            Some((name, v)) => Some((name.clone(), v.as_str()?.to_string())),
+            None => None,
+        };
+        Some(Record {
+            sent: humantime::parse_rfc3339(value.get("sent")?.as_str()?).ok()?,
+            offset: Duration::try_from_secs_f64(value.get("offset_s")?.as_f64()?).ok()?,
+            url: value.get("url")?.as_str()?.to_string(),
+            version,
+            status,
+            error,
+            ok: value.get("ok")?.as_bool()?,
+            latency: micros("latency_us")?,
+            ttfb: micros("ttfb_us")?,
+            body_file,
+            header,
+        })
+    }
+}
+
+impl RequestLog {
+    /// Create the file up front so a bad path fails before the run starts
+    pub fn create(path: &Path) -> io::Result<RequestLog> {
+        Ok(RequestLog(BufWriter::new(File::create(path)?)))
+    }
+
+    pub fn write(&mut self, entry: &Entry) -> io::Result<()> {
+        serde_json::to_writer(&mut self.0, &entry.to_json())?;
+        writeln!(self.0)
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+fn micros(d: Duration) -> f64 {
+    d.as_nanos() as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn a_line_per_request() {
+        let path = std::env::temp_dir().join(format!("benchy-request-log-{}.jsonl", std::process::id()));
+        let mut log = RequestLog::create(&path).unwrap();
+        let sent = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        log.write(&Entry {
+            sent,
+            offset: Duration::from_millis(1500),
+            url: "http://localhost:8080/a",
+            version: Some(reqwest::Version::HTTP_2),
+            status: Some(503),
+            error: None,
+            ok: false,
+            latency: Duration::from_micros(2500),
+            ttfb: Duration::from_micros(2000),
+            worker: 3,
+            request_id: Some("01HZX"),
+            body_file: Some("order-17.json"),
+            group: Some(("x-backend", "b")),
+        })
+        .unwrap();
+        log.write(&Entry {
+            sent: sent + Duration::from_secs(1),
+            offset: Duration::from_millis(2500),
+            url: "http://localhost:8080/b",
+            version: None,
+            status: None,
+            error: Some(ErrorKind::Timeout),
+            ok: false,
+            latency: Duration::from_secs(5),
+            ttfb: Duration::ZERO,
+            worker: 0,
+            request_id: None,
+            body_file: None,
+            group: None,
+        })
+        .unwrap();
+        log.finish().unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let lines: Vec<Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["sent"], "2023-11-14T22:13:20.000000Z");
+        assert_eq!(lines[0]["offset_s"], 1.5);
+        assert_eq!(lines[0]["version"], "HTTP/2.0");
+        assert_eq!(lines[0]["status"], 503);
+        assert_eq!(lines[0]["latency_us"], 2500.0);
+        assert_eq!(lines[0]["body_file"], "order-17.json");
+        assert_eq!(lines[0]["headers"]["x-backend"], "b");
+        assert_eq!(lines[1]["status"], Value::Null);
+        assert_eq!(lines[1]["error"], "timeout");
+        assert_eq!(lines[1]["request_id"], Value::Null);
+        assert_eq!(lines[1]["body_file"], Value::Null);
+        assert_eq!(lines[1]["headers"], json!({}));
+
+        let records: Vec<Record> = text.lines().map(|line| Record::parse(line.as_bytes()).unwrap()).collect();
+        assert_eq!(records[0].sent, sent);
+        assert_eq!(records[0].offset, Duration::from_millis(1500));
+        assert_eq!(records[0].version.as_deref(), Some("HTTP/2.0"));
+        assert_eq!((records[0].status, records[0].ok), (Some(503), false));
+        assert_eq!((records[0].latency, records[0].ttfb), (Duration::from_micros(2500), Duration::from_micros(2000)));
+        assert_eq!(records[0].body_file.as_deref(), Some("order-17.json"));
+        assert_eq!(records[0].header, Some(("x-backend".to_string(), "b".to_string())));
+        assert_eq!(records[1].url, "http://localhost:8080/b");
+        assert_eq!((records[1].status, records[1].error), (None, Some(ErrorKind::Timeout)));
+        assert_eq!((records[1].body_file.as_deref(), records[1].header.as_ref()), (None, None));
+    }
+
+    #[test]
+    fn malformed_lines_are_refused() {
+        let good = r#"{"sent":"2023-11-14T22:13:20.000000Z","offset_s":0.5,"url":"http://a/","version":null,"status":200,"error":null,"ok":true,"latency_us":10.0,"ttfb_us":5.0,"worker":0,"request_id":null,"headers":{}}"#;
+        assert!(Record::parse(good.as_bytes()).is_some());
+        for bad in [
+            "",
+            "not json",
+            "{}",
+            &good[..good.len() - 1],
+            &good.replace(r#""status":200"#, r#""status":"200""#),
+            &good.replace(r#""status":200"#, r#""status":70000"#),
+            &good.replace(r#""error":null"#, r#""error":"gremlins""#),
+            &good.replace("2023-11-14T22:13:20.000000Z", "yesterday"),
+            &good.replace(r#""latency_us":10.0"#, r#""latency_us":-1.0"#),
+            &good.replace(r#""headers":{}"#, r#""headers":{"x-backend":1}"#),
+            &good.replace(r#""request_id":null"#, r#""request_id":null,"body_file":7"#),
+        ] {
+            assert!(Record::parse(bad.as_bytes()).is_none(), "{}", bad);
+        }
+    }
+}
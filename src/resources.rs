@@ -0,0 +1,160 @@
+//! benchy's own CPU and memory use, so a saturated client box doesn't pass for a slow
+//! server. Read from /proc on Linux and sampled on the throughput ticker; elsewhere
+//! nothing is reported.
+
+use colored::Colorize;
+use crate::render;
+use std::time::{Duration, Instant};
+
+/// Average CPU, as a share of all cores, above which the client is called saturated
+const SATURATED: f64 = 0.9;
+
+/// Clock ticks per second in /proc/self/stat. `USER_HZ` is part of the /proc ABI and
+/// 100 on every architecture Linux still ships.
+const USER_HZ: f64 = 100.0;
+
+/// One reading of the process's CPU time and resident memory
+#[derive(Clone, Copy)]
+struct Reading {
+    at: Instant,
+    cpu: Duration,
+    rss: u64,
+}
+
+impl Reading {
+    fn take() -> Option<Reading> {
+        let at = Instant::now();
+        // Fields 14 and 15, counted after the parenthesised command name, which may
+        // itself contain spaces
+        let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+        let mut fields = stat.get(stat.rfind(')')? + 2..)?.split(' ');
+        let utime: u64 = fields.nth(11)?.parse().ok()?;
+        let stime: u64 = fields.next()?.parse().ok()?;
+        let cpu = Duration::from_secs_f64((utime + stime) as f64 / USER_HZ);
+
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let kb: u64 = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))?
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse()
+            .ok()?;
+        Some(Reading { at, cpu, rss: kb * 1024 })
+    }
+}
+
+/// CPU and RSS over the run, one window per throughput tick
+pub struct ResourceUsage {
+    cores: usize,
+    start: Option<Reading>,
+    last: Option<Reading>,
+    /// CPU use in each window, as a share of all cores
+    cpu: Vec<f64>,
+    rss: Vec<u64>,
+}
+
+/// What `--output-format table` and markdown report
+#[derive(Clone, Copy, Debug)]
+pub struct ResourceSummary {
+    pub cores: usize,
+    /// Shares of all cores, 0 to 1
+    pub cpu_avg: f64,
+    pub cpu_peak: f64,
+    pub rss_avg: u64,
+    pub rss_peak: u64,
+}
+
+impl ResourceSummary {
+    /// e.g. "8 cores"
+    pub fn cores(&self) -> String {
+        format!("{} core{}", self.cores, if self.cores == 1 { "" } else { "s" })
+    }
+
+    pub fn saturated(&self) -> bool {
+        self.cpu_avg >= SATURATED
+    }
+
+    pub fn print(&self) {
+        render::print(
+            "Client CPU:".white(),
+            format!("avg {}, peak {} of {}", percent(self.cpu_avg), percent(self.cpu_peak), self.cores()),
+        );
+        render::print(
+            "Client RSS:".white(),
+            format!("avg {}, peak {}", mib(self.rss_avg).green(), mib(self.rss_peak).green()),
+        );
+        if self.saturated() {
+            render::print_wrapped(
+                "Warning:".yellow().bold(),
+                "client saturated — results may understate server capacity".red().bold(),
+            );
+        }
+    }
+}
+
+fn percent(share: f64) -> colored::ColoredString {
+    let text = format!("{:.1}%", share * 100.0);
+    if share >= SATURATED {
+        text.red().bold()
+    } else {
+        text.green()
+    }
+}
+
+pub fn mib(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+impl ResourceUsage {
+    pub fn start() -> Self {
+        let start = Reading::take();
+        ResourceUsage {
+            cores: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            start,
+            last: start,
+            cpu: Vec::new(),
+            rss: Vec::new(),
+        }
+    }
+
+    /// Close a window
+    pub fn tick(&mut self) {
+        let (Some(last), Some(now)) = (self.last, Reading::take()) else {
+            return;
+        };
+        let wall = now.at.saturating_duration_since(last.at).as_secs_f64();
+        if wall > 0.0 {
+            self.cpu.push(share(now.cpu.saturating_sub(last.cpu), wall, self.cores));
+        }
+        self.rss.push(now.rss);
+        self.last = Some(now);
+    }
+
+    /// Averages over the whole run, including the last partial window. `None` where
+    /// /proc can't be read.
+    pub fn finish(mut self) -> Option<ResourceSummary> {
+        let start = self.start?;
+        self.tick();
+        let end = self.last?;
+        let wall = end.at.saturating_duration_since(start.at).as_secs_f64();
+        if wall <= 0.0 {
+            return None;
+        }
+        let cpu_avg = share(end.cpu.saturating_sub(start.cpu), wall, self.cores);
+        Some(ResourceSummary {
+            cores: self.cores,
+            cpu_avg,
+            // Windows are noisy at the edges, but the peak can't be below the average
+            cpu_peak: self.cpu.iter().copied().fold(cpu_avg, f64::max),
+            rss_avg: self.rss.iter().sum::<u64>() / self.rss.len().max(1) as u64,
+            rss_peak: self.rss.iter().copied().max().unwrap_or(end.rss),
+        })
+    }
+}
+
+/// `cpu` spent over `wall` seconds, as a share of `cores`
+fn share(cpu: Duration, wall: f64, cores: usize) -> f64 {
+    cpu.as_secs_f64() / wall / cores as f64
+}
@@ -0,0 +1,516 @@
+//! A run's results as JSON, written with `--save-results` and by `--processes`
+//! children, and read back by `benchy merge` and the `--processes` parent.
+//!
+//! Latencies are kept raw rather than as percentiles, so merged percentiles are exact.
+//! They are also written as an HdrHistogram (`latency_histogram`, the compressed V2 log
+//! format in base64) for `benchy report` and other HdrHistogram tools, next to its
+//! percentiles (`latency`) for readers without histogram support.
+
+use crate::clock;
+use crate::errors::{ErrorCounts, ErrorKind};
+use crate::floor::Breach;
+use crate::limits;
+use crate::output::RunSummary;
+use crate::phases::Phases;
+use crate::render;
+use crate::resources::ResourceSummary;
+use crate::schedlag::SchedulerLag;
+use crate::script::{self, Metric, Metrics};
+use crate::units::LatencyUnit;
+use crate::workers;
+use crate::{print_percentiles, print_phases, thousands, Percentiles};
+use base64::Engine;
+use colored::Colorize;
+use hdrhistogram::serialization::{Deserializer, Serializer, V2DeflateSerializer};
+use hdrhistogram::Histogram;
+use serde_json::{json, Value};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Bumped when a field changes meaning, so old files are refused rather than misread
+const FORMAT_VERSION: u64 = 1;
+
+/// Significant digits kept by `latency_histogram`
+const HISTOGRAM_SIGFIG: u8 = 3;
+
+/// One run's results, or several merged
+#[derive(Default)]
+pub struct SavedRun {
+    pub url: String,
+    pub proto: String,
+    /// Every distinct `url (proto)` merged in; more than one makes a mixed-target report
+    pub targets: Vec<String>,
+    pub conns: usize,
+    pub pipeline: usize,
+    /// When each merged run started, in milliseconds since the Unix epoch, and how long
+    /// it was active
+    pub spans: Vec<(u64, Duration)>,
+    pub requested: u64,
+    pub success: u64,
+    pub failed: u64,
+    pub errors: ErrorCounts,
+    pub mismatches: u64,
+    pub stopped_early: bool,
+    pub latencies: Vec<Duration>,
+    pub ttfbs: Vec<Duration>,
+    pub resources: Option<ResourceSummary>,
+    /// The worst of the merged runs'
+    pub scheduler_lag: Option<SchedulerLag>,
+    /// What `--script` recorded with `metric`
+    pub script_metrics: Metrics,
+    /// Where `--abort-if-rps-below` stopped the run, with the throughput before it
+    pub rps_breach: Option<Breach>,
+    /// Each worker's share of the run with a request in flight, the merged runs' in turn
+    pub worker_utilization: Vec<f64>,
+    /// Where a single run's time went; not kept through a merge
+    pub phases: Option<Phases>,
+}
+
+impl SavedRun {
+    /// A run that ended just now after being active for `active`
+    pub fn new(
+        summary: &RunSummary,
+        requested: u64,
+        active: Duration,
+        latencies: &[Duration],
+        ttfbs: &[Duration],
+    ) -> Self {
+        let errors = ErrorCounts::default();
+        for kind in ErrorKind::ALL {
+            errors.add(kind, summary.errors.get(kind));
+        }
+        SavedRun {
+            url: summary.url.to_string(),
+            proto: summary.proto.to_string(),
+            targets: vec![target(summary.url, summary.proto)],
+            conns: summary.conns,
+            pipeline: summary.pipeline,
+            spans: vec![(unix_ms(summary.started), active)],
+            requested,
+            success: summary.success,
+            failed: summary.failed,
+            errors,
+            latencies: latencies.to_vec(),
+            ttfbs: ttfbs.to_vec(),
+            resources: summary.resources,
+            scheduler_lag: summary.scheduler_lag,
+            ..Default::default()
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let nanos = |samples: &[Duration]| samples.iter().map(|d| d.as_nanos() as u64).collect::<Vec<u64>>();
+        let errors: serde_json::Map<String, Value> = ErrorKind::ALL
+            .into_iter()
+            .map(|kind| (kind.label().to_string(), self.errors.get(kind).into()))
+            .collect();
+        let spans: Vec<Value> = self
+            .spans
+            .iter()
+            .map(|(start, active)| json!([start, active.as_nanos() as u64]))
+            .collect();
+        let histogram = histogram(&self.latencies);
+        json!({
+            "format": FORMAT_VERSION,
+            "url": self.url,
+            "proto": self.proto,
+            "targets": self.targets,
+            "conns": self.conns,
+            "pipeline": self.pipeline,
+            "spans": spans,
+            "requested": self.requested,
+            "success": self.success,
+            "failed": self.failed,
+            "errors": errors,
+            "mismatches": self.mismatches,
+            "stopped_early": self.stopped_early,
+            "latencies_ns": nanos(&self.latencies),
+            "ttfbs_ns": nanos(&self.ttfbs),
+            "latency_histogram": encode_histogram(&histogram),
+            "latency": {
+                "count": histogram.len(),
+                "avg_ns": histogram.mean().round() as u64,
+                "p50_ns": histogram.value_at_quantile(0.50),
+                "p95_ns": histogram.value_at_quantile(0.95),
+                "p99_ns": histogram.value_at_quantile(0.99),
+                "max_ns": histogram.max(),
+            },
+            "resources": self.resources.map(|r| json!({
+                "cores": r.cores,
+                "cpu_avg": r.cpu_avg,
+                "cpu_peak": r.cpu_peak,
+                "rss_avg": r.rss_avg,
+                "rss_peak": r.rss_peak,
+            })),
+            "scheduler_lag": self.scheduler_lag.map(|lag| lag.to_json()),
+            "script_metrics": script::to_json(&self.script_metrics),
+            "rps_breach": self.rps_breach.as_ref().map(Breach::to_json),
+            "worker_utilization": self.worker_utilization,
+            "phases": self.phases.as_ref().map(Phases::to_json),
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Result<SavedRun, String> {
+        let format = value.get("format").and_then(Value::as_u64);
+        if format != Some(FORMAT_VERSION) {
+            return Err(format!("unsupported results format {:?}, expected {}", format, FORMAT_VERSION));
+        }
+        Self::parse(value).ok_or_else(|| "malformed results".to_string())
+    }
+
+    fn parse(value: &Value) -> Option<SavedRun> {
+        let u64_at = |key: &str| value.get(key)?.as_u64();
+        let str_at = |key: &str| Some(value.get(key)?.as_str()?.to_string());
+        let durations = |key: &str| -> Option<Vec<Duration>> {
+            value.get(key)?.as_array()?.iter().map(|n| n.as_u64().map(Duration::from_nanos)).collect()
+        };
+
+        let errors = ErrorCounts::default();
+        let counts = value.get("errors")?.as_object()?;
+        for kind in ErrorKind::ALL {
+            errors.add(kind, counts.get(kind.label()).and_then(Value::as_u64).unwrap_or(0));
+        }
+        let spans = value
+            .get("spans")?
+            .as_array()?
+            .iter()
+            .map(|span| Some((span.get(0)?.as_u64()?, Duration::from_nanos(span.get(1)?.as_u64()?))))
+            .collect::<Option<Vec<_>>>()?;
+        let targets = value
+            .get("targets")?
+            .as_array()?
+            .iter()
+            .map(|t| t.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()?;
+        let resources = match value.get("resources").filter(|r| !r.is_null()) {
+            Some(r) => Some(ResourceSummary {
+                cores: r.get("cores")?.as_u64()? as usize,
+                cpu_avg: r.get("cpu_avg")?.as_f64()?,
+                cpu_peak: r.get("cpu_peak")?.as_f64()?,
+                rss_avg: r.get("rss_avg")?.as_u64()?,
+                rss_peak: r.get("rss_peak")?.as_u64()?,
+            }),
+            None => None,
+        };
+        // Absent from files written before --script
+        let script_metrics = match value.get("script_metrics") {
+            Some(metrics) => metrics
+                .as_object()?
+                .iter()
+                .map(|(name, m)| {
+                    let metric = Metric {
+                        count: m.get("count")?.as_u64()?,
+                        sum: m.get("sum")?.as_f64()?,
+                        min: m.get("min")?.as_f64()?,
+                        max: m.get("max")?.as_f64()?,
+                    };
+                    Some((name.clone(), metric))
+                })
+                .collect::<Option<Metrics>>()?,
+            None => Metrics::new(),
+        };
+
+        Some(SavedRun {
+            url: str_at("url")?,
+            proto: str_at("proto")?,
+            targets,
+            conns: u64_at("conns")? as usize,
+            pipeline: u64_at("pipeline")? as usize,
+            spans,
+            requested: u64_at("requested")?,
+            success: u64_at("success")?,
+            failed: u64_at("failed")?,
+            errors,
+            mismatches: u64_at("mismatches")?,
+            stopped_early: value.get("stopped_early")?.as_bool()?,
+            latencies: durations("latencies_ns")?,
+            ttfbs: durations("ttfbs_ns")?,
+            resources,
+            // Absent from files written before scheduler lag was measured
+            scheduler_lag: match value.get("scheduler_lag").filter(|lag| !lag.is_null()) {
+                Some(lag) => Some(SchedulerLag::from_json(lag)?),
+                None => None,
+            },
+            script_metrics,
+            // Absent from files written before --abort-if-rps-below
+            rps_breach: match value.get("rps_breach").filter(|breach| !breach.is_null()) {
+                Some(breach) => Some(Breach::from_json(breach)?),
+                None => None,
+            },
+            // Absent from files written before per-worker utilization
+            worker_utilization: match value.get("worker_utilization") {
+                Some(utilization) => utilization.as_array()?.iter().map(Value::as_f64).collect::<Option<_>>()?,
+                None => Vec::new(),
+            },
+            // Kept in the file, but a merged run's time isn't split into phases
+            phases: None,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<SavedRun, String> {
+        let text = std::fs::read(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let value: Value = serde_json::from_slice(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::from_json(&value).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, serde_json::to_vec(&self.to_json())?)
+    }
+
+    /// Targets differ between the merged runs
+    pub fn is_mixed(&self) -> bool {
+        self.targets.len() > 1
+    }
+
+    /// Fold `other` in: counters and connections add up and latencies are pooled. CPU
+    /// and memory are averaged over the merged time span, so concurrent runs add up and
+    /// back-to-back ones don't; the peak is the highest any one run saw. A different
+    /// target makes this a mixed-target report.
+    pub fn merge(&mut self, other: SavedRun) {
+        if self.targets.is_empty() {
+            *self = other;
+            return;
+        }
+        // CPU-seconds and byte-seconds, taken before the spans are merged
+        let weighted = |run: &SavedRun| {
+            let secs = run.elapsed().as_secs_f64();
+            run.resources
+                .map(|r| (r, r.cpu_avg * r.cores as f64 * secs, r.rss_avg as f64 * secs))
+        };
+        let resources = weighted(self).zip(weighted(&other));
+        for target in other.targets {
+            if !self.targets.contains(&target) {
+                self.targets.push(target);
+            }
+        }
+        if self.url != other.url {
+            self.url = "(mixed)".to_string();
+        }
+        if self.proto != other.proto {
+            self.proto = "mixed".to_string();
+        }
+        self.resources = self.resources.or(other.resources);
+        self.scheduler_lag = match (self.scheduler_lag, other.scheduler_lag) {
+            (Some(mine), Some(theirs)) => Some(mine.worst(theirs)),
+            (mine, theirs) => mine.or(theirs),
+        };
+        self.conns += other.conns;
+        self.pipeline = self.pipeline.max(other.pipeline);
+        self.spans.extend(other.spans);
+        self.requested += other.requested;
+        self.success += other.success;
+        self.failed += other.failed;
+        for kind in ErrorKind::ALL {
+            self.errors.add(kind, other.errors.get(kind));
+        }
+        self.mismatches += other.mismatches;
+        self.stopped_early |= other.stopped_early;
+        self.rps_breach = self.rps_breach.take().or(other.rps_breach);
+        self.latencies.extend(other.latencies);
+        self.ttfbs.extend(other.ttfbs);
+        self.worker_utilization.extend(other.worker_utilization);
+        script::merge(&mut self.script_metrics, other.script_metrics);
+        if let Some(((mine, cpu_a, rss_a), (theirs, cpu_b, rss_b))) = resources {
+            let cores = mine.cores.max(theirs.cores);
+            let secs = self.elapsed().as_secs_f64().max(f64::MIN_POSITIVE);
+            let cpu_avg = (cpu_a + cpu_b) / secs / cores as f64;
+            let rss_avg = ((rss_a + rss_b) / secs) as u64;
+            self.resources = Some(ResourceSummary {
+                cores,
+                cpu_avg,
+                cpu_peak: mine.cpu_peak.max(theirs.cpu_peak).max(cpu_avg),
+                rss_avg,
+                rss_peak: mine.rss_peak.max(theirs.rss_peak).max(rss_avg),
+            });
+        }
+    }
+
+    /// When the earliest merged run started
+    fn started(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.spans.iter().map(|&(start, _)| start).min().unwrap_or(0))
+    }
+
+    /// When the latest merged run ended
+    fn ended(&self) -> SystemTime {
+        let end = |&(start, active): &(u64, Duration)| UNIX_EPOCH + Duration::from_millis(start) + active;
+        self.spans.iter().map(end).max().unwrap_or(UNIX_EPOCH)
+    }
+
+    /// Wall time covered by the union of the merged runs' active spans: overlapping runs
+    /// count once, gaps between runs not at all
+    pub fn elapsed(&self) -> Duration {
+        let mut spans: Vec<(u64, u64)> = self
+            .spans
+            .iter()
+            .map(|&(start, active)| (start, start + active.as_millis() as u64))
+            .collect();
+        spans.sort_unstable();
+        let mut total = 0;
+        let mut covered_to = 0;
+        for (start, end) in spans {
+            let start = start.max(covered_to);
+            if end > start {
+                total += end - start;
+                covered_to = end;
+            }
+        }
+        // Spans are to the millisecond, which a short run would lose entirely
+        Duration::from_millis(total).max(self.spans.iter().map(|&(_, active)| active).max().unwrap_or_default())
+    }
+
+    pub fn rps(&self) -> f64 {
+        self.latencies.len() as f64 / self.elapsed().as_secs_f64()
+    }
+
+    /// For the table and markdown output and the thresholds; TTFB is the only phase
+    pub fn summary<'a>(&'a self, latency: &'a Percentiles, ttfb: &'a Percentiles) -> RunSummary<'a> {
+        RunSummary {
+            url: &self.url,
+            proto: &self.proto,
+            conns: self.conns,
+            pipeline: self.pipeline,
+            reqs: self.latencies.len() as u64,
+            started: self.started(),
+            duration: self.elapsed(),
+            rps: self.rps(),
+            success: self.success,
+            failed: self.failed,
+            errors: &self.errors,
+            latency,
+            phases: vec![("TTFB", ttfb)],
+            urls: &[],
+            resources: self.resources,
+            scheduler_lag: self.scheduler_lag,
+            status_timeline: None,
+            crypto_drift: None,
+        }
+    }
+
+    /// The results block: the counters, errors, latency and TTFB. `runs` names what was
+    /// merged, e.g. "4 processes".
+    pub fn print(&self, latency: &Percentiles, ttfb: &Percentiles, unit: LatencyUnit, runs: &str, require_protocol: bool) {
+        let completed = self.latencies.len() as u64;
+        println!("\n{}", "--- Results ---".cyan().bold());
+        if self.is_mixed() {
+            render::print("Targets:".white(), format!("mixed: {}", self.targets.join(", ")).yellow());
+        }
+        render::print("Total time:".white(), format!("{:?}", self.elapsed()));
+        render::print("Started:".white(), clock::rfc3339(self.started()));
+        render::print("Ended:".white(), clock::rfc3339(self.ended()));
+        if self.stopped_early {
+            render::print(
+                "Partial:".white(),
+                format!(
+                    "stopped at max duration with {}/{} requests completed",
+                    thousands(completed),
+                    thousands(self.requested)
+                )
+                .yellow()
+                .bold(),
+            );
+        }
+        render::print("Requests/sec:".white(), format!("{:.2}", self.rps()).green().bold());
+        render::print("Merged:".white(), runs.green());
+        workers::print_utilization(&self.worker_utilization);
+        if let Some(resources) = &self.resources {
+            resources.print();
+        }
+        if let Some(lag) = &self.scheduler_lag {
+            lag.print();
+        }
+        render::print("Success:".white(), self.success.to_string().green());
+        if self.failed > 0 {
+            render::print("Failed:".white(), self.failed.to_string().red().bold());
+        } else {
+            render::print("Failed:".white(), "0".dimmed());
+        }
+        if self.mismatches > 0 {
+            let line = format!("{} requests not served over {}", self.mismatches, self.proto);
+            let line = if require_protocol { line.red().bold() } else { line.yellow() };
+            render::print("Mismatched:".white(), line);
+        }
+
+        self.errors.print();
+        limits::print_hint(self.errors.get(ErrorKind::ClientResources), self.conns);
+        print_percentiles("Latency", latency, unit);
+        script::print(&self.script_metrics);
+        print_phases(&[("TTFB", ttfb)], unit);
+    }
+}
+
+/// How a target is listed in `targets`
+fn target(url: &str, proto: &str) -> String {
+    format!("{} ({})", url, proto)
+}
+
+fn unix_ms(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+/// `samples` in nanoseconds
+pub fn histogram(samples: &[Duration]) -> Histogram<u64> {
+    let mut histogram = Histogram::new(HISTOGRAM_SIGFIG).expect("3 significant digits are supported");
+    for sample in samples {
+        // Auto-resizing up to i64::MAX / 2 nanoseconds, some 146 years
+        let _ = histogram.record(sample.as_nanos() as u64);
+    }
+    histogram
+}
+
+/// The compressed V2 log format, in base64
+pub fn encode_histogram(histogram: &Histogram<u64>) -> String {
+    let mut bytes = Vec::new();
+    V2DeflateSerializer::new()
+        .serialize(histogram, &mut bytes)
+        .expect("an in-memory histogram serializes");
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+/// Accepts the compressed and uncompressed V2 formats
+pub fn decode_histogram(encoded: &str) -> Result<Histogram<u64>, String> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| format!("latency_histogram is not base64: {}", e))?;
+    Deserializer::new()
+        .deserialize(&mut bytes.as_slice())
+        .map_err(|e| format!("latency_histogram is not an HdrHistogram: {:?}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn histogram_survives_save_and_load() {
+        // Long-tailed: mostly around 2ms, a few up to seconds
+        let mut rng = SmallRng::seed_from_u64(147);
+        let latencies: Vec<Duration> = (0..50_000)
+            .map(|_| Duration::from_nanos((2_000_000.0 / (1.0 - rng.gen::<f64>()).powf(0.8)) as u64))
+            .collect();
+        let run = SavedRun {
+            url: "http://localhost:8080/".to_string(),
+            latencies: latencies.clone(),
+            ..Default::default()
+        };
+        let path = std::env::temp_dir().join(format!("benchy-histogram-{}.json", std::process::id()));
+        run.save(&path).unwrap();
+        let text = std::fs::read(&path).unwrap();
+        let loaded = SavedRun::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let value: Value = serde_json::from_slice(&text).unwrap();
+        let decoded = decode_histogram(value["latency_histogram"].as_str().unwrap()).unwrap();
+        let recorded = histogram(&latencies);
+        assert_eq!(decoded.len(), 50_000);
+        for quantile in [0.5, 0.99, 0.999] {
+            assert_eq!(decoded.value_at_quantile(quantile), recorded.value_at_quantile(quantile), "at {}", quantile);
+        }
+        assert_eq!(decoded.max(), recorded.max());
+        assert!(recorded.max() > 100 * recorded.value_at_quantile(0.5), "the tail is too short to test");
+
+        // The raw latencies too, which a merge pools
+        assert_eq!(histogram(&loaded.unwrap().latencies), recorded);
+    }
+}
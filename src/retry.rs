@@ -0,0 +1,99 @@
+//! `--retry-on 502,503 --retry-budget 1%`: resend requests answered with a transient
+//! status, such as a gateway's 502s while it reloads its config, so they don't show up
+//! as failures, but only up to a budget of the run's `-n`. Past it a matching response
+//! counts as failed again, so an outage can't hide behind retries.
+//!
+//! Unrelated to `--retry-goaway`, which resends requests the server never saw.
+
+use crate::render;
+use crate::thousands;
+use colored::Colorize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Retries one request gets at most, so a status that never clears can't spend the
+/// whole budget on it
+pub const MAX_PER_REQUEST: u32 = 3;
+
+/// The statuses to retry and the run's budget for them
+pub struct StatusRetry {
+    codes: Vec<u16>,
+    budget: u64,
+    /// Retries sent, up to `budget`
+    used: AtomicU64,
+    /// Requests that succeeded after one or more retries
+    recovered: AtomicU64,
+    /// Matching responses counted as failures because the budget was spent
+    unretried: AtomicU64,
+    /// Requests that still got a matching response after `MAX_PER_REQUEST` retries
+    gave_up: AtomicU64,
+}
+
+impl StatusRetry {
+    /// `budget` is a fraction of `requests`
+    pub fn new(codes: Vec<u16>, budget: f64, requests: u64) -> Self {
+        StatusRetry {
+            codes,
+            budget: (budget * requests as f64).floor() as u64,
+            used: AtomicU64::new(0),
+            recovered: AtomicU64::new(0),
+            unretried: AtomicU64::new(0),
+            gave_up: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether a response with `status`, after `retries` retries of its request, is
+    /// sent again. Takes one retry from the budget if so.
+    pub fn retry(&self, status: u16, retries: u32) -> bool {
+        if !self.codes.contains(&status) {
+            return false;
+        }
+        if retries >= MAX_PER_REQUEST {
+            self.gave_up.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        let taken = self
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| (used < self.budget).then_some(used + 1))
+            .is_ok();
+        if !taken {
+            self.unretried.fetch_add(1, Ordering::Relaxed);
+        }
+        taken
+    }
+
+    /// Count a request that succeeded after being retried
+    pub fn recovered(&self) {
+        self.recovered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn print(&self) {
+        let used = self.used.load(Ordering::Relaxed);
+        let unretried = self.unretried.load(Ordering::Relaxed);
+        let gave_up = self.gave_up.load(Ordering::Relaxed);
+        let codes: Vec<String> = self.codes.iter().map(u16::to_string).collect();
+        let gave_up = if gave_up > 0 {
+            format!(", {} still failed after {} retries", thousands(gave_up), MAX_PER_REQUEST)
+        } else {
+            String::new()
+        };
+        render::print(
+            "Status retry:".white(),
+            format!(
+                "{} of {} budgeted retries on {}, {} requests then succeeded{}",
+                thousands(used).yellow(),
+                thousands(self.budget),
+                codes.join("/"),
+                thousands(self.recovered.load(Ordering::Relaxed)).green(),
+                gave_up
+            ),
+        );
+        if unretried > 0 {
+            let note = format!(
+                "budget exhausted; {} more {} responses counted as failures",
+                thousands(unretried),
+                codes.join("/")
+            );
+            render::print_wrapped("Note:".yellow().bold(), note.yellow());
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! `--sanity-checks`: a look at the first successful responses' bodies for signs the run
+//! isn't measuring the service it was pointed at. A captive portal or a load balancer's
+//! error page answers 200 with HTML, fast, and the results look great; a stub answering
+//! every request to a dynamic endpoint with the same few bytes does too. Only the first
+//! `SAMPLE` bodies are buffered and looked at, so the rest of the run pays one atomic
+//! increment per response.
+
+use crate::render;
+use colored::Colorize;
+use reqwest::Url;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Responses inspected, the first ones to succeed
+const SAMPLE: u64 = 20;
+
+/// Inspected responses below which the identical-body check says nothing
+const MIN_IDENTICAL_SAMPLE: u64 = 10;
+
+/// Bodies up to this many bytes count as tiny
+const TINY: usize = 256;
+
+/// Share of the inspected responses one tiny body must make up to look like a stub
+const IDENTICAL: f64 = 0.9;
+
+/// Why the responses should be JSON, if anything says so
+#[derive(Clone, Copy)]
+enum Expected {
+    /// `--assert-json`
+    AssertJson,
+    /// A request body that parses as JSON
+    JsonBody,
+}
+
+impl Expected {
+    fn describe(self) -> &'static str {
+        match self {
+            Expected::AssertJson => "--assert-json expects JSON",
+            Expected::JsonBody => "the request body is JSON",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Observed {
+    inspected: u64,
+    /// HTML bodies where JSON was expected or declared
+    html: u64,
+    /// The first of them: its Content-Type and its first bytes
+    html_example: Option<(String, String)>,
+    /// Tiny bodies by content, with how many times each came back
+    tiny: HashMap<Vec<u8>, u64>,
+    /// Inspected responses to URLs that look dynamic
+    dynamic: u64,
+    dynamic_example: Option<String>,
+}
+
+pub struct Sanity {
+    expected: Option<Expected>,
+    claimed: AtomicU64,
+    observed: Mutex<Observed>,
+}
+
+impl Sanity {
+    /// `body` is the `-d` or `--data-file` request body, when it is the same every time
+    pub fn new(assert_json: bool, body: Option<&[u8]>) -> Sanity {
+        let expected = if assert_json {
+            Some(Expected::AssertJson)
+        } else if body.is_some_and(|body| serde_json::from_slice::<serde_json::Value>(body).is_ok()) {
+            Some(Expected::JsonBody)
+        } else {
+            None
+        };
+        Sanity {
+            expected,
+            claimed: AtomicU64::new(0),
+            observed: Mutex::new(Observed::default()),
+        }
+    }
+
+    /// Whether to buffer this response's body for `record`; true for the first `SAMPLE`
+    pub fn claim(&self) -> bool {
+        self.claimed.load(Ordering::Relaxed) < SAMPLE && self.claimed.fetch_add(1, Ordering::Relaxed) < SAMPLE
+    }
+
+    /// A claimed response, from `target`
+    pub fn record(&self, target: &str, content_type: Option<&str>, body: &[u8]) {
+        let declared_json = content_type.is_some_and(|value| value.to_ascii_lowercase().contains("json"));
+        let html = content_type.is_some_and(|value| value.to_ascii_lowercase().contains("text/html")) || sniff_html(body);
+        let mut observed = self.observed.lock().expect("sanity lock poisoned");
+        observed.inspected += 1;
+        if html && (self.expected.is_some() || declared_json) {
+            observed.html += 1;
+            observed.html_example.get_or_insert_with(|| {
+                let start = String::from_utf8_lossy(&body[..body.len().min(60)]);
+                (content_type.unwrap_or("none").to_string(), start.split_whitespace().collect::<Vec<_>>().join(" "))
+            });
+        }
+        if body.len() <= TINY {
+            *observed.tiny.entry(body.to_vec()).or_default() += 1;
+        }
+        if looks_dynamic(target) {
+            observed.dynamic += 1;
+            observed.dynamic_example.get_or_insert_with(|| target.to_string());
+        }
+    }
+
+    /// What looks wrong, as warnings; to stdout with the text report and to stderr otherwise
+    pub fn print(&self, text: bool) {
+        let observed = self.observed.lock().expect("sanity lock poisoned");
+        let mut warnings = Vec::new();
+        if let Some((content_type, start)) = &observed.html_example {
+            let expected = match self.expected {
+                Some(expected) => expected.describe(),
+                None => "the Content-Type says JSON",
+            };
+            warnings.push(format!(
+                "{} of the first {} responses were HTML (Content-Type: {}, body starting {:?}) where {} — a captive \
+                 portal, proxy or load balancer error page may be answering, and the results may not measure the \
+                 service",
+                observed.html, observed.inspected, content_type, start, expected
+            ));
+        }
+        let identical = observed.tiny.iter().max_by_key(|(_, count)| **count);
+        if let (Some((body, &count)), Some(url)) = (identical, &observed.dynamic_example) {
+            if observed.inspected >= MIN_IDENTICAL_SAMPLE && count as f64 > IDENTICAL * observed.inspected as f64 {
+                let body = String::from_utf8_lossy(&body[..body.len().min(60)]).into_owned();
+                warnings.push(format!(
+                    "{} of the first {} responses were the same {}-byte body ({:?}), though {} looks like a dynamic \
+                     endpoint — check the run reaches the real service rather than a stub or a cached error",
+                    count,
+                    observed.inspected,
+                    body.len(),
+                    body,
+                    url
+                ));
+            }
+        }
+        for warning in warnings {
+            let line = render::wrapped("Warning:".yellow().bold(), warning.bold());
+            if text {
+                println!("{}", line);
+            } else {
+                eprintln!("{}", line);
+            }
+        }
+    }
+}
+
+/// Whether `body` starts like an HTML document, past whitespace and a byte-order mark
+fn sniff_html(body: &[u8]) -> bool {
+    let body = body.strip_prefix(b"\xef\xbb\xbf").unwrap_or(body);
+    let start = body.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(body.len());
+    let head = body[start..body.len().min(start + 14)].to_ascii_lowercase();
+    head.starts_with(b"<!doctype html") || head.starts_with(b"<html")
+}
+
+/// A URL with a query, an ID-like path segment or an API-like path, whose responses
+/// should differ now and then
+fn looks_dynamic(target: &str) -> bool {
+    let Ok(url) = Url::parse(target) else {
+        return false;
+    };
+    url.query().is_some_and(|query| !query.is_empty())
+        || url.path_segments().into_iter().flatten().any(|segment| {
+            let segment = segment.to_ascii_lowercase();
+            matches!(segment.as_str(), "api" | "graphql" | "rpc")
+                || (segment.len() > 1 && segment.starts_with('v') && segment[1..].bytes().all(|b| b.is_ascii_digit()))
+                || (!segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit()))
+        })
+}
@@ -0,0 +1,117 @@
+//! How late benchy's own runtime runs its tasks. A probe task sleeps 1ms at a time and
+//! records how far past the deadline it woke: on an idle runtime that is the timer's
+//! granularity, on an overloaded one it is the time a ready task sat waiting for a
+//! worker thread, and every request's latency includes it.
+
+use crate::render;
+use colored::Colorize;
+use hdrhistogram::Histogram;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+const PERIOD: Duration = Duration::from_millis(1);
+
+/// P99 lag above which the client, not the server, is called the bottleneck
+const OVERLOADED: Duration = Duration::from_millis(10);
+
+/// The running probe, started and stopped with the throughput sampler
+pub struct LagProbe {
+    stop: Arc<AtomicBool>,
+    task: JoinHandle<Histogram<u64>>,
+}
+
+/// Scheduler lag over a run
+#[derive(Clone, Copy, Debug)]
+pub struct SchedulerLag {
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LagProbe {
+    pub fn start() -> LagProbe {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let task = tokio::spawn(async move {
+            // Microseconds, up to a minute
+            let mut lags = Histogram::new_with_bounds(1, 60_000_000, 2).expect("valid histogram bounds");
+            while !stopped.load(Ordering::Relaxed) {
+                let due = Instant::now() + PERIOD;
+                tokio::time::sleep_until(due).await;
+                lags.saturating_record(Instant::now().saturating_duration_since(due).as_micros() as u64);
+            }
+            lags
+        });
+        LagProbe { stop, task }
+    }
+
+    /// `None` if the probe never woke
+    pub async fn finish(self) -> Option<SchedulerLag> {
+        self.stop.store(true, Ordering::Relaxed);
+        let lags = self.task.await.ok()?;
+        if lags.is_empty() {
+            return None;
+        }
+        let at = |quantile: f64| Duration::from_micros(lags.value_at_quantile(quantile));
+        Some(SchedulerLag {
+            p50: at(0.50),
+            p99: at(0.99),
+            max: Duration::from_micros(lags.max()),
+        })
+    }
+}
+
+impl SchedulerLag {
+    pub fn overloaded(&self) -> bool {
+        self.p99 > OVERLOADED
+    }
+
+    /// The worse of two runs', since their samples aren't kept
+    pub fn worst(self, other: SchedulerLag) -> SchedulerLag {
+        SchedulerLag {
+            p50: self.p50.max(other.p50),
+            p99: self.p99.max(other.p99),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// For `--save-results` and `--output json`, in microseconds
+    pub fn to_json(self) -> Value {
+        json!({
+            "p50_us": self.p50.as_micros() as u64,
+            "p99_us": self.p99.as_micros() as u64,
+            "max_us": self.max.as_micros() as u64,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Option<SchedulerLag> {
+        let at = |key: &str| value.get(key)?.as_u64().map(Duration::from_micros);
+        Some(SchedulerLag {
+            p50: at("p50_us")?,
+            p99: at("p99_us")?,
+            max: at("max_us")?,
+        })
+    }
+
+    pub fn print(&self) {
+        let p99 = format!("{:.1?}", self.p99);
+        render::print(
+            "Client lag:".white(),
+            format!(
+                "p50 {:.1?}, p99 {}, max {:.1?}",
+                self.p50,
+                if self.overloaded() { p99.red().bold() } else { p99.green() },
+                self.max
+            ),
+        );
+        if self.overloaded() {
+            render::print_wrapped("Warning:".yellow().bold(), OVERLOADED_WARNING.red().bold());
+        }
+    }
+}
+
+pub const OVERLOADED_WARNING: &str = "client event loop overloaded — add --processes or reduce concurrency";
@@ -0,0 +1,336 @@
+//! `--script`: per-request hooks written in Rhai, for workloads flags can't describe
+//! (following a redirect the previous response named, signing a header, skipping
+//! requests).
+//!
+//! A script defines either hook or both:
+//!
+//! - `on_request(ctx)` before each send. `ctx` is a map of `worker`, `seq`, `method`,
+//!   `url`, `headers` (a map, empty to begin with) and `body`. Returning the map sends
+//!   it as changed, `()` sends the request unchanged, and `false` skips it.
+//! - `on_response(ctx, status, headers)` once response headers arrive, with the `ctx`
+//!   that was sent. Returning `false`, or a string saying why, marks the request
+//!   failed.
+//!
+//! The script is compiled once and each worker runs it on its own engine, so hooks
+//! never wait on each other. Both hooks see the worker's state as `this`, a map kept
+//! between requests; top-level statements don't run. `metric(name, value)` records a
+//! number under `name`, reported with the results as count, sum, min and max.
+
+use bytes::Bytes;
+use colored::Colorize;
+use crate::render;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Method;
+use rhai::{Blob, CallFnOptions, Dynamic, Engine, ImmutableString, Map, Scope, AST};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// The values one custom metric was given
+#[derive(Clone, Copy, Debug)]
+pub struct Metric {
+    pub count: u64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Metric {
+    fn new(value: f64) -> Self {
+        Metric {
+            count: 1,
+            sum: value,
+            min: value,
+            max: value,
+        }
+    }
+
+    pub fn merge(&mut self, other: Metric) {
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+}
+
+/// Custom metrics by name, sorted for the report
+pub type Metrics = BTreeMap<String, Metric>;
+
+/// Fold `other` into `metrics`
+pub fn merge(metrics: &mut Metrics, other: Metrics) {
+    for (name, metric) in other {
+        metrics
+            .entry(name)
+            .and_modify(|mine| mine.merge(metric))
+            .or_insert(metric);
+    }
+}
+
+/// `metrics` by name, as `--save-results` and the `json` sink write them
+pub fn to_json(metrics: &Metrics) -> Value {
+    metrics
+        .iter()
+        .map(|(name, m)| (name.clone(), json!({"count": m.count, "sum": m.sum, "min": m.min, "max": m.max})))
+        .collect::<serde_json::Map<String, Value>>()
+        .into()
+}
+
+/// A compiled script, shared by every worker
+pub struct Script {
+    ast: Arc<AST>,
+    on_request: bool,
+    on_response: bool,
+}
+
+impl Script {
+    /// Compile `path`; a syntax error names its line
+    pub fn load(path: &Path) -> Result<Script, String> {
+        let ast = Engine::new()
+            .compile_file(path.to_path_buf())
+            .map_err(|e| format!("--script {}: {}", path.display(), e))?;
+        let defines = |name: &str, params: usize| ast.iter_functions().any(|f| f.name == name && f.params.len() == params);
+        let on_request = defines("on_request", 1);
+        let on_response = defines("on_response", 3);
+        if !on_request && !on_response {
+            return Err(format!(
+                "--script {}: defines neither on_request(ctx) nor on_response(ctx, status, headers)",
+                path.display()
+            ));
+        }
+        Ok(Script {
+            ast: Arc::new(ast),
+            on_request,
+            on_response,
+        })
+    }
+
+    /// Worker `worker`'s own engine and state
+    pub fn worker(&self, worker: usize) -> WorkerScript {
+        let metrics = Arc::new(Mutex::new(Metrics::new()));
+        let mut engine = Engine::new();
+        let record = {
+            let metrics = metrics.clone();
+            move |name: ImmutableString, value: f64| {
+                let mut metrics = metrics.lock().expect("metrics lock poisoned");
+                match metrics.get_mut(name.as_str()) {
+                    Some(metric) => metric.merge(Metric::new(value)),
+                    None => {
+                        metrics.insert(name.to_string(), Metric::new(value));
+                    }
+                }
+            }
+        };
+        let record_int = record.clone();
+        engine.register_fn("metric", record);
+        engine.register_fn("metric", move |name: ImmutableString, value: i64| record_int(name, value as f64));
+
+        WorkerScript {
+            engine,
+            ast: self.ast.clone(),
+            on_request: self.on_request,
+            on_response: self.on_response,
+            state: Dynamic::from_map(Map::new()),
+            metrics,
+            worker,
+        }
+    }
+}
+
+/// A request as `on_request` left it
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    /// Added to the request, after any the other flags add
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+    pub body: Option<Bytes>,
+    /// What `on_response` is called with
+    ctx: Map,
+}
+
+/// One worker's engine, with the state its hooks keep in `this`
+pub struct WorkerScript {
+    engine: Engine,
+    ast: Arc<AST>,
+    on_request: bool,
+    on_response: bool,
+    state: Dynamic,
+    metrics: Arc<Mutex<Metrics>>,
+    worker: usize,
+}
+
+impl WorkerScript {
+    /// Run `on_request` for request `seq` of the worker: the request to send, or
+    /// `None` to skip it. An error, with its line, ends the run.
+    pub fn on_request(
+        &mut self,
+        seq: u64,
+        method: &Method,
+        url: &str,
+        body: Option<&Bytes>,
+    ) -> Result<Option<Request>, String> {
+        let mut ctx = Map::new();
+        ctx.insert("worker".into(), (self.worker as i64).into());
+        ctx.insert("seq".into(), (seq as i64).into());
+        ctx.insert("method".into(), method.as_str().into());
+        ctx.insert("url".into(), url.into());
+        ctx.insert("headers".into(), Map::new().into());
+        ctx.insert(
+            "body".into(),
+            match body.map(|body| std::str::from_utf8(body)) {
+                Some(Ok(text)) => text.into(),
+                // Left as bytes, so a body that isn't UTF-8 goes out as it came
+                Some(Err(_)) => Dynamic::from_blob(body.map(|body| body.to_vec()).unwrap_or_default()),
+                None => Dynamic::UNIT,
+            },
+        );
+        if !self.on_request {
+            return request(ctx).map(Some);
+        }
+
+        let returned = self.call("on_request", (Dynamic::from_map(ctx.clone()),))?;
+        if returned.is_unit() {
+            return request(ctx).map(Some);
+        }
+        if returned.as_bool() == Ok(false) {
+            return Ok(None);
+        }
+        match returned.try_cast::<Map>() {
+            Some(ctx) => request(ctx).map(Some),
+            None => Err("--script: on_request must return ctx, () or false".to_string()),
+        }
+    }
+
+    /// Run `on_response` for a request sent as `request`: why the script marked it
+    /// failed, if it did. An error, with its line, ends the run.
+    pub fn on_response(
+        &mut self,
+        request: &Request,
+        status: u16,
+        headers: &HeaderMap,
+    ) -> Result<Option<String>, String> {
+        if !self.on_response {
+            return Ok(None);
+        }
+        let mut map = Map::new();
+        for (name, value) in headers {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            match map.get_mut(name.as_str()) {
+                // Repeated headers are joined, as they would be on one line
+                Some(joined) => *joined = format!("{}, {}", joined, value).into(),
+                None => {
+                    map.insert(name.as_str().into(), value.as_ref().into());
+                }
+            }
+        }
+
+        let args = (Dynamic::from_map(request.ctx.clone()), status as i64, Dynamic::from_map(map));
+        let returned = self.call("on_response", args)?;
+        if returned.is_unit() || returned.as_bool() == Ok(true) {
+            Ok(None)
+        } else if returned.as_bool() == Ok(false) {
+            Ok(Some("--script marked the request failed".to_string()))
+        } else if returned.is_string() {
+            Ok(Some(returned.to_string()))
+        } else {
+            Err(format!(
+                "--script: on_response must return (), true, false or a reason, not {}",
+                returned.type_name()
+            ))
+        }
+    }
+
+    /// What `metric` recorded on this worker
+    pub fn take_metrics(&self) -> Metrics {
+        std::mem::take(&mut *self.metrics.lock().expect("metrics lock poisoned"))
+    }
+
+    fn call(&mut self, name: &str, args: impl rhai::FuncArgs) -> Result<Dynamic, String> {
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut self.state);
+        self.engine
+            .call_fn_with_options(options, &mut Scope::new(), &self.ast, name, args)
+            .map_err(|e| format!("--script: {}", e))
+    }
+}
+
+/// The request `ctx` describes
+fn request(mut ctx: Map) -> Result<Request, String> {
+    let field = |ctx: &Map, name: &str| -> Result<String, String> {
+        match ctx.get(name) {
+            Some(value) if value.is_string() => Ok(value.to_string()),
+            Some(value) => Err(format!("--script: ctx.{} must be a string, not {}", name, value.type_name())),
+            None => Err(format!("--script: ctx.{} is missing", name)),
+        }
+    };
+    let method = field(&ctx, "method")?;
+    let method = Method::from_bytes(method.as_bytes()).map_err(|_| format!("--script: invalid method {:?}", method))?;
+    let url = field(&ctx, "url")?;
+
+    let mut headers = Vec::new();
+    if let Some(map) = ctx.get("headers").filter(|value| !value.is_unit()) {
+        let map = map
+            .read_lock::<Map>()
+            .ok_or_else(|| format!("--script: ctx.headers must be a map, not {}", map.type_name()))?;
+        for (name, value) in map.iter().filter(|(_, value)| !value.is_unit()) {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("--script: invalid header name {:?}", name))?;
+            let value = HeaderValue::from_str(&value.to_string())
+                .map_err(|_| format!("--script: invalid value for header {}", name))?;
+            headers.push((name, value));
+        }
+    }
+
+    let body = match ctx.get_mut("body").map(std::mem::take) {
+        None => None,
+        Some(body) if body.is_unit() => None,
+        Some(body) if body.is_string() => {
+            let text = body.to_string();
+            ctx.insert("body".into(), text.clone().into());
+            Some(Bytes::from(text))
+        }
+        Some(body) if body.is_blob() => {
+            let bytes = body.cast::<Blob>();
+            ctx.insert("body".into(), Dynamic::from_blob(bytes.clone()));
+            Some(Bytes::from(bytes))
+        }
+        Some(body) => return Err(format!("--script: ctx.body must be a string or blob, not {}", body.type_name())),
+    };
+
+    Ok(Request {
+        method,
+        url,
+        headers,
+        body,
+        ctx,
+    })
+}
+
+/// The `--- Script metrics ---` section, if the script recorded any
+pub fn print(metrics: &Metrics) {
+    if metrics.is_empty() {
+        return;
+    }
+    println!("\n{}", "--- Script metrics ---".cyan().bold());
+    for (name, metric) in metrics {
+        render::print(
+            format!("{}:", name).white(),
+            format!(
+                "count {}, avg {}, min {}, max {}, sum {}",
+                crate::thousands(metric.count),
+                number(metric.sum / metric.count.max(1) as f64),
+                number(metric.min),
+                number(metric.max),
+                number(metric.sum)
+            ),
+        );
+    }
+}
+
+/// Whole numbers without a fraction, others to two places
+pub fn number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{:.2}", value)
+    }
+}
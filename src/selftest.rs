@@ -0,0 +1,296 @@
+//! `benchy selftest`: a short run against a server started in this process, checked for
+//! numbers that don't add up. For new users without a target at hand, and for CI.
+//!
+//! The server listens on a random localhost port, speaking cleartext HTTP/1.1 or h2c,
+//! and can be slowed down and made to answer some requests with 500. The run is a
+//! benchy child, started as `--processes` starts its children, so it goes through the
+//! normal code path and writes its results in the `--save-results` format, which are
+//! then held against what the server saw.
+
+use crate::processes;
+use crate::protocol::Protocol;
+use crate::results::SavedRun;
+use crate::units::LatencyUnit;
+use crate::{render, Percentiles};
+use bytes::Bytes;
+use clap::Parser;
+use colored::Colorize;
+use http_body_util::Full;
+use hyper::body::Incoming;
+use hyper::server::conn::{http1, http2};
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode, Version};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use serde_json::Value;
+use std::convert::Infallible;
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+#[derive(Parser)]
+#[command(
+    name = "benchy selftest",
+    bin_name = "benchy selftest",
+    about = "Benchmark a built-in local server and check that the report adds up"
+)]
+struct SelftestArgs {
+    /// Number of requests
+    #[arg(short = 'n', long, default_value_t = 1000)]
+    requests: u64,
+
+    /// Concurrent connections
+    #[arg(short = 'c', long, default_value_t = 4)]
+    connections: usize,
+
+    /// Worker tasks driving the connections, as benchy's --workers (default: one per connection)
+    #[arg(long, value_name = "N")]
+    workers: Option<usize>,
+
+    /// h1 or h2 (h2c); the server has no certificate, so h3 isn't covered
+    #[arg(long, value_enum, default_value = "h2")]
+    protocol: Protocol,
+
+    /// How long the server waits before answering each request
+    #[arg(long = "selftest-latency", value_name = "DURATION", value_parser = humantime::parse_duration, default_value = "0s")]
+    latency: Duration,
+
+    /// Share of requests the server answers with 500, from 0 to 1
+    #[arg(long = "selftest-error-rate", value_name = "RATE", default_value_t = 0.0)]
+    error_rate: f64,
+}
+
+/// What the server saw of the run's GET requests; HEAD probes aren't counted
+struct Server {
+    latency: Duration,
+    error_rate: f64,
+    version: Version,
+    requests: AtomicU64,
+    /// Answered with 500 on purpose
+    injected: AtomicU64,
+    /// Arrived over another version than the one asked for
+    wrong_version: AtomicU64,
+    /// Requests per connection, in the order they were accepted
+    connections: Mutex<Vec<Arc<AtomicU64>>>,
+}
+
+impl Server {
+    async fn handle(&self, req: Request<Incoming>, connection: &AtomicU64) -> Result<Response<Full<Bytes>>, Infallible> {
+        let counted = req.method() == Method::GET;
+        if counted {
+            self.requests.fetch_add(1, Ordering::Relaxed);
+            connection.fetch_add(1, Ordering::Relaxed);
+            if req.version() != self.version {
+                self.wrong_version.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+        let mut resp = Response::new(Full::new(Bytes::from_static(b"ok\n")));
+        if counted && rand::random::<f64>() < self.error_rate {
+            self.injected.fetch_add(1, Ordering::Relaxed);
+            *resp.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        }
+        Ok(resp)
+    }
+}
+
+pub async fn run() -> Result<(), Box<dyn Error>> {
+    // Skips `selftest` itself, as merge and report do
+    let args = SelftestArgs::parse_from(std::env::args_os().skip(1));
+    let version = match args.protocol {
+        Protocol::H2 => Version::HTTP_2,
+        // Without TLS there is no ALPN, so auto speaks HTTP/1.1
+        Protocol::H1 | Protocol::Auto => Version::HTTP_11,
+        Protocol::H3 => return Err("the self-test server has no certificate, so it can't serve HTTP/3".into()),
+    };
+    if !(0.0..=1.0).contains(&args.error_rate) {
+        return Err("--selftest-error-rate must be between 0 and 1".into());
+    }
+    if args.requests == 0 || args.connections == 0 {
+        return Err("-n and -c must be at least 1".into());
+    }
+    if args.workers.is_some_and(|workers| workers == 0 || workers > args.connections) {
+        return Err("--workers must be from 1 up to -c".into());
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    let server = Arc::new(Server {
+        latency: args.latency,
+        error_rate: args.error_rate,
+        version,
+        requests: AtomicU64::new(0),
+        injected: AtomicU64::new(0),
+        wrong_version: AtomicU64::new(0),
+        connections: Mutex::new(Vec::new()),
+    });
+    let protocol = args.protocol;
+    let accepting = server.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let server = accepting.clone();
+            let connection = Arc::new(AtomicU64::new(0));
+            server.connections.lock().expect("connections lock poisoned").push(connection.clone());
+            tokio::spawn(async move {
+                let service = service_fn(move |req| {
+                    let server = server.clone();
+                    let connection = connection.clone();
+                    async move { server.handle(req, &connection).await }
+                });
+                let io = TokioIo::new(stream);
+                let served = match protocol {
+                    Protocol::H2 => http2::Builder::new(TokioExecutor::new()).serve_connection(io, service).await,
+                    _ => http1::Builder::new().serve_connection(io, service).await,
+                };
+                if let Err(e) = served {
+                    tracing::info!(error = %e, "self-test connection failed");
+                }
+            });
+        }
+    });
+
+    let workers = args.workers.map_or(String::new(), |workers| format!(" driven by {} workers", workers));
+    let details = format!(
+        "({}) against the built-in server, {} connections{}, {} requests, {:?} latency, {}% answered with 500",
+        args.protocol.label().magenta(),
+        args.connections.to_string().green(),
+        workers,
+        args.requests.to_string().green(),
+        args.latency,
+        args.error_rate * 100.0
+    );
+    println!("{}", render::banner(&url, details));
+
+    let mut argv = vec![
+        "-n".to_string(),
+        args.requests.to_string(),
+        "-c".to_string(),
+        args.connections.to_string(),
+        "--protocol".to_string(),
+        args.protocol.name().to_string(),
+    ];
+    if let Some(workers) = args.workers {
+        argv.extend(["--workers".to_string(), workers.to_string()]);
+    }
+    argv.push(url);
+    let output = processes::child_command(&std::env::current_exe()?, &argv, 0, 1, rand::random())
+        .output()
+        .await
+        .map_err(|e| format!("could not start the run: {}", e))?;
+    let run = output
+        .status
+        .success()
+        .then(|| serde_json::from_slice::<Value>(&output.stdout).ok())
+        .flatten()
+        .and_then(|report| SavedRun::from_json(&report).ok());
+    let Some(mut run) = run else {
+        // Whatever the child printed instead
+        print!("{}", String::from_utf8_lossy(&output.stdout));
+        return Err(format!("the run failed ({})", output.status).into());
+    };
+
+    let min = run.latencies.iter().min().copied().unwrap_or_default();
+    let latency = Percentiles::from_unsorted(&mut run.latencies);
+    let ttfb = Percentiles::from_unsorted(&mut run.ttfbs);
+    let unit = LatencyUnit::Auto.resolve(latency.p50);
+    run.print(&latency, &ttfb, unit, "1 self-test run", false);
+
+    let served = server.requests.load(Ordering::Relaxed);
+    let injected = server.injected.load(Ordering::Relaxed);
+    let wrong_version = server.wrong_version.load(Ordering::Relaxed);
+    let completed = latency.count as u64;
+    // Connections the run's requests went over; the HEAD probe's has none
+    let spread: Vec<u64> = server
+        .connections
+        .lock()
+        .expect("connections lock poisoned")
+        .iter()
+        .map(|connection| connection.load(Ordering::Relaxed))
+        .filter(|&n| n > 0)
+        .collect();
+    let (fewest, most) = (spread.iter().min().copied().unwrap_or(0), spread.iter().max().copied().unwrap_or(0));
+    let mut checks = vec![
+        (
+            "Counts:",
+            run.success + run.failed == args.requests && completed == args.requests,
+            format!(
+                "{} requested, {} succeeded + {} failed, {} latencies",
+                args.requests, run.success, run.failed, completed
+            ),
+        ),
+        (
+            "Server:",
+            served == args.requests,
+            format!("{} requests reached the server", served),
+        ),
+        (
+            "Errors:",
+            run.failed == injected && run.errors.total() == 0,
+            format!(
+                "{} failed, {} answered with 500, {} transport errors",
+                run.failed,
+                injected,
+                run.errors.total()
+            ),
+        ),
+        (
+            "Percentiles:",
+            latency.p50 <= latency.p95
+                && latency.p95 <= latency.p99
+                && ttfb.p50 <= latency.p50
+                && ttfb.p99 <= latency.p99
+                && min >= args.latency,
+            format!(
+                "P50 {} <= P95 {} <= P99 {}, TTFB within latency, fastest {}{}",
+                unit.format(latency.p50),
+                unit.format(latency.p95),
+                unit.format(latency.p99),
+                unit.format(min),
+                if args.latency.is_zero() { String::new() } else { format!(" of at least {:?}", args.latency) }
+            ),
+        ),
+        (
+            "Protocol:",
+            run.mismatches == 0 && wrong_version == 0,
+            format!(
+                "{} responses and {} requests not over {:?}",
+                run.mismatches, wrong_version, version
+            ),
+        ),
+    ];
+    // An HTTP/1.1 client opens a connection per request in flight, so only h2 maps one
+    // client to one connection. -n is split by connection, and a worker takes its
+    // clients in turn, so no two connections differ by more than a request or two.
+    if version == Version::HTTP_2 {
+        checks.push((
+            "Spread:",
+            spread.len() == args.connections && most - fewest <= 2,
+            format!(
+                "{} connections used of {}, {} to {} requests each",
+                spread.len(),
+                args.connections,
+                fewest,
+                most
+            ),
+        ));
+    }
+
+    println!("\n{}", "--- Self-test ---".cyan().bold());
+    let mut failed = 0;
+    for (label, passed, detail) in &checks {
+        if *passed {
+            render::print(label.white(), format!("{} {}", "ok".green(), detail));
+        } else {
+            failed += 1;
+            render::print_wrapped(label.white(), format!("{} {}", "FAILED".red().bold(), detail));
+        }
+    }
+    if failed > 0 {
+        eprintln!("{} {} of {} self-test checks failed", "Error:".red().bold(), failed, checks.len());
+        std::process::exit(1);
+    }
+    Ok(())
+}
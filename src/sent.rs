@@ -0,0 +1,198 @@
+//! What the client put on the wire: each request's header block and body, summed over
+//! the run for capacity planning, next to the response body sizes.
+//!
+//! Sizes are worked out from the built request rather than counted at the socket,
+//! which reqwest doesn't give us. Over HTTP/1.1 that is the header block as written.
+//! Over HTTP/2 and HTTP/3 it is the header fields' names and values before HPACK or
+//! QPACK, since neither hyper nor h3 says what they compressed them to; the results
+//! note when that is the case.
+
+use crate::render;
+use crate::thousands;
+use colored::Colorize;
+use http::header::{ACCEPT, CONTENT_LENGTH, HOST};
+use http::Version;
+use http_body::Body;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Added by reqwest to requests that don't set Accept
+const DEFAULT_ACCEPT: &str = "*/*";
+
+/// What each field adds to a header list's size on top of its name and value, as
+/// SETTINGS_MAX_HEADER_LIST_SIZE and MAX_FIELD_SECTION_SIZE count it
+const FIELD_OVERHEAD: usize = 32;
+
+/// One request's header block, as written over each kind of connection, and body
+pub struct RequestSize {
+    /// Request line, fields and the blank line, CRLFs included
+    http1: u64,
+    /// Names and values of the fields, pseudo-headers included
+    fields: u64,
+    /// `fields` plus the per-field overhead, as an HTTP/2 or HTTP/3 peer limits it
+    list: u64,
+    body: u64,
+}
+
+impl RequestSize {
+    pub fn of(request: &reqwest::Request) -> RequestSize {
+        let url = request.url();
+        let authority = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let method = request.method().as_str();
+        let body = request.body().and_then(|body| body.size_hint().exact()).unwrap_or(0);
+
+        // The request's fields, and those reqwest and hyper add when it has none
+        let headers = request.headers();
+        let mut fields: Vec<(&str, usize)> =
+            headers.iter().map(|(name, value)| (name.as_str(), value.len())).collect();
+        if !headers.contains_key(HOST) {
+            fields.push((HOST.as_str(), authority.len()));
+        }
+        if !headers.contains_key(ACCEPT) {
+            fields.push((ACCEPT.as_str(), DEFAULT_ACCEPT.len()));
+        }
+        if body > 0 && !headers.contains_key(CONTENT_LENGTH) {
+            fields.push((CONTENT_LENGTH.as_str(), body.to_string().len()));
+        }
+
+        // "METHOD /path HTTP/1.1", "name: value" lines and a blank line
+        let mut http1 = method.len() + 1 + path.len() + " HTTP/1.1\r\n".len() + "\r\n".len();
+        let mut h2 = ":method".len()
+            + method.len()
+            + ":scheme".len()
+            + url.scheme().len()
+            + ":authority".len()
+            + authority.len()
+            + ":path".len()
+            + path.len();
+        let mut h2_fields = 4;
+        for (name, value) in fields {
+            http1 += name.len() + ": \r\n".len() + value;
+            // HTTP/2 and HTTP/3 carry Host as :authority
+            if name != HOST.as_str() {
+                h2 += name.len() + value;
+                h2_fields += 1;
+            }
+        }
+
+        RequestSize {
+            http1: http1 as u64,
+            fields: h2 as u64,
+            list: (h2 + h2_fields * FIELD_OVERHEAD) as u64,
+            body,
+        }
+    }
+
+    /// The header block as a server over `version` would measure it against its limit;
+    /// over an undecided version, the larger
+    pub fn headers(&self, version: Option<Version>) -> u64 {
+        match version {
+            Some(Version::HTTP_2 | Version::HTTP_3) => self.list,
+            Some(_) => self.http1,
+            None => self.http1.max(self.list),
+        }
+    }
+}
+
+/// Bytes sent across every worker, for requests that got a response
+#[derive(Default)]
+pub struct SentBytes {
+    requests: AtomicU64,
+    headers: AtomicU64,
+    body: AtomicU64,
+    /// Requests whose header size is from before HPACK or QPACK compression
+    uncompressed: AtomicU64,
+    /// The largest header blocks built, answered or not, as HTTP/1.1 and as a header list
+    largest_http1: AtomicU64,
+    largest_list: AtomicU64,
+}
+
+impl SentBytes {
+    /// Note a request's size as it is sent, before it is known whether it got through
+    pub fn observe(&self, size: &RequestSize) {
+        self.largest_http1.fetch_max(size.http1, Ordering::Relaxed);
+        self.largest_list.fetch_max(size.list, Ordering::Relaxed);
+    }
+
+    /// The `Header sizes:` line, for when requests were rejected for their headers
+    pub fn print_largest(&self, limit: u64) {
+        render::print_wrapped(
+            "Header sizes:".white(),
+            format!(
+                "largest request header block {} as HTTP/1.1, {} as an HTTP/2 or HTTP/3 header list (names, values \
+                 and {} B per field); --max-header-bytes {}",
+                bytes(self.largest_http1.load(Ordering::Relaxed)).yellow(),
+                bytes(self.largest_list.load(Ordering::Relaxed)).yellow(),
+                FIELD_OVERHEAD,
+                limit
+            ),
+        );
+    }
+
+    /// Count a request answered over `version`, so sized as it went out on it
+    pub fn record(&self, size: &RequestSize, version: Version) {
+        let headers = match version {
+            Version::HTTP_2 | Version::HTTP_3 => {
+                self.uncompressed.fetch_add(1, Ordering::Relaxed);
+                size.fields
+            }
+            _ => size.http1,
+        };
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.headers.fetch_add(headers, Ordering::Relaxed);
+        self.body.fetch_add(size.body, Ordering::Relaxed);
+    }
+
+    /// The `Sent:` line, with the rate over `elapsed`
+    pub fn print(&self, elapsed: Duration) {
+        let requests = self.requests.load(Ordering::Relaxed);
+        if requests == 0 {
+            return;
+        }
+        let headers = self.headers.load(Ordering::Relaxed);
+        let body = self.body.load(Ordering::Relaxed);
+        let total = headers + body;
+        render::print(
+            "Sent:".white(),
+            format!(
+                "{} in {} requests (headers {}, body {}), avg {} B per request, {}/s",
+                bytes(total).green(),
+                thousands(requests),
+                bytes(headers),
+                bytes(body),
+                (total / requests).to_string().green(),
+                bytes((total as f64 / elapsed.as_secs_f64().max(f64::EPSILON)) as u64).green()
+            ),
+        );
+        let uncompressed = self.uncompressed.load(Ordering::Relaxed);
+        if uncompressed > 0 {
+            let note = format!(
+                "header sizes of {} HTTP/2 or HTTP/3 requests are before HPACK/QPACK compression, which the transport doesn't report",
+                thousands(uncompressed)
+            );
+            render::print_wrapped("Note:".yellow().bold(), note.yellow());
+        }
+    }
+}
+
+/// `bytes` in the largest binary unit that keeps it at 1 or more
+pub fn bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
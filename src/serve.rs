@@ -0,0 +1,555 @@
+//! `benchy serve`: a small HTTP API for starting runs from a test orchestrator instead
+//! of over SSH.
+//!
+//! Each run is a benchy child process, started the way `--processes` starts its
+//! children: it writes its results to stdout in the `--save-results` format, and with
+//! [`PROGRESS_ENV`] set also writes a JSON progress line to stderr every second.
+//! Deleting a run sends the child SIGTERM, which cancels its in-flight requests and
+//! still reports what was done. One run goes at a time.
+//!
+//! The API has no authentication, so a run only takes the flags in [`SERVED_FLAGS`]:
+//! the ones that shape the load and the requests. Anything that reads or writes a
+//! local file, runs a script, or sends something somewhere other than the target is
+//! refused, so an API reachable beyond localhost can't be used to read or write files.
+//!
+//! ```text
+//! POST   /runs              {"url": "...", "args": ["-n", "1000"]}  -> 201 {"id": 1, ...}
+//! GET    /runs/:id          state and the latest progress
+//! GET    /runs/:id/report   the results, once the run is over
+//! DELETE /runs/:id          abort the run
+//! ```
+
+use crate::processes;
+use crate::Args;
+use bytes::Bytes;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
+use colored::Colorize;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::header::{CONTENT_TYPE, LOCATION};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpListener;
+
+/// Set on runs started by `benchy serve`: write a progress snapshot to stderr each second
+pub const PROGRESS_ENV: &str = "BENCHY_PROGRESS";
+
+/// Lines of a run's other stderr output kept for when it fails
+const LOG_LINES: usize = 50;
+
+/// The flags, by their `Args` field, a served run may be given
+const SERVED_FLAGS: &[&str] = &[
+    "url",
+    "connections",
+    "requests",
+    "pipeline",
+    "workers",
+    "fill",
+    "rate",
+    "arrival",
+    "auto_rate",
+    "correct_latency",
+    "burst",
+    "data",
+    "compress_body",
+    "method",
+    "seed",
+    "http3",
+    "protocol",
+    "require_protocol",
+    "ignore_server_stream_limit",
+    "fallback",
+    "h3_alt_svc",
+    "require_h3",
+    "h3_max_idle_timeout",
+    "h3_initial_max_streams",
+    "h3_congestion",
+    "h3_max_udp_payload",
+    "h3_keepalive",
+    "h2_keepalive",
+    "connect_timeout",
+    "expect_continue",
+    "h2c_upgrade",
+    "insecure",
+    "fail_fast",
+    "download_rate",
+    "requests_per_connection",
+    "no_session_resumption",
+    "retry_goaway",
+    "retry_on",
+    "retry_budget",
+    "dns_ttl_override",
+    "address_order",
+    "spread_dns",
+    "assert_header",
+    "assert_trailer",
+    "lint_responses",
+    "lint_strict",
+    "ratelimit_headers",
+    "collect_header",
+    "group_by_header",
+    "identity",
+    "max_header_bytes",
+    "request_id_header",
+    "affinity_header",
+    "verify_consistency",
+    "expect_body_sha256",
+    "expect_size",
+    "expect_size_range",
+    "assert_json",
+    "assert_json_sample",
+    "sanity_checks",
+    "preflight_origin",
+    "preflight_method",
+    "preflight_headers",
+    "cors_check",
+    "slowest",
+    "max_duration",
+    "ramp_down",
+    "drain_timeout",
+    "backoff_on_connect_errors",
+    "latency_unit",
+    "max_p99",
+    "max_error_rate",
+    "min_rps",
+    "abort_if_rps_below",
+    "rps_window",
+    "flap_threshold",
+    "work_stealing",
+    "per_worker_stats",
+    "verbose",
+    "preconnect",
+    "prime",
+    "keep_going",
+    "no_probe",
+    "wait_ready",
+    "ready_path",
+    "ready_status",
+];
+
+#[derive(Parser)]
+#[command(
+    name = "benchy serve",
+    bin_name = "benchy serve",
+    about = "Start benchmarks over HTTP: POST /runs, GET /runs/:id, GET /runs/:id/report, DELETE /runs/:id"
+)]
+struct ServeArgs {
+    /// Address to accept API requests on
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Running,
+    /// Sent SIGTERM, waiting for the partial results
+    Aborting,
+    Finished,
+    Aborted,
+    Failed,
+}
+
+impl State {
+    fn name(self) -> &'static str {
+        match self {
+            State::Running => "running",
+            State::Aborting => "aborting",
+            State::Finished => "finished",
+            State::Aborted => "aborted",
+            State::Failed => "failed",
+        }
+    }
+
+    fn is_over(self) -> bool {
+        matches!(self, State::Finished | State::Aborted | State::Failed)
+    }
+}
+
+struct Run {
+    url: String,
+    args: Vec<String>,
+    state: State,
+    pid: Option<u32>,
+    /// The latest snapshot from the child's stderr
+    progress: Option<Value>,
+    /// The child's results, once it has exited
+    report: Option<Value>,
+    /// Why the run failed: the child's output other than progress
+    error: Option<String>,
+}
+
+impl Run {
+    fn status(&self, id: usize) -> Value {
+        let mut status = json!({
+            "id": id,
+            "url": self.url,
+            "args": self.args,
+            "state": self.state.name(),
+            "progress": self.progress,
+        });
+        if let Some(error) = &self.error {
+            status["error"] = error.as_str().into();
+        }
+        status
+    }
+}
+
+/// Every run since the server started; a run's id is its index plus one
+struct Server {
+    exe: PathBuf,
+    runs: Mutex<Vec<Run>>,
+}
+
+pub async fn run() -> Result<(), Box<dyn Error>> {
+    // Skips `serve` itself, as merge and report do
+    let args = ServeArgs::parse_from(std::env::args_os().skip(1));
+    let server = Arc::new(Server {
+        exe: std::env::current_exe()?,
+        runs: Mutex::new(Vec::new()),
+    });
+
+    let listener = TcpListener::bind(args.listen)
+        .await
+        .map_err(|e| format!("could not listen on {}: {}", args.listen, e))?;
+    println!("{} on http://{}", "Serving".cyan().bold(), listener.local_addr()?.to_string().yellow());
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let server = server.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req| {
+                let server = server.clone();
+                async move { Ok::<_, Infallible>(server.handle(req).await) }
+            });
+            if let Err(e) = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await {
+                tracing::info!(%peer, error = %e, "API connection failed");
+            }
+        });
+    }
+}
+
+fn respond(status: StatusCode, body: &Value) -> Response<Full<Bytes>> {
+    let mut resp = Response::new(Full::new(Bytes::from(body.to_string())));
+    *resp.status_mut() = status;
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, hyper::header::HeaderValue::from_static("application/json"));
+    resp
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response<Full<Bytes>> {
+    respond(status, &json!({ "error": message.into() }))
+}
+
+impl Server {
+    async fn handle(self: Arc<Self>, req: Request<Incoming>) -> Response<Full<Bytes>> {
+        let path: Vec<&str> = req.uri().path().trim_matches('/').split('/').collect();
+        let id = path.get(1).map(|id| id.parse::<usize>());
+        match (req.method(), path.as_slice()) {
+            (&Method::POST, ["runs"]) => self.start(req).await,
+            (_, ["runs", _, ..]) if !matches!(id, Some(Ok(_))) => error(StatusCode::NOT_FOUND, "no such run"),
+            (&Method::GET, ["runs", _]) => self.with_run(id, |run, id| respond(StatusCode::OK, &run.status(id))),
+            (&Method::GET, ["runs", _, "report"]) => self.with_run(id, report),
+            (&Method::DELETE, ["runs", _]) => self.with_run(id, abort),
+            (_, ["runs"] | ["runs", _] | ["runs", _, "report"]) => {
+                error(StatusCode::METHOD_NOT_ALLOWED, format!("{} not allowed here", req.method()))
+            }
+            _ => error(StatusCode::NOT_FOUND, "not found"),
+        }
+    }
+
+    fn with_run(
+        &self,
+        id: Option<Result<usize, std::num::ParseIntError>>,
+        f: impl FnOnce(&mut Run, usize) -> Response<Full<Bytes>>,
+    ) -> Response<Full<Bytes>> {
+        let Some(Ok(id)) = id else {
+            return error(StatusCode::NOT_FOUND, "no such run");
+        };
+        let mut runs = self.runs.lock().unwrap();
+        match id.checked_sub(1).and_then(|index| runs.get_mut(index)) {
+            Some(run) => f(run, id),
+            None => error(StatusCode::NOT_FOUND, format!("no run {}", id)),
+        }
+    }
+
+    /// Check the config the way a run would, then start its child
+    async fn start(self: Arc<Self>, req: Request<Incoming>) -> Response<Full<Bytes>> {
+        let body = match req.into_body().collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(e) => return error(StatusCode::BAD_REQUEST, e.to_string()),
+        };
+        let (url, args) = match parse_config(&body) {
+            Ok(config) => config,
+            Err(message) => return error(StatusCode::BAD_REQUEST, message),
+        };
+        let argv: Vec<&str> = args.iter().map(String::as_str).chain([url.as_str()]).collect();
+        let parsed = match parse_args(&argv) {
+            Ok(parsed) => parsed,
+            Err(message) => return error(StatusCode::BAD_REQUEST, message),
+        };
+
+        let mut runs = self.runs.lock().unwrap();
+        if let Some(index) = runs.iter().position(|run| !run.state.is_over()) {
+            return error(StatusCode::CONFLICT, format!("run {} is still going", index + 1));
+        }
+        let seed = parsed.seed.unwrap_or_else(rand::random);
+        let mut command = processes::child_command(&self.exe, &argv, 0, 1, seed);
+        let child = command
+            .stderr(Stdio::piped())
+            .env(PROGRESS_ENV, "1")
+            .env("NO_COLOR", "1")
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, format!("could not start the run: {}", e)),
+        };
+        runs.push(Run {
+            url,
+            args,
+            state: State::Running,
+            pid: child.id(),
+            progress: None,
+            report: None,
+            error: None,
+        });
+        let id = runs.len();
+        let status = runs[id - 1].status(id);
+        drop(runs);
+
+        let stderr = child.stderr.take();
+        let server = self.clone();
+        tokio::spawn(async move {
+            let mut log = Vec::new();
+            let watch_stderr = async {
+                let Some(stderr) = stderr else {
+                    return;
+                };
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    match serde_json::from_str::<Value>(&line) {
+                        Ok(progress) if progress.get("completed").is_some() => {
+                            server.runs.lock().unwrap()[id - 1].progress = Some(progress)
+                        }
+                        _ => {
+                            if log.len() == LOG_LINES {
+                                log.remove(0);
+                            }
+                            log.push(line);
+                        }
+                    }
+                }
+            };
+            let (output, ()) = tokio::join!(child.wait_with_output(), watch_stderr);
+            server.finish(id, output, log);
+        });
+
+        let mut resp = respond(StatusCode::CREATED, &status);
+        if let Ok(location) = format!("/runs/{}", id).parse() {
+            resp.headers_mut().insert(LOCATION, location);
+        }
+        resp
+    }
+
+    /// Record how run `id`'s child ended
+    fn finish(&self, id: usize, output: std::io::Result<std::process::Output>, log: Vec<String>) {
+        let mut runs = self.runs.lock().unwrap();
+        let run = &mut runs[id - 1];
+        run.pid = None;
+        let output = match output {
+            Ok(output) => output,
+            Err(e) => {
+                run.state = State::Failed;
+                run.error = Some(e.to_string());
+                return;
+            }
+        };
+        let report = output
+            .status
+            .success()
+            .then(|| serde_json::from_slice::<Value>(&output.stdout).ok())
+            .flatten();
+        match report {
+            Some(report) => {
+                run.state = if run.state == State::Aborting {
+                    State::Aborted
+                } else {
+                    State::Finished
+                };
+                // Snapshots stop a little before the end
+                let completed = ["success", "failed"].iter().filter_map(|key| report[key].as_u64()).sum::<u64>();
+                run.progress.get_or_insert_with(|| json!({}))["completed"] = completed.into();
+                run.report = Some(report);
+            }
+            None => {
+                // Whatever the child printed instead, e.g. -f error details
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let mut error = format!("run exited with {}", output.status);
+                for line in stdout.lines().chain(log.iter().map(String::as_str)) {
+                    if !line.trim().is_empty() {
+                        error.push('\n');
+                        error.push_str(line);
+                    }
+                }
+                run.state = State::Failed;
+                run.error = Some(error);
+            }
+        }
+        tracing::info!(id, state = run.state.name(), "run ended");
+    }
+}
+
+/// `{"url": "https://...", "args": ["-n", "1000", "-c", "10"]}`: the target and any
+/// other command-line arguments
+fn parse_config(body: &[u8]) -> Result<(String, Vec<String>), String> {
+    let config: Value = serde_json::from_slice(body).map_err(|e| format!("invalid JSON: {}", e))?;
+    let url = config
+        .get("url")
+        .and_then(Value::as_str)
+        .ok_or("\"url\" must be a string")?
+        .to_string();
+    let args = match config.get("args") {
+        None => Vec::new(),
+        Some(Value::Array(args)) => args
+            .iter()
+            .map(|arg| match arg {
+                Value::String(arg) => Ok(arg.clone()),
+                Value::Number(n) => Ok(n.to_string()),
+                _ => Err("\"args\" must be strings".to_string()),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(_) => return Err("\"args\" must be an array".to_string()),
+    };
+    Ok((url, args))
+}
+
+/// `argv` as a run would parse it, if it only sets [`SERVED_FLAGS`]
+fn parse_args(argv: &[&str]) -> Result<Args, String> {
+    let matches = Args::command()
+        .try_get_matches_from(std::iter::once("benchy").chain(argv.iter().copied()))
+        .map_err(|e| e.to_string().trim_end().to_string())?;
+    let refused = refused(&matches);
+    if !refused.is_empty() {
+        return Err(format!("{} can't be given to a served run", refused.join(", ")));
+    }
+    Args::from_arg_matches(&matches).map_err(|e| e.to_string().trim_end().to_string())
+}
+
+/// The flags set in `matches` that a served run doesn't take, as they are spelled
+fn refused(matches: &ArgMatches) -> Vec<String> {
+    let command = Args::command();
+    // Argument groups are in `matches` too, but only their arguments count
+    command
+        .get_arguments()
+        .filter(|arg| {
+            let id = arg.get_id().as_str();
+            matches.value_source(id) == Some(ValueSource::CommandLine) && !SERVED_FLAGS.contains(&id)
+        })
+        .map(|arg| match (arg.get_long(), arg.get_short()) {
+            (Some(long), _) => format!("--{}", long),
+            (None, Some(short)) => format!("-{}", short),
+            (None, None) => format!("<{}>", arg.get_id().as_str().to_ascii_uppercase()),
+        })
+        .collect()
+}
+
+fn report(run: &mut Run, id: usize) -> Response<Full<Bytes>> {
+    match (&run.report, run.state) {
+        (Some(report), _) => respond(StatusCode::OK, report),
+        (None, State::Failed) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            run.error.clone().unwrap_or_else(|| "run failed".to_string()),
+        ),
+        _ => error(StatusCode::CONFLICT, format!("run {} is still {}", id, run.state.name())),
+    }
+}
+
+fn abort(run: &mut Run, id: usize) -> Response<Full<Bytes>> {
+    if run.state != State::Running {
+        return error(StatusCode::CONFLICT, format!("run {} is already {}", id, run.state.name()));
+    }
+    if let Some(pid) = run.pid {
+        terminate(pid);
+    }
+    run.state = State::Aborting;
+    respond(StatusCode::ACCEPTED, &run.status(id))
+}
+
+/// SIGTERM, which the run takes as a cancellation
+#[cfg(unix)]
+fn terminate(pid: u32) {
+    // SAFETY: kill has no memory safety requirements; the pid is our own child's
+    unsafe {
+        libc::kill(pid as libc::pid_t, libc::SIGTERM);
+    }
+}
+
+#[cfg(not(unix))]
+fn terminate(_pid: u32) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_shaping_flags_are_served() {
+        let argv = [
+            "-n", "1000", "-c", "8", "-p", "4", "--rate", "200", "-m", "PUT", "-d", "{\"a\":1}", "--h2-keepalive",
+            "5s", "--max-p99", "250ms", "http://127.0.0.1:1/",
+        ];
+        let args = parse_args(&argv).expect("every flag is served");
+        assert_eq!(args.requests, 1000);
+        assert_eq!(args.connections, 8);
+        assert_eq!(args.data.as_deref(), Some("{\"a\":1}"));
+    }
+
+    #[test]
+    fn file_script_and_outbound_flags_are_refused() {
+        let refused_flags: &[&[&str]] = &[
+            &["--data-file", "/etc/shadow"],
+            &["--data-dir", "/etc"],
+            &["--header-file", "/etc/passwd"],
+            &["--url-file", "/etc/passwd"],
+            &["--hosts-file", "/etc/hosts"],
+            &["--workload", "/etc/passwd"],
+            &["--script", "/tmp/evil.rhai"],
+            &["--output", "json=/tmp/out.json"],
+            &["--save-results", "/tmp/out.json"],
+            &["--request-log", "/tmp/requests.jsonl"],
+            &["--error-dump", "/tmp/dump"],
+            &["--record", "/tmp/run.json"],
+            &["--heatmap", "/tmp/heatmap.csv"],
+            &["--junit", "/tmp/junit.xml"],
+            &["--publish", "http://attacker.example/"],
+            &["--proxy", "http://attacker.example:3128"],
+            &["--dns-server", "203.0.113.1"],
+            &["--prometheus-listen", "0.0.0.0:9000"],
+            &["--processes", "2"],
+            &["--handshake-only"],
+        ];
+        for flags in refused_flags {
+            let argv: Vec<&str> = flags.iter().copied().chain(["http://127.0.0.1:1/"]).collect();
+            let message = match parse_args(&argv) {
+                Ok(_) => panic!("{:?} was served", flags),
+                Err(message) => message,
+            };
+            assert!(message.contains(flags[0]), "{:?}: {}", flags, message);
+        }
+    }
+
+    #[test]
+    fn refusals_are_all_listed() {
+        let argv = ["--data-file", "/etc/shadow", "--record", "/tmp/x", "-n", "5", "http://127.0.0.1:1/"];
+        let message = parse_args(&argv).err().expect("refused");
+        assert_eq!(message, "--data-file, --record can't be given to a served run");
+    }
+}
@@ -0,0 +1,655 @@
+//! `--output SINK[=PATH]`: several result formats from one run, each to stdout or a
+//! file of its own. Every format is an [`OutputSink`]: each gets the same
+//! [`BenchReport`] at the end, and `influx` also writes a point for every second of the
+//! run. `human` and `plain` (the same without colors) are the text report, which only
+//! goes to stdout.
+//!
+//! `--output-format` stands for a single `--output` to stdout.
+
+use crate::clock;
+use crate::cryptodrift::DriftReport;
+use crate::floor::Breach;
+use crate::output::{OutputFormat, RunSummary};
+use crate::phases::Phases;
+use crate::script::{self, Metrics};
+use crate::textreport::{self, TextDetails};
+use crate::thresholds::Evaluation;
+use crate::timeline::StatusTimeline;
+use crate::units::LatencyUnit;
+use crate::urls;
+use crate::Percentiles;
+use colored::Colorize;
+use serde_json::{json, Value};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Quantiles of the `csv` sink's rows, besides every whole percent
+const TAIL_QUANTILES: [f64; 3] = [0.999, 0.9999, 1.0];
+
+/// What `--output` writes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SinkKind {
+    /// The text report
+    Human,
+    /// The text report without colors
+    Plain,
+    /// The results as one JSON object
+    Json,
+    /// Latency and TTFB at each percentile
+    Csv,
+    Markdown,
+    /// InfluxDB line protocol: a point per second, and the results
+    Influx,
+    /// A tab-separated header and row, as `--output-format table`
+    Table,
+}
+
+impl SinkKind {
+    const ALL: [(SinkKind, &'static str); 7] = [
+        (SinkKind::Human, "human"),
+        (SinkKind::Plain, "plain"),
+        (SinkKind::Json, "json"),
+        (SinkKind::Csv, "csv"),
+        (SinkKind::Markdown, "markdown"),
+        (SinkKind::Influx, "influx"),
+        (SinkKind::Table, "table"),
+    ];
+
+    fn name(self) -> &'static str {
+        SinkKind::ALL.iter().find(|&&(kind, _)| kind == self).map_or("", |&(_, name)| name)
+    }
+}
+
+/// One `--output`: a format, to stdout or a file
+#[derive(Clone, Debug)]
+pub struct OutputSpec {
+    pub kind: SinkKind,
+    pub path: Option<PathBuf>,
+}
+
+impl OutputSpec {
+    /// Parse `SINK` or `SINK=PATH`, e.g. `json=results.json`
+    pub fn parse(s: &str) -> Result<OutputSpec, String> {
+        let (name, path) = match s.split_once('=') {
+            Some((name, "")) => return Err(format!("expected a path after {}=", name)),
+            Some((name, path)) => (name, Some(PathBuf::from(path))),
+            None => (s, None),
+        };
+        let kind = SinkKind::ALL.iter().find(|&&(_, known)| known == name).map(|&(kind, _)| kind).ok_or_else(|| {
+            let names: Vec<&str> = SinkKind::ALL.iter().map(|&(_, name)| name).collect();
+            format!("unknown output {:?}, expected one of {}", name, names.join(", "))
+        })?;
+        Ok(OutputSpec { kind, path })
+    }
+
+    /// The text report, which only goes to stdout
+    pub fn is_text(&self) -> bool {
+        matches!(self.kind, SinkKind::Human | SinkKind::Plain)
+    }
+
+    fn describe(&self) -> String {
+        match &self.path {
+            Some(path) => format!("{}={}", self.kind.name(), path.display()),
+            None => self.kind.name().to_string(),
+        }
+    }
+}
+
+/// The `--output`s given, or the one `--output-format` stands for
+pub fn specs(outputs: &[OutputSpec], format: OutputFormat) -> Vec<OutputSpec> {
+    if !outputs.is_empty() {
+        return outputs.to_vec();
+    }
+    let kind = match format {
+        OutputFormat::Text => SinkKind::Human,
+        OutputFormat::Table => SinkKind::Table,
+        OutputFormat::Markdown => SinkKind::Markdown,
+    };
+    vec![OutputSpec { kind, path: None }]
+}
+
+/// What's wrong with a set of `--output`s, for the preflight
+pub fn problems(outputs: &[OutputSpec]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for output in outputs.iter().filter(|output| output.is_text() && output.path.is_some()) {
+        problems.push(format!("--output {}: the text report only goes to stdout", output.describe()));
+    }
+    let stdout: Vec<String> = outputs.iter().filter(|output| output.path.is_none()).map(OutputSpec::describe).collect();
+    if stdout.len() > 1 {
+        problems.push(format!(
+            "--output {} would all write to stdout; give all but one a path, e.g. {}=FILE",
+            stdout.join(", "),
+            stdout[1]
+        ));
+    }
+    problems
+}
+
+/// What the run's sinks get once a second
+pub struct Interval {
+    pub at: SystemTime,
+    /// Requests completed in the second
+    pub completed: u64,
+    /// Requests completed so far
+    pub total: u64,
+}
+
+/// The results every sink renders
+pub struct BenchReport<'a> {
+    pub summary: &'a RunSummary<'a>,
+    pub evaluations: &'a [Evaluation],
+    /// Resolved for the run's latencies
+    pub unit: LatencyUnit,
+    /// Sorted
+    pub latencies: &'a [Duration],
+    pub ttfbs: &'a [Duration],
+    /// A single run's; not a merged one's
+    pub phases: Option<&'a Phases>,
+    /// `--group-by-header`, or `benchy report --group-by`: what the requests are grouped
+    /// by, and latencies by its value, the most requests first. A single run's
+    pub groups: Option<(&'a str, &'a [(String, Percentiles)])>,
+    /// `--script`'s custom metrics
+    pub script_metrics: &'a Metrics,
+    /// Where `--abort-if-rps-below` stopped the run, with the completions per second
+    /// up to it
+    pub rps_breach: Option<&'a Breach>,
+    /// Each worker's busy share of the measured window; none for a replayed log
+    pub utilization: &'a [f64],
+    /// `--protocol auto`: connections by the HTTP version they negotiated, the most
+    /// first. A single run's
+    pub protocols: &'a [(reqwest::Version, u64)],
+    /// What only the text report shows
+    pub text: TextDetails<'a>,
+}
+
+/// A format results can be written in
+pub trait OutputSink: Send {
+    /// A second of the run has passed
+    fn on_interval(&mut self, _interval: &Interval) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// The run is over
+    fn render(&mut self, report: &BenchReport) -> io::Result<()>;
+}
+
+type Out = BufWriter<Box<dyn Write + Send>>;
+
+/// Every `--output`, opened before the run so a bad path fails it up front
+pub struct Sinks(Vec<(String, Box<dyn OutputSink>)>);
+
+impl Sinks {
+    /// Open `outputs` for a run against `url`
+    pub fn open(outputs: &[OutputSpec], url: &str, no_header: bool) -> Result<Sinks, String> {
+        let mut sinks = Vec::new();
+        for output in outputs {
+            let out: Box<dyn Write + Send> = match &output.path {
+                Some(path) => Box::new(
+                    File::create(path).map_err(|e| format!("--output: could not create {}: {}", path.display(), e))?,
+                ),
+                None => Box::new(io::stdout()),
+            };
+            sinks.push((output.describe(), sink(output.kind, BufWriter::new(out), url, no_header)));
+        }
+        Ok(Sinks(sinks))
+    }
+
+    pub fn interval(&mut self, interval: &Interval) {
+        // A sink that can't be written to is warned about once and dropped
+        self.0.retain_mut(|(name, sink)| match sink.on_interval(interval) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("{} could not write --output {}: {}", "Warning:".yellow(), name, e);
+                false
+            }
+        });
+    }
+
+    pub fn render(&mut self, report: &BenchReport) {
+        for (name, sink) in &mut self.0 {
+            if let Err(e) = sink.render(report) {
+                eprintln!("{} could not write --output {}: {}", "Warning:".yellow(), name, e);
+            }
+        }
+    }
+}
+
+/// The sink for `kind`, writing to `out`; the text report prints to stdout itself
+fn sink(kind: SinkKind, out: Out, url: &str, no_header: bool) -> Box<dyn OutputSink> {
+    match kind {
+        SinkKind::Human => Box::new(HumanSink),
+        SinkKind::Plain => Box::new(PlainSink),
+        SinkKind::Json => Box::new(JsonSink(out)),
+        SinkKind::Csv => Box::new(CsvSink(out)),
+        SinkKind::Markdown => Box::new(MarkdownSink(out)),
+        SinkKind::Influx => Box::new(InfluxSink { out, url: escape_tag(url) }),
+        SinkKind::Table => Box::new(TableSink { out, header: !no_header }),
+    }
+}
+
+struct HumanSink;
+
+impl OutputSink for HumanSink {
+    fn render(&mut self, report: &BenchReport) -> io::Result<()> {
+        textreport::print(report);
+        io::stdout().flush()
+    }
+}
+
+struct PlainSink;
+
+impl OutputSink for PlainSink {
+    fn render(&mut self, report: &BenchReport) -> io::Result<()> {
+        colored::control::set_override(false);
+        HumanSink.render(report)
+    }
+}
+
+struct TableSink {
+    out: Out,
+    header: bool,
+}
+
+impl OutputSink for TableSink {
+    fn render(&mut self, report: &BenchReport) -> io::Result<()> {
+        if self.header {
+            writeln!(self.out, "{}", RunSummary::table_header())?;
+        }
+        writeln!(self.out, "{}", report.summary.table_row())?;
+        self.out.flush()
+    }
+}
+
+struct MarkdownSink(Out);
+
+impl OutputSink for MarkdownSink {
+    fn render(&mut self, report: &BenchReport) -> io::Result<()> {
+        write!(self.0, "{}", report.summary.markdown(report.unit, report.evaluations))?;
+        write!(self.0, "{}", extras_markdown(report))?;
+        self.0.flush()
+    }
+}
+
+struct JsonSink(Out);
+
+impl OutputSink for JsonSink {
+    fn render(&mut self, report: &BenchReport) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut self.0, &to_json(report))?;
+        writeln!(self.0)?;
+        self.0.flush()
+    }
+}
+
+/// The tables the summary's markdown leaves out: worker utilization, the protocol mix,
+/// `--group-by-header` and `--script`'s metrics. The `--abort-if-rps-below` breach is
+/// among the thresholds.
+fn extras_markdown(report: &BenchReport) -> String {
+    let mut md = String::new();
+    if let Some((lowest, min)) = report.utilization.iter().copied().enumerate().min_by(|a, b| a.1.total_cmp(&b.1)) {
+        let workers = report.utilization.len();
+        let avg = report.utilization.iter().sum::<f64>() / workers as f64;
+        md.push_str("\n| Workers | Avg utilization | Lowest |\n|---:|---:|---:|\n");
+        let _ = writeln!(md, "| {} | {:.0}% | {:.0}% (worker {}) |", workers, avg * 100.0, min * 100.0, lowest);
+    }
+    if !report.protocols.is_empty() {
+        md.push_str("\n| Negotiated | Connections |\n|---|---:|\n");
+        for (version, n) in report.protocols {
+            let _ = writeln!(md, "| {:?} | {} |", version, n);
+        }
+    }
+    if let Some((header, rows)) = report.groups.filter(|(_, rows)| !rows.is_empty()) {
+        let _ = write!(md, "\n| {} | Count | P50 | P99 |\n|---|---:|---:|---:|\n", header);
+        for (value, p) in rows {
+            let _ = writeln!(md, "| {} | {} | {} | {} |", value, p.count, report.unit.format(p.p50), report.unit.format(p.p99));
+        }
+    }
+    if !report.script_metrics.is_empty() {
+        md.push_str("\n| Metric | Count | Avg | Min | Max | Sum |\n|---|---:|---:|---:|---:|---:|\n");
+        for (name, m) in report.script_metrics {
+            let avg = script::number(m.sum / m.count.max(1) as f64);
+            let (min, max, sum) = (script::number(m.min), script::number(m.max), script::number(m.sum));
+            let _ = writeln!(md, "| {} | {} | {} | {} | {} | {} |", name, m.count, avg, min, max, sum);
+        }
+    }
+    md
+}
+
+/// The `json` sink's report, which `--publish` sends too
+pub fn to_json(report: &BenchReport) -> Value {
+    let summary = report.summary;
+    let percentiles = |p: &Percentiles| {
+        json!({
+            "count": p.count,
+            "avg_us": micros(p.avg),
+            "p50_us": micros(p.p50),
+            "p95_us": micros(p.p95),
+            "p99_us": micros(p.p99),
+        })
+    };
+    let errors: serde_json::Map<String, Value> = crate::errors::ErrorKind::ALL
+        .into_iter()
+        .map(|kind| (kind.label().to_string(), summary.errors.get(kind).into()))
+        .collect();
+    let request_phases: serde_json::Map<String, Value> = summary
+        .phases
+        .iter()
+        .filter(|(_, p)| p.count > 0)
+        .map(|&(name, p)| (name.to_string(), percentiles(p)))
+        .collect();
+    let groups = report.groups.map(|(header, rows)| {
+        json!({
+            "header": header,
+            "groups": rows.iter().map(|(value, p)| json!({
+                "value": value,
+                "latency": percentiles(p),
+            })).collect::<Vec<Value>>(),
+        })
+    });
+    json!({
+        "url": summary.url,
+        "proto": summary.proto,
+        "conns": summary.conns,
+        "pipeline": summary.pipeline,
+        "started": clock::rfc3339(summary.started),
+        "ended": clock::rfc3339(summary.started + summary.duration),
+        "duration_s": summary.duration.as_secs_f64(),
+        "requests": summary.reqs,
+        "rps": summary.rps,
+        "success": summary.success,
+        "failed": summary.failed,
+        "err_rate": summary.err_rate(),
+        "errors": errors,
+        "latency": percentiles(summary.latency),
+        "request_phases": request_phases,
+        "run_phases": report.phases.map(Phases::to_json),
+        "urls": urls::to_json(summary.urls),
+        "worker_utilization": report.utilization,
+        "protocols": report.protocols.iter().map(|(version, n)| json!({
+            "version": format!("{:?}", version),
+            "connections": n,
+        })).collect::<Vec<Value>>(),
+        "resources": summary.resources.map(|r| json!({
+            "cores": r.cores,
+            "cpu_avg": r.cpu_avg,
+            "cpu_peak": r.cpu_peak,
+            "rss_avg": r.rss_avg,
+            "rss_peak": r.rss_peak,
+        })),
+        "scheduler_lag": summary.scheduler_lag.map(|lag| lag.to_json()),
+        "status_timeline": summary.status_timeline.map(StatusTimeline::to_json),
+        "tls_handshake_drift": summary.crypto_drift.map(DriftReport::to_json),
+        "group_by_header": groups,
+        "script_metrics": script::to_json(report.script_metrics),
+        "rps_breach": report.rps_breach.map(Breach::to_json),
+        "thresholds": report.evaluations.iter().map(|e| json!({
+            "name": e.name,
+            "measured": e.measured,
+            "required": e.required,
+            "passed": e.passed,
+        })).collect::<Vec<Value>>(),
+    })
+}
+
+struct CsvSink(Out);
+
+impl OutputSink for CsvSink {
+    fn render(&mut self, report: &BenchReport) -> io::Result<()> {
+        writeln!(self.0, "quantile,latency_us,ttfb_us")?;
+        let quantiles = (0..100).map(|percent| percent as f64 / 100.0).chain(TAIL_QUANTILES);
+        for q in quantiles {
+            let (Some(latency), Some(ttfb)) = (at_quantile(report.latencies, q), at_quantile(report.ttfbs, q)) else {
+                break;
+            };
+            writeln!(self.0, "{},{:.3},{:.3}", q, micros(latency), micros(ttfb))?;
+        }
+        self.0.flush()
+    }
+}
+
+/// The sample at quantile `q` of sorted `samples`, to the nearest rank
+fn at_quantile(samples: &[Duration], q: f64) -> Option<Duration> {
+    let last = samples.len().checked_sub(1)?;
+    Some(samples[(last as f64 * q).round() as usize])
+}
+
+struct InfluxSink {
+    out: Out,
+    /// The `url` tag, escaped
+    url: String,
+}
+
+impl OutputSink for InfluxSink {
+    fn on_interval(&mut self, interval: &Interval) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "benchy_interval,url={} completed={}i,total={}i {}",
+            self.url,
+            interval.completed,
+            interval.total,
+            nanos(interval.at)
+        )?;
+        self.out.flush()
+    }
+
+    fn render(&mut self, report: &BenchReport) -> io::Result<()> {
+        let summary = report.summary;
+        writeln!(
+            self.out,
+            "benchy,url={},proto={} requests={}i,success={}i,failed={}i,rps={},duration_s={},avg_us={},p50_us={},p95_us={},p99_us={} {}",
+            self.url,
+            escape_tag(summary.proto),
+            summary.reqs,
+            summary.success,
+            summary.failed,
+            summary.rps,
+            summary.duration.as_secs_f64(),
+            micros(summary.latency.avg),
+            micros(summary.latency.p50),
+            micros(summary.latency.p95),
+            micros(summary.latency.p99),
+            nanos(summary.started + summary.duration)
+        )?;
+        for row in summary.urls {
+            writeln!(
+                self.out,
+                "benchy_url,url={},target={} requests={}i,err_rate={},p50_us={},p99_us={} {}",
+                self.url,
+                escape_tag(&row.label()),
+                row.count,
+                row.err_rate,
+                micros(row.p50),
+                micros(row.p99),
+                nanos(summary.started + summary.duration)
+            )?;
+        }
+        self.out.flush()
+    }
+}
+
+/// A tag value with line protocol's special characters escaped
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | '=' | ' ') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn micros(d: Duration) -> f64 {
+    d.as_nanos() as f64 / 1000.0
+}
+
+fn nanos(at: SystemTime) -> u128 {
+    at.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorCounts;
+    use crate::results::SavedRun;
+    use crate::script::Metric;
+    use crate::urls::UrlRow;
+    use std::sync::{Arc, Mutex};
+
+    /// A writer the test reads back
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    fn percentiles(count: usize, ms: u64) -> Percentiles {
+        let d = Duration::from_millis(ms);
+        Percentiles {
+            count,
+            avg: d,
+            p50: d,
+            p95: d * 2,
+            p99: d * 3,
+        }
+    }
+
+    #[test]
+    fn every_sink_renders_the_same_report() {
+        let errors = ErrorCounts::default();
+        let latency = percentiles(1234, 5);
+        let url = |url, count, p50| UrlRow {
+            url,
+            method: None,
+            count,
+            share: count as f64 / 1234.0,
+            expected_share: 0.5,
+            err_rate: 0.0,
+            p50: Duration::from_millis(p50),
+            p99: Duration::from_millis(p50 * 3),
+        };
+        let urls = [url("http://localhost:8080/a", 1000, 4), url("http://localhost:8080/b", 234, 9)];
+        let summary = RunSummary {
+            url: "http://localhost:8080/",
+            proto: "h2",
+            conns: 4,
+            pipeline: 2,
+            reqs: 1234,
+            started: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            duration: Duration::from_secs(10),
+            rps: 123.4,
+            success: 1234,
+            failed: 0,
+            errors: &errors,
+            latency: &latency,
+            phases: Vec::new(),
+            urls: &urls,
+            resources: None,
+            scheduler_lag: None,
+            status_timeline: None,
+            crypto_drift: None,
+        };
+        let groups = vec![("a".to_string(), percentiles(1000, 4)), ("b".to_string(), percentiles(234, 9))];
+        let mut script_metrics = Metrics::new();
+        script_metrics.insert("bytes".to_string(), Metric { count: 2, sum: 30.0, min: 10.0, max: 20.0 });
+        let breach = Breach {
+            at: Duration::from_secs(10),
+            window: Duration::from_secs(5),
+            rps: 90.0,
+            min: 100.0,
+            series: vec![150, 140, 90, 80, 90, 90, 95, 85, 90, 100],
+        };
+        let evaluations = [Evaluation {
+            name: "rps floor",
+            passed: false,
+            measured: "90.00 in the 5s ending at 10s".to_string(),
+            required: ">= 100.00 over any 5s".to_string(),
+        }];
+        let latencies: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        let merged = SavedRun::default();
+        let report = BenchReport {
+            summary: &summary,
+            evaluations: &evaluations,
+            unit: LatencyUnit::Ms,
+            latencies: &latencies,
+            ttfbs: &latencies,
+            phases: None,
+            groups: Some(("x-backend", &groups)),
+            script_metrics: &script_metrics,
+            rps_breach: Some(&breach),
+            utilization: &[0.9, 0.6],
+            protocols: &[(reqwest::Version::HTTP_2, 3), (reqwest::Version::HTTP_11, 1)],
+            text: TextDetails::Merged {
+                run: &merged,
+                ttfb: &latency,
+                runs: "1 run",
+                require_protocol: false,
+            },
+        };
+
+        let kinds = [SinkKind::Json, SinkKind::Csv, SinkKind::Markdown, SinkKind::Influx, SinkKind::Table];
+        let outs: Vec<Shared> = kinds.iter().map(|_| Shared::default()).collect();
+        let mut sinks = Sinks(
+            kinds
+                .iter()
+                .zip(&outs)
+                .map(|(&kind, out)| {
+                    let out: Out = BufWriter::new(Box::new(out.clone()));
+                    (kind.name().to_string(), sink(kind, out, summary.url, false))
+                })
+                .collect(),
+        );
+        sinks.render(&report);
+        let [json, csv, markdown, influx, table] = [0, 1, 2, 3, 4].map(|i| outs[i].text());
+
+        // What every format has: the run's counts
+        let json: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json, to_json(&report));
+        assert_eq!(json["requests"], 1234);
+        assert!(markdown.contains("| Requests | 1234 |"), "{}", markdown);
+        assert!(influx.contains("requests=1234i,success=1234i,failed=0i"), "{}", influx);
+        assert!(table.lines().nth(1).unwrap().contains("\t1234\t"), "{}", table);
+        assert!(csv.contains("\n0.5,51000.000,51000.000\n"), "{}", csv);
+
+        // And what only the report-shaped ones can hold
+        assert_eq!(json["group_by_header"]["header"], "x-backend");
+        assert_eq!(json["group_by_header"]["groups"][1]["value"], "b");
+        assert_eq!(json["group_by_header"]["groups"][1]["latency"]["count"], 234);
+        assert_eq!(json["script_metrics"]["bytes"]["sum"], 30.0);
+        assert_eq!(json["rps_breach"]["completed_per_sec"][2], 90);
+        assert_eq!(json["thresholds"][0]["passed"], false);
+        assert!(markdown.contains("| b | 234 | 9.000ms | 27.000ms |"), "{}", markdown);
+        assert!(markdown.contains("| bytes | 2 | 15 | 10 | 20 | 30 |"), "{}", markdown);
+        assert!(markdown.contains("| rps floor |"), "{}", markdown);
+
+        // Every report-shaped format has the per-URL rows, utilization and protocol mix
+        assert_eq!(json["urls"][1]["url"], "http://localhost:8080/b");
+        assert_eq!((&json["urls"][1]["count"], &json["urls"][1]["p99_us"]), (&234.into(), &27000.0.into()));
+        assert!(markdown.contains("| `http://localhost:8080/a` | 1000 | 0.00% | 4.000ms | 12.000ms |"), "{}", markdown);
+        assert!(markdown.contains("| `http://localhost:8080/b` | 234 | 0.00% | 9.000ms | 27.000ms |"), "{}", markdown);
+        for (path, count) in [("a", 1000), ("b", 234)] {
+            let point = format!("url=http://localhost:8080/,target=http://localhost:8080/{} requests={}i,", path, count);
+            assert!(influx.contains(&point), "{}", influx);
+        }
+        assert_eq!(json["worker_utilization"], json!([0.9, 0.6]));
+        assert!(markdown.contains("| 2 | 75% | 60% (worker 1) |"), "{}", markdown);
+        assert_eq!(json["protocols"][0], json!({"version": "HTTP/2.0", "connections": 3}));
+        assert!(markdown.contains("| HTTP/1.1 | 1 |"), "{}", markdown);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::render::Table;
+use crate::units::LatencyUnit;
+use colored::Colorize;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use crate::clock;
+use reqwest::header::HeaderValue;
+use std::time::{Duration, SystemTime};
+
+/// One straggler kept by `--slowest`
+struct Entry {
+    latency: Duration,
+    /// When the request was sent, relative to the start of the run
+    started: Duration,
+    worker: usize,
+    status: Option<u16>,
+    /// Index into the run's URLs, as `Sample::url`
+    url: usize,
+    /// The `--request-id-header` value sent
+    request_id: Option<String>,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.latency == other.latency
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.latency.cmp(&other.latency)
+    }
+}
+
+/// The N slowest requests of the run, kept in a min-heap so memory stays O(N)
+pub struct Slowest {
+    limit: usize,
+    heap: BinaryHeap<Reverse<Entry>>,
+}
+
+impl Slowest {
+    pub fn new(limit: usize) -> Self {
+        Slowest {
+            limit,
+            heap: BinaryHeap::with_capacity(limit + 1),
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        latency: Duration,
+        started: Duration,
+        worker: usize,
+        status: Option<u16>,
+        url: usize,
+        request_id: Option<&HeaderValue>,
+    ) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.heap.len() == self.limit {
+            match self.heap.peek() {
+                Some(Reverse(fastest)) if latency > fastest.latency => {
+                    self.heap.pop();
+                }
+                _ => return,
+            }
+        }
+        self.heap.push(Reverse(Entry {
+            latency,
+            started,
+            worker,
+            status,
+            url,
+            request_id: request_id.map(|id| String::from_utf8_lossy(id.as_bytes()).into_owned()),
+        }));
+    }
+
+    /// `started` is when the run began, which the send times are relative to. With
+    /// more than one target, `url` names the one each request went to.
+    pub fn print<'a>(self, unit: LatencyUnit, started: SystemTime, url: Option<impl Fn(usize) -> &'a str>) {
+        if self.heap.is_empty() {
+            return;
+        }
+
+        // Ascending order of Reverse is descending latency
+        let entries = self.heap.into_sorted_vec();
+
+        println!("\n{}", format!("--- Slowest {} Requests ---", entries.len()).cyan().bold());
+        let ids = entries.iter().any(|Reverse(entry)| entry.request_id.is_some());
+        let mut header = vec!["Latency", "Sent at", "Worker", "Status"];
+        if ids {
+            header.push("Request ID");
+        }
+        header.push("Wall clock");
+        if url.is_some() {
+            header.push("URL");
+        }
+        let mut table = Table::new(header);
+        for Reverse(entry) in entries {
+            let status = match entry.status {
+                Some(status) => status.to_string(),
+                None => "error".to_string(),
+            };
+            let mut row = vec![
+                unit.format(entry.latency).red().to_string(),
+                format!("+{}", unit.format(entry.started)),
+                entry.worker.to_string(),
+                status,
+            ];
+            if ids {
+                row.push(entry.request_id.unwrap_or_else(|| "-".to_string()));
+            }
+            row.push(clock::rfc3339(started + entry.started));
+            if let Some(url) = &url {
+                row.push(url(entry.url).to_string());
+            }
+            table.row(row);
+        }
+        table.print();
+    }
+}
@@ -0,0 +1,186 @@
+use bytes::{Bytes, BytesMut};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use std::fmt::Write as _;
+use std::time::SystemTime;
+
+const FIRST_NAMES: &[&str] = &[
+    "Alice", "Bob", "Carol", "David", "Emma", "Farid", "Grace", "Hiro", "Ines", "James", "Kenji", "Laura",
+    "Mateo", "Nadia", "Omar", "Priya", "Quinn", "Rosa", "Samir", "Tara", "Umar", "Vera", "Wei", "Yara",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Anderson", "Brown", "Chen", "Diaz", "Evans", "Fischer", "Garcia", "Haddad", "Ito", "Jones", "Kim",
+    "Lopez", "Muller", "Nguyen", "Okafor", "Patel", "Rossi", "Silva", "Tanaka", "Walker", "Zhang",
+];
+
+#[derive(Debug)]
+enum Segment {
+    Static(Bytes),
+    Name,
+    Email,
+    /// Inclusive bounds
+    Int(i64, i64),
+    Uuid,
+    NowIso8601,
+}
+
+/// A `-d` body split once at startup into static text and `{generator}` slots, so
+/// each request only fills in the slots
+#[derive(Debug)]
+pub struct BodyTemplate {
+    segments: Vec<Segment>,
+    /// Rough rendered size, used to reserve the buffer up front
+    size_hint: usize,
+}
+
+impl BodyTemplate {
+    /// Braces that don't hold a known generator stay literal, so ordinary JSON bodies
+    /// are unaffected
+    pub fn parse(s: &str) -> Self {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut rest = s;
+
+        while let Some(open) = rest.find('{') {
+            let generator = rest[open + 1..]
+                .find(['{', '}'])
+                .filter(|&close| rest.as_bytes()[open + 1 + close] == b'}')
+                .and_then(|close| Some((parse_generator(&rest[open + 1..open + 1 + close])?, open + close + 2)));
+
+            match generator {
+                Some((segment, end)) => {
+                    literal.push_str(&rest[..open]);
+                    if !literal.is_empty() {
+                        segments.push(Segment::Static(Bytes::from(std::mem::take(&mut literal))));
+                    }
+                    segments.push(segment);
+                    rest = &rest[end..];
+                }
+                None => {
+                    literal.push_str(&rest[..=open]);
+                    rest = &rest[open + 1..];
+                }
+            }
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            segments.push(Segment::Static(Bytes::from(literal)));
+        }
+
+        let size_hint = segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Static(text) => text.len(),
+                Segment::Name => 16,
+                Segment::Email => 32,
+                Segment::Int(..) => 20,
+                Segment::Uuid => 36,
+                Segment::NowIso8601 => 24,
+            })
+            .sum();
+
+        BodyTemplate { segments, size_hint }
+    }
+
+    /// A body sent as is, braces and all, such as a `--data-file`
+    pub fn fixed(body: Bytes) -> Self {
+        let size_hint = body.len();
+        BodyTemplate {
+            segments: vec![Segment::Static(body)],
+            size_hint,
+        }
+    }
+
+    /// Whether every request gets the same body
+    pub fn is_static(&self) -> bool {
+        self.segments.iter().all(|segment| matches!(segment, Segment::Static(_)))
+    }
+
+    pub fn render(&self, generator: &mut BodyGenerator) -> Bytes {
+        if let [Segment::Static(text)] = self.segments.as_slice() {
+            return text.clone();
+        }
+
+        let BodyGenerator { rng, buf } = generator;
+        buf.reserve(self.size_hint);
+        for segment in &self.segments {
+            match segment {
+                Segment::Static(text) => buf.extend_from_slice(text),
+                Segment::Name => {
+                    let _ = write!(buf, "{} {}", pick(rng, FIRST_NAMES), pick(rng, LAST_NAMES));
+                }
+                Segment::Email => {
+                    let _ = write!(
+                        buf,
+                        "{}.{}{}@example.com",
+                        pick(rng, FIRST_NAMES).to_lowercase(),
+                        pick(rng, LAST_NAMES).to_lowercase(),
+                        rng.gen_range(1..10_000)
+                    );
+                }
+                Segment::Int(min, max) => {
+                    let _ = write!(buf, "{}", rng.gen_range(*min..=*max));
+                }
+                Segment::Uuid => {
+                    // Version 4, RFC 4122 variant
+                    let mut bytes: [u8; 16] = rng.gen();
+                    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+                    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+                    for (i, byte) in bytes.iter().enumerate() {
+                        if matches!(i, 4 | 6 | 8 | 10) {
+                            buf.extend_from_slice(b"-");
+                        }
+                        let _ = write!(buf, "{:02x}", byte);
+                    }
+                }
+                Segment::NowIso8601 => {
+                    let _ = write!(buf, "{}", humantime::format_rfc3339_millis(SystemTime::now()));
+                }
+            }
+        }
+
+        // The buffer's spare capacity is reused once the previous body is dropped
+        buf.split().freeze()
+    }
+}
+
+/// Per-worker generator state: its own seeded RNG and a reusable buffer
+pub struct BodyGenerator {
+    rng: SmallRng,
+    buf: BytesMut,
+}
+
+impl BodyGenerator {
+    /// Workers derive their seed from the run seed, so a seeded run is repeatable
+    pub fn new(seed: u64, worker: usize) -> Self {
+        BodyGenerator {
+            rng: SmallRng::seed_from_u64(seed.wrapping_add(worker as u64)),
+            buf: BytesMut::new(),
+        }
+    }
+}
+
+fn pick<'a>(rng: &mut SmallRng, values: &[&'a str]) -> &'a str {
+    values[rng.gen_range(0..values.len())]
+}
+
+fn parse_generator(s: &str) -> Option<Segment> {
+    let mut words = s.split_whitespace();
+    let segment = match words.next()? {
+        "name()" => Segment::Name,
+        "email()" => Segment::Email,
+        "uuid" => Segment::Uuid,
+        "now_iso8601" => Segment::NowIso8601,
+        "int" => {
+            let min: i64 = words.next()?.parse().ok()?;
+            let max: i64 = words.next()?.parse().ok()?;
+            if min > max {
+                return None;
+            }
+            Segment::Int(min, max)
+        }
+        _ => return None,
+    };
+    words.next().is_none().then_some(segment)
+}
@@ -0,0 +1,597 @@
+//! The `human` and `plain` sinks' report: the results as text, for a terminal. Besides
+//! the [`BenchReport`] every sink gets, each kind of run hands it what only the text
+//! report shows, as [`TextDetails`]. The banner, notes and progress before the results
+//! are printed by the run itself as it goes.
+
+use crate::affinity::AffinityTally;
+use crate::alternate::{SliceStats, Slicer};
+use crate::arrival::{Arrival, BurstLatencies};
+use crate::connect::ConnectStats;
+use crate::cryptodrift::DriftReport;
+use crate::dns::DnsStats;
+use crate::headerfile::SentGroups;
+use crate::heatmap::Heatmap;
+use crate::hol::HolStats;
+use crate::hosts::HostStats;
+use crate::progress::Throughput;
+use crate::protocol::Protocol;
+use crate::report::LogText;
+use crate::results::SavedRun;
+use crate::sinks::BenchReport;
+use crate::slowest::Slowest;
+use crate::transport::TlsStats;
+use crate::upgrade::UpgradeStats;
+use crate::workers::{self, Drain, WorkerReport};
+use crate::{
+    clock, errors, expect, headers, limits, print_percentiles, print_phases, render, report, script, thousands,
+    thresholds, urls, Args, Percentiles, RequestConfig, Stats,
+};
+use colored::Colorize;
+use std::cell::RefCell;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+/// What the text report shows besides the results every sink gets
+pub enum TextDetails<'a> {
+    /// A run of this process's own
+    Run(&'a RunDetails<'a>),
+    /// `--processes`: what the children saved, merged. `runs` names them, e.g.
+    /// "4 processes".
+    Merged {
+        run: &'a SavedRun,
+        ttfb: &'a Percentiles,
+        runs: &'a str,
+        require_protocol: bool,
+    },
+    /// `benchy report --from-request-log`
+    Replayed(&'a LogText<'a>),
+}
+
+/// Everything a run of this process's own counted, for the text report. The tables
+/// that sort their samples as they print are behind `RefCell`s, since sinks only get
+/// the report shared.
+pub struct RunDetails<'a> {
+    pub args: &'a Args,
+    pub config: &'a RequestConfig,
+    pub stats: &'a Stats,
+    pub conn_stats: &'a ConnectStats,
+    pub dns_stats: &'a DnsStats,
+    pub tls_stats: &'a TlsStats,
+    pub upgrade_stats: Option<&'a UpgradeStats>,
+    /// `--protocol` as given, before any fallback
+    pub requested: Protocol,
+    pub is_https: bool,
+    pub seed: u64,
+    /// Paused with SIGUSR2, and the measured window less that
+    pub paused_time: Duration,
+    pub active_time: Duration,
+    /// By `--max-duration`
+    pub stopped_early: bool,
+    /// By SIGTERM or `--abort-if-rps-below`
+    pub cancelled: bool,
+    pub throughput: &'a Throughput,
+    /// Requests sent per second, for `--rate`
+    pub submissions: &'a Throughput,
+    pub drain: Option<&'a Drain>,
+    pub worker_reports: &'a [WorkerReport],
+    /// `--correct-latency`: latency from when each request was scheduled
+    pub response: Option<&'a Percentiles>,
+    pub client_queue: &'a Percentiles,
+    pub continue_wait: &'a Percentiles,
+    pub affinity: Option<&'a AffinityTally>,
+    pub heatmap: Option<&'a Heatmap>,
+    pub slicer: Option<&'a Slicer>,
+    pub slowest: RefCell<Option<Slowest>>,
+    pub burst_latencies: RefCell<BurstLatencies>,
+    pub sent_groups: RefCell<Option<SentGroups>>,
+    pub slice_stats: RefCell<Option<SliceStats>>,
+    pub host_stats: RefCell<Option<HostStats>>,
+    pub hol_stats: RefCell<Option<HolStats>>,
+}
+
+/// The results section and thresholds, to stdout
+pub fn print(report: &BenchReport) {
+    match &report.text {
+        TextDetails::Run(run) => print_run(report, run),
+        TextDetails::Merged { run, ttfb, runs, require_protocol } => {
+            run.print(report.summary.latency, ttfb, report.unit, runs, *require_protocol)
+        }
+        TextDetails::Replayed(log) => report::print_log(report, log),
+    }
+    thresholds::print(report.evaluations);
+}
+
+fn print_run(report: &BenchReport, run: &RunDetails) {
+    let RunDetails {
+        args,
+        config,
+        stats,
+        conn_stats,
+        dns_stats,
+        tls_stats,
+        upgrade_stats,
+        requested,
+        is_https,
+        seed,
+        paused_time,
+        active_time,
+        stopped_early,
+        cancelled,
+        throughput,
+        submissions,
+        drain,
+        worker_reports,
+        response,
+        client_queue,
+        continue_wait,
+        affinity,
+        heatmap,
+        slicer,
+        ..
+    } = *run;
+    let (summary, unit) = (report.summary, report.unit);
+    let run_phases = report.phases.expect("a run's report has its phases");
+    let (completed, rps, success, failed, latency) =
+        (summary.reqs, summary.rps, summary.success, summary.failed, summary.latency);
+
+    println!("\n{}", "--- Results ---".cyan().bold());
+    render::print("Total time:".white(), format!("{:?} ({})", run_phases.total(), run_phases.describe()));
+    render::print("Started:".white(), clock::rfc3339(run_phases.started()));
+    render::print("Ended:".white(), clock::rfc3339(run_phases.ended()));
+    if !paused_time.is_zero() {
+        render::print("Active time:".white(), format!("{:?} ({:?} paused)", active_time, paused_time));
+    }
+    if stopped_early {
+        render::print(
+            "Partial:".white(),
+            format!(
+                "stopped at max duration with {}/{} requests completed",
+                thousands(completed),
+                thousands(args.requests)
+            )
+            .yellow()
+            .bold(),
+        );
+    }
+    if cancelled {
+        render::print(
+            "Partial:".white(),
+            format!(
+                "{} with {}/{} requests completed",
+                if report.rps_breach.is_some() { "stopped by --abort-if-rps-below" } else { "cancelled by SIGTERM" },
+                thousands(completed),
+                thousands(args.requests)
+            )
+            .yellow()
+            .bold(),
+        );
+    }
+    if let Some(drain) = drain {
+        render::print(
+            "Drain:".white(),
+            format!(
+                "{} in flight at last submission, drained in {}",
+                thousands(drain.in_flight),
+                unit.format(drain.time)
+            ),
+        );
+    }
+    let abandoned = stats.requests.abandoned();
+    if abandoned > 0 {
+        render::print(
+            "Abandoned:".white(),
+            format!("{} requests still in flight after --drain-timeout", thousands(abandoned))
+                .red()
+                .bold(),
+        );
+    }
+    stats.requests.print(args.requests);
+    render::print("Requests/sec:".white(), format!("{:.2}", rps).green().bold());
+    if let Some((best, worst)) = throughput.range() {
+        render::print(
+            "Throughput:".white(),
+            format!(
+                "avg {:.0}, best {}, worst {} (req/s over 1s windows)",
+                rps,
+                best.to_string().green(),
+                if worst * 2 < best { worst.to_string().yellow() } else { worst.to_string().green() }
+            ),
+        );
+    }
+    workers::print_utilization(report.utilization);
+    if let Some(resources) = &summary.resources {
+        resources.print();
+    }
+    if let Some(lag) = &summary.scheduler_lag {
+        lag.print();
+    }
+    if let Some(rate) = args.rate {
+        let seed = match args.arrival {
+            Arrival::Poisson => format!(" (seed {})", seed),
+            Arrival::Uniform => String::new(),
+        };
+        render::print("Arrival:".white(), format!("{}, {} req/s target{}", args.arrival.name(), rate, seed));
+        stats.pacing.print(rate, paused_time, submissions.range(), unit);
+    }
+    if let Some(burst) = args.burst {
+        render::print("Burst:".white(), format!("{} every {}", burst.size, humantime::format_duration(burst.interval)));
+        // Sends bunch up at the start of each burst, so one-second windows say little
+        let target = burst.size as f64 / burst.interval.as_secs_f64();
+        stats.pacing.print(target, paused_time, None, unit);
+        let backlogged = stats.backlogged.load(Ordering::Relaxed);
+        if backlogged > 0 {
+            render::print(
+                "Backlogged:".white(),
+                format!("{} requests dropped, the previous burst was still in flight", thousands(backlogged)).yellow(),
+            );
+        }
+    }
+    render::print("Success:".white(), success.to_string().green());
+    if failed > 0 {
+        render::print("Failed:".white(), failed.to_string().red().bold());
+    } else {
+        render::print("Failed:".white(), "0".dimmed());
+    }
+    let established = conn_stats.established.load(Ordering::Relaxed);
+    render::print("Connections:".white(), established.to_string().green());
+    if let Some(upgrade_stats) = &upgrade_stats {
+        upgrade_stats.print();
+    }
+    if args.http3 {
+        render::print("Congestion:".white(), args.h3_congestion.unwrap_or_default().name().green());
+    }
+    let remotes = conn_stats.remotes();
+    if !remotes.is_empty() {
+        let by_remote: Vec<String> = remotes.iter().map(|(ip, n)| format!("{} \u{d7}{}", ip, n)).collect();
+        render::print("Remote:".white(), by_remote.join(", ").green());
+    }
+    if args.spread_dns {
+        let spread = dns_stats.spread();
+        let line = if spread.is_empty() {
+            "nothing resolved, the target is an IP".dimmed().to_string()
+        } else {
+            let by_address: Vec<String> = spread.iter().map(|(ip, n)| format!("{} \u{d7}{}", ip, n)).collect();
+            format!("{} {}", by_address.join(", "), "(connections assigned each address)".dimmed())
+        };
+        render::print("DNS spread:".white(), line);
+    }
+    if requested != args.protocol {
+        render::print(
+            "Protocol:".white(),
+            format!("{} (fell back from {})", args.protocol.label().yellow(), requested.label()),
+        );
+    }
+    let mismatches = stats.version_mismatches.load(Ordering::Relaxed);
+    if mismatches > 0 {
+        let line = format!("{} requests not served over {}", mismatches, args.protocol.label());
+        let line = if args.require_protocol { line.red().bold() } else { line.yellow() };
+        render::print("Mismatched:".white(), line);
+    }
+    // Which connections of a mixed fleet didn't get HTTP/2, and from where
+    let downgrades = conn_stats.downgrades();
+    if !downgrades.is_empty() {
+        let workers: Vec<String> = downgrades
+            .iter()
+            .map(|(worker, remote, n)| match remote {
+                Some(remote) => format!("{} ({} \u{d7}{})", worker, remote, n),
+                None => format!("{} (\u{d7}{})", worker, n),
+            })
+            .collect();
+        render::print_wrapped(
+            "Downgraded:".white(),
+            format!(
+                "{} of {} workers' connections negotiated HTTP/1.1 instead of HTTP/2: {}",
+                downgrades.len(),
+                args.tasks(),
+                workers.join(", ")
+            )
+            .yellow(),
+        );
+    }
+    if args.protocol == Protocol::Auto {
+        let mix: Vec<String> =
+            report.protocols.iter().map(|(version, n)| format!("{} connections {:?}", n, version)).collect();
+        if mix.is_empty() {
+            render::print("Protocols:".white(), "no responses".dimmed());
+        } else {
+            render::print("Protocols:".white(), mix.join(", ").green());
+        }
+    }
+
+    let goaway_connections = stats.goaway_connections.load(Ordering::Relaxed);
+    let retried = stats.retried.load(Ordering::Relaxed);
+    if goaway_connections > 0 || retried > 0 {
+        render::print(
+            "GOAWAY:".white(),
+            format!(
+                "{} connections, {} requests retried on new connections",
+                goaway_connections.to_string().yellow(),
+                retried.to_string().yellow()
+            ),
+        );
+    }
+
+    if let Some(retry) = &config.status_retry {
+        retry.print();
+    }
+
+    let keepalive_connections = stats.keepalive_connections.load(Ordering::Relaxed);
+    if keepalive_connections > 0 {
+        render::print(
+            "Keepalive:".white(),
+            format!("{} connections found dead by PING and re-established", keepalive_connections.to_string().yellow()),
+        );
+    }
+
+    if args.h3_alt_svc {
+        let alt = &stats.alt_svc;
+        let switched = alt.switched.load(Ordering::Relaxed);
+        let not_switched = alt.not_switched.load(Ordering::Relaxed);
+        let mut switch_times = alt.take_switch_times();
+        switch_times.sort_unstable();
+
+        let mut line = format!("{} of {} connections switched to HTTP/3", switched, switched + not_switched);
+        if let (Some(first), Some(last)) = (switch_times.first(), switch_times.last()) {
+            line.push_str(&format!(", at {:?} to {:?} into the run", first, last));
+        }
+        render::print("Alt-Svc:".white(), line.green());
+        render::print(
+            "By protocol:".white(),
+            format!(
+                "{} over HTTP/2, {} over HTTP/3",
+                alt.h2_requests.load(Ordering::Relaxed).to_string().green(),
+                alt.h3_requests.load(Ordering::Relaxed).to_string().green()
+            ),
+        );
+        if not_switched > 0 {
+            println!(
+                "{} {} connections found no usable h3 Alt-Svc and stayed on HTTP/2",
+                "Warning:".yellow(),
+                not_switched
+            );
+        }
+    }
+
+    stats.sent.print(active_time);
+    if let Some((min, avg, max)) = stats.body.sizes() {
+        render::print(
+            "Body size:".white(),
+            format!(
+                "min {} B, avg {} B, max {} B",
+                min.to_string().green(),
+                avg.to_string().green(),
+                max.to_string().green()
+            ),
+        );
+    }
+    if let Some((min, avg, max)) = stats.body.declared_sizes() {
+        render::print(
+            "Virtual size:".white(),
+            format!(
+                "min {} B, avg {} B, max {} B {}",
+                min.to_string().green(),
+                avg.to_string().green(),
+                max.to_string().green(),
+                "(HEAD Content-Length, not transferred)".dimmed()
+            ),
+        );
+    }
+    let size_mismatches = stats.body.size_mismatches.load(Ordering::Relaxed);
+    if size_mismatches > 0 {
+        render::print(
+            "Size check:".white(),
+            format!("{} responses outside the expected size", size_mismatches).red().bold(),
+        );
+    }
+    let length_mismatches = stats.body.length_mismatches.load(Ordering::Relaxed);
+    if let Some(example) = stats.body.length_example().filter(|_| length_mismatches > 0) {
+        render::print(
+            "Length check:".white(),
+            format!(
+                "{} {}",
+                format!("{} bodies disagreed with Content-Length", length_mismatches).red().bold(),
+                format!("(first: {})", example).dimmed()
+            ),
+        );
+    }
+    if args.verify_consistency {
+        let variants = stats.body.variants();
+        let shown: Vec<String> = variants
+            .iter()
+            .take(5)
+            .map(|(hash, n)| format!("{:016x} x{}", hash, n))
+            .collect();
+        let more = if variants.len() > shown.len() { ", ..." } else { "" };
+        let line = format!("{} ({}{})", variants.len(), shown.join(", "), more);
+        let line = if variants.len() > 1 { line.yellow() } else { line.green() };
+        render::print("Variants:".white(), line);
+    }
+    let digest_mismatches = stats.body.digest_mismatches.load(Ordering::Relaxed);
+    if digest_mismatches > 0 {
+        render::print(
+            "Body digest:".white(),
+            format!("{} responses did not match --expect-body-sha256", digest_mismatches).red().bold(),
+        );
+    }
+    if let Some(dump) = &config.error_dump {
+        if dump.failures() > 0 {
+            render::print(
+                "Dumped:".white(),
+                format!(
+                    "{} of {} failures written to {}",
+                    dump.dumped().to_string().yellow(),
+                    dump.failures(),
+                    dump.dir().display()
+                ),
+            );
+        }
+    }
+    if let Some(checks) = &config.json_checks {
+        let parse_failures = checks.parse_failures.load(Ordering::Relaxed);
+        let assertion_failures = checks.assertion_failures.load(Ordering::Relaxed);
+        if parse_failures > 0 || assertion_failures > 0 {
+            render::print(
+                "JSON:".white(),
+                format!(
+                    "{} bodies failed --assert-json, {} were not valid JSON",
+                    assertion_failures, parse_failures
+                )
+                .red()
+                .bold(),
+            );
+        }
+    }
+    let cors_failures = stats.cors_failures.load(Ordering::Relaxed);
+    if cors_failures > 0 {
+        render::print(
+            "CORS:".white(),
+            format!("{} responses had missing or mismatching CORS headers", cors_failures).red().bold(),
+        );
+    }
+    let episodes = stats.backoff.episodes();
+    if episodes > 0 {
+        render::print(
+            "Backoff:".white(),
+            format!("{} episodes, {:?} paused across workers", episodes.to_string().yellow(), stats.backoff.paused()),
+        );
+    }
+    if args.expect_continue {
+        let expect = &stats.expect;
+        let timed_out = expect.timed_out.load(Ordering::Relaxed);
+        let line = format!(
+            "{} got 100 Continue, {} timed out after {:?}, {} answered with a final status first",
+            continue_wait.count,
+            timed_out,
+            expect::CONTINUE_TIMEOUT,
+            expect.final_first.load(Ordering::Relaxed)
+        );
+        render::print("Continue:".white(), if timed_out > 0 { line.yellow() } else { line.green() });
+        let expectation_failed = expect.expectation_failed.load(Ordering::Relaxed);
+        if expectation_failed > 0 {
+            render::print(
+                "417:".white(),
+                format!("{} responses were 417 Expectation Failed", expectation_failed).red().bold(),
+            );
+        }
+    }
+    let header_failures = stats.header_assertion_failures.load(Ordering::Relaxed);
+    if header_failures > 0 {
+        render::print(
+            "Assertions:".white(),
+            format!("{} responses failed header checks", header_failures).red().bold(),
+        );
+    }
+    let trailer_failures = stats.trailer_assertion_failures.load(Ordering::Relaxed);
+    if trailer_failures > 0 {
+        render::print(
+            "Trailers:".white(),
+            format!("{} responses failed trailer checks", trailer_failures).red().bold(),
+        );
+    }
+    if let Some(name) = &config.request_id {
+        stats.request_ids.print(name);
+    }
+    let script_failures = stats.script_failures.load(Ordering::Relaxed);
+    if script_failures > 0 {
+        render::print(
+            "Script:".white(),
+            format!("{} responses marked failed by on_response", script_failures).red().bold(),
+        );
+    }
+
+    stats.errors.print();
+    if stats.errors.get(errors::ErrorKind::HeadersTooLarge) > 0 {
+        stats.sent.print_largest(args.max_header_bytes);
+    }
+    limits::print_hint(stats.errors.get(errors::ErrorKind::ClientResources), args.connections);
+    if let Some(timeline) = summary.status_timeline {
+        timeline.print();
+    }
+    if let Some(warning) = summary.crypto_drift.and_then(DriftReport::warning) {
+        render::print_wrapped("Warning:".yellow().bold(), warning.red().bold());
+    }
+
+    match response {
+        // Service latency is what the server took from send; response latency adds the
+        // time each request spent queued past its schedule
+        Some(response) => {
+            print_percentiles("Latency (service, from send)", latency, unit);
+            print_percentiles("Latency (response, from schedule)", response, unit);
+        }
+        None => print_percentiles("Latency", latency, unit),
+    }
+    print_percentiles("Client Queue", client_queue, unit);
+    // Over a tenth of the tail spent waiting inside benchy isn't the server's latency
+    if client_queue.p99 > latency.p99 / 10 {
+        render::print_wrapped(
+            "Warning:".yellow().bold(),
+            format!(
+                "client queue P99 is {} of latency P99 {}; benchy's own -p or CPU, not the server, is shaping latency",
+                unit.format(client_queue.p99),
+                unit.format(latency.p99)
+            )
+            .yellow(),
+        );
+    }
+    if let Some(slowest) = run.slowest.borrow_mut().take() {
+        slowest.print(unit, run_phases.started(), config.several_urls().then_some(|index| config.url(index)));
+    }
+    script::print(report.script_metrics);
+    if args.per_worker_stats {
+        let downgraded: Vec<usize> = downgrades.iter().map(|&(worker, _, _)| worker).collect();
+        workers::print(worker_reports, report.utilization, &downgraded, unit);
+    }
+
+    if let Some((header, rows)) = report.groups {
+        headers::print_groups(header, rows, unit);
+    }
+    if let (Some(sent_groups), Some(file)) = (run.sent_groups.borrow_mut().as_mut(), &config.header_file) {
+        sent_groups.print(file, unit);
+    }
+    if let Some(lint) = &config.lint {
+        lint.print();
+    }
+    if let Some(rate_limits) = &config.rate_limits {
+        let completed = stats.requests.completed.load(Ordering::Relaxed);
+        rate_limits.print(stats.success.load(Ordering::Relaxed), completed, active_time);
+    }
+    urls::print(summary.urls, unit);
+    if let (Some(slice_stats), Some(slicer), Some(targets)) =
+        (run.slice_stats.borrow_mut().as_mut(), slicer, &args.alternate)
+    {
+        slice_stats.print(targets, &slicer.starts(), unit);
+    }
+    if let (Some(host_stats), Some(hosts)) = (run.host_stats.borrow_mut().as_mut(), &config.hosts) {
+        host_stats.print(hosts, active_time, unit);
+    }
+    if let (Some(hol_stats), Some(hol)) = (run.hol_stats.borrow_mut().as_mut(), &config.hol) {
+        hol_stats.print(hol, summary.proto, unit);
+    }
+    if let Some(compression) = &config.compression {
+        compression.print();
+    }
+    if let Some(affinity) = affinity {
+        affinity.print();
+    }
+
+    for tally in &stats.header_tallies {
+        tally.print();
+    }
+
+    print_phases(&summary.phases, unit);
+    run.burst_latencies.borrow_mut().print(unit);
+    if let Some(heatmap) = heatmap {
+        heatmap.print(render::terminal_width());
+    }
+
+    if is_https || args.http3 {
+        println!("\n{}", "--- TLS ---".cyan().bold());
+        render::print("Handshakes:".white(), tls_stats.handshakes.load(Ordering::Relaxed).to_string().green());
+        render::print("Full:".white(), tls_stats.full.load(Ordering::Relaxed).to_string().green());
+        render::print("Resumed:".white(), tls_stats.resumed.load(Ordering::Relaxed).to_string().green());
+        let failed = tls_stats.failed.load(Ordering::Relaxed);
+        if failed > 0 {
+            render::print("Failed:".white(), failed.to_string().red());
+        }
+    }
+}
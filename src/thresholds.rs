@@ -0,0 +1,104 @@
+use crate::output::RunSummary;
+use crate::render;
+use crate::Args;
+use crate::units::LatencyUnit;
+use colored::Colorize;
+use std::time::Duration;
+
+/// Pass/fail limits a run is evaluated against (`--max-p99`, `--max-error-rate`, `--min-rps`)
+#[derive(Default)]
+pub struct Thresholds {
+    pub max_p99: Option<Duration>,
+    /// Fraction of completed requests, e.g. 0.01 for 1%
+    pub max_error_rate: Option<f64>,
+    pub min_rps: Option<f64>,
+}
+
+/// One threshold's outcome
+pub struct Evaluation {
+    pub name: &'static str,
+    pub passed: bool,
+    pub measured: String,
+    pub required: String,
+}
+
+impl Thresholds {
+    pub fn of(args: &Args) -> Thresholds {
+        Thresholds {
+            max_p99: args.max_p99,
+            max_error_rate: args.max_error_rate,
+            min_rps: args.min_rps,
+        }
+    }
+
+    /// Each threshold set, as its requirement, e.g. `p99 <= 250.000ms`
+    pub fn describe(&self, unit: LatencyUnit) -> Vec<String> {
+        let mut described = Vec::new();
+        if let Some(max) = self.max_p99 {
+            described.push(format!("p99 <= {}", unit.resolve(max).format(max)));
+        }
+        if let Some(max) = self.max_error_rate {
+            described.push(format!("error rate <= {:.2}%", max * 100.0));
+        }
+        if let Some(min) = self.min_rps {
+            described.push(format!("min rps >= {:.2}", min));
+        }
+        described
+    }
+
+    pub fn evaluate(&self, summary: &RunSummary, unit: LatencyUnit) -> Vec<Evaluation> {
+        let mut evaluations = Vec::new();
+        if let Some(max) = self.max_p99 {
+            let p99 = summary.latency.p99;
+            evaluations.push(Evaluation {
+                name: "p99",
+                passed: p99 <= max,
+                measured: unit.format(p99),
+                required: format!("<= {}", unit.format(max)),
+            });
+        }
+        if let Some(max) = self.max_error_rate {
+            let rate = summary.err_rate();
+            evaluations.push(Evaluation {
+                name: "error rate",
+                passed: rate <= max,
+                measured: format!("{:.2}%", rate * 100.0),
+                required: format!("<= {:.2}%", max * 100.0),
+            });
+        }
+        if let Some(min) = self.min_rps {
+            evaluations.push(Evaluation {
+                name: "min rps",
+                passed: summary.rps >= min,
+                measured: format!("{:.2}", summary.rps),
+                required: format!(">= {:.2}", min),
+            });
+        }
+        evaluations
+    }
+}
+
+pub fn print(evaluations: &[Evaluation]) {
+    if evaluations.is_empty() {
+        return;
+    }
+
+    println!("\n{}", "--- Thresholds ---".cyan().bold());
+    for e in evaluations {
+        let verdict = if e.passed { "PASS".green().bold() } else { "FAIL".red().bold() };
+        render::print(format!("{}:", e.name).white(), format!("{} {} (required {})", verdict, e.measured, e.required));
+    }
+}
+
+/// Parse `--max-error-rate`: a percentage (`1%`) or a fraction (`0.01`)
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+    let s = s.trim();
+    let rate = match s.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>().map_err(|e| e.to_string())? / 100.0,
+        None => s.parse::<f64>().map_err(|e| e.to_string())?,
+    };
+    if !(0.0..=1.0).contains(&rate) {
+        return Err("expected a rate between 0 and 1 (or 0% and 100%)".to_string());
+    }
+    Ok(rate)
+}
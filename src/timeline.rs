@@ -0,0 +1,250 @@
+//! Responses by status class in each one-second window, to catch a backend that flaps
+//! between healthy and failing: its run-wide error rate can look fine while users see
+//! outage after outage. A window is ok, degraded or down by its share of 5xx responses
+//! and transport errors; a run of windows that aren't ok is an error burst, and several
+//! of them with healthy windows between is flapping.
+
+use crate::clock;
+use crate::render;
+use colored::Colorize;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+/// Status classes by a status's first digit, then requests that got no status at all
+const CLASSES: [&str; 6] = ["1xx", "2xx", "3xx", "4xx", "5xx", "error"];
+
+/// Share of a window's responses failing at which it is down rather than degraded
+const DOWN: f64 = 0.5;
+
+/// Error bursts from which the run is called flapping
+const FLAPPING_BURSTS: usize = 3;
+
+/// Windows per line of the strip, so each line is a minute
+const STRIP_WIDTH: usize = 60;
+
+/// Responses so far by status class, updated by the workers
+#[derive(Default)]
+pub struct StatusClasses([AtomicU64; CLASSES.len()]);
+
+impl StatusClasses {
+    /// A completed request, with its status unless it failed before getting one
+    pub fn record(&self, status: Option<u16>) {
+        let class = match status {
+            Some(status @ 100..=599) => status as usize / 100 - 1,
+            _ => CLASSES.len() - 1,
+        };
+        self.0[class].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn totals(&self) -> [u64; CLASSES.len()] {
+        std::array::from_fn(|class| self.0[class].load(Ordering::Relaxed))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Health {
+    /// Nothing completed, or the run was paused
+    Idle,
+    Ok,
+    Degraded,
+    Down,
+}
+
+impl Health {
+    fn name(self) -> &'static str {
+        match self {
+            Health::Idle => "idle",
+            Health::Ok => "ok",
+            Health::Degraded => "degraded",
+            Health::Down => "down",
+        }
+    }
+
+    fn symbol(self) -> String {
+        match self {
+            Health::Idle => "·".dimmed().to_string(),
+            Health::Ok => "▁".green().to_string(),
+            Health::Degraded => "▄".yellow().to_string(),
+            Health::Down => "█".red().to_string(),
+        }
+    }
+}
+
+struct Window {
+    at: SystemTime,
+    counts: [u64; CLASSES.len()],
+    paused: bool,
+}
+
+impl Window {
+    fn failing(&self) -> u64 {
+        self.counts[4] + self.counts[5]
+    }
+
+    fn completed(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+/// Error bursts and the healthy stretches between them, in windows; idle windows
+/// are skipped, neither ending a stretch nor adding to it
+struct Runs {
+    bursts: Vec<usize>,
+    longest_healthy: usize,
+}
+
+/// Status-class counts per window, sampled while the run is going
+pub struct StatusTimeline {
+    /// `--flap-threshold`, as a fraction
+    threshold: f64,
+    windows: Vec<Window>,
+    last: [u64; CLASSES.len()],
+}
+
+impl StatusTimeline {
+    pub fn new(threshold_percent: f64) -> StatusTimeline {
+        StatusTimeline {
+            threshold: threshold_percent / 100.0,
+            windows: Vec::new(),
+            last: [0; CLASSES.len()],
+        }
+    }
+
+    /// Close a window ending `at`
+    pub fn tick(&mut self, classes: &StatusClasses, at: SystemTime, paused: bool) {
+        let totals = classes.totals();
+        let counts = std::array::from_fn(|class| totals[class].saturating_sub(self.last[class]));
+        self.last = totals;
+        self.windows.push(Window { at, counts, paused });
+    }
+
+    /// Drop the windows after the last one anything completed in
+    pub fn trim_idle_tail(&mut self) {
+        while self.windows.last().is_some_and(|window| window.completed() == 0) {
+            self.windows.pop();
+        }
+    }
+
+    fn health(&self, window: &Window) -> Health {
+        let completed = window.completed();
+        if window.paused || completed == 0 {
+            return Health::Idle;
+        }
+        let rate = window.failing() as f64 / completed as f64;
+        if rate >= DOWN {
+            Health::Down
+        } else if rate > self.threshold {
+            Health::Degraded
+        } else {
+            Health::Ok
+        }
+    }
+
+    fn runs(&self) -> Runs {
+        let mut runs = Runs {
+            bursts: Vec::new(),
+            longest_healthy: 0,
+        };
+        let (mut burst, mut healthy) = (0, 0);
+        for health in self.windows.iter().map(|window| self.health(window)) {
+            match health {
+                Health::Idle => continue,
+                Health::Ok => {
+                    if burst > 0 {
+                        runs.bursts.push(burst);
+                        burst = 0;
+                    }
+                    healthy += 1;
+                    runs.longest_healthy = runs.longest_healthy.max(healthy);
+                }
+                Health::Degraded | Health::Down => {
+                    healthy = 0;
+                    burst += 1;
+                }
+            }
+        }
+        if burst > 0 {
+            runs.bursts.push(burst);
+        }
+        runs
+    }
+
+    /// For `--output json`: every window's counts and health, and the bursts
+    pub fn to_json(&self) -> Value {
+        let runs = self.runs();
+        let windows: Vec<Value> = self
+            .windows
+            .iter()
+            .map(|window| {
+                let mut value = json!({
+                    "at": clock::rfc3339(window.at),
+                    "health": self.health(window).name(),
+                });
+                for (class, count) in CLASSES.iter().zip(window.counts) {
+                    value[*class] = count.into();
+                }
+                value
+            })
+            .collect();
+        json!({
+            "threshold_pct": self.threshold * 100.0,
+            "windows": windows,
+            "error_bursts": runs.bursts.len(),
+            "burst_durations_s": runs.bursts,
+            "longest_healthy_s": runs.longest_healthy,
+            "flapping": runs.bursts.len() >= FLAPPING_BURSTS,
+        })
+    }
+
+    /// The strip and the bursts, if there were any
+    pub fn print(&self) {
+        let runs = self.runs();
+        if runs.bursts.is_empty() {
+            return;
+        }
+        println!("\n{}", "--- Status Timeline ---".cyan().bold());
+        for (line, windows) in self.windows.chunks(STRIP_WIDTH).enumerate() {
+            let strip: String = windows.iter().map(|window| self.health(window).symbol()).collect();
+            render::print(format!("+{}s:", line * STRIP_WIDTH).white(), strip);
+        }
+        render::print(
+            "Legend:".white(),
+            format!(
+                "{} ok, {} over {:.0}% 5xx or errors, {} {:.0}% or more, {} idle; 1 per second",
+                Health::Ok.symbol(),
+                Health::Degraded.symbol(),
+                self.threshold * 100.0,
+                Health::Down.symbol(),
+                DOWN * 100.0,
+                Health::Idle.symbol()
+            ),
+        );
+        let longest = runs.bursts.iter().max().copied().unwrap_or(0);
+        let total: usize = runs.bursts.iter().sum();
+        render::print(
+            "Error bursts:".white(),
+            format!(
+                "{}, {}s in all, longest {}s, avg {:.1}s",
+                runs.bursts.len(),
+                total,
+                longest,
+                total as f64 / runs.bursts.len() as f64
+            )
+            .yellow(),
+        );
+        render::print("Healthy:".white(), format!("longest fully healthy stretch {}s", runs.longest_healthy));
+        if runs.bursts.len() >= FLAPPING_BURSTS {
+            render::print_wrapped(
+                "Flapping:".white(),
+                format!(
+                    "the error rate crossed {:.0}% {} times; the run-wide error rate hides repeated outages",
+                    self.threshold * 100.0,
+                    runs.bursts.len()
+                )
+                .red()
+                .bold(),
+            );
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! Response trailers for `--assert-trailer`.
+//!
+//! reqwest reads bodies as data only and drops trailer frames, so responses that
+//! need their trailers get a body wrapper that keeps them as they pass.
+
+use bytes::Bytes;
+use http_body::{Body, Frame, SizeHint};
+use reqwest::header::HeaderMap;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{ready, Context, Poll};
+
+/// Where a response's trailers end up once its body has been read, found in the
+/// response's extensions
+#[derive(Clone, Default)]
+pub struct Trailers(Arc<OnceLock<HeaderMap>>);
+
+impl Trailers {
+    /// `None` until the body has been read, and after that if it had no trailers
+    pub fn get(&self) -> Option<&HeaderMap> {
+        self.0.get()
+    }
+}
+
+/// Have `resp`'s trailers kept in a [`Trailers`] in its extensions
+pub fn capture(resp: reqwest::Response) -> reqwest::Response {
+    let (mut parts, body) = http::Response::<reqwest::Body>::from(resp).into_parts();
+    let trailers = Trailers::default();
+    parts.extensions.insert(trailers.clone());
+    let body = CaptureBody { inner: body, trailers };
+    reqwest::Response::from(http::Response::from_parts(parts, reqwest::Body::wrap(body)))
+}
+
+struct CaptureBody {
+    inner: reqwest::Body,
+    trailers: Trailers,
+}
+
+impl Body for CaptureBody {
+    type Data = Bytes;
+    type Error = reqwest::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        if let Some(trailers) = frame.as_ref().and_then(|f| f.as_ref().ok()).and_then(Frame::trailers_ref) {
+            let _ = self.trailers.0.set(trailers.clone());
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        // reqwest reads Content-Length from here
+        self.inner.size_hint()
+    }
+}
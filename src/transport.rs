@@ -0,0 +1,475 @@
+//! Direct connection primitives for modes that need to see individual handshakes.
+//!
+//! reqwest hides connection setup behind its pool, so anything that measures the
+//! handshake itself drives tokio-rustls (h2) or quinn (h3) from here instead.
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Empty};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::{ClientSessionMemoryCache, ClientSessionStore, Resumption, WebPkiServerVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, HandshakeKind, NamedGroup, SignatureScheme};
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+pub type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Resolved benchmark target
+pub struct Target {
+    pub uri: http::Uri,
+    pub addr: SocketAddr,
+    pub server_name: ServerName<'static>,
+    pub is_https: bool,
+}
+
+impl Target {
+    pub async fn resolve(url: &str) -> Result<Target, BoxError> {
+        let uri: http::Uri = url.parse()?;
+        let is_https = uri.scheme_str() == Some("https");
+        let host = uri.host().ok_or("URL has no host")?.to_string();
+        let port = uri.port_u16().unwrap_or(if is_https { 443 } else { 80 });
+
+        let host_for_lookup = host.trim_start_matches('[').trim_end_matches(']');
+        let addr = tokio::net::lookup_host((host_for_lookup, port))
+            .await?
+            .next()
+            .ok_or_else(|| format!("{} did not resolve to any address", host))?;
+
+        let server_name = ServerName::try_from(host_for_lookup.to_string())?;
+
+        Ok(Target {
+            uri,
+            addr,
+            server_name,
+            is_https,
+        })
+    }
+}
+
+/// Outcome of a single connection establishment
+pub struct Handshake {
+    /// TCP connect + TLS handshake, or the QUIC handshake
+    pub duration: Duration,
+    pub resumed: bool,
+}
+
+/// TLS handshake counters shared by every config built from [`tls_config`]
+///
+/// `full`, `resumed` and `failed` are counted as connections finish setting up, and only
+/// for those set up through [`crate::connect`]; the others as the handshake goes.
+#[derive(Debug, Default)]
+pub struct TlsStats {
+    /// Handshakes started (one key-share lookup per ClientHello)
+    pub handshakes: AtomicU64,
+    /// Certificates the server presented, whether or not the handshake went on to complete
+    pub verified: AtomicU64,
+    /// Handshakes that completed with a certificate exchange
+    pub full: AtomicU64,
+    /// Handshakes that completed without one
+    pub resumed: AtomicU64,
+    /// Handshakes that failed or timed out
+    pub failed: AtomicU64,
+    pub zero_rtt_accepted: AtomicU64,
+    pub zero_rtt_rejected: AtomicU64,
+}
+
+/// The handshake a config last started, for its certificate verifier. quinn finishes the
+/// handshake on the connection's own task, out of reach of [`crate::connect`]'s
+/// task-local; a worker's QUIC connections are set up one at a time, so this is the one.
+type Pending = Arc<Mutex<Option<Arc<crate::connect::Handshake>>>>;
+
+/// Session store that counts handshakes and can refuse to cache sessions at all.
+#[derive(Debug)]
+struct CountingSessionStore {
+    inner: ClientSessionMemoryCache,
+    resumption: bool,
+    stats: Arc<TlsStats>,
+    pending: Pending,
+}
+
+impl ClientSessionStore for CountingSessionStore {
+    fn set_kx_hint(&self, server_name: ServerName<'static>, group: NamedGroup) {
+        self.inner.set_kx_hint(server_name, group)
+    }
+
+    fn kx_hint(&self, server_name: &ServerName<'_>) -> Option<NamedGroup> {
+        // rustls asks for the hint exactly once per ClientHello
+        self.stats.handshakes.fetch_add(1, Ordering::Relaxed);
+        *self.pending.lock().unwrap() = crate::connect::tls_started(&self.stats);
+        self.inner.kx_hint(server_name)
+    }
+
+    fn set_tls12_session(
+        &self,
+        server_name: ServerName<'static>,
+        value: rustls::client::Tls12ClientSessionValue,
+    ) {
+        if self.resumption {
+            self.inner.set_tls12_session(server_name, value)
+        }
+    }
+
+    fn tls12_session(
+        &self,
+        server_name: &ServerName<'_>,
+    ) -> Option<rustls::client::Tls12ClientSessionValue> {
+        self.inner.tls12_session(server_name)
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName<'static>) {
+        self.inner.remove_tls12_session(server_name)
+    }
+
+    fn insert_tls13_ticket(
+        &self,
+        server_name: ServerName<'static>,
+        value: rustls::client::Tls13ClientSessionValue,
+    ) {
+        if self.resumption {
+            self.inner.insert_tls13_ticket(server_name, value)
+        }
+    }
+
+    fn take_tls13_ticket(
+        &self,
+        server_name: &ServerName<'static>,
+    ) -> Option<rustls::client::Tls13ClientSessionValue> {
+        self.inner.take_tls13_ticket(server_name)
+    }
+}
+
+/// Verifier wrapper that counts certificate verifications.
+///
+/// A resumed handshake skips the certificate entirely, so this is how full
+/// handshakes are told apart from resumed ones on both TCP and QUIC.
+#[derive(Debug)]
+struct CountingVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+    stats: Arc<TlsStats>,
+    pending: Pending,
+}
+
+impl ServerCertVerifier for CountingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.stats.verified.fetch_add(1, Ordering::Relaxed);
+        if !crate::connect::certificate_verified() {
+            if let Some(handshake) = &*self.pending.lock().unwrap() {
+                handshake.certificate_verified();
+            }
+        }
+        self.inner
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+
+    fn requires_raw_public_keys(&self) -> bool {
+        self.inner.requires_raw_public_keys()
+    }
+}
+
+/// Certificate verifier used for `-k`: accepts anything the server presents
+#[derive(Debug)]
+struct AcceptAnyCert(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Build a rustls config offering the given ALPN protocols.
+///
+/// Each call gets its own session cache, so callers decide how widely tickets are
+/// shared by how they share the config. QUIC requires TLS 1.3, so offering `h3`
+/// restricts the protocol versions and enables early data.
+pub fn tls_config(
+    alpn: &[&[u8]],
+    insecure: bool,
+    resumption: bool,
+    stats: &Arc<TlsStats>,
+) -> Result<Arc<ClientConfig>, BoxError> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let quic = alpn.contains(&&b"h3"[..]);
+
+    let builder = ClientConfig::builder_with_provider(provider.clone());
+    let builder = if quic {
+        builder.with_protocol_versions(&[&rustls::version::TLS13])?
+    } else {
+        builder.with_safe_default_protocol_versions()?
+    };
+
+    let verifier: Arc<dyn ServerCertVerifier> = if insecure {
+        Arc::new(AcceptAnyCert(provider.clone()))
+    } else {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider).build()?
+    };
+
+    let pending = Pending::default();
+    let mut config = builder
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(CountingVerifier {
+            inner: verifier,
+            stats: stats.clone(),
+            pending: pending.clone(),
+        }))
+        .with_no_client_auth();
+
+    config.alpn_protocols = alpn.iter().map(|p| p.to_vec()).collect();
+    config.resumption = Resumption::store(Arc::new(CountingSessionStore {
+        inner: ClientSessionMemoryCache::new(256),
+        resumption,
+        stats: stats.clone(),
+        pending,
+    }));
+    config.enable_early_data = quic && resumption;
+
+    Ok(Arc::new(config))
+}
+
+/// Open a TCP connection (plus TLS when `tls` is set), optionally confirm it with
+/// a single HTTP/2 GET, then close it.
+pub async fn handshake_tcp(
+    target: &Target,
+    tls: Option<&Arc<ClientConfig>>,
+    liveness: bool,
+) -> Result<Handshake, BoxError> {
+    let start = Instant::now();
+    let tcp = TcpStream::connect(target.addr).await?;
+    tcp.set_nodelay(true)?;
+
+    let Some(tls) = tls else {
+        let duration = start.elapsed();
+        if liveness {
+            h2_liveness(tcp, &target.uri).await?;
+        }
+        return Ok(Handshake {
+            duration,
+            resumed: false,
+        });
+    };
+
+    let mut stream = TlsConnector::from(tls.clone())
+        .connect(target.server_name.clone(), tcp)
+        .await?;
+    let duration = start.elapsed();
+    let resumed = stream.get_ref().1.handshake_kind() == Some(HandshakeKind::Resumed);
+
+    if liveness {
+        h2_liveness(stream, &target.uri).await?;
+    } else {
+        // Send close_notify so the server doesn't log an abrupt disconnect
+        let _ = stream.shutdown().await;
+    }
+
+    Ok(Handshake { duration, resumed })
+}
+
+/// What a TLS handshake settled on
+pub struct TlsDetails {
+    pub version: String,
+    pub cipher_suite: String,
+}
+
+/// Handshake with `addr` and report the TLS version and cipher suite, which reqwest
+/// doesn't expose for its own connections
+pub async fn tls_details(
+    addr: SocketAddr,
+    server_name: ServerName<'static>,
+    tls: &Arc<ClientConfig>,
+) -> Result<TlsDetails, BoxError> {
+    let tcp = TcpStream::connect(addr).await?;
+    let mut stream = TlsConnector::from(tls.clone()).connect(server_name, tcp).await?;
+    let conn = stream.get_ref().1;
+    let version = match conn.protocol_version() {
+        Some(rustls::ProtocolVersion::TLSv1_3) => "TLS 1.3".to_string(),
+        Some(rustls::ProtocolVersion::TLSv1_2) => "TLS 1.2".to_string(),
+        other => format!("{:?}", other),
+    };
+    let cipher_suite = conn
+        .negotiated_cipher_suite()
+        .map_or_else(|| "unknown".to_string(), |suite| format!("{:?}", suite.suite()));
+    let _ = stream.shutdown().await;
+    Ok(TlsDetails { version, cipher_suite })
+}
+
+async fn h2_liveness<T>(io: T, uri: &http::Uri) -> Result<(), BoxError>
+where
+    T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, conn) =
+        hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(io)).await?;
+    let driver = tokio::spawn(conn);
+
+    let req = http::Request::get(uri.clone()).body(Empty::<Bytes>::new())?;
+    let resp = sender.send_request(req).await?;
+    resp.into_body().collect().await?;
+
+    drop(sender);
+    let _ = driver.await;
+    Ok(())
+}
+
+/// The server's SETTINGS_MAX_CONCURRENT_STREAMS, read from a throwaway HTTP/2
+/// connection. `None` when the server doesn't advertise a limit.
+pub async fn h2_stream_limit(target: &Target, tls: Option<&Arc<ClientConfig>>) -> Result<Option<usize>, BoxError> {
+    let tcp = TcpStream::connect(target.addr).await?;
+    tcp.set_nodelay(true)?;
+    match tls {
+        Some(tls) => {
+            let stream = TlsConnector::from(tls.clone())
+                .connect(target.server_name.clone(), tcp)
+                .await?;
+            read_stream_limit(stream).await
+        }
+        None => read_stream_limit(tcp).await,
+    }
+}
+
+async fn read_stream_limit<T>(io: T) -> Result<Option<usize>, BoxError>
+where
+    T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let (_client, mut conn) = h2::client::handshake(io).await?;
+    let mut ping_pong = conn.ping_pong().ok_or("HTTP/2 PING unavailable")?;
+
+    // The server's SETTINGS open its preface, so they have been applied by the time
+    // our PING is acknowledged
+    tokio::select! {
+        result = &mut conn => {
+            result?;
+            return Err("connection closed before SETTINGS arrived".into());
+        }
+        result = ping_pong.ping(h2::Ping::opaque()) => {
+            result?;
+        }
+    }
+
+    let limit = conn.max_concurrent_send_streams();
+    Ok((limit != usize::MAX).then_some(limit))
+}
+
+/// Complete a QUIC handshake on `endpoint`, optionally confirm it with a single
+/// HTTP/3 GET, then close it.
+///
+/// `stats` must be the counters behind `config`'s TLS session; the handshake
+/// counts as resumed when no certificate verification happened during it.
+pub async fn handshake_quic(
+    endpoint: &quinn::Endpoint,
+    config: &quinn::ClientConfig,
+    stats: &TlsStats,
+    target: &Target,
+    liveness: bool,
+) -> Result<Handshake, BoxError> {
+    let host = target.uri.host().unwrap_or_default();
+    let verified_before = stats.verified.load(Ordering::Relaxed);
+
+    let start = Instant::now();
+    let connecting = endpoint.connect_with(config.clone(), target.addr, host)?;
+    let conn = match connecting.into_0rtt() {
+        Ok((conn, accepted)) => {
+            // A cached ticket allowed 0-RTT keys; the future resolves once the
+            // handshake completes and tells us whether the server took them
+            let counter = if accepted.await {
+                &stats.zero_rtt_accepted
+            } else {
+                &stats.zero_rtt_rejected
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+            conn
+        }
+        Err(connecting) => connecting.await?,
+    };
+    let duration = start.elapsed();
+    let resumed = stats.verified.load(Ordering::Relaxed) == verified_before;
+
+    if liveness {
+        h3_liveness(conn.clone(), &target.uri).await?;
+    }
+    conn.close(0u32.into(), b"");
+
+    Ok(Handshake { duration, resumed })
+}
+
+async fn h3_liveness(conn: quinn::Connection, uri: &http::Uri) -> Result<(), BoxError> {
+    let (mut driver, mut sender) = h3::client::new(h3_quinn::Connection::new(conn)).await?;
+    let driver = tokio::spawn(async move {
+        std::future::poll_fn(|cx| driver.poll_close(cx)).await;
+    });
+
+    let req = http::Request::get(uri.clone()).body(())?;
+    let mut stream = sender.send_request(req).await?;
+    stream.finish().await?;
+    stream.recv_response().await?;
+    while stream.recv_data().await?.is_some() {}
+
+    drop(sender);
+    driver.abort();
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use clap::ValueEnum;
+use std::time::Duration;
+
+/// How latencies are rendered (`--latency-unit`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LatencyUnit {
+    /// Milliseconds, 3 decimals
+    Ms,
+    /// Microseconds, 1 decimal
+    Us,
+    /// Seconds, 3 decimals
+    S,
+    /// Pick one of the above per results block, from its typical latency
+    #[default]
+    Auto,
+}
+
+impl LatencyUnit {
+    /// Settle `auto` on a concrete unit for a block whose typical latency is `reference`
+    /// (the median), so every value in the block shares a unit and precision
+    pub fn resolve(self, reference: Duration) -> LatencyUnit {
+        match self {
+            LatencyUnit::Auto if reference < Duration::from_millis(1) => LatencyUnit::Us,
+            LatencyUnit::Auto if reference < Duration::from_secs(1) => LatencyUnit::Ms,
+            LatencyUnit::Auto => LatencyUnit::S,
+            unit => unit,
+        }
+    }
+
+    pub fn format(self, d: Duration) -> String {
+        match self.resolve(d) {
+            LatencyUnit::Us => format!("{:.1}µs", d.as_secs_f64() * 1e6),
+            LatencyUnit::Ms => format!("{:.3}ms", d.as_secs_f64() * 1e3),
+            _ => format!("{:.3}s", d.as_secs_f64()),
+        }
+    }
+}
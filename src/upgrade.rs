@@ -0,0 +1,268 @@
+//! HTTP/2 over cleartext via HTTP/1.1's `Upgrade: h2c` for `--h2c-upgrade`.
+//!
+//! reqwest's h2c is prior knowledge only, which servers that only switch to HTTP/2 on
+//! request don't understand, so these requests go over our own connections: each opens
+//! as HTTP/1.1 with an `OPTIONS *` asking to upgrade, and once the server switches
+//! protocols the run's requests are streams on it. As with quic.rs, requests are built
+//! with reqwest's builder and responses handed back as `reqwest::Response`.
+//!
+//! The server answers the upgrade request on stream 1, which is then half closed on our
+//! side, so our own streams start at 3 and h2 resets that one when its response comes.
+
+use crate::connect::{self, ConnectStats};
+use crate::dns::BenchResolver;
+use crate::render;
+use crate::transport::BoxError;
+use bytes::Bytes;
+use colored::Colorize;
+use h2::client::SendRequest;
+use http::header::{CONNECTION, HOST, UPGRADE};
+use http::{Method, StatusCode, Version};
+use http_body::{Body, Frame};
+use http_body_util::{BodyExt, Empty};
+use hyper_util::rt::TokioIo;
+use reqwest::dns::{Name, Resolve};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+
+/// The HTTP2-Settings header: a SETTINGS payload of ENABLE_PUSH = 0, base64url-encoded
+const HTTP2_SETTINGS: &str = "AAIAAAAA";
+
+/// How `--h2c-upgrade` connections went, across every worker
+#[derive(Default)]
+pub struct UpgradeStats {
+    pub upgraded: AtomicU64,
+    /// The server answered the upgrade request without switching protocols
+    pub declined: AtomicU64,
+}
+
+impl UpgradeStats {
+    pub fn print(&self) {
+        let upgraded = self.upgraded.load(Ordering::Relaxed);
+        let declined = self.declined.load(Ordering::Relaxed);
+        let stayed = format!("{} stayed on HTTP/1.1", declined);
+        render::print(
+            "h2c upgrade:".white(),
+            format!(
+                "{} of {} connections upgraded, {}",
+                upgraded.to_string().green(),
+                upgraded + declined,
+                if declined > 0 { stayed.red() } else { stayed.normal() }
+            ),
+        );
+    }
+}
+
+/// A connection that opened but couldn't be upgraded; its requests fail with this, and
+/// are counted and labelled as `h2c upgrade` failures by errors.rs
+#[derive(Debug)]
+pub struct UpgradeError(String);
+
+impl fmt::Display for UpgradeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for UpgradeError {}
+
+type Sender = SendRequest<Bytes>;
+
+/// One worker's upgraded connection, opened on first use and reopened once it closes
+pub struct UpgradeClient {
+    resolver: Arc<BenchResolver>,
+    conn_stats: Arc<ConnectStats>,
+    stats: Arc<UpgradeStats>,
+    connect_timeout: Duration,
+    live: tokio::sync::Mutex<Option<Sender>>,
+}
+
+impl UpgradeClient {
+    pub fn new(
+        resolver: Arc<BenchResolver>,
+        conn_stats: Arc<ConnectStats>,
+        stats: Arc<UpgradeStats>,
+        connect_timeout: Duration,
+    ) -> UpgradeClient {
+        UpgradeClient {
+            resolver,
+            conn_stats,
+            stats,
+            connect_timeout,
+            live: tokio::sync::Mutex::default(),
+        }
+    }
+
+    /// The live connection's request sender, connecting first if there is none.
+    ///
+    /// Held under the lock so pipelined requests share one connection instead of
+    /// each opening their own.
+    async fn sender(&self, uri: &http::Uri) -> Result<Sender, BoxError> {
+        let mut live = self.live.lock().await;
+        if let Some(sender) = live.clone() {
+            // Fails once the connection has closed
+            if let Ok(sender) = sender.ready().await {
+                return Ok(sender);
+            }
+        }
+        *live = None;
+        let sender = self.connect(uri).await?;
+        *live = Some(sender.clone());
+        Ok(sender)
+    }
+
+    async fn connect(&self, uri: &http::Uri) -> Result<Sender, BoxError> {
+        let authority = uri.authority().ok_or("URL has no host")?.as_str();
+        let host = uri.host().ok_or("URL has no host")?.trim_start_matches('[').trim_end_matches(']');
+        let port = uri.port_u16().unwrap_or(80);
+        let addr = match IpAddr::from_str(host) {
+            Ok(ip) => SocketAddr::new(ip, port),
+            Err(_) => {
+                let mut addr = self
+                    .resolver
+                    .resolve(Name::from_str(host)?)
+                    .await?
+                    .next()
+                    .ok_or_else(|| format!("{} did not resolve to any address", host))?;
+                addr.set_port(port);
+                addr
+            }
+        };
+
+        let start = Instant::now();
+        let tcp = match connect::within(self.connect_timeout, tokio::net::TcpStream::connect(addr)).await {
+            Ok(tcp) => tcp,
+            Err(e) => {
+                tracing::info!(error = %e, "connection failed");
+                self.conn_stats.failed.fetch_add(1, Ordering::Relaxed);
+                return Err(e);
+            }
+        };
+        tcp.set_nodelay(true)?;
+        match self.upgrade(tcp, authority).await {
+            Ok(sender) => {
+                let setup = start.elapsed();
+                self.conn_stats.record(setup, Some(addr));
+                self.stats.upgraded.fetch_add(1, Ordering::Relaxed);
+                tracing::info!(remote = ?addr, protocol = "h2", upgrade = "h2c", ?setup, "connection established");
+                Ok(sender)
+            }
+            Err(e) => {
+                tracing::info!(error = %e, "connection failed");
+                self.conn_stats.failed.fetch_add(1, Ordering::Relaxed);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Ask to switch `tcp` to HTTP/2, and start HTTP/2 on it if the server agrees
+    async fn upgrade(&self, tcp: tokio::net::TcpStream, authority: &str) -> Result<Sender, UpgradeError> {
+        let failed = |e: &dyn fmt::Display| UpgradeError(e.to_string());
+        let (mut http1, conn) = hyper::client::conn::http1::handshake(TokioIo::new(tcp))
+            .await
+            .map_err(|e| failed(&e))?;
+        tokio::spawn(conn.with_upgrades());
+
+        let request = http::Request::builder()
+            .method(Method::OPTIONS)
+            .uri("*")
+            .header(HOST, authority)
+            .header(CONNECTION, "Upgrade, HTTP2-Settings")
+            .header(UPGRADE, "h2c")
+            .header("http2-settings", HTTP2_SETTINGS)
+            .body(Empty::<Bytes>::new())
+            .map_err(|e| failed(&e))?;
+        let response = http1.send_request(request).await.map_err(|e| failed(&e))?;
+        if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+            self.stats.declined.fetch_add(1, Ordering::Relaxed);
+            return Err(UpgradeError(format!(
+                "the server answered {} and stayed on HTTP/1.1",
+                response.status()
+            )));
+        }
+
+        let upgraded = hyper::upgrade::on(response).await.map_err(|e| failed(&e))?;
+        let (sender, connection) = h2::client::Builder::new()
+            .initial_stream_id(3)
+            .handshake::<_, Bytes>(TokioIo::new(upgraded))
+            .await
+            .map_err(|e| failed(&e))?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                tracing::debug!(error = %e, "upgraded connection closed");
+            }
+        });
+        Ok(sender)
+    }
+
+    pub async fn send(&self, request: reqwest::Request) -> Result<reqwest::Response, BoxError> {
+        let (mut parts, mut body) = http::Request::<reqwest::Body>::try_from(request)?.into_parts();
+        let mut sender = self.sender(&parts.uri).await?;
+
+        parts.version = Version::HTTP_2;
+        let end = body.is_end_stream();
+        let (response, mut stream) = sender.send_request(http::Request::from_parts(parts, ()), end)?;
+        if !end {
+            // h2 buffers what flow control doesn't let out yet
+            while let Some(frame) = body.frame().await {
+                if let Ok(data) = frame?.into_data() {
+                    stream.send_data(data, false)?;
+                }
+            }
+            stream.send_data(Bytes::new(), true)?;
+        }
+
+        let response = response.await?;
+        let response = response.map(|body| {
+            reqwest::Body::wrap(ResponseBody {
+                stream: body,
+                data_done: false,
+                trailers_done: false,
+            })
+        });
+        Ok(reqwest::Response::from(response))
+    }
+}
+
+/// A response's stream, read by reqwest as the response body
+struct ResponseBody {
+    stream: h2::RecvStream,
+    /// The data is all read, so the trailers (if any) come next
+    data_done: bool,
+    trailers_done: bool,
+}
+
+impl Body for ResponseBody {
+    type Data = Bytes;
+    type Error = h2::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        if !self.data_done {
+            match ready!(self.stream.poll_data(cx)) {
+                Some(Ok(data)) => {
+                    // Read, so the server may send that much more
+                    let _ = self.stream.flow_control().release_capacity(data.len());
+                    return Poll::Ready(Some(Ok(Frame::data(data))));
+                }
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => self.data_done = true,
+            }
+        }
+        if self.trailers_done {
+            return Poll::Ready(None);
+        }
+        let trailers = ready!(self.stream.poll_trailers(cx));
+        self.trailers_done = true;
+        match trailers {
+            Ok(Some(trailers)) => Poll::Ready(Some(Ok(Frame::trailers(trailers)))),
+            Ok(None) => Poll::Ready(None),
+            Err(e) => Poll::Ready(Some(Err(e))),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use bytes::Bytes;
+use http_body::{Body, Frame, SizeHint};
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// Handed to the connection this much at a time, so HTTP/2 flow control and socket
+/// backpressure pace the upload instead of the whole body vanishing into a buffer
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A request body that records when its last chunk was taken by the connection
+pub struct UploadBody {
+    remaining: Bytes,
+    done: Arc<OnceLock<Instant>>,
+}
+
+impl UploadBody {
+    /// The body, and where the time it was fully written will be recorded
+    pub fn new(body: Bytes) -> (Self, Arc<OnceLock<Instant>>) {
+        let done = Arc::new(OnceLock::new());
+        if body.is_empty() {
+            let _ = done.set(Instant::now());
+        }
+        (UploadBody { remaining: body, done: done.clone() }, done)
+    }
+}
+
+impl Body for UploadBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        if self.remaining.is_empty() {
+            return Poll::Ready(None);
+        }
+        let len = self.remaining.len().min(CHUNK_SIZE);
+        let chunk = self.remaining.split_to(len);
+        if self.remaining.is_empty() {
+            let _ = self.done.set(Instant::now());
+        }
+        Poll::Ready(Some(Ok(Frame::data(chunk))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.remaining.is_empty()
+    }
+
+    // Exact, so Content-Length is still sent instead of chunked encoding
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining.len() as u64)
+    }
+}
+
+/// Each request split into upload, server wait and download
+pub struct TransferTimes {
+    /// Until the body was fully written; only requests with a body
+    pub upload: Vec<Duration>,
+    /// From the end of the upload to the response headers; only requests with a body,
+    /// since without one it is the TTFB
+    pub server_wait: Vec<Duration>,
+    /// From the response headers to the end of the body; none under `-m HEAD`, whose
+    /// responses end with their headers
+    pub download: Vec<Duration>,
+    head: bool,
+}
+
+impl TransferTimes {
+    pub fn new(head: bool) -> Self {
+        TransferTimes {
+            upload: Vec::new(),
+            server_wait: Vec::new(),
+            download: Vec::new(),
+            head,
+        }
+    }
+
+    /// `responded` is false when the request errored before any response
+    pub fn record(&mut self, latency: Duration, ttfb: Duration, upload: Option<Duration>, responded: bool) {
+        if let Some(upload) = upload {
+            self.upload.push(upload);
+            if responded {
+                self.server_wait.push(ttfb.saturating_sub(upload));
+            }
+        }
+        if responded && !self.head {
+            self.download.push(latency.saturating_sub(ttfb));
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use crate::render::Table;
+use crate::units::LatencyUnit;
+use crate::Percentiles;
+use colored::Colorize;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use serde_json::{json, Value};
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::Duration;
+
+/// Mixed into the seed so URL picks don't shift generated bodies or `--arrival poisson`
+const URL_STREAM: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// The URLs of `--url-file` and how often each is requested.
+///
+/// Each line is a path (or full URL) resolved against the target URL, optionally
+/// followed by a weight: `/search 8`. Blank lines and `#` comments are skipped.
+/// `--workload` builds one too, with a method, headers and body for every URL.
+pub struct UrlMix {
+    urls: Vec<String>,
+    weights: Vec<f64>,
+    /// One per URL for `--workload`, empty for `--url-file`
+    requests: Vec<EntryRequest>,
+    alias: AliasTable,
+}
+
+/// What a `--workload` entry sends, in place of `--method` and `--body`
+pub struct EntryRequest {
+    pub method: reqwest::Method,
+    /// Sent before `--header-file`'s and `--script`'s
+    pub headers: Vec<(reqwest::header::HeaderName, reqwest::header::HeaderValue)>,
+    pub body: Option<bytes::Bytes>,
+}
+
+impl UrlMix {
+    pub fn load(path: &Path, base: &str) -> Result<UrlMix, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let base = reqwest::Url::parse(base).map_err(|e| e.to_string())?;
+
+        let mut urls = Vec::new();
+        let mut weights = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let (Some(url), weight, None) = (words.next(), words.next(), words.next()) else {
+                return Err(format!("{}:{}: expected PATH [WEIGHT]", path.display(), n + 1));
+            };
+            let url = base
+                .join(url)
+                .map_err(|e| format!("{}:{}: invalid URL {:?}: {}", path.display(), n + 1, url, e))?;
+            let weight = match weight.map(str::parse::<f64>) {
+                None => 1.0,
+                Some(Ok(weight)) if weight > 0.0 && weight.is_finite() => weight,
+                Some(_) => {
+                    return Err(format!("{}:{}: weight must be a positive number", path.display(), n + 1));
+                }
+            };
+            urls.push(url.to_string());
+            weights.push(weight);
+        }
+        if urls.is_empty() {
+            return Err(format!("{} lists no URLs", path.display()));
+        }
+        Ok(UrlMix::with_requests(urls, weights, Vec::new()))
+    }
+
+    /// `requests` is empty, or has one entry per URL
+    pub fn with_requests(urls: Vec<String>, weights: Vec<f64>, requests: Vec<EntryRequest>) -> UrlMix {
+        let alias = AliasTable::new(&weights);
+        UrlMix {
+            urls,
+            weights,
+            requests,
+            alias,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.urls.len()
+    }
+
+    pub fn url(&self, index: usize) -> &str {
+        &self.urls[index]
+    }
+
+    /// The method, headers and body of a `--workload` entry
+    pub fn request(&self, index: usize) -> Option<&EntryRequest> {
+        self.requests.get(index)
+    }
+
+    /// Index of the next URL to request, drawn in proportion to the weights
+    pub fn pick(&self, picker: &mut UrlPicker) -> usize {
+        self.alias.sample(&mut picker.rng)
+    }
+
+    /// Share of requests expected to go to each URL
+    fn expected_share(&self, index: usize) -> f64 {
+        self.weights[index] / self.weights.iter().sum::<f64>()
+    }
+}
+
+/// Vose's alias table: one uniform draw picks a column, a second decides between the
+/// column and its alias, so every pick is O(1) however many URLs there are
+struct AliasTable {
+    prob: Vec<f64>,
+    alias: Vec<usize>,
+}
+
+impl AliasTable {
+    fn new(weights: &[f64]) -> AliasTable {
+        let n = weights.len();
+        let total: f64 = weights.iter().sum();
+        let mut scaled: Vec<f64> = weights.iter().map(|w| w * n as f64 / total).collect();
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) = (0..n).partition(|&i| scaled[i] < 1.0);
+
+        let mut prob = vec![1.0; n];
+        let mut alias: Vec<usize> = (0..n).collect();
+        while let (Some(&s), Some(&l)) = (small.last(), large.last()) {
+            small.pop();
+            prob[s] = scaled[s];
+            alias[s] = l;
+            scaled[l] -= 1.0 - scaled[s];
+            if scaled[l] < 1.0 {
+                large.pop();
+                small.push(l);
+            }
+        }
+        // Whatever is left is 1 up to rounding error, and keeps prob 1
+        AliasTable { prob, alias }
+    }
+
+    fn sample(&self, rng: &mut SmallRng) -> usize {
+        let column = rng.gen_range(0..self.prob.len());
+        if rng.gen::<f64>() < self.prob[column] {
+            column
+        } else {
+            self.alias[column]
+        }
+    }
+}
+
+/// One worker's URL draws, seeded from `--seed` plus the worker index like its bodies
+pub struct UrlPicker {
+    rng: SmallRng,
+}
+
+impl UrlPicker {
+    pub fn new(seed: u64, worker: usize) -> Self {
+        UrlPicker {
+            rng: SmallRng::seed_from_u64(seed.wrapping_add(worker as u64) ^ URL_STREAM),
+        }
+    }
+}
+
+#[derive(Default)]
+struct UrlEntry {
+    latencies: Vec<Duration>,
+    failed: u64,
+}
+
+/// `--per-url-stats` and `--per-entry-stats`: latencies and failures for each URL of
+/// the mix, owned by the collector. One entry per line of `--url-file` or
+/// `--workload`, so memory is bounded by the file.
+pub struct UrlStats {
+    entries: Vec<UrlEntry>,
+}
+
+/// One URL's results, as printed
+pub struct UrlRow<'a> {
+    pub url: &'a str,
+    /// A `--workload` entry's method
+    pub method: Option<&'a reqwest::Method>,
+    pub count: usize,
+    /// Share of all completed requests that went to this URL
+    pub share: f64,
+    pub expected_share: f64,
+    pub err_rate: f64,
+    pub p50: Duration,
+    pub p99: Duration,
+}
+
+impl UrlRow<'_> {
+    /// The URL, after its method for a `--workload` entry
+    pub fn label(&self) -> String {
+        match self.method {
+            Some(method) => format!("{} {}", method, self.url),
+            None => self.url.to_string(),
+        }
+    }
+}
+
+impl UrlStats {
+    pub fn new(urls: usize) -> Self {
+        UrlStats {
+            entries: (0..urls).map(|_| UrlEntry::default()).collect(),
+        }
+    }
+
+    pub fn record(&mut self, index: usize, latency: Duration, failed: bool) {
+        let entry = &mut self.entries[index];
+        entry.latencies.push(latency);
+        if failed {
+            entry.failed += 1;
+        }
+    }
+
+    /// One row per URL that was requested, in file order
+    pub fn rows<'a>(&mut self, mix: &'a UrlMix) -> Vec<UrlRow<'a>> {
+        let total: usize = self.entries.iter().map(|e| e.latencies.len()).sum();
+        self.entries
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, e)| !e.latencies.is_empty())
+            .map(|(i, e)| {
+                let count = e.latencies.len();
+                let p = Percentiles::from_unsorted(&mut e.latencies);
+                UrlRow {
+                    url: mix.url(i),
+                    method: mix.request(i).map(|request| &request.method),
+                    count,
+                    share: count as f64 / total as f64,
+                    expected_share: mix.expected_share(i),
+                    err_rate: e.failed as f64 / count as f64,
+                    p50: p.p50,
+                    p99: p.p99,
+                }
+            })
+            .collect()
+    }
+}
+
+pub fn print(rows: &[UrlRow], unit: LatencyUnit) {
+    if rows.is_empty() {
+        return;
+    }
+
+    let title = if rows[0].method.is_some() { "--- Per entry ---" } else { "--- Per URL ---" };
+    println!("\n{}", title.cyan().bold());
+    // The URL last, where it can be cut short
+    let mut table = Table::new(["Count", "Share (weight)", "Errors", "P50", "P99", "URL"]);
+    for row in rows {
+        let errors = format!("{:.2}%", row.err_rate * 100.0);
+        table.row([
+            row.count.to_string().white().to_string(),
+            format!("{:.1}% ({:.1}%)", row.share * 100.0, row.expected_share * 100.0),
+            if row.err_rate > 0.0 { errors.red().to_string() } else { errors },
+            unit.format(row.p50),
+            unit.format(row.p99).red().to_string(),
+            row.label(),
+        ]);
+    }
+    table.print();
+}
+
+/// The per-URL table as GitHub-flavored markdown
+pub fn markdown(rows: &[UrlRow], unit: LatencyUnit) -> String {
+    let mut md = String::from("\n| URL | Count | Error rate | P50 | P99 |\n|---|---:|---:|---:|---:|\n");
+    for row in rows {
+        let _ = writeln!(
+            md,
+            "| `{}` | {} | {:.2}% | {} | {} |",
+            row.label(),
+            row.count,
+            row.err_rate * 100.0,
+            unit.format(row.p50),
+            unit.format(row.p99)
+        );
+    }
+    md
+}
+
+/// The per-URL table for `--output json`, with latencies in microseconds
+pub fn to_json(rows: &[UrlRow]) -> Value {
+    rows.iter()
+        .map(|row| {
+            json!({
+                "url": row.url,
+                "method": row.method.map(reqwest::Method::as_str),
+                "count": row.count,
+                "share": row.share,
+                "expected_share": row.expected_share,
+                "err_rate": row.err_rate,
+                "p50_us": row.p50.as_nanos() as f64 / 1000.0,
+                "p99_us": row.p99.as_nanos() as f64 / 1000.0,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,306 @@
+use crate::render::{self, Table};
+use crate::thousands;
+use crate::script::Metrics;
+use crate::units::LatencyUnit;
+use colored::Colorize;
+use reqwest::Version;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A worker counts as a straggler when it ran this much longer than the median worker
+const STRAGGLER_FACTOR: f64 = 1.5;
+
+/// Below this average share of the run with a request in flight, `-c` or `-p` promise
+/// more concurrency than the run had
+const UNDERUTILIZED: f64 = 0.8;
+
+/// How many requests a worker may send
+pub enum Quota {
+    /// A fixed share of `-n`, decided up front
+    Fixed(u64),
+    /// Claimed one at a time from a counter shared by every worker (`--work-stealing`)
+    Shared(Arc<AtomicU64>),
+}
+
+impl Quota {
+    /// Whether any request is left for this worker, without claiming it
+    pub fn has_more(&self, sent: u64) -> bool {
+        match self {
+            Quota::Fixed(n) => sent < *n,
+            Quota::Shared(remaining) => remaining.load(Ordering::Relaxed) > 0,
+        }
+    }
+
+    /// Take the right to send one more request
+    pub fn claim(&self, sent: u64) -> bool {
+        match self {
+            Quota::Fixed(n) => sent < *n,
+            Quota::Shared(remaining) => remaining
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                .is_ok(),
+        }
+    }
+}
+
+/// Where the requests of `-n` went. Every one is either never sent, skipped by
+/// `--script`, or sent and then completed, cancelled by an abort, or abandoned at
+/// `--drain-timeout`.
+#[derive(Default)]
+pub struct RequestCounts {
+    /// Counted as each request is submitted
+    pub submitted: AtomicU64,
+    /// Resolved with a response or an error, successful or not
+    pub completed: AtomicU64,
+    /// Still in flight when the run was aborted; neither succeeded nor failed
+    pub cancelled: AtomicU64,
+    /// Still in flight when their worker's `--drain-timeout` ran out
+    pub abandoned: AtomicU64,
+    /// Dropped by `--script`'s `on_request` instead of being sent
+    pub skipped: AtomicU64,
+}
+
+impl RequestCounts {
+    pub fn submitted(&self) -> u64 {
+        self.submitted.load(Ordering::Relaxed)
+    }
+
+    pub fn cancelled(&self) -> u64 {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn abandoned(&self) -> u64 {
+        self.abandoned.load(Ordering::Relaxed)
+    }
+
+    pub fn skipped(&self) -> u64 {
+        self.skipped.load(Ordering::Relaxed)
+    }
+
+    /// Submitted and not yet ended
+    pub fn in_flight(&self) -> u64 {
+        let ended = self.completed.load(Ordering::Relaxed) + self.cancelled() + self.abandoned();
+        self.submitted().saturating_sub(ended)
+    }
+
+    /// Every submitted request ended one way or another; only a worker that panicked
+    /// leaves some unaccounted for
+    pub fn settled(&self) -> bool {
+        self.submitted() == self.completed.load(Ordering::Relaxed) + self.cancelled() + self.abandoned()
+    }
+
+    /// Whether all of `requested` were sent and completed
+    pub fn complete(&self, requested: u64) -> bool {
+        self.submitted() == requested && self.completed.load(Ordering::Relaxed) == requested
+    }
+
+    /// A `Requests` line with every count, unless all of `requested` were sent and
+    /// completed
+    pub fn print(&self, requested: u64) {
+        if self.complete(requested) {
+            return;
+        }
+        let submitted = self.submitted();
+        let mut line = format!(
+            "{} requested, {} sent, {} completed, {} cancelled, {} never sent",
+            thousands(requested),
+            thousands(submitted),
+            thousands(self.completed.load(Ordering::Relaxed)),
+            thousands(self.cancelled()),
+            thousands(requested.saturating_sub(submitted + self.skipped()))
+        );
+        if self.skipped() > 0 {
+            line += &format!(", {} skipped by --script", thousands(self.skipped()));
+        }
+        if self.abandoned() > 0 {
+            line += &format!(", {} abandoned", thousands(self.abandoned()));
+        }
+        render::print("Requests:".white(), line.yellow());
+    }
+}
+
+/// How the run wound down: requests still in flight when the last one was sent, and
+/// how long they took to finish
+pub struct Drain {
+    pub in_flight: u64,
+    pub time: Duration,
+    /// When the last request completed
+    pub ended: Instant,
+}
+
+impl Drain {
+    /// From the send and completion time of every request
+    pub fn measure(last_sent: Option<Instant>, completions: &[Instant]) -> Option<Drain> {
+        let last_sent = last_sent?;
+        let in_flight = completions.iter().filter(|&&done| done > last_sent).count() as u64;
+        let last_done = completions.iter().max()?;
+        Some(Drain {
+            in_flight,
+            time: last_done.saturating_duration_since(last_sent),
+            ended: *last_done,
+        })
+    }
+}
+
+/// The versions one worker's responses came back over
+#[derive(Default)]
+pub struct Protocols(Vec<(Version, u64)>);
+
+impl Protocols {
+    pub fn record(&mut self, version: Version) {
+        match self.0.iter_mut().find(|(seen, _)| *seen == version) {
+            Some((_, n)) => *n += 1,
+            None => self.0.push((version, 1)),
+        }
+    }
+
+    /// `HTTP/2.0`, or `HTTP/1.1 ×3, HTTP/2.0 ×97` when the worker saw several
+    pub fn describe(&self) -> String {
+        match self.0.as_slice() {
+            [] => "-".to_string(),
+            [(version, _)] => format!("{:?}", version),
+            seen => seen
+                .iter()
+                .map(|(version, n)| format!("{:?} \u{d7}{}", version, n))
+                .collect::<Vec<_>>()
+                .join(", "),
+        }
+    }
+}
+
+/// How long one worker had at least one request in flight, as opposed to waiting on
+/// pacing, backoff, a pause or its quota. Updated only when the count of requests in
+/// flight goes from none to some or back.
+#[derive(Default)]
+pub struct Activity {
+    busy_since: Option<Instant>,
+    busy: Duration,
+}
+
+impl Activity {
+    /// After requests were submitted or one completed
+    pub fn update(&mut self, in_flight: usize) {
+        match (in_flight, self.busy_since) {
+            (0, Some(since)) => {
+                self.busy += since.elapsed();
+                self.busy_since = None;
+            }
+            (1.., None) => self.busy_since = Some(Instant::now()),
+            _ => {}
+        }
+    }
+
+    /// The busy time, counting requests still in flight until now
+    pub fn finish(mut self) -> Duration {
+        self.update(0);
+        self.busy
+    }
+}
+
+/// What one worker got through, returned when it finishes
+pub struct WorkerReport {
+    pub sent: u64,
+    /// From the start of the run until the worker's last request completed
+    pub elapsed: Duration,
+    /// With at least one request in flight
+    pub busy: Duration,
+    /// What `--script` recorded with `metric`
+    pub script_metrics: Metrics,
+    pub protocols: Protocols,
+}
+
+/// Each worker's share of `active` (the run's time, less pauses) with a request in
+/// flight, from 0 to 1
+pub fn utilization(reports: &[WorkerReport], active: Duration) -> Vec<f64> {
+    reports
+        .iter()
+        .map(|r| (r.busy.as_secs_f64() / active.as_secs_f64().max(f64::EPSILON)).min(1.0))
+        .collect()
+}
+
+/// A `Utilization` line: the average across workers and the least busy one, with a
+/// hint when the configured concurrency went mostly unused
+pub fn print_utilization(utilization: &[f64]) {
+    let Some((lowest, min)) = utilization.iter().copied().enumerate().min_by(|a, b| a.1.total_cmp(&b.1)) else {
+        return;
+    };
+    let avg = utilization.iter().sum::<f64>() / utilization.len() as f64;
+    let mut line = format!("average worker utilization {:.0}%", avg * 100.0);
+    if utilization.len() > 1 {
+        line += &format!(", lowest {:.0}% (worker {})", min * 100.0, lowest);
+    }
+    if avg < UNDERUTILIZED {
+        line += "; consider lowering -c or -p";
+        render::print_wrapped("Utilization:".white(), line.yellow());
+    } else {
+        render::print_wrapped("Utilization:".white(), line);
+    }
+}
+
+/// `--per-worker-stats`: requests, duration, utilization and protocol per worker,
+/// flagging stragglers and the workers in `downgraded`
+pub fn print(reports: &[WorkerReport], utilization: &[f64], downgraded: &[usize], unit: LatencyUnit) {
+    if reports.is_empty() {
+        return;
+    }
+
+    let mut durations: Vec<Duration> = reports.iter().map(|r| r.elapsed).collect();
+    durations.sort_unstable();
+    let median = durations[durations.len() / 2];
+
+    println!("\n{}", "--- Workers ---".cyan().bold());
+    let mut table = Table::new(["Worker", "Requests", "Duration", "Busy", "Protocol", "Req/s"]);
+    for (i, report) in reports.iter().enumerate() {
+        let rps = report.sent as f64 / report.elapsed.as_secs_f64().max(f64::EPSILON);
+        let straggler = report.elapsed.as_secs_f64() > median.as_secs_f64() * STRAGGLER_FACTOR;
+        let duration = unit.format(report.elapsed);
+        table.row([
+            format!("{}:", i).white().to_string(),
+            report.sent.to_string(),
+            if straggler { duration.yellow().to_string() } else { duration },
+            format!("{:.0}%", utilization[i] * 100.0),
+            if downgraded.contains(&i) {
+                report.protocols.describe().yellow().to_string()
+            } else {
+                report.protocols.describe()
+            },
+            format!("{:.2}{}", rps, if straggler { " (straggler)".yellow() } else { "".normal() }),
+        ]);
+    }
+    table.print();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_shared_quota_is_claimed_exactly_once_per_request() {
+        let quota = Arc::new(Quota::Shared(Arc::new(AtomicU64::new(1000))));
+        let claimed: u64 = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..7)
+                .map(|_| {
+                    let quota = quota.clone();
+                    scope.spawn(move || {
+                        let mut sent = 0;
+                        while quota.claim(sent) {
+                            sent += 1;
+                        }
+                        assert!(!quota.has_more(sent));
+                        sent
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|worker| worker.join().unwrap()).sum()
+        });
+        assert_eq!(claimed, 1000);
+    }
+
+    #[test]
+    fn a_fixed_quota_stops_at_its_share() {
+        let quota = Quota::Fixed(3);
+        assert!(quota.has_more(2) && quota.claim(2));
+        assert!(!quota.has_more(3) && !quota.claim(3));
+        assert!(!Quota::Fixed(0).claim(0));
+    }
+}
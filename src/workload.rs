@@ -0,0 +1,104 @@
+//! `--workload`: a mix of whole requests rather than URLs alone. Each line of the file
+//! is a JSON object, `{"method": "POST", "path": "/orders", "headers": {...}, "body":
+//! "...", "weight": 2}`, with `body_file` naming a file to send instead of `body`.
+//! Requests are drawn from the entries by weight like `--url-file`'s lines, and
+//! bodies are read once, when the file is loaded.
+
+use crate::urls::{EntryRequest, UrlMix};
+use bytes::Bytes;
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::{Method, Url};
+use serde_json::{Map, Value};
+use std::path::Path;
+
+/// Keys an entry may have
+const KEYS: [&str; 6] = ["method", "path", "headers", "body", "body_file", "weight"];
+
+/// Read `path` into a mix against `base`. Every entry must be on `base`'s origin.
+pub fn load(path: &Path, base: &str) -> Result<UrlMix, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let base = Url::parse(base).map_err(|e| e.to_string())?;
+    // body_file is relative to the workload file
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut urls = Vec::new();
+    let mut weights = Vec::new();
+    let mut requests = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let at = |message: String| format!("{}:{}: {}", path.display(), n + 1, message);
+        let entry = match serde_json::from_str::<Value>(line) {
+            Ok(Value::Object(entry)) => entry,
+            Ok(_) => return Err(at("expected a JSON object".to_string())),
+            Err(e) => return Err(at(format!("invalid JSON: {}", e))),
+        };
+        let (url, weight, request) = parse(&entry, &base, dir).map_err(at)?;
+        urls.push(url);
+        weights.push(weight);
+        requests.push(request);
+    }
+    if urls.is_empty() {
+        return Err(format!("{} lists no requests", path.display()));
+    }
+    Ok(UrlMix::with_requests(urls, weights, requests))
+}
+
+/// One entry's URL, weight and what it sends
+fn parse(entry: &Map<String, Value>, base: &Url, dir: &Path) -> Result<(String, f64, EntryRequest), String> {
+    if let Some(key) = entry.keys().find(|key| !KEYS.contains(&key.as_str())) {
+        return Err(format!("unknown key {:?}, expected {}", key, KEYS.join(", ")));
+    }
+    let text = |key: &str| -> Result<Option<&str>, String> {
+        match entry.get(key) {
+            None => Ok(None),
+            Some(Value::String(value)) => Ok(Some(value)),
+            Some(_) => Err(format!("{} must be a string", key)),
+        }
+    };
+
+    let method = text("method")?.ok_or("missing method")?;
+    let method = Method::from_bytes(method.as_bytes()).map_err(|_| format!("invalid method {:?}", method))?;
+    let path = text("path")?.ok_or("missing path")?;
+    let url = base.join(path).map_err(|e| format!("invalid path {:?}: {}", path, e))?;
+    // One connection pool serves every entry, so all of them go to the target
+    if url.origin() != base.origin() {
+        return Err(format!("{} is not on the target's origin {}", url, base.origin().ascii_serialization()));
+    }
+
+    let mut headers = Vec::new();
+    match entry.get("headers") {
+        None => {}
+        Some(Value::Object(map)) => {
+            for (name, value) in map {
+                let value = value.as_str().ok_or_else(|| format!("header {} must be a string", name))?;
+                let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("invalid header name {:?}", name))?;
+                let value = HeaderValue::from_str(value).map_err(|_| format!("invalid value for header {}", name))?;
+                headers.push((name, value));
+            }
+        }
+        Some(_) => return Err("headers must be an object of names to values".to_string()),
+    }
+
+    let body = match (text("body")?, text("body_file")?) {
+        (Some(_), Some(_)) => return Err("body and body_file can't both be given".to_string()),
+        (Some(body), None) => Some(Bytes::copy_from_slice(body.as_bytes())),
+        (None, Some(file)) => {
+            let file = dir.join(file);
+            let body = std::fs::read(&file).map_err(|e| format!("body_file {}: {}", file.display(), e))?;
+            Some(Bytes::from(body))
+        }
+        (None, None) => None,
+    };
+
+    let weight = match entry.get("weight") {
+        None => 1.0,
+        Some(weight) => match weight.as_f64() {
+            Some(weight) if weight > 0.0 && weight.is_finite() => weight,
+            _ => return Err("weight must be a positive number".to_string()),
+        },
+    };
+    Ok((url.to_string(), weight, EntryRequest { method, headers, body }))
+}
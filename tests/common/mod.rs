@@ -0,0 +1,98 @@
+//! A local server for the end-to-end tests, which drive the benchy binary against it.
+//! `benchy selftest` brings its own, which tests/selftest.rs runs.
+
+#![allow(dead_code)]
+
+pub mod quic;
+pub mod raw_h2;
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::body::Incoming;
+use hyper::server::conn::{http1, http2};
+use hyper::service::service_fn;
+use hyper::{Request, Response};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::process::Command;
+use tokio::net::TcpListener;
+
+/// Serve `handle` on a random localhost port, over cleartext HTTP/1.1 or, with `h2`, h2c
+pub async fn serve<F, Fut>(h2: bool, handle: F) -> SocketAddr
+where
+    F: Fn(Request<Incoming>) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Response<Full<Bytes>>> + Send + 'static,
+{
+    serve_h2_limited(h2, None, handle).await
+}
+
+/// [`serve`], with h2c advertising a SETTINGS_MAX_HEADER_LIST_SIZE of `max_header_list`
+pub async fn serve_h2_limited<F, Fut>(h2: bool, max_header_list: Option<u32>, handle: F) -> SocketAddr
+where
+    F: Fn(Request<Incoming>) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Response<Full<Bytes>>> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind a local port");
+    let addr = listener.local_addr().expect("local address");
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let handle = handle.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |req| {
+                    let resp = handle(req);
+                    async move { Ok::<_, Infallible>(resp.await) }
+                });
+                let io = TokioIo::new(stream);
+                let _ = if h2 {
+                    let mut builder = http2::Builder::new(TokioExecutor::new());
+                    if let Some(max) = max_header_list {
+                        builder.max_header_list_size(max);
+                    }
+                    builder.serve_connection(io, service).await
+                } else {
+                    http1::Builder::new().serve_connection(io, service).await
+                };
+            });
+        }
+    });
+    addr
+}
+
+/// Answers every request with `ok`
+pub async fn ok(_req: Request<Incoming>) -> Response<Full<Bytes>> {
+    Response::new(Full::new(Bytes::from_static(b"ok\n")))
+}
+
+/// The benchy binary, without colours
+pub fn benchy() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_benchy"));
+    command.env("NO_COLOR", "1");
+    command
+}
+
+/// Run benchy with `args` and parse its `--output json` report from stdout
+pub fn json_run(args: &[&str]) -> serde_json::Value {
+    json_run_with(benchy(), args)
+}
+
+/// [`json_run`] from `command`, e.g. [`benchy`] with an environment variable set
+pub fn json_run_with(mut command: Command, args: &[&str]) -> serde_json::Value {
+    let output = command
+        .args(["--output", "json", "--no-probe"])
+        .args(args)
+        .output()
+        .expect("run benchy");
+    assert!(
+        output.status.success(),
+        "benchy {:?} exited with {}:\n{}{}",
+        args,
+        output.status,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).unwrap_or_else(|e| {
+        panic!("benchy {:?} printed no JSON report ({}):\n{}", args, e, String::from_utf8_lossy(&output.stdout))
+    })
+}
@@ -0,0 +1,57 @@
+//! A QUIC endpoint for HTTP/3 test servers, and a relay that can make the path between
+//! benchy and one go dead
+
+use quinn::crypto::rustls::QuicServerConfig;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+
+/// A server endpoint on a random localhost port offering `h3`, with a self-signed
+/// certificate for localhost (so benchy needs `-k`)
+pub fn server() -> quinn::Endpoint {
+    let cert = CertificateDer::from(include_bytes!("localhost.crt.der").to_vec());
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(include_bytes!("localhost.key.der").to_vec()));
+    let mut tls = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .expect("TLS 1.3")
+        .with_no_client_auth()
+        .with_single_cert(vec![cert], key)
+        .expect("test certificate");
+    tls.alpn_protocols = vec![b"h3".to_vec()];
+    let crypto = QuicServerConfig::try_from(tls).expect("QUIC TLS config");
+    let config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+    quinn::Endpoint::server(config, "127.0.0.1:0".parse().unwrap()).expect("bind a local UDP port")
+}
+
+/// Forward datagrams between one client and `server` until the returned flag is set,
+/// then drop everything in both directions, as a path that died would
+pub async fn relay(server: SocketAddr) -> (SocketAddr, Arc<AtomicBool>) {
+    let front = UdpSocket::bind("127.0.0.1:0").await.expect("bind a local UDP port");
+    let back = UdpSocket::bind("127.0.0.1:0").await.expect("bind a local UDP port");
+    back.connect(server).await.expect("connect to the server");
+    let addr = front.local_addr().expect("local address");
+    let dead = Arc::new(AtomicBool::new(false));
+    let cut = dead.clone();
+    tokio::spawn(async move {
+        let mut client = None;
+        let (mut up, mut down) = (vec![0u8; 65536], vec![0u8; 65536]);
+        loop {
+            tokio::select! {
+                Ok((len, from)) = front.recv_from(&mut up) => {
+                    client = Some(from);
+                    if !cut.load(Ordering::Relaxed) {
+                        let _ = back.send(&up[..len]).await;
+                    }
+                }
+                Ok(len) = back.recv(&mut down) => {
+                    if let (Some(client), false) = (client, cut.load(Ordering::Relaxed)) {
+                        let _ = front.send_to(&down[..len], client).await;
+                    }
+                }
+            }
+        }
+    });
+    (addr, dead)
+}
@@ -0,0 +1,63 @@
+//! Just enough HTTP/2 framing for test servers that must misbehave in ways hyper and
+//! h2 won't, e.g. a GOAWAY that leaves out the last stream or a PING left unanswered
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+pub const DATA: u8 = 0x0;
+pub const HEADERS: u8 = 0x1;
+pub const RST_STREAM: u8 = 0x3;
+pub const SETTINGS: u8 = 0x4;
+pub const PING: u8 = 0x6;
+pub const GOAWAY: u8 = 0x7;
+pub const END_STREAM: u8 = 0x1;
+pub const END_HEADERS: u8 = 0x4;
+pub const ACK: u8 = 0x1;
+pub const COMPRESSION_ERROR: u32 = 0x9;
+
+/// One frame as read off the connection
+pub struct Frame {
+    pub kind: u8,
+    pub flags: u8,
+    pub stream: u32,
+    pub payload: Vec<u8>,
+}
+
+pub async fn write(io: &mut TcpStream, kind: u8, flags: u8, stream: u32, payload: &[u8]) -> std::io::Result<()> {
+    let len = (payload.len() as u32).to_be_bytes();
+    let mut header = [0u8; 9];
+    header[..3].copy_from_slice(&len[1..]);
+    header[3] = kind;
+    header[4] = flags;
+    header[5..].copy_from_slice(&stream.to_be_bytes());
+    io.write_all(&header).await?;
+    io.write_all(payload).await
+}
+
+pub async fn read(io: &mut TcpStream) -> std::io::Result<Frame> {
+    let mut header = [0u8; 9];
+    io.read_exact(&mut header).await?;
+    let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+    let mut payload = vec![0u8; len];
+    io.read_exact(&mut payload).await?;
+    Ok(Frame {
+        kind: header[3],
+        flags: header[4],
+        stream: u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & 0x7fff_ffff,
+        payload,
+    })
+}
+
+/// Read the client's preface and send our (empty) SETTINGS
+pub async fn accept(io: &mut TcpStream) -> std::io::Result<()> {
+    let mut preface = [0u8; 24];
+    io.read_exact(&mut preface).await?;
+    write(io, SETTINGS, 0, 0, &[]).await
+}
+
+/// Answer `stream` with 200 and the body "ok"
+pub async fn respond_ok(io: &mut TcpStream, stream: u32) -> std::io::Result<()> {
+    // 0x88 is `:status: 200` from HPACK's static table
+    write(io, HEADERS, END_HEADERS, stream, &[0x88]).await?;
+    write(io, DATA, END_STREAM, stream, b"ok").await
+}
@@ -0,0 +1,59 @@
+//! `--fallback` steps down only when the server was reached and didn't speak the
+//! protocol; a server that can't be reached at all ends the run
+
+mod common;
+
+use std::process::Output;
+use tokio::net::TcpListener;
+
+async fn run(args: &'static [&'static str], url: String) -> (Output, String) {
+    let output = tokio::task::spawn_blocking(move || {
+        common::benchy().args(["--fallback", "-n", "4", "-c", "1"]).args(args).arg(&url).output().expect("run benchy")
+    })
+    .await
+    .unwrap();
+    let text = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    (output, text)
+}
+
+/// A localhost port nothing listens on, over TCP or UDP
+async fn closed_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind a local port");
+    listener.local_addr().expect("local address").port()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn an_http1_server_is_stepped_down_to() {
+    let addr = common::serve(false, common::ok).await;
+    let (output, text) = run(&["--protocol", "h2"], format!("http://{}/", addr)).await;
+    assert!(output.status.success(), "{}", text);
+    assert!(text.contains("HTTP/2 unavailable, trying HTTP/1.1"), "{}", text);
+    assert!(text.contains("(HTTP/1.1)"), "{}", text);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_refused_connection_ends_the_run() {
+    let (output, text) = run(&["--protocol", "h2"], format!("http://127.0.0.1:{}/", closed_port().await)).await;
+    assert_eq!(output.status.code(), Some(1), "{}", text);
+    assert!(text.contains("HTTP/2 probe failed before negotiating"), "{}", text);
+    assert!(!text.contains("Fallback:"), "{}", text);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_failed_lookup_ends_the_run() {
+    let (output, text) = run(&["--protocol", "h2"], "http://benchy-fallback.invalid/".to_string()).await;
+    assert_eq!(output.status.code(), Some(1), "{}", text);
+    assert!(text.contains("HTTP/2 probe failed before negotiating"), "{}", text);
+    assert!(!text.contains("Fallback:"), "{}", text);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn an_unanswered_quic_handshake_steps_down() {
+    // Nothing answers QUIC on the h2c server's port, and TLS over TCP fails to connect,
+    // so the run steps down from HTTP/3 once and then gives up
+    let addr = common::serve(true, common::ok).await;
+    let (output, text) = run(&["--h3", "-k"], format!("https://{}/", addr)).await;
+    assert_eq!(output.status.code(), Some(1), "{}", text);
+    assert!(text.contains("HTTP/3 unavailable, trying HTTP/2"), "{}", text);
+    assert!(text.contains("HTTP/2 probe failed before negotiating"), "{}", text);
+}
@@ -0,0 +1,62 @@
+//! `--fill` as the server sees it: how many requests it has in hand at once over the
+//! start of the run, against a server that holds each for a while
+
+mod common;
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::Response;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// When each request arrived after the first, and how many the server then had in hand
+type Arrivals = Arc<Mutex<Vec<(Duration, usize)>>>;
+
+async fn fill_run(fill: &str) -> Vec<(Duration, usize)> {
+    let arrivals = Arrivals::default();
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let first = Arc::new(Mutex::new(None::<Instant>));
+    let seen = arrivals.clone();
+    let addr = common::serve(true, move |_req| {
+        let now = Instant::now();
+        let first = *first.lock().unwrap().get_or_insert(now);
+        let held = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        seen.lock().unwrap().push((now - first, held));
+        let in_flight = in_flight.clone();
+        async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            Response::new(Full::new(Bytes::from_static(b"ok\n")))
+        }
+    })
+    .await;
+    let url = format!("http://{}/", addr);
+    let fill = fill.to_string();
+    tokio::task::spawn_blocking(move || common::json_run(&["-n", "80", "-c", "1", "-p", "8", "--fill", &fill, &url]))
+        .await
+        .unwrap();
+    let arrivals = arrivals.lock().unwrap().clone();
+    arrivals
+}
+
+/// The most the server had in hand at once among arrivals in `from..to`
+fn peak(arrivals: &[(Duration, usize)], from: u64, to: u64) -> usize {
+    let window = Duration::from_millis(from)..Duration::from_millis(to);
+    arrivals.iter().filter(|(at, _)| window.contains(at)).map(|&(_, held)| held).max().unwrap_or(0)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn eager_fills_the_pipeline_at_once() {
+    let arrivals = fill_run("eager").await;
+    assert_eq!(peak(&arrivals, 0, 50), 8, "{:?}", arrivals);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn gradual_starts_at_one_and_ramps_to_the_pipeline() {
+    // -p 8 over 700ms: one more every 100ms
+    let arrivals = fill_run("gradual:700ms").await;
+    assert_eq!(peak(&arrivals, 0, 50), 1, "{:?}", arrivals);
+    assert!(peak(&arrivals, 250, 350) <= 4, "{:?}", arrivals);
+    assert_eq!(peak(&arrivals, 700, u64::MAX), 8, "{:?}", arrivals);
+}
@@ -0,0 +1,83 @@
+//! `--retry-goaway` against a server that answers a few streams per connection and then
+//! sends GOAWAY, leaving out the stream it just received.
+//!
+//! hyper and h2 only ever send GOAWAY naming the last stream they received, which the
+//! client must treat as possibly processed, so the server speaks the few frames this
+//! needs itself.
+
+mod common;
+
+use common::raw_h2::{self, ACK, GOAWAY, HEADERS, PING, SETTINGS};
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// One h2c connection: `per_connection` streams answered with 200 "ok", then GOAWAY
+/// with the stream before the next one as the last processed
+async fn connection(mut io: TcpStream, per_connection: usize) -> std::io::Result<()> {
+    raw_h2::accept(&mut io).await?;
+    let mut answered = 0;
+    let mut last = 0u32;
+    loop {
+        let frame = raw_h2::read(&mut io).await?;
+        match frame.kind {
+            SETTINGS if frame.flags & ACK == 0 => raw_h2::write(&mut io, SETTINGS, ACK, 0, &[]).await?,
+            PING if frame.flags & ACK == 0 => raw_h2::write(&mut io, PING, ACK, 0, &frame.payload).await?,
+            HEADERS if answered < per_connection => {
+                raw_h2::respond_ok(&mut io, frame.stream).await?;
+                answered += 1;
+                last = frame.stream;
+            }
+            HEADERS => {
+                let mut goaway = last.to_be_bytes().to_vec();
+                goaway.extend_from_slice(&0u32.to_be_bytes());
+                raw_h2::write(&mut io, GOAWAY, 0, 0, &goaway).await?;
+                io.flush().await?;
+                // The client closes once it has moved its requests elsewhere
+                let mut rest = Vec::new();
+                let _ = io.read_to_end(&mut rest).await;
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn serve_goaway(per_connection: usize) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind a local port");
+    let addr = listener.local_addr().expect("local address");
+    tokio::spawn(async move {
+        while let Ok((io, _)) = listener.accept().await {
+            tokio::spawn(connection(io, per_connection));
+        }
+    });
+    addr
+}
+
+#[tokio::test]
+async fn unprocessed_requests_are_retried_on_a_new_connection() {
+    let addr = serve_goaway(4).await;
+    let url = format!("http://{}/", addr);
+    let output = tokio::task::spawn_blocking(move || {
+        common::benchy().args(["-n", "10", "-c", "1", "-p", "1", "--no-probe", &url]).output().expect("run benchy")
+    })
+    .await
+    .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+
+    // Requests 5 and 9 each reach a connection that has answered 4, get left out of its
+    // GOAWAY and go again on the next one
+    let line = |label: &str| {
+        stdout
+            .lines()
+            .find_map(|line| line.strip_prefix(label))
+            .unwrap_or_else(|| panic!("no {} line in:\n{}", label, stdout))
+            .trim()
+            .to_string()
+    };
+    assert_eq!(line("GOAWAY:"), "2 connections, 2 requests retried on new connections");
+    assert_eq!(line("Success:"), "10");
+    assert_eq!(line("Failed:"), "0");
+    assert_eq!(line("Connections:"), "3");
+}
@@ -0,0 +1,80 @@
+//! `-m HEAD` against a local server: the body its Content-Length announces is never
+//! read, and status and header checks still decide what fails
+
+mod common;
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::body::Incoming;
+use hyper::header::CONTENT_LENGTH;
+use hyper::{Method, Request, Response, StatusCode};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Announces 1000 bytes on `/` and answers 404 elsewhere, counting the HEADs it gets
+async fn serve_head(heads: Arc<AtomicU64>) -> String {
+    let addr = common::serve(false, move |req: Request<Incoming>| {
+        if req.method() == Method::HEAD {
+            heads.fetch_add(1, Ordering::Relaxed);
+        }
+        async move {
+            let body = if req.method() == Method::HEAD { Bytes::new() } else { Bytes::from(vec![b'x'; 1000]) };
+            let mut resp = Response::new(Full::new(body));
+            resp.headers_mut().insert(CONTENT_LENGTH, 1000.into());
+            resp.headers_mut().insert("x-served-by", "edge-1".parse().unwrap());
+            if req.uri().path() != "/" {
+                *resp.status_mut() = StatusCode::NOT_FOUND;
+            }
+            resp
+        }
+    })
+    .await;
+    format!("http://{}", addr)
+}
+
+fn head_run(args: &[&str]) -> serde_json::Value {
+    common::json_run(&[&["-m", "HEAD", "-n", "20", "-c", "2", "--protocol", "h1"], args].concat())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn head_requests_read_no_body() {
+    let heads = Arc::new(AtomicU64::new(0));
+    let base = serve_head(heads.clone()).await;
+    let url = format!("{}/", base);
+    let output = tokio::task::spawn_blocking(move || {
+        common::benchy()
+            .args(["-m", "HEAD", "-n", "20", "-c", "2", "--protocol", "h1", "--no-probe", &url])
+            .output()
+            .expect("run benchy")
+    })
+    .await
+    .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+    assert_eq!(heads.load(Ordering::Relaxed), 20);
+
+    // The announced size is reported as virtual; nothing was downloaded
+    let virtual_size = stdout.lines().find_map(|line| line.strip_prefix("Virtual size:"));
+    assert_eq!(
+        virtual_size.map(str::trim),
+        Some("min 1000 B, avg 1000 B, max 1000 B (HEAD Content-Length, not transferred)"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.lines().any(|line| line.starts_with("Body size:")), "{}", stdout);
+    assert!(!stdout.lines().any(|line| line.trim_start().starts_with("Download")), "{}", stdout);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn head_responses_are_still_checked() {
+    let base = serve_head(Arc::new(AtomicU64::new(0))).await;
+
+    let report = head_run(&["--assert-header", "x-served-by: /edge-/", &format!("{}/", base)]);
+    assert_eq!((&report["success"], &report["failed"]), (&20.into(), &0.into()));
+
+    let report = head_run(&["--assert-header", "x-served-by: /origin-/", &format!("{}/", base)]);
+    assert_eq!((&report["success"], &report["failed"]), (&0.into(), &20.into()));
+
+    let report = head_run(&[&format!("{}/missing", base)]);
+    assert_eq!((&report["success"], &report["failed"]), (&0.into(), &20.into()));
+}
@@ -0,0 +1,90 @@
+//! Request header blocks past what the server accepts, reported as `headers too large`
+
+mod common;
+
+use common::raw_h2::{self, ACK, HEADERS, PING, RST_STREAM, SETTINGS};
+use std::future::Future;
+use std::net::SocketAddr;
+use tokio::net::{TcpListener, TcpStream};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_tiny_max_header_list_size_fails_as_headers_too_large() {
+    let addr = common::serve_h2_limited(true, Some(64), common::ok).await;
+    let url = format!("http://{}/", addr);
+    // One header that alone is over the limit, sent on every request
+    let path = std::env::temp_dir().join(format!("benchy-padding-{}.headers", std::process::id()));
+    std::fs::write(&path, format!("x-padding: {}\n", "x".repeat(64))).unwrap();
+    let header_file = path.to_str().unwrap().to_string();
+    let report = tokio::task::spawn_blocking(move || {
+        common::json_run(&["-n", "20", "-c", "2", "--protocol", "h2", "--header-file", &header_file, &url])
+    })
+    .await
+    .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!((&report["success"], &report["failed"]), (&0.into(), &20.into()), "{}", report["errors"]);
+    assert_eq!(report["errors"]["headers too large"], 20, "{}", report["errors"]);
+}
+
+/// An h2c server that answers every request with `answer`
+async fn serve_raw<F, Fut>(answer: F) -> SocketAddr
+where
+    F: Fn(TcpStream, u32) -> Fut + Copy + Send + 'static,
+    Fut: Future<Output = std::io::Result<TcpStream>> + Send,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind a local port");
+    let addr = listener.local_addr().expect("local address");
+    tokio::spawn(async move {
+        while let Ok((mut io, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                raw_h2::accept(&mut io).await?;
+                loop {
+                    let frame = raw_h2::read(&mut io).await?;
+                    match frame.kind {
+                        SETTINGS if frame.flags & ACK == 0 => raw_h2::write(&mut io, SETTINGS, ACK, 0, &[]).await?,
+                        HEADERS => io = answer(io, frame.stream).await?,
+                        _ => {}
+                    }
+                }
+                #[allow(unreachable_code)]
+                Ok::<_, std::io::Error>(())
+            });
+        }
+    });
+    addr
+}
+
+fn errors(addr: SocketAddr) -> serde_json::Value {
+    let output = common::benchy()
+        .args(["-n", "5", "-c", "1", "--protocol", "h2", "--output", "json", "--no-probe"])
+        .arg(format!("http://{}/", addr))
+        .output()
+        .expect("run benchy");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|_| panic!("no JSON report:\n{}", String::from_utf8_lossy(&output.stderr)));
+    report["errors"].clone()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_reset_of_the_sent_headers_is_headers_too_large() {
+    let addr = serve_raw(|mut io, stream| async move {
+        raw_h2::write(&mut io, RST_STREAM, 0, stream, &raw_h2::COMPRESSION_ERROR.to_be_bytes()).await?;
+        Ok(io)
+    })
+    .await;
+    let errors = tokio::task::spawn_blocking(move || errors(addr)).await.unwrap();
+    assert_eq!(errors["headers too large"], 5, "{}", errors);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_malformed_frame_from_the_server_is_not_headers_too_large() {
+    // A PING must carry 8 bytes; h2 answers 4 with its own FRAME_SIZE_ERROR GOAWAY
+    let addr = serve_raw(|mut io, _| async move {
+        raw_h2::write(&mut io, PING, 0, 0, &[0; 4]).await?;
+        Ok(io)
+    })
+    .await;
+    let errors = tokio::task::spawn_blocking(move || errors(addr)).await.unwrap();
+    assert_eq!(errors["headers too large"], 0, "{}", errors);
+    assert_eq!(errors["protocol"], 5, "{}", errors);
+}
@@ -0,0 +1,118 @@
+//! `--h2-keepalive` and `--h3-keepalive` against servers that answer the first request
+//! and then go silent, PINGs included: the request left waiting fails as a `keepalive`
+//! error rather than hanging for good.
+
+mod common;
+
+use bytes::Bytes;
+use common::raw_h2::{self, ACK, HEADERS, SETTINGS};
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use tokio::net::{TcpListener, TcpStream};
+
+/// One h2c connection that answers a single request and afterwards reads on, answering nothing
+async fn connection(mut io: TcpStream) -> std::io::Result<()> {
+    raw_h2::accept(&mut io).await?;
+    let mut answered = false;
+    loop {
+        let frame = raw_h2::read(&mut io).await?;
+        match frame.kind {
+            SETTINGS if frame.flags & ACK == 0 && !answered => raw_h2::write(&mut io, SETTINGS, ACK, 0, &[]).await?,
+            HEADERS if !answered => {
+                raw_h2::respond_ok(&mut io, frame.stream).await?;
+                answered = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn serve_silent() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind a local port");
+    let addr = listener.local_addr().expect("local address");
+    tokio::spawn(async move {
+        while let Ok((io, _)) = listener.accept().await {
+            tokio::spawn(connection(io));
+        }
+    });
+    addr
+}
+
+/// An HTTP/3 server behind a relay that answers the first request on each connection
+/// and, when the next one arrives, cuts the relay off so that nothing more reaches
+/// benchy, ACKs included
+async fn serve_h3_silent() -> SocketAddr {
+    let endpoint = common::quic::server();
+    let (addr, dead) = common::quic::relay(endpoint.local_addr().expect("local address")).await;
+    tokio::spawn(async move {
+        while let Some(incoming) = endpoint.accept().await {
+            let dead = dead.clone();
+            tokio::spawn(async move {
+                let Ok(conn) = incoming.await else { return };
+                let Ok(mut conn) = h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(conn)).await
+                else {
+                    return;
+                };
+                let mut answered = false;
+                let mut held = Vec::new();
+                while let Ok(Some(resolver)) = conn.accept().await {
+                    let Ok((_, mut stream)) = resolver.resolve_request().await else { return };
+                    if answered {
+                        dead.store(true, Ordering::Relaxed);
+                        held.push(stream);
+                        continue;
+                    }
+                    if stream.send_response(http::Response::new(())).await.is_ok() {
+                        let _ = stream.send_data(Bytes::from_static(b"ok")).await;
+                        let _ = stream.finish().await;
+                    }
+                    answered = true;
+                }
+            });
+        }
+    });
+    addr
+}
+
+/// Run benchy with `args` and return the value of each report line by its label
+fn run(args: &[&str]) -> impl Fn(&str) -> String {
+    let output = common::benchy().arg("--no-probe").args(args).output().expect("run benchy");
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    assert!(output.status.success(), "{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+    move |label| {
+        stdout
+            .lines()
+            .find_map(|line| line.strip_prefix(label))
+            .unwrap_or_else(|| panic!("no {} line in:\n{}", label, stdout))
+            .trim()
+            .to_string()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unanswered_h2_pings_fail_requests_as_keepalive() {
+    let addr = serve_silent().await;
+    let url = format!("http://{}/", addr);
+    let line = tokio::task::spawn_blocking(move || run(&["-n", "2", "-c", "1", "-p", "1", "--h2-keepalive", "200ms", &url]))
+        .await
+        .unwrap();
+    assert_eq!(line("Success:"), "1");
+    assert_eq!(line("Failed:"), "1");
+    assert_eq!(line("keepalive:"), "1");
+    assert_eq!(line("Keepalive:"), "1 connections found dead by PING and re-established");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_dead_quic_path_fails_requests_as_keepalive() {
+    let addr = serve_h3_silent().await;
+    let url = format!("https://{}/", addr);
+    let line = tokio::task::spawn_blocking(move || {
+        run(&["--h3", "-k", "-n", "2", "-c", "1", "-p", "1", "--h3-keepalive", "200ms", "--h3-max-idle-timeout", "1s", &url])
+    })
+    .await
+    .unwrap();
+    assert_eq!(line("Success:"), "1");
+    assert_eq!(line("Failed:"), "1");
+    assert_eq!(line("keepalive:"), "1");
+    assert_eq!(line("Keepalive:"), "1 connections found dead by PING and re-established");
+}
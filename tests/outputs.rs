@@ -0,0 +1,53 @@
+//! Several `--output`s from one run: the text report and every file hold the same
+//! results, the per-URL rows, worker utilization and protocol mix included
+
+mod common;
+
+use serde_json::Value;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn every_output_has_the_per_url_rows() {
+    let addr = common::serve(false, common::ok).await;
+    let url = format!("http://{}/", addr);
+    let dir = std::env::temp_dir().join(format!("benchy-outputs-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("urls.txt"), "/a 3\n/b 1\n").unwrap();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+    let (urls, json, markdown, influx) = (path("urls.txt"), path("run.json"), path("run.md"), path("run.influx"));
+    let output = {
+        let (url, urls) = (url.clone(), urls.clone());
+        let outputs = [format!("json={}", json), format!("markdown={}", markdown), format!("influx={}", influx)];
+        tokio::task::spawn_blocking(move || {
+            common::benchy()
+                .args(["-n", "40", "-c", "2", "--protocol", "auto", "--no-probe", "--url-file", &urls, "--per-url-stats"])
+                .args(["--output", "human", "--output", &outputs[0], "--output", &outputs[1], "--output", &outputs[2]])
+                .arg(&url)
+                .output()
+                .expect("run benchy")
+        })
+        .await
+        .unwrap()
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+    let read = |path: &str| std::fs::read_to_string(path).unwrap();
+    let (json, markdown, influx) = (read(&json), read(&markdown), read(&influx));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let (a, b) = (format!("{}a", url), format!("{}b", url));
+    let text_rows = stdout.split_once("--- Per URL ---").map(|(_, rows)| rows).unwrap_or_default();
+    assert!(text_rows.contains(&a) && text_rows.contains(&b), "{}", stdout);
+    assert!(stdout.contains("Utilization:") && stdout.contains("connections HTTP/1.1"), "{}", stdout);
+
+    let json: Value = serde_json::from_str(&json).unwrap();
+    let rows = json["urls"].as_array().unwrap();
+    assert_eq!((rows.len(), &rows[0]["url"], &rows[1]["url"]), (2, &a.clone().into(), &b.clone().into()));
+    let counted: u64 = rows.iter().map(|row| row["count"].as_u64().unwrap()).sum();
+    assert_eq!(counted, 40);
+    assert_eq!(json["worker_utilization"].as_array().unwrap().len(), 2);
+    assert_eq!(json["protocols"][0]["version"], "HTTP/1.1");
+
+    assert!(markdown.contains(&format!("| `{}` |", a)) && markdown.contains(&format!("| `{}` |", b)), "{}", markdown);
+    assert!(markdown.contains("| HTTP/1.1 |"), "{}", markdown);
+    assert_eq!(influx.lines().filter(|line| line.starts_with("benchy_url,")).count(), 2, "{}", influx);
+}
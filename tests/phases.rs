@@ -0,0 +1,41 @@
+//! Setup cost kept out of throughput: `--preconnect` with every client's construction
+//! stalled (a debug-build hook), against the same run without the stall
+
+mod common;
+
+use serde_json::Value;
+
+/// The run's setup and measured phases, in milliseconds
+fn phases(report: &Value) -> (u64, u64) {
+    let ms = |name: &str| report["run_phases"][name].as_u64().unwrap_or_else(|| panic!("no {} in {}", name, report));
+    (
+        ms("measured_start_ms") - ms("setup_start_ms"),
+        ms("measured_end_ms") - ms("measured_start_ms"),
+    )
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn slow_client_construction_is_not_measured() {
+    let addr = common::serve(true, common::ok).await;
+    let url = format!("http://{}/", addr);
+    let (fast, slow) = tokio::task::spawn_blocking(move || {
+        let args = ["-n", "200", "-c", "2", "--preconnect", &url];
+        let fast = common::json_run(&args);
+        let mut stalled = common::benchy();
+        stalled.env("BENCHY_TEST_SLOW_CLIENT_MS", "600");
+        (fast, common::json_run_with(stalled, &args))
+    })
+    .await
+    .unwrap();
+
+    assert_eq!((&fast["success"], &slow["success"]), (&200.into(), &200.into()));
+    let ((_, fast_measured), (slow_setup, slow_measured)) = (phases(&fast), phases(&slow));
+    // Two workers' clients at 600ms each, all of it before the first send
+    assert!(slow_setup >= 1200, "setup {}ms", slow_setup);
+    assert!(
+        slow_measured < fast_measured + 600,
+        "measured {}ms with slow clients, {}ms without",
+        slow_measured,
+        fast_measured
+    );
+}
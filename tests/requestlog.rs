@@ -0,0 +1,169 @@
+//! `--request-log` against a local server: a line for every request, each with what
+//! the results count it as, and `benchy report --from-request-log` rebuilding the
+//! results from it
+
+mod common;
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{Response, StatusCode};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Every third request is a 503, and each names the backend that served it
+async fn serve_mixed() -> String {
+    let seen = Arc::new(AtomicU64::new(0));
+    let addr = common::serve(true, move |_req| {
+        let nth = seen.fetch_add(1, Ordering::Relaxed);
+        async move {
+            let mut resp = Response::new(Full::new(Bytes::from_static(b"ok\n")));
+            if nth % 3 == 2 {
+                *resp.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+            }
+            let backend = if nth.is_multiple_of(2) { "a" } else { "b" };
+            resp.headers_mut().insert("x-backend", backend.parse().unwrap());
+            resp
+        }
+    })
+    .await;
+    format!("http://{}/", addr)
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("benchy-{}-{}.jsonl", name, std::process::id()))
+}
+
+/// Echoes the request body back in `x-body`
+async fn serve_echo() -> String {
+    let addr = common::serve(true, |req: hyper::Request<hyper::body::Incoming>| async move {
+        let body = http_body_util::BodyExt::collect(req.into_body()).await.unwrap().to_bytes();
+        let mut resp = Response::new(Full::new(Bytes::from_static(b"ok\n")));
+        resp.headers_mut().insert("x-body", hyper::header::HeaderValue::from_bytes(&body).unwrap());
+        resp
+    })
+    .await;
+    format!("http://{}/", addr)
+}
+
+fn report(args: &[&str]) -> std::process::Output {
+    common::benchy().arg("report").args(args).output().expect("run benchy report")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn every_request_is_logged() {
+    let url = serve_mixed().await;
+    let path = temp_path("request-log");
+    let log = path.to_str().unwrap().to_string();
+    let report = tokio::task::spawn_blocking(move || {
+        common::json_run(&["-n", "30", "-c", "3", "--group-by-header", "x-backend", "--request-log", &log, &url])
+    })
+    .await
+    .unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let lines: Vec<Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 30, "{}", text);
+    let ok = lines.iter().filter(|line| line["ok"] == true).count();
+    assert_eq!((ok as u64, lines.len() as u64 - ok as u64), (20, 10));
+    assert_eq!((&report["success"], &report["failed"]), (&20.into(), &10.into()));
+    for line in &lines {
+        assert_eq!(line["ok"], line["status"] == 200, "{}", line);
+        assert!(line["url"].as_str().unwrap().starts_with("http://127.0.0.1:"), "{}", line);
+        assert_eq!(line["version"], "HTTP/2.0", "{}", line);
+        assert!(line["latency_us"].as_f64().unwrap() >= line["ttfb_us"].as_f64().unwrap(), "{}", line);
+        assert!(["a", "b"].contains(&line["headers"]["x-backend"].as_str().unwrap()), "{}", line);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn report_rebuilds_the_results_from_the_log() {
+    let url = serve_mixed().await;
+    let path = temp_path("report-log");
+    let log = path.to_str().unwrap().to_string();
+    let (run, text, json, window) = tokio::task::spawn_blocking(move || {
+        let run = common::json_run(&[
+            "-n", "30", "-c", "3", "--group-by-header", "x-backend", "--request-log", &log, &url,
+        ]);
+        // A torn last line, as a killed run would leave
+        let mut file = std::fs::OpenOptions::new().append(true).open(&log).unwrap();
+        std::io::Write::write_all(&mut file, b"{\"sent\":\"2026-").unwrap();
+        let text = report(&["--from-request-log", &log, "--group-by", "status", "-p", "99.9"]);
+        let json = report(&["--from-request-log", &log, "--group-by", "header", "--output", "json"]);
+        let window = report(&["--from-request-log", &log, "--from", "1h"]);
+        (run, text, json, window)
+    })
+    .await
+    .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let stdout = String::from_utf8_lossy(&text.stdout);
+    let stderr = String::from_utf8_lossy(&text.stderr);
+    assert!(text.status.success(), "{}{}", stdout, stderr);
+    assert!(stderr.contains("skipped 1 malformed lines"), "{}", stderr);
+    assert!(stdout.contains("(30 requests from"), "{}", stdout);
+    assert!(stdout.lines().any(|line| line.starts_with("P99.9:")), "{}", stdout);
+    assert!(stdout.contains("--- Latency by status ---"), "{}", stdout);
+    let count = |value: &str| {
+        stdout
+            .lines()
+            .find_map(|line| line.trim_start().strip_prefix(value))
+            .and_then(|rest| rest.split_whitespace().next())
+            .unwrap_or_else(|| panic!("no {} row in:\n{}", value, stdout))
+            .to_string()
+    };
+    assert_eq!((count("200:"), count("503:")), ("20".to_string(), "10".to_string()));
+
+    // The same counts as the run's own results, in the json sink's shape
+    assert!(json.status.success(), "{}", String::from_utf8_lossy(&json.stderr));
+    let rebuilt: Value = serde_json::from_slice(&json.stdout).unwrap();
+    for key in ["requests", "success", "failed"] {
+        assert_eq!(rebuilt[key], run[key], "{}", key);
+    }
+    assert_eq!(rebuilt["latency"]["count"], 30);
+    assert_eq!(rebuilt["group_by_header"]["header"], "x-backend");
+    let groups = rebuilt["group_by_header"]["groups"].as_array().unwrap();
+    let counted: u64 = groups.iter().map(|group| group["latency"]["count"].as_u64().unwrap()).sum();
+    assert_eq!((groups.len(), counted), (2, 30), "{}", rebuilt);
+
+    // Nothing was sent an hour in
+    assert!(!window.status.success());
+    assert!(String::from_utf8_lossy(&window.stderr).contains("no requests between --from and --to"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn each_line_names_the_data_dir_file_sent() {
+    let url = serve_echo().await;
+    let dir = std::env::temp_dir().join(format!("benchy-data-dir-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (name, body) in [("a.json", "alpha"), ("b.json", "bravo"), ("c.json", "charlie")] {
+        std::fs::write(dir.join(name), body).unwrap();
+    }
+    let path = temp_path("body-file-log");
+    let (data_dir, log) = (dir.to_str().unwrap().to_string(), path.to_str().unwrap().to_string());
+    tokio::task::spawn_blocking(move || {
+        common::json_run(&[
+            "-n", "30", "-c", "3", "--data-dir", &data_dir, "--group-by-header", "x-body", "--request-log", &log, &url,
+        ])
+    })
+    .await
+    .unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let lines: Vec<Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 30);
+    for line in &lines {
+        // The server saw the body of the file the line names
+        let sent = match line["body_file"].as_str() {
+            Some("a.json") => "alpha",
+            Some("b.json") => "bravo",
+            Some("c.json") => "charlie",
+            other => panic!("unexpected body_file {:?} in {}", other, line),
+        };
+        assert_eq!(line["headers"]["x-body"], sent, "{}", line);
+    }
+}
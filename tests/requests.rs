@@ -0,0 +1,78 @@
+//! Every request of `-n` accounted for, with `-n`, `-c`, `-p` and `--workers` that don't
+//! divide evenly, and when a run is aborted with requests still in flight
+
+mod common;
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{Response, StatusCode};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn uneven_splits_send_exactly_n() {
+    let addr = common::serve(true, common::ok).await;
+    let url = format!("http://{}/", addr);
+    let runs: &[&[&str]] = &[
+        &["-n", "7", "-c", "3", "-p", "10"],
+        &["-n", "2", "-c", "3"],
+        &["-n", "1", "-c", "4", "-p", "3"],
+        &["-n", "10", "-c", "4", "--workers", "3"],
+        &["-n", "11", "-c", "5", "-p", "2", "--workers", "2"],
+        &["-n", "7", "-c", "3", "-p", "10", "--work-stealing"],
+        &["-n", "13", "-c", "4", "--workers", "3", "--work-stealing"],
+    ];
+    for args in runs {
+        let requests: u64 = args[1].parse().unwrap();
+        let report = common::json_run(&[args, &[url.as_str()][..]].concat());
+        assert_eq!(report["requests"], requests, "{:?}", args);
+        assert_eq!(report["success"], requests, "{:?}", args);
+        assert_eq!(report["failed"], 0, "{:?}", args);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn an_abort_accounts_for_every_request() {
+    // The fourth request fails at once while the others take a while, so -f stops the
+    // run with some in flight and some never sent
+    let seen = Arc::new(AtomicU64::new(0));
+    let addr = common::serve(true, move |_req| {
+        let nth = seen.fetch_add(1, Ordering::Relaxed) + 1;
+        async move {
+            if nth == 4 {
+                let mut resp = Response::new(Full::new(Bytes::from_static(b"no\n")));
+                *resp.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                return resp;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Response::new(Full::new(Bytes::from_static(b"ok\n")))
+        }
+    })
+    .await;
+    let url = format!("http://{}/", addr);
+    let output = tokio::task::spawn_blocking(move || {
+        common::benchy()
+            .args(["-n", "7", "-c", "3", "-p", "10", "-f", "--no-probe", &url])
+            .output()
+            .expect("run benchy")
+    })
+    .await
+    .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+
+    // "7 requested, 7 sent, 1 completed, 6 cancelled, 0 never sent"
+    let line = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("Requests:"))
+        .unwrap_or_else(|| panic!("no Requests line in:\n{}", stdout));
+    let counts: Vec<u64> = line.split(',').map(|part| part.split_whitespace().next().unwrap().parse().unwrap()).collect();
+    let [requested, sent, completed, cancelled, never_sent] = counts[..] else {
+        panic!("unexpected Requests line: {}", line);
+    };
+    assert_eq!(requested, 7);
+    assert_eq!(sent + never_sent, requested, "{}", line);
+    assert_eq!(completed + cancelled, sent, "{}", line);
+    assert!(completed >= 1 && cancelled >= 1, "{}", line);
+}
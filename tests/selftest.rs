@@ -0,0 +1,56 @@
+//! `benchy selftest` itself, over each protocol its server speaks: every check it makes
+//! of the run against what the server saw has to pass
+
+mod common;
+
+fn selftest(args: &[&str]) -> String {
+    let output = common::benchy().arg("selftest").args(args).output().expect("run benchy selftest");
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(
+        output.status.success(),
+        "benchy selftest {:?} exited with {}:\n{}{}",
+        args,
+        output.status,
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    stdout
+}
+
+/// The value of the `label` line of the self-test section
+fn check<'a>(stdout: &'a str, label: &str) -> &'a str {
+    let (_, checks) = stdout.split_once("--- Self-test ---").unwrap_or_else(|| panic!("no checks in:\n{}", stdout));
+    checks
+        .lines()
+        .find_map(|line| line.trim_start().strip_prefix(label))
+        .unwrap_or_else(|| panic!("no {} check in:\n{}", label, stdout))
+        .trim_start()
+}
+
+#[test]
+fn h2_with_injected_errors_adds_up() {
+    let stdout = selftest(&[
+        "-n", "400", "-c", "4", "--workers", "2", "--selftest-latency", "1ms", "--selftest-error-rate", "0.1",
+    ]);
+    for label in ["Counts:", "Server:", "Errors:", "Percentiles:", "Protocol:", "Spread:"] {
+        assert!(check(&stdout, label).starts_with("ok "), "{}", stdout);
+    }
+    assert!(check(&stdout, "Counts:").starts_with("ok 400 requested"), "{}", stdout);
+}
+
+#[test]
+fn h1_adds_up() {
+    let stdout = selftest(&["-n", "200", "-c", "2", "--protocol", "h1"]);
+    for label in ["Counts:", "Server:", "Errors:", "Percentiles:", "Protocol:"] {
+        assert!(check(&stdout, label).starts_with("ok "), "{}", stdout);
+    }
+    // Only h2 maps a connection to a client, so there's no spread to check
+    assert!(!stdout.contains("Spread:"), "{}", stdout);
+}
+
+#[test]
+fn h3_is_refused() {
+    let output = common::benchy().args(["selftest", "--protocol", "h3"]).output().expect("run benchy selftest");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("can't serve HTTP/3"));
+}
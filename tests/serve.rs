@@ -0,0 +1,86 @@
+//! `benchy serve` end to end: a run started over the API against a local server, and
+//! its report fetched once it is over
+
+mod common;
+
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Stdio};
+use std::time::{Duration, Instant};
+
+/// Kills the server when the test ends, passed or not
+struct Serving(Child);
+
+impl Drop for Serving {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// `benchy serve` on a random port, and its base URL
+fn start_serve() -> (Serving, String) {
+    let mut child = common::benchy()
+        .args(["serve", "--listen", "127.0.0.1:0"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("start benchy serve");
+    let stdout = child.stdout.take().expect("piped stdout");
+    let mut line = String::new();
+    BufReader::new(stdout).read_line(&mut line).expect("read the listening line");
+    let base = line.split_whitespace().last().expect("Serving on http://ADDR").to_string();
+    (Serving(child), base)
+}
+
+async fn body(resp: reqwest::Response) -> Value {
+    let bytes = resp.bytes().await.expect("response body");
+    serde_json::from_slice(&bytes).expect("JSON body")
+}
+
+#[tokio::test]
+async fn posted_run_reports_against_local_server() {
+    let target = common::serve(false, common::ok).await;
+    let (_serving, base) = start_serve();
+    let api = reqwest::Client::new();
+
+    let created = api
+        .post(format!("{}/runs", base))
+        .body(json!({ "url": format!("http://{}/", target), "args": ["-n", "50", "-c", "2", "--protocol", "h1"] }).to_string())
+        .send()
+        .await
+        .expect("POST /runs");
+    assert_eq!(created.status(), 201);
+    let run = body(created).await;
+    let id = run["id"].as_u64().expect("run id");
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let status = loop {
+        let status = body(api.get(format!("{}/runs/{}", base, id)).send().await.expect("GET /runs/:id")).await;
+        if status["state"] != "running" {
+            break status;
+        }
+        assert!(Instant::now() < deadline, "run still going after 30s: {}", status);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    };
+    assert_eq!(status["state"], "finished", "{}", status);
+
+    let report = api.get(format!("{}/runs/{}/report", base, id)).send().await.expect("GET report");
+    assert_eq!(report.status(), 200);
+    let report = body(report).await;
+    assert_eq!(report["success"], 50, "{}", report);
+    assert_eq!(report["failed"], 0, "{}", report);
+}
+
+#[tokio::test]
+async fn file_flags_are_refused() {
+    let (_serving, base) = start_serve();
+    let resp = reqwest::Client::new()
+        .post(format!("{}/runs", base))
+        .body(json!({ "url": "http://127.0.0.1:1/", "args": ["--data-file", "/etc/passwd"] }).to_string())
+        .send()
+        .await
+        .expect("POST /runs");
+    assert_eq!(resp.status(), 400);
+    let error = body(resp).await;
+    assert_eq!(error["error"], "--data-file can't be given to a served run");
+}
@@ -0,0 +1,43 @@
+//! `--slowest` naming what it lists: with several URLs, the one each request went to,
+//! and with `--request-id-header`, the id it was sent with
+
+mod common;
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::Response;
+use std::time::Duration;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn the_slowest_requests_carry_their_url_and_id() {
+    let addr = common::serve(true, |req| async move {
+        if req.uri().path() == "/slow" {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        Response::new(Full::new(Bytes::from_static(b"ok\n")))
+    })
+    .await;
+    let (fast, slow) = (format!("http://{}/fast", addr), format!("http://{}/slow", addr));
+    let output = tokio::task::spawn_blocking(move || {
+        common::benchy()
+            .args(["-n", "20", "-c", "2", "--slowest", "3", "--request-id-header", "x-request-id", "--no-probe", &fast, &slow])
+            .output()
+            .expect("run benchy")
+    })
+    .await
+    .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+
+    let mut table = stdout.lines().skip_while(|line| !line.starts_with("--- Slowest 3 Requests ---")).skip(1);
+    let header = table.next().unwrap_or_else(|| panic!("no --slowest table in:\n{}", stdout));
+    let header: Vec<&str> = header.split("  ").map(str::trim).filter(|cell| !cell.is_empty()).collect();
+    assert_eq!(header, ["Latency", "Sent at", "Worker", "Status", "Request ID", "Wall clock", "URL"]);
+    for row in table.take(3) {
+        let cells: Vec<&str> = row.split_whitespace().collect();
+        assert_eq!(cells[3], "200", "{}", row);
+        // A ULID
+        assert_eq!(cells[4].len(), 26, "{}", row);
+        assert!(cells[6].ends_with("/slow"), "{}", row);
+    }
+}